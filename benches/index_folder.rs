@@ -0,0 +1,74 @@
+// Time it takes `serux index` to index 5 000 XML files on one thread and on one thread per CPU.
+// Run with `cargo bench --bench index_folder`.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: usize = 5_000;
+const WORDS_PER_DOCUMENT: usize = 500;
+const DISTINCT_TERMS: u64 = 20_000;
+// Runs per thread count, the fastest one is reported so that a cold page cache doesn't count
+const RUNS: usize = 3;
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+fn write_corpus(dir: &Path) {
+    fs::create_dir_all(dir).expect("corpus directory is created");
+    // xorshift, so that every run indexes the same corpus
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for i in 0..DOCUMENTS {
+        let words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        let content = format!("<doc><title>{}</title><p>{}</p></doc>", words[0], words.join(" "));
+        fs::write(dir.join(format!("{i}.xml")), content).expect("document is written");
+    }
+}
+
+fn serux(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_serux"))
+        .arg("--no-progress")
+        .args(args)
+        .env("SERUX_LOG", "warn")
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .status()
+        .expect("serux runs");
+    assert!(status.success(), "serux {args:?} failed");
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("serux-bench-index-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    write_corpus(&dir.join("corpus"));
+
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get()).to_string();
+    println!("{DOCUMENTS} XML files of {WORDS_PER_DOCUMENT} words, best of {RUNS} runs:");
+    let mut thread_counts = vec!["1", cpus.as_str()];
+    thread_counts.dedup();
+    for threads in thread_counts {
+        let fastest = (0..RUNS).map(|_| {
+            let start = Instant::now();
+            serux(&dir, &["index", "--threads", threads, "corpus"]);
+            start.elapsed()
+        }).min().unwrap_or(Duration::ZERO);
+        println!("    {threads:>3} threads  {:.2} s", fastest.as_secs_f64());
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,77 @@
+// Time it takes `serux search` to load an index of 10 000 documents of 300 words each (30 000 distinct terms)
+// and run one query, for every index format. Run with `cargo bench --bench load_index`.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: usize = 10_000;
+const WORDS_PER_DOCUMENT: usize = 300;
+const DISTINCT_TERMS: u64 = 30_000;
+// Searches per format, the fastest one is reported so that a cold page cache doesn't count
+const RUNS: usize = 5;
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+fn write_corpus(dir: &Path) {
+    fs::create_dir_all(dir).expect("corpus directory is created");
+    // xorshift, so that every run indexes the same corpus
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for i in 0..DOCUMENTS {
+        let words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        fs::write(dir.join(format!("{i}.txt")), words.join(" ")).expect("document is written");
+    }
+}
+
+fn serux(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_serux"))
+        .arg("--no-progress")
+        .env("SERUX_LOG", "warn")
+        .args(args)
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .status()
+        .expect("serux runs");
+    assert!(status.success(), "serux {args:?} failed");
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("serux-bench-load-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    write_corpus(&dir.join("corpus"));
+
+    let formats: &[(&str, &[&str])] = &[
+        ("index.json", &[]),
+        ("index.bin", &["--format", "binary"]),
+        ("index.json.zst", &["--compress"]),
+    ];
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words, {DISTINCT_TERMS} distinct terms, best of {RUNS} searches:");
+    for (index_file, index_args) in formats {
+        serux(&dir, &[&["index"], *index_args, &["corpus"]].concat());
+        let size = fs::metadata(dir.join(index_file)).expect("index is saved").len();
+        let fastest = (0..RUNS).map(|_| {
+            let start = Instant::now();
+            serux(&dir, &["search", index_file, &word(42)]);
+            start.elapsed()
+        }).min().unwrap_or(Duration::ZERO);
+        println!("    {index_file:<15} {:>3} MB  {:.2} s", size / 1_000_000, fastest.as_secs_f64());
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
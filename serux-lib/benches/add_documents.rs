@@ -0,0 +1,78 @@
+// Time it takes to add 10 000 small documents from memory one by one and with Model::add_document_batch,
+// to an InMemoryModel and to a SqliteModel. Run with `cargo bench -p serux-lib --bench add_documents`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serux_lib::{InMemoryModel, Model, SqliteModel};
+
+const DOCUMENTS: usize = 10_000;
+const WORDS_PER_DOCUMENT: usize = 20;
+const DISTINCT_TERMS: u64 = 5_000;
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+fn corpus() -> Vec<(PathBuf, Vec<char>)> {
+    // xorshift, so that every run adds the same documents
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..DOCUMENTS).map(|i| {
+        let words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        (PathBuf::from(format!("{i}.txt")), words.join(" ").chars().collect())
+    }).collect()
+}
+
+fn time(add: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    add();
+    start.elapsed()
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("serux-bench-add-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("temporary directory is created");
+    let docs = corpus();
+
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words, {DISTINCT_TERMS} distinct terms:");
+    let one_by_one = time(|| {
+        let mut model = InMemoryModel::default();
+        for (path, content) in docs.clone() {
+            model.add_document(path, &content, None).expect("document is added");
+        }
+    });
+    let batch = time(|| {
+        InMemoryModel::default().add_document_batch(docs.clone().into_iter()).expect("documents are added");
+    });
+    println!("    InMemoryModel  one by one {:>7.2} s  batch {:>7.2} s", one_by_one.as_secs_f64(), batch.as_secs_f64());
+
+    // Every document is a transaction of its own unless they are added in a batch
+    let one_by_one = time(|| {
+        let mut model = SqliteModel::open(&dir.join("one-by-one.db")).expect("database is created");
+        for (path, content) in docs.clone() {
+            model.add_document(path, &content, None).expect("document is added");
+        }
+    });
+    let batch = time(|| {
+        let mut model = SqliteModel::open(&dir.join("batch.db")).expect("database is created");
+        model.add_document_batch(docs.clone().into_iter()).expect("documents are added");
+    });
+    println!("    SqliteModel    one by one {:>7.2} s  batch {:>7.2} s", one_by_one.as_secs_f64(), batch.as_secs_f64());
+
+    let _ = fs::remove_dir_all(&dir);
+}
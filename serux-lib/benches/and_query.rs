@@ -0,0 +1,82 @@
+// Time it takes to find the documents containing both terms of a query, one rare and one common, by
+// intersecting their posting lists, against checking every document for them as boolean searches do.
+// Run with `cargo bench -p serux-lib --bench and_query`.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serux_lib::query::{ParsedQuery, QueryParser};
+use serux_lib::{InMemoryModel, Model};
+
+const DOCUMENTS: usize = 100_000;
+const WORDS_PER_DOCUMENT: usize = 20;
+const DISTINCT_TERMS: u64 = 5_000;
+// Documents containing each of the query terms
+const RARE: usize = 100;
+const COMMON: usize = 50_000;
+const RUNS: u32 = 5;
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+// Random words, with "needle" in every thousandth document and "hay" in every other one, so that half
+// of the documents with "needle" have both
+fn corpus() -> Vec<(PathBuf, Vec<char>)> {
+    // xorshift, so that every run indexes the same documents
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..DOCUMENTS).map(|i| {
+        let mut words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        if i % (DOCUMENTS / RARE) == 0 {
+            words[0] = "needle".to_string();
+        }
+        if i % (DOCUMENTS / COMMON) == 1 || i % (2 * DOCUMENTS / RARE) == 0 {
+            words[1] = "hay".to_string();
+        }
+        (PathBuf::from(format!("{i}.txt")), words.join(" ").chars().collect())
+    }).collect()
+}
+
+fn time<T>(mut run: impl FnMut() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let mut result = run();
+    for _ in 1..RUNS {
+        result = run();
+    }
+    (start.elapsed() / RUNS, result)
+}
+
+fn paths(results: &[(PathBuf, f32)]) -> HashSet<&PathBuf> {
+    results.iter().map(|(path, _)| path).collect()
+}
+
+fn main() {
+    let mut model = InMemoryModel::default();
+    model.add_document_batch(corpus().into_iter()).expect("documents are added");
+    model.finalize();
+
+    let all_words = ParsedQuery { min_should_match: 2, ..QueryParser::parse("needle hay").expect("query parses") };
+    let (intersecting, intersected) = time(|| model.search_query_parsed(&all_words).expect("search runs"));
+    let boolean = QueryParser::parse_boolean("needle AND hay").expect("query parses");
+    let (scanning, scanned) = time(|| model.search_boolean(&boolean).expect("search runs"));
+    assert_eq!(intersected.len(), RARE / 2);
+    assert_eq!(paths(&intersected), paths(&scanned));
+
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words, {RARE} with one term, {COMMON} with the other, {} with both:", intersected.len());
+    println!("    intersected posting lists   {:>9.3} ms", intersecting.as_secs_f64() * 1e3);
+    println!("    every document checked      {:>9.3} ms", scanning.as_secs_f64() * 1e3);
+}
@@ -0,0 +1,81 @@
+// Time it takes to rank 50 000 documents for a query of 1 000 terms with and without the IDF of every term
+// cached. Run with `cargo bench -p serux-lib --bench idf_cache`.
+//
+// TF-IDF searches read the IDF from the posting lists built by finalize(), so this ranks with search_boolean,
+// which looks the IDF of every query term up for every document it matches.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serux_lib::query::Query;
+use serux_lib::{IdfVariant, InMemoryModel, Model};
+
+const DOCUMENTS: usize = 50_000;
+const WORDS_PER_DOCUMENT: usize = 20;
+const DISTINCT_TERMS: u64 = 20_000;
+const QUERY_TERMS: u64 = 1_000;
+const RUNS: u32 = 5;
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+fn corpus() -> Vec<(PathBuf, Vec<char>)> {
+    // xorshift, so that every run indexes the same documents
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..DOCUMENTS).map(|i| {
+        let words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        (PathBuf::from(format!("{i}.txt")), words.join(" ").chars().collect())
+    }).collect()
+}
+
+// term OR term OR ..., balanced so that matching it doesn't recurse 1 000 levels deep
+fn or_query(terms: &[String]) -> Query {
+    match terms {
+        [term] => Query::Term(term.clone()),
+        _ => {
+            let (lhs, rhs) = terms.split_at(terms.len() / 2);
+            Query::Or(Box::new(or_query(lhs)), Box::new(or_query(rhs)))
+        }
+    }
+}
+
+fn time(model: &InMemoryModel, query: &Query) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut matches = 0;
+    for _ in 0..RUNS {
+        matches = model.search_boolean(query).expect("search runs").len();
+    }
+    (start.elapsed() / RUNS, matches)
+}
+
+fn main() {
+    let mut model = InMemoryModel::default();
+    model.add_document_batch(corpus().into_iter()).expect("documents are added");
+    model.finalize();
+    let terms = (0..QUERY_TERMS).map(|n| word(n * (DISTINCT_TERMS / QUERY_TERMS))).collect::<Vec<_>>();
+    let query = or_query(&terms);
+
+    let (cached, matches) = time(&model, &query);
+    // Changing the IDF variant drops the cache, and nothing builds it again until the next finalize()
+    model.set_idf(IdfVariant::Smooth);
+    model.set_idf(IdfVariant::Standard);
+    let (uncached, _) = time(&model, &query);
+
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words, query of {QUERY_TERMS} terms matching {matches} of them:");
+    println!("    IDF computed for every document  {:>7.3} s", uncached.as_secs_f64());
+    println!("    IDF cached                       {:>7.3} s", cached.as_secs_f64());
+}
@@ -0,0 +1,73 @@
+// Time it takes to rank the 1 000 documents matching a query, and to rerank them by Maximal Marginal
+// Relevance on top of that. Run with `cargo bench -p serux-lib --bench mmr`.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serux_lib::model::mmr_rerank;
+use serux_lib::{InMemoryModel, Model, RankingAlgorithm};
+
+const DOCUMENTS: usize = 10_000;
+const MATCHING: usize = 1_000;
+const WORDS_PER_DOCUMENT: usize = 50;
+const DISTINCT_TERMS: u64 = 5_000;
+const RUNS: u32 = 5;
+const LAMBDA: f32 = 0.7;
+// Results shown: a page, and all of them
+const PICKED: [usize; 2] = [10, MATCHING];
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+// Every tenth document contains the query term, the others only random words
+fn corpus() -> Vec<(PathBuf, Vec<char>)> {
+    // xorshift, so that every run indexes the same documents
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..DOCUMENTS).map(|i| {
+        let mut words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        if i % (DOCUMENTS / MATCHING) == 0 {
+            words[i % WORDS_PER_DOCUMENT] = "needle".to_string();
+        }
+        (PathBuf::from(format!("{i}.txt")), words.join(" ").chars().collect())
+    }).collect()
+}
+
+fn time<T>(mut run: impl FnMut() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let mut result = run();
+    for _ in 1..RUNS {
+        result = run();
+    }
+    (start.elapsed() / RUNS, result)
+}
+
+fn main() {
+    let mut model = InMemoryModel::default();
+    model.add_document_batch(corpus().into_iter()).expect("documents are added");
+    model.finalize();
+    let query = "needle".chars().collect::<Vec<_>>();
+
+    let (ranking, results) = time(|| model.search_query_ranked(&query, RankingAlgorithm::TfIdf).expect("search runs"));
+    assert_eq!(results.len(), MATCHING);
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words, {MATCHING} of them matching:");
+    println!("    plain ranking                     {:>9.3} ms", ranking.as_secs_f64() * 1e3);
+    for k in PICKED {
+        let (reranking, reranked) = time(|| mmr_rerank(&results, &model, LAMBDA, k));
+        assert_eq!(reranked.len(), k);
+        println!("    MMR reranking of the top {k:<5}   {:>9.3} ms", reranking.as_secs_f64() * 1e3);
+    }
+}
@@ -0,0 +1,56 @@
+// Time it takes to find the terms starting with a prefix in a dictionary of 500 000 terms, scanning a range
+// of the sorted DocFreq against filtering every key of a HashMap. Run with
+// `cargo bench -p serux-lib --bench prefix_scan`.
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use serux_lib::model::{terms_with_prefix, DocFreq};
+
+const TERMS: u64 = 500_000;
+const RUNS: u32 = 20;
+const PREFIXES: [&str; 4] = ["A", "QU", "ZEB", "MNOPQ"];
+
+// Upper case words, as the Lexer leaves them, spread over the whole alphabet
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+fn time(mut scan: impl FnMut() -> usize) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut matches = 0;
+    for _ in 0..RUNS {
+        matches = black_box(scan());
+    }
+    (start.elapsed() / RUNS, matches)
+}
+
+fn main() {
+    // xorshift, so that every run builds the same dictionary
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let terms = (0..TERMS).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (word(state), (state % 1000) as usize + 1)
+    }).collect::<Vec<_>>();
+    let sorted = terms.iter().cloned().collect::<DocFreq>();
+    let hashed = terms.into_iter().collect::<HashMap<_, _>>();
+
+    println!("Prefix scan of {} terms:", sorted.len());
+    for prefix in PREFIXES {
+        let (range, matches) = time(|| terms_with_prefix(&sorted, prefix).count());
+        let (linear, linear_matches) = time(|| hashed.keys().filter(|term| term.starts_with(prefix)).count());
+        assert_eq!(matches, linear_matches);
+        println!("    {prefix:<6} {matches:>6} terms   BTreeMap range {:>10.3} ms   HashMap linear scan {:>10.3} ms",
+                 range.as_secs_f64() * 1e3, linear.as_secs_f64() * 1e3);
+    }
+}
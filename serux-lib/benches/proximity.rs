@@ -0,0 +1,77 @@
+// Time it takes to rank the documents matching a two-term query with and without proximity, and where
+// the documents with the terms next to each other end up. Run with `cargo bench -p serux-lib --bench proximity`.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serux_lib::{InMemoryModel, Model, RankingAlgorithm};
+
+const DOCUMENTS: usize = 10_000;
+const MATCHING: usize = 1_000;
+const WORDS_PER_DOCUMENT: usize = 200;
+const DISTINCT_TERMS: u64 = 5_000;
+const RUNS: u32 = 5;
+const PROXIMITY_WEIGHTS: [f32; 2] = [0.0, 1.0];
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+// Every tenth document contains both query terms once, next to each other in every other one of them and at
+// opposite ends of the document in the rest
+fn corpus() -> Vec<(PathBuf, Vec<char>)> {
+    // xorshift, so that every run indexes the same documents
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..DOCUMENTS).map(|i| {
+        let mut words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            word(state % DISTINCT_TERMS)
+        }).collect::<Vec<_>>();
+        if i % (DOCUMENTS / MATCHING) == 0 {
+            let (a, b) = if i % (2 * DOCUMENTS / MATCHING) == 0 { (0, 1) } else { (0, WORDS_PER_DOCUMENT - 1) };
+            words[a] = "needle".to_string();
+            words[b] = "haystack".to_string();
+        }
+        (PathBuf::from(format!("{i}.txt")), words.join(" ").chars().collect())
+    }).collect()
+}
+
+fn time<T>(mut run: impl FnMut() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let mut result = run();
+    for _ in 1..RUNS {
+        result = run();
+    }
+    (start.elapsed() / RUNS, result)
+}
+
+fn main() {
+    let corpus = corpus();
+    let query = "needle haystack".chars().collect::<Vec<_>>();
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words, {MATCHING} of them matching, half with the terms next to each other:");
+    for proximity_weight in PROXIMITY_WEIGHTS {
+        let mut model = InMemoryModel::builder().proximity_weight(proximity_weight).build();
+        model.add_document_batch(corpus.clone().into_iter()).expect("documents are added");
+        model.finalize();
+
+        let (ranking, results) = time(|| model.search_query_ranked(&query, RankingAlgorithm::TfIdf).expect("search runs"));
+        assert_eq!(results.len(), MATCHING);
+        // Documents with the terms next to each other are numbered by multiples of 2 * DOCUMENTS / MATCHING
+        let near_first = results.iter().take(MATCHING / 2)
+            .filter(|(path, _)| path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<usize>().ok())
+                .is_some_and(|i| i % (2 * DOCUMENTS / MATCHING) == 0))
+            .count();
+        println!("    proximity weight {proximity_weight:.1}   {:>9.3} ms, {near_first:>4} of the top {} with the terms next to each other",
+            ranking.as_secs_f64() * 1e3, MATCHING / 2);
+    }
+}
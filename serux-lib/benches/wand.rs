@@ -0,0 +1,113 @@
+// Time it takes to find the top 10 documents of a query mixing a rare and a common term, with WAND and by
+// scoring every document that matches, and how many documents WAND scores on the way.
+// Run with `cargo bench -p serux-lib --bench wand`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serux_lib::{InMemoryModel, Model};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+const DOCUMENTS: usize = 100_000;
+const WORDS_PER_DOCUMENT: usize = 20;
+const DISTINCT_TERMS: u64 = 5_000;
+const RUNS: u32 = 5;
+const K: usize = 10;
+
+// The documents the last WAND search scored, from its debug event
+static SCORED: AtomicU64 = AtomicU64::new(0);
+
+struct ScoredCounter;
+
+impl Visit for ScoredCounter {
+    // usize fields are only recorded as Debug
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "scored" {
+            SCORED.store(format!("{value:?}").parse().expect("scored is a number"), Ordering::Relaxed);
+        }
+    }
+}
+
+// Only looks at the events of search_topk
+struct ScoredSubscriber;
+
+impl Subscriber for ScoredSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_event()
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        event.record(&mut ScoredCounter);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+// Words made of letters only, so that the Lexer keeps each of them as one term
+fn word(mut n: u64) -> String {
+    let mut word = String::new();
+    loop {
+        word.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+// Terms drawn with a skewed distribution, the square of a uniform one, so that the first ones are in
+// most documents and the last ones in a few
+fn corpus() -> Vec<(PathBuf, Vec<char>)> {
+    // xorshift, so that every run indexes the same documents
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..DOCUMENTS).map(|i| {
+        let words = (0..WORDS_PER_DOCUMENT).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let uniform = (state >> 11) as f64 / (1u64 << 53) as f64;
+            word((uniform * uniform * DISTINCT_TERMS as f64) as u64)
+        }).collect::<Vec<_>>();
+        (PathBuf::from(format!("{i}.txt")), words.join(" ").chars().collect())
+    }).collect()
+}
+
+fn time<T>(mut run: impl FnMut() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let mut result = run();
+    for _ in 1..RUNS {
+        result = run();
+    }
+    (start.elapsed() / RUNS, result)
+}
+
+fn main() {
+    tracing::subscriber::set_global_default(ScoredSubscriber).expect("no other subscriber is set");
+    let mut model = InMemoryModel::default();
+    model.add_document_batch(corpus().into_iter()).expect("documents are added");
+    model.finalize();
+
+    println!("{DOCUMENTS} documents of {WORDS_PER_DOCUMENT} words of {DISTINCT_TERMS}, top {K}:");
+    // A common term with a rarer one, two common ones, and a rare one with two common ones
+    for query in [word(1) + " " + &word(3_000), word(1) + " " + &word(2), word(1) + " " + &word(2) + " " + &word(4_000)] {
+        let chars = query.chars().collect::<Vec<_>>();
+        let (exhaustive, all) = time(|| model.search_query(&chars, 0, usize::MAX).expect("search runs").results);
+        let (wand, top) = time(|| model.search_topk(&chars, K).expect("search runs"));
+        assert_eq!(top, all[..K.min(all.len())]);
+        println!("    {query:<12} every match  {:>9.3} ms, {:>6} documents scored", exhaustive.as_secs_f64() * 1e3, all.len());
+        println!("    {:<12} WAND         {:>9.3} ms, {:>6} documents scored", "", wand.as_secs_f64() * 1e3, SCORED.load(Ordering::Relaxed));
+    }
+}
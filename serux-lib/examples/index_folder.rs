@@ -0,0 +1,51 @@
+//! Index the text and Markdown files of a folder into a JSON index.
+//!
+//! ```sh
+//! cargo run -p serux-lib --example index_folder -- docs index.json
+//! ```
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::process::ExitCode;
+
+use serux_lib::{InMemoryModel, Model, SearchError};
+
+fn add_folder(model: &mut InMemoryModel, dir_path: &Path) -> Result<(), SearchError> {
+    for entry in fs::read_dir(dir_path).map_err(|err| SearchError::io_at(dir_path, err))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            add_folder(model, &path)?;
+        } else if path.extension().is_some_and(|extension| extension == "txt" || extension == "md") {
+            let content = fs::read_to_string(&path).map_err(|err| SearchError::io_at(&path, err))?;
+            model.add_document(path, &content.chars().collect::<Vec<_>>(), Some("text/plain"))?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().collect::<Vec<_>>();
+    let [_, dir_path, index_path] = args.as_slice() else {
+        eprintln!("Usage: index_folder <folder> <index-file>");
+        return ExitCode::FAILURE;
+    };
+
+    let mut model = InMemoryModel::builder().stemming(true).build();
+    let result = add_folder(&mut model, Path::new(dir_path)).and_then(|()| {
+        // The IDF of every term is saved with the index, so that loading it is quick
+        model.cache_idf();
+        let index_file = File::create(index_path).map_err(|err| SearchError::io_at(Path::new(index_path), err))?;
+        Ok(serde_json::to_writer(BufWriter::new(index_file), &model)?)
+    });
+    match result {
+        Ok(()) => {
+            println!("indexed {} documents into {index_path}", model.document_count().unwrap_or_default());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Search a JSON index, such as the one the index_folder example writes, with BM25.
+//!
+//! ```sh
+//! cargo run -p serux-lib --example search -- index.json "borrow checker"
+//! ```
+
+use std::fs;
+use std::process::ExitCode;
+
+use serux_lib::{schema, Model, RankingAlgorithm, SearchError};
+
+// Number of results printed
+const MAX_RESULTS: usize = 10;
+
+fn search(index_path: &str, query: &str) -> Result<(), SearchError> {
+    let json = fs::read(index_path).map_err(|err| SearchError::io_at(index_path.as_ref(), err))?;
+    // Indexes saved by older versions of serux are migrated on the way
+    let mut model = schema::model_from_json(&json)?;
+    model.finalize();
+
+    let query = query.chars().collect::<Vec<_>>();
+    for (path, score) in model.search_query_ranked(&query, RankingAlgorithm::bm25())?.into_iter().take(MAX_RESULTS) {
+        println!("{score:>10.6} {path}", path = path.display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().collect::<Vec<_>>();
+    let [_, index_path, query] = args.as_slice() else {
+        eprintln!("Usage: search <index-file> <query>");
+        return ExitCode::FAILURE;
+    };
+    match search(index_path, query) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+// Every term sets this many bits of the filter
+const HASH_COUNT: u32 = 4;
+
+// Share of the terms that are not in the filter but still pass it, when it holds as many terms as it was sized for
+const FALSE_POSITIVE_RATE: f64 = 0.001;
+
+// FNV-1a, so that the filter saved with an index gives the same answers in every build of serux
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// Finalizer of SplitMix64, to derive a second hash from the first one
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Bloom filter of the indexed terms. A term it doesn't contain is certainly not indexed,
+/// so the lookups of query terms that are nowhere in the corpus, e.g. misspelled ones, stop there.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TermBloomFilter {
+    bits: Vec<u64>,
+    bit_count: u64,
+}
+
+impl TermBloomFilter {
+    /// Empty filter sized for `term_count` terms: with k hashes, the false positive rate p needs
+    /// m = -k n / ln(1 - p^(1/k)) bits, about 20 per term for 4 hashes and 0.1%
+    pub fn with_capacity(term_count: usize) -> Self {
+        let bits_per_term = -(HASH_COUNT as f64) / (1f64 - FALSE_POSITIVE_RATE.powf(1f64 / HASH_COUNT as f64)).ln();
+        let bit_count = ((term_count.max(1) as f64 * bits_per_term).ceil() as u64).max(64);
+        Self { bits: vec![0; bit_count.div_ceil(64) as usize], bit_count }
+    }
+
+    pub fn from_terms<'a>(terms: impl ExactSizeIterator<Item = &'a String>) -> Self {
+        let mut filter = Self::with_capacity(terms.len());
+        for term in terms {
+            filter.insert(term);
+        }
+        filter
+    }
+
+    // Positions of the bits of `term`, by double hashing
+    fn bit_indexes(&self, term: &str) -> impl Iterator<Item = u64> {
+        let h1 = fnv1a(term.as_bytes());
+        let h2 = mix(h1) | 1;
+        let bit_count = self.bit_count;
+        (0..HASH_COUNT as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bit_count)
+    }
+
+    pub fn insert(&mut self, term: &str) {
+        for i in self.bit_indexes(term) {
+            self.bits[(i / 64) as usize] |= 1 << (i % 64);
+        }
+    }
+
+    /// False if `term` was never inserted; true if it was, or, rarely, if it wasn't.
+    /// The filter of a corrupted index, with fewer bits than it says, lets every term through.
+    pub fn might_contain(&self, term: &str) -> bool {
+        self.bit_count == 0 || self.bit_indexes(term).all(|i| {
+            self.bits.get((i / 64) as usize).is_none_or(|word| word & (1 << (i % 64)) != 0)
+        })
+    }
+}
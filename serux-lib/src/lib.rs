@@ -0,0 +1,38 @@
+//! Full-text search of documents, ranked by TF-IDF or BM25.
+//!
+//! An [`InMemoryModel`] keeps the whole index in memory and is saved as JSON, a [`SqliteModel`]
+//! keeps it in an SQLite database. Both are searched through the [`Model`] trait, and both read
+//! the terms of documents and queries with the same [`Lexer`].
+//!
+//! # Examples
+//!
+//! ```
+//! use serux_lib::{InMemoryModel, Model};
+//!
+//! let mut model = InMemoryModel::default();
+//! let documents = [("fox.txt", "the quick brown fox"), ("dog.txt", "the lazy dog")];
+//! for (path, content) in documents {
+//!     model.add_document(path.into(), &content.chars().collect::<Vec<_>>(), None)?;
+//! }
+//! model.finalize();
+//!
+//! let page = model.search_query(&"fox".chars().collect::<Vec<_>>(), 0, 10)?;
+//! assert_eq!(page.results[0].0, std::path::Path::new("fox.txt"));
+//! # Ok::<(), serux_lib::SearchError>(())
+//! ```
+
+pub mod model;
+pub mod query;
+pub mod schema;
+pub mod stats;
+#[doc(hidden)]
+pub mod cache;
+
+mod bloom;
+mod pool;
+mod stemmer;
+
+#[cfg(test)]
+mod tests;
+
+pub use model::{compute_idf, compute_tf, compute_tf_augmented, IdfVariant, InMemoryModel, Lexer, Model, ModelConfig, RankingAlgorithm, SearchError, SqliteModel, TfNormalization, TfVariant};
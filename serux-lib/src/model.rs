@@ -0,0 +1,4988 @@
+//! Search models. An `InMemoryModel` is built by adding documents with `Model::add_document`,
+//! then `InMemoryModel::finalize` computes the ranked posting lists that `Model::search_query` reads.
+//! Adding documents after that makes the posting lists stale until `finalize` is called again.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::ops::{Bound, Range};
+use std::time::SystemTime;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use std::result::Result;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+use unicode_properties::{GeneralCategory, UnicodeGeneralCategory};
+use super::stemmer::porter_stem;
+use super::query::{ParsedQuery, Query};
+use super::cache::LruCache;
+use super::pool::ConnectionPool;
+use super::bloom::TermBloomFilter;
+use super::schema::SCHEMA_VERSION;
+use super::stats::{corpus_stats_of, CorpusStats, TermCount, TOP_TERMS};
+use regex::{Regex, RegexBuilder};
+use sha2::{Digest, Sha256};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+#[derive(Debug)]
+pub enum SearchError {
+    IoError(io::Error),
+    XmlParseError { path: PathBuf, row: u64, col: u64, msg: String },
+    JsonError(serde_json::Error),
+    SqliteError(sqlite::Error),
+    IndexCorrupted(String),
+    // The model has no implementation of the named feature
+    Unsupported(&'static str),
+    // The command line does not make sense; the message says why
+    Usage(String),
+    // serux.toml can't be parsed or has out of range values
+    InvalidConfig(String),
+    // The path is not one of the documents of the index
+    DocumentNotFound(PathBuf),
+    // The query can't be searched for, e.g. a regular expression that doesn't compile
+    InvalidQuery(String),
+    // The index was saved by a newer version of serux, in a layout this one doesn't know
+    SchemaVersionTooNew { found: u32, supported: u32 },
+}
+
+impl SearchError {
+    /// I/O error that names the file it happened on
+    pub fn io_at(path: &Path, err: io::Error) -> Self {
+        SearchError::IoError(io::Error::new(err.kind(), format!("{path}: {err}", path = path.display())))
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::IoError(err) => write!(f, "{err}"),
+            SearchError::XmlParseError { path, row, col, msg } => write!(f, "{path}:{row}:{col}: {msg}", path = path.display()),
+            SearchError::JsonError(err) => write!(f, "invalid JSON: {err}"),
+            SearchError::SqliteError(err) => write!(f, "SQLite: {err}"),
+            SearchError::IndexCorrupted(msg) => write!(f, "index is corrupted: {msg}"),
+            SearchError::Unsupported(feature) => write!(f, "{feature} is not supported by this model"),
+            SearchError::Usage(msg) => write!(f, "{msg}"),
+            SearchError::InvalidConfig(msg) => write!(f, "invalid configuration: {msg}"),
+            SearchError::DocumentNotFound(path) => write!(f, "{path} is not in the index", path = path.display()),
+            SearchError::InvalidQuery(msg) => write!(f, "invalid query: {msg}"),
+            SearchError::SchemaVersionTooNew { found, supported } => {
+                write!(f, "the index has schema version {found}, but this version of serux only reads up to {supported}; upgrade serux to use it")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SearchError::IoError(err) => Some(err),
+            SearchError::JsonError(err) => Some(err),
+            SearchError::SqliteError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SearchError {
+    fn from(err: io::Error) -> Self {
+        SearchError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for SearchError {
+    fn from(err: serde_json::Error) -> Self {
+        SearchError::JsonError(err)
+    }
+}
+
+impl From<sqlite::Error> for SearchError {
+    fn from(err: sqlite::Error) -> Self {
+        SearchError::SqliteError(err)
+    }
+}
+
+pub trait Model {
+    /// Rank the documents against `query` and return the `limit` results following the first `offset` ones
+    fn search_query(&self, query: &[char], offset: usize, limit: usize) -> Result<SearchPage, SearchError>;
+
+    fn document_count(&self) -> Result<usize, SearchError>;
+
+    fn stats(&self) -> Result<IndexStats, SearchError>;
+
+    /// Metadata of an indexed document, or None if the path is not in the index
+    fn document_info(&self, path: &Path) -> Result<Option<DocumentInfo>, SearchError>;
+    /// `mime_type` describes the format the text was extracted from (e.g. "text/plain", "text/html");
+    /// the content itself is always plain text
+    fn add_document(&mut self, path: PathBuf, content: &[char], mime_type: Option<&str>) -> Result<(), SearchError>;
+
+    /// Drop a document from the index, e.g. after its file was deleted. Returns false if it was not indexed.
+    fn remove_document(&mut self, path: &Path) -> Result<bool, SearchError>;
+
+    /// Add documents that only exist in memory, e.g. generated by a program, without writing them to files first.
+    /// Returns how many documents were added. The CLI itself always indexes files.
+    fn add_document_batch(&mut self, docs: impl Iterator<Item = (PathBuf, Vec<char>)>) -> Result<usize, SearchError> where Self: Sized {
+        add_each_document(self, docs)
+    }
+
+    /// Whether the file at `path` has to be (re)indexed, given its last modification time.
+    /// Models that do not track modification times always reindex.
+    fn requires_reindexing(&mut self, _path: &Path, _last_modified: SystemTime) -> Result<bool, SearchError> {
+        Ok(true)
+    }
+
+    /// Attach a human readable title to an already added document. Models that keep no metadata ignore it.
+    fn set_document_title(&mut self, _path: &Path, _title: String) -> Result<(), SearchError> {
+        Ok(())
+    }
+
+    fn document_title(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    /// Sorted character offsets in the file of the path where the terms of the query occur,
+    /// empty if the model doesn't know them
+    fn match_positions(&self, _path: &Path, _query: &[char]) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// Excerpt of the document around the terms of the query, for models that cut their own snippets
+    /// from what they stored; None lets the caller cut one from the file
+    fn match_snippet(&self, _path: &Path, _query: &[char]) -> Option<String> {
+        None
+    }
+
+    /// Search for documents containing the terms of `phrase` next to each other, in order
+    fn search_phrase_query(&self, _phrase: &[char]) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("phrase search"))
+    }
+
+    /// Search where every query term also matches indexed terms within `max_distance` edits
+    fn search_fuzzy_query(&self, _query: &[char], _max_distance: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("fuzzy search"))
+    }
+
+    /// Search where words ending with '*' match every indexed term starting with the rest of the word
+    fn search_wildcard_query(&self, _query: &[char], _max_expansions: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("wildcard search"))
+    }
+
+    fn search_boolean_query(&self, _query: &Query) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("boolean search"))
+    }
+
+    /// λ to rerank results with by default, see mmr_rerank, or None if the index was not built to rerank them
+    fn mmr_lambda(&self) -> Option<f32> {
+        None
+    }
+
+    /// The `k` of `results` to show first, reordered by Maximal Marginal Relevance, see mmr_rerank
+    fn mmr_rerank(&self, _results: &[(PathBuf, f32)], _lambda: f32, _k: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("MMR reranking"))
+    }
+
+    /// `query` expanded with up to `expand_terms` terms of its `top_k` first results, see InMemoryModel::expand_query_rocchio
+    fn expand_query(&self, _query: &[char], _top_k: usize, _expand_terms: usize) -> Result<QueryExpansion, SearchError> {
+        Err(SearchError::Unsupported("query expansion"))
+    }
+
+    /// Documents containing any of the indexed `terms`, ranked with the score of every term multiplied by its weight
+    fn search_weighted_terms(&self, _terms: &[(String, f32)], _ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("searching weighted terms"))
+    }
+
+    /// Like search_query_ranked, with the score of every term multiplied by its boost
+    fn search_parsed_query(&self, _query: &ParsedQuery, _ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("term boosts"))
+    }
+
+    /// Search for documents containing indexed terms matched by `regex`, see term_regex.
+    /// Only the first `max_terms` matching terms are searched for.
+    fn search_regex_query(&self, _regex: &Regex, _max_terms: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("regex search"))
+    }
+
+    /// Indexed terms completing `prefix`, see suggest_terms
+    fn suggest_terms(&self, _prefix: &str, _limit: usize) -> Result<Vec<String>, SearchError> {
+        Err(SearchError::Unsupported("term suggestions"))
+    }
+
+    /// How the score of the document at `path` for `query` comes about, or None if the path is not in the index
+    fn explain_score(&self, _path: &Path, _query: &[char], _ranking: RankingAlgorithm) -> Result<Option<ScoreExplanation>, SearchError> {
+        Err(SearchError::Unsupported("score explanations"))
+    }
+
+    /// The `limit` documents most similar to the one at `path`, see InMemoryModel::find_similar
+    fn find_similar(&self, _path: &Path, _limit: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Err(SearchError::Unsupported("similar documents"))
+    }
+
+    /// Ranking to use when the user did not choose one
+    fn default_ranking(&self) -> RankingAlgorithm {
+        RankingAlgorithm::TfIdf
+    }
+
+    /// Search with an explicit ranking algorithm. Models that only know TF-IDF keep this default.
+    fn search_query_ranked(&self, query: &[char], ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        match ranking {
+            RankingAlgorithm::TfIdf => self.search_query(query, 0, usize::MAX).map(|page| page.results),
+            RankingAlgorithm::Bm25 { .. } => Err(SearchError::Unsupported("BM25 ranking")),
+        }
+    }
+
+    /// Tokenizes text into the terms the model indexes, e.g. to find the terms of a query in a document again
+    fn lexer<'a>(&'a self, content: &'a [char]) -> Lexer<'a> {
+        Lexer::new(content)
+    }
+
+    /// Whether terms that only differ in case are distinct, see ModelConfig::case_sensitive
+    fn is_case_sensitive(&self) -> bool {
+        false
+    }
+
+    /// Whether the model can be searched as it is, see InMemoryModel::finalize
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Like search_query_ranked, but the terms of the query match the indexed terms in any case.
+    /// Models that are not case sensitive ignore case anyway.
+    fn search_query_ignoring_case(&self, query: &[char], ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        self.search_query_ranked(query, ranking)
+    }
+}
+
+/// One page of ranked search results
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<(PathBuf, f32)>,
+    /// Number of matching documents before pagination
+    pub total_hits: usize,
+    pub has_more: bool,
+    /// Number of matching documents by file extension, see facet_by_extension
+    pub facets: HashMap<String, usize>,
+}
+
+impl SearchPage {
+    /// Cut a page out of the complete, already sorted result list
+    pub fn paginate(results: Vec<(PathBuf, f32)>, offset: usize, limit: usize) -> Self {
+        let total_hits = results.len();
+        let facets = facet_by_extension(&results);
+        let results = results.into_iter().skip(offset).take(limit).collect::<Vec<_>>();
+        let has_more = offset.saturating_add(results.len()) < total_hits;
+        Self { results, total_hits, has_more, facets }
+    }
+
+    /// Cut the page of the `limit` results that follow `after` out of the complete result list.
+    /// Unlike with an offset, no result is skipped or shown twice when documents were added or removed
+    /// since the cursor was handed out.
+    pub fn after(mut results: Vec<(PathBuf, f32)>, after: Option<&SearchCursor>, limit: usize) -> Self {
+        let total_hits = results.len();
+        let facets = facet_by_extension(&results);
+        sort_for_cursors(&mut results);
+        let mut rest = results.into_iter().filter(|(path, score)| after.is_none_or(|after| after.precedes(path, *score)));
+        let results = rest.by_ref().take(limit).collect::<Vec<_>>();
+        let has_more = rest.next().is_some();
+        Self { results, total_hits, has_more, facets }
+    }
+
+    /// Where the next page starts, None for the last one.
+    /// Only meaningful for results in the order of sort_for_cursors.
+    pub fn next_cursor(&self) -> Option<SearchCursor> {
+        let (path, score) = self.results.last().filter(|_| self.has_more)?;
+        Some(SearchCursor { score: *score, path: path.clone() })
+    }
+}
+
+/// Order of results that cursors continue in: by score, then by path, so that equal scores have an order too
+pub fn sort_for_cursors(results: &mut [(PathBuf, f32)]) {
+    results.sort_by(|(path1, score1), (path2, score2)| score2.total_cmp(score1).then_with(|| path1.cmp(path2)));
+}
+
+/// The last result of a page, which the next page starts after, see SearchPage::after
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchCursor {
+    pub score: f32,
+    pub path: PathBuf,
+}
+
+impl SearchCursor {
+    /// Opaque to clients: URL-safe base64 of JSON, so that it can be passed in a query string as it is.
+    /// A path that is not UTF-8 is encoded lossily, the page after it may then repeat or skip that document.
+    pub fn encode(&self) -> String {
+        // A tuple of a number and a string always converts
+        let json = serde_json::to_string(&(self.score, self.path.to_string_lossy())).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// None unless `cursor` was made by encode
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let (score, path) = serde_json::from_slice::<(f32, PathBuf)>(&json).ok()?;
+        score.is_finite().then_some(Self { score, path })
+    }
+
+    // Whether a result comes after the cursor in the order of sort_for_cursors
+    fn precedes(&self, path: &Path, score: f32) -> bool {
+        score.total_cmp(&self.score).is_lt() || score == self.score && path > self.path.as_path()
+    }
+}
+
+/// Lowest score a search result needs to be kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MinScoreStrategy {
+    Absolute(f32),
+    // This fraction of the score of the best result
+    FractionOfTop(f32),
+}
+
+impl Default for MinScoreStrategy {
+    // Every result is kept, they all score more than 0
+    fn default() -> Self {
+        MinScoreStrategy::Absolute(0f32)
+    }
+}
+
+impl MinScoreStrategy {
+    /// A score of 0 or more is absolute, a negative one is minus the fraction of the top score,
+    /// e.g. -0.1 keeps the results that score at least a tenth of the best one
+    pub fn from_min_score(min_score: f32) -> Option<Self> {
+        match min_score {
+            min_score if min_score.is_nan() || min_score < -1f32 => None,
+            min_score if min_score < 0f32 => Some(MinScoreStrategy::FractionOfTop(-min_score)),
+            min_score => Some(MinScoreStrategy::Absolute(min_score)),
+        }
+    }
+
+    /// Drop the results that score less than the threshold
+    pub fn retain(self, results: &mut Vec<(PathBuf, f32)>) {
+        let threshold = match self {
+            MinScoreStrategy::Absolute(min_score) => min_score,
+            MinScoreStrategy::FractionOfTop(fraction) => {
+                results.iter().map(|(_, score)| *score).fold(f32::NEG_INFINITY, f32::max) * fraction
+            }
+        };
+        results.retain(|(_, score)| *score >= threshold);
+    }
+}
+
+/// Lowercase extension of the path, empty if it has none
+pub fn file_extension(path: &Path) -> String {
+    path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+/// Number of results for every file extension, see file_extension
+pub fn facet_by_extension(results: &[(PathBuf, f32)]) -> HashMap<String, usize> {
+    let mut facets = HashMap::new();
+    for (path, _) in results {
+        *facets.entry(file_extension(path)).or_default() += 1;
+    }
+    facets
+}
+
+/// Size of an index
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStats {
+    pub document_count: usize,
+    pub unique_terms: usize,
+    /// Total size of the indexed files that are still on disk
+    pub corpus_size_bytes: u64,
+}
+
+/// What the index knows about a single document
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentInfo {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub mime_type: Option<String>,
+    pub term_count: usize,
+    pub unique_terms: usize,
+}
+
+fn add_each_document(model: &mut impl Model, docs: impl Iterator<Item = (PathBuf, Vec<char>)>) -> Result<usize, SearchError> {
+    let mut added = 0;
+    for (path, content) in docs {
+        model.add_document(path, &content, None)?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+/// The text a path is kept as in SQLite: the path itself if it is UTF-8, otherwise its bytes with the
+/// non-printable ones and % percent-encoded, so that any path can be indexed
+pub fn sqlite_path(path: &Path) -> String {
+    if let Some(path) = path.to_str() {
+        return path.to_string();
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().iter().map(|&byte| match byte {
+            b' '..=b'~' if byte != b'%' => char::from(byte).to_string(),
+            _ => format!("%{byte:02X}"),
+        }).collect()
+    }
+    #[cfg(not(unix))]
+    path.to_string_lossy().into_owned()
+}
+
+fn corpus_size_bytes<'a>(paths: impl Iterator<Item = &'a Path>) -> u64 {
+    paths.filter_map(|path| fs::metadata(path).ok()).map(|metadata| metadata.len()).sum()
+}
+
+/// How documents are scored against a query
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub enum RankingAlgorithm {
+    #[default]
+    TfIdf,
+    Bm25 { k1: f32, b: f32 },
+}
+
+impl RankingAlgorithm {
+    pub const BM25_K1: f32 = 1.2;
+    pub const BM25_B: f32 = 0.75;
+
+    /// Okapi BM25 with the usual k1 and b
+    pub fn bm25() -> Self {
+        Self::Bm25 { k1: Self::BM25_K1, b: Self::BM25_B }
+    }
+}
+
+/// How the raw count f of a term in a document becomes its TF for TF-IDF ranking
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub enum TfNormalization {
+    // f divided by the length of the document
+    #[default]
+    Raw,
+    // 1 + ln(f), so that repeating a term adds less and less
+    LogNorm,
+    // 0.5 + 0.5 * f / max_f, with max_f the count of the most frequent term of the document
+    AugmentedFreq,
+    // 1 for every term of the document, which ranks by the IDF of the query terms the document contains
+    BooleanPresence,
+}
+
+/// Another name of TfNormalization, e.g. TfVariant::AugmentedFreq
+pub type TfVariant = TfNormalization;
+
+impl TfNormalization {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(TfNormalization::Raw),
+            "log" => Some(TfNormalization::LogNorm),
+            "augmented" => Some(TfNormalization::AugmentedFreq),
+            "boolean" => Some(TfNormalization::BooleanPresence),
+            _ => None,
+        }
+    }
+}
+
+/// How the document frequency of a term becomes its IDF for TF-IDF ranking
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub enum IdfVariant {
+    // ln(N / df)
+    #[default]
+    Standard,
+    // ln((N + 1) / (df + 1)) + 1, never 0 so that terms of every document still count
+    Smooth,
+    // ln((N - df) / df), clamped to 0 for terms of more than half of the documents
+    Probabilistic,
+    // Always 1, to compare against TF alone
+    UnaryOne,
+}
+
+impl IdfVariant {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(IdfVariant::Standard),
+            "smooth" => Some(IdfVariant::Smooth),
+            "probabilistic" => Some(IdfVariant::Probabilistic),
+            "unary" => Some(IdfVariant::UnaryOne),
+            _ => None,
+        }
+    }
+}
+
+// Time a connection waits for the database to be unlocked by another one before giving up
+const SQLITE_BUSY_TIMEOUT_MS: usize = 5000;
+
+/// Index kept in an SQLite database, which is written as documents are added, so that it
+/// doesn't have to fit in memory. Adding many documents is a lot faster in one transaction.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::{Model, SqliteModel};
+///
+/// let path = std::env::temp_dir().join(format!("serux-doc-{}.db", std::process::id()));
+/// let mut model = SqliteModel::open(&path)?;
+/// model.begin()?;
+/// model.add_document("a.txt".into(), &"the quick brown fox".chars().collect::<Vec<_>>(), None)?;
+/// model.add_document("b.txt".into(), &"the lazy dog".chars().collect::<Vec<_>>(), None)?;
+/// model.commit()?;
+///
+/// let page = model.search_query(&"fox".chars().collect::<Vec<_>>(), 0, 10)?;
+/// assert_eq!(page.results.len(), 1);
+/// # drop(model);
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), serux_lib::SearchError>(())
+/// ```
+pub struct SqliteModel {
+    // Serialized by SQLite itself, so that the server can share the model between threads.
+    // All writes go through it.
+    connection: sqlite::ConnectionWithFullMutex,
+    // Connections for searching of a model opened with `open_pooled`, so that searches don't wait for each other
+    readers: Option<ConnectionPool>,
+}
+
+impl SqliteModel {
+    fn execute(&self, statement: &str) -> Result<(), SearchError> {
+        Ok(self.connection.execute(statement)?)
+    }
+
+    // Run the queries of `f` on a connection of the pool in a read transaction, so that they all see
+    // the same version of the index even while it is written to. Without a pool they run on the
+    // connection that also writes, which may be in the middle of a transaction of its own.
+    fn read<R>(&self, f: impl FnOnce(&sqlite::Connection) -> Result<R, SearchError>) -> Result<R, SearchError> {
+        let Some(readers) = &self.readers else {
+            return f(&self.connection);
+        };
+        let connection = readers.acquire();
+        connection.execute("BEGIN DEFERRED;")?;
+        let result = f(&connection);
+        connection.execute(if result.is_ok() { "COMMIT;" } else { "ROLLBACK;" })?;
+        result
+    }
+
+    /// The write lock is taken right away, so that the transaction never fails halfway because
+    /// another connection started writing first
+    pub fn begin(&self) -> Result<(), SearchError> {
+        self.execute("BEGIN IMMEDIATE;")
+    }
+
+    pub fn commit(&self) -> Result<(), SearchError> {
+        self.execute("COMMIT;")
+    }
+
+    pub fn rollback(&self) -> Result<(), SearchError> {
+        self.execute("ROLLBACK;")
+    }
+
+    /// Open the database at `path`, creating it and its tables if they don't exist
+    pub fn open(path: &Path) -> Result<Self, SearchError> {
+        let mut connection = sqlite::Connection::open_with_full_mutex(path)?;
+        connection.set_busy_timeout(SQLITE_BUSY_TIMEOUT_MS)?;
+        let this = Self { connection, readers: None };
+
+        // The total number of terms for a document
+        this.execute("
+            CREATE TABLE IF NOT EXISTS Documents (
+                id INTEGER NOT NULL PRIMARY KEY,    -- 文档ID
+                path TEXT,                          -- 文档路径
+                term_count INTEGER,                 -- 本文档单词数量
+                UNIQUE(path)                        -- 路径唯一
+            );
+        ")?;
+
+        // The term frequency of a document
+        this.execute("
+            CREATE TABLE IF NOT EXISTS TermFreq (
+                term TEXT,              -- 单词
+                doc_id INTEGER,         -- 文档ID
+                freq INTEGER,           -- 单词在本文档的频率
+                UNIQUE(term, doc_id),   -- (单词, 文档ID)唯一
+                FOREIGN KEY(doc_id) REFERENCES Documents(id)
+            );
+       ")?;
+
+        // Term frequency for all documents
+        this.execute("
+            CREATE TABLE IF NOT EXISTS DocFreq (
+                term TEXT,              -- 单词
+                freq INTEGER,           -- 频率
+                UNIQUE(term)
+            );
+        ")?;
+
+        Ok(this)
+    }
+
+    /// Like `open`, with `pool_size` more connections for searching. The index is switched to write-ahead
+    /// logging, which lets them read while it is written to; that setting stays with the database file.
+    pub fn open_pooled(path: &Path, pool_size: usize) -> Result<Self, SearchError> {
+        const PRAGMAS: &str = "PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL; PRAGMA cache_size=-64000;";
+
+        let mut this = Self::open(path)?;
+        this.execute(PRAGMAS)?;
+        let connections = (0..pool_size.max(1)).map(|_| {
+            let flags = sqlite::OpenFlags::new().set_read_only().set_no_mutex();
+            let mut connection = sqlite::Connection::open_with_flags(path, flags)?;
+            connection.set_busy_timeout(SQLITE_BUSY_TIMEOUT_MS)?;
+            connection.execute(PRAGMAS)?;
+            Ok(connection)
+        }).collect::<Result<Vec<_>, SearchError>>()?;
+        this.readers = Some(ConnectionPool::new(connections));
+        Ok(this)
+    }
+
+    /// Add a document whose terms were counted elsewhere, e.g. by an InMemoryModel with the same tokenizer
+    /// settings as Lexer::new; `path` is stored as it is
+    pub fn add_term_freqs<'t>(&mut self, path: &str, term_count: usize, tf: impl IntoIterator<Item = (&'t str, usize)>) -> Result<(), SearchError> {
+        let doc_id = {
+            let query = "INSERT INTO Documents (path, term_count) VALUES (:path, :count)";
+            let mut stmt = self.connection.prepare(query)?;
+            stmt.bind_iter::<_,(_,sqlite::Value)>([
+                (":path", path.into()),
+                (":count", (term_count as i64).into()),
+            ])?;
+            stmt.next()?;
+            unsafe {
+                sqlite3_sys::sqlite3_last_insert_rowid(self.connection.as_raw())
+            }
+        };
+
+        for (term, freq) in tf {
+            {
+                let query = "INSERT INTO TermFreq (term, doc_id, freq) VALUES (:term, :doc_id, :freq)";
+                let mut stmt = self.connection.prepare(query)?;
+                stmt.bind_iter::<_,(_,sqlite::Value)>([
+                    (":term", term.into()),
+                    (":doc_id", doc_id.into()),
+                    (":freq", (freq as i64).into()),
+                ])?;
+                stmt.next()?;
+            }
+
+            {
+                let query = "INSERT INTO DocFreq (term, freq) VALUES (:term, 1) ON CONFLICT(term) DO UPDATE SET freq = freq + 1";
+                let mut stmt = self.connection.prepare(query)?;
+                stmt.bind((":term", term))?;
+                stmt.next()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the database file and that the counts of the tables agree with each other, like `verify` does
+    /// for an InMemoryModel. The first inconsistency found is returned as SearchError::IndexCorrupted.
+    pub fn verify_integrity(&self) -> Result<(), SearchError> {
+        let checks = [
+            ("PRAGMA integrity_check", "the database file is damaged"),
+            ("
+                SELECT DocFreq.term FROM DocFreq
+                LEFT JOIN (SELECT term, COUNT(*) AS docs FROM TermFreq GROUP BY term) AS Counted ON Counted.term = DocFreq.term
+                WHERE DocFreq.freq != IFNULL(Counted.docs, 0)
+                UNION ALL
+                SELECT term FROM TermFreq WHERE term NOT IN (SELECT term FROM DocFreq)
+            ", "document frequencies don't match the term frequencies"),
+            ("
+                SELECT Documents.path FROM Documents
+                LEFT JOIN TermFreq ON TermFreq.doc_id = Documents.id
+                GROUP BY Documents.id
+                HAVING Documents.term_count != IFNULL(SUM(TermFreq.freq), 0)
+            ", "term counts don't match the term frequencies"),
+            ("SELECT CAST(doc_id AS TEXT) FROM TermFreq WHERE doc_id NOT IN (SELECT id FROM Documents)", "term frequencies of missing documents"),
+        ];
+        self.read(|connection| {
+            for (query, problem) in checks {
+                let mut stmt = connection.prepare(query)?;
+                // integrity_check answers "ok" when there is nothing to report, the other checks answer nothing
+                if let sqlite::State::Row = stmt.next()? {
+                    let found = stmt.read::<String, _>(0)?;
+                    if found != "ok" {
+                        return Err(SearchError::IndexCorrupted(format!("{problem}: {found}")));
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// What the corpus is made of, counted by SQLite, like stats::corpus_stats for an InMemoryModel
+    pub fn corpus_stats(&self) -> Result<CorpusStats, SearchError> {
+        // `query` selects a term and a count
+        let top_terms = |connection: &sqlite::Connection, query: &str| -> Result<Vec<TermCount>, SearchError> {
+            let mut stmt = connection.prepare(query)?;
+            stmt.bind((":limit", TOP_TERMS as i64))?;
+            let mut terms = Vec::new();
+            while let sqlite::State::Row = stmt.next()? {
+                terms.push(TermCount {
+                    term: stmt.read::<String, _>("term")?,
+                    count: stmt.read::<i64, _>("count")? as usize,
+                });
+            }
+            Ok(terms)
+        };
+
+        self.read(|connection| {
+            let unique_terms = {
+                let mut stmt = connection.prepare("SELECT COUNT(*) FROM DocFreq")?;
+                stmt.next()?;
+                stmt.read::<i64, _>(0)? as usize
+            };
+            let top_terms_by_document_frequency = top_terms(connection, "
+                SELECT term, freq AS count FROM DocFreq ORDER BY freq DESC, term LIMIT :limit
+            ")?;
+            let top_terms_by_frequency = top_terms(connection, "
+                SELECT term, SUM(freq) AS count FROM TermFreq GROUP BY term ORDER BY count DESC, term LIMIT :limit
+            ")?;
+
+            let mut stmt = connection.prepare("SELECT path, term_count FROM Documents")?;
+            let mut lengths = Vec::new();
+            while let sqlite::State::Row = stmt.next()? {
+                let path = PathBuf::from(stmt.read::<String, _>("path")?);
+                lengths.push((path, stmt.read::<i64, _>("term_count")? as usize));
+            }
+
+            Ok(corpus_stats_of(unique_terms, top_terms_by_document_frequency, top_terms_by_frequency, lengths))
+        })
+    }
+}
+
+fn count_documents(connection: &sqlite::Connection) -> Result<usize, SearchError> {
+    let query = "SELECT COUNT(*) FROM Documents";
+    let mut stmt = connection.prepare(query)?;
+    stmt.next()?;
+    Ok(stmt.read::<i64, _>(0)? as usize)
+}
+
+impl Model for SqliteModel {
+    fn search_query(&self, query: &[char], offset: usize, limit: usize) -> Result<SearchPage, SearchError> {
+        let tokens = Lexer::new(query).collect::<HashSet<String>>();
+
+        let ranks = self.read(|connection| {
+            let n = count_documents(connection)?;
+
+            let mut ranks = HashMap::<PathBuf, f32>::new();
+            for token in &tokens {
+                // If the term is not in the corpus, the default frequency of 1 avoids a division-by-zero
+                let m = {
+                    let query = "SELECT freq FROM DocFreq WHERE term = :term";
+                    let mut stmt = connection.prepare(query)?;
+                    stmt.bind((":term", token.as_str()))?;
+                    match stmt.next()? {
+                        sqlite::State::Row => stmt.read::<i64, _>("freq")? as usize,
+                        sqlite::State::Done => 1,
+                    }
+                };
+                let idf = (n as f32 / m as f32).ln();
+
+                let query = "
+                    SELECT Documents.path, TermFreq.freq, Documents.term_count
+                    FROM TermFreq
+                    INNER JOIN Documents ON Documents.id = TermFreq.doc_id
+                    WHERE TermFreq.term = :term AND Documents.term_count > 0
+                ";
+                let mut stmt = connection.prepare(query)?;
+                stmt.bind((":term", token.as_str()))?;
+                while let sqlite::State::Row = stmt.next()? {
+                    let path = stmt.read::<String, _>("path")?;
+                    let freq = stmt.read::<i64, _>("freq")? as f32;
+                    let term_count = stmt.read::<i64, _>("term_count")? as f32;
+                    *ranks.entry(PathBuf::from(path)).or_insert(0f32) += freq / term_count * idf;
+                }
+            }
+            Ok(ranks)
+        })?;
+
+        let mut results: Vec<(PathBuf, f32)> = ranks.into_iter().filter(|(_, rank)| *rank > 0f32).collect();
+        results.sort_by(|(_, rank1), (_, rank2)| rank2.partial_cmp(rank1).unwrap());
+        Ok(SearchPage::paginate(results, offset, limit))
+    }
+
+    fn document_count(&self) -> Result<usize, SearchError> {
+        self.read(count_documents)
+    }
+
+    fn stats(&self) -> Result<IndexStats, SearchError> {
+        let (unique_terms, paths) = self.read(|connection| {
+            let unique_terms = {
+                let query = "SELECT COUNT(*) FROM DocFreq";
+                let mut stmt = connection.prepare(query)?;
+                stmt.next()?;
+                stmt.read::<i64, _>(0)? as usize
+            };
+
+            let query = "SELECT path FROM Documents";
+            let mut stmt = connection.prepare(query)?;
+            let mut paths = Vec::new();
+            while let sqlite::State::Row = stmt.next()? {
+                paths.push(PathBuf::from(stmt.read::<String, _>("path")?));
+            }
+            Ok((unique_terms, paths))
+        })?;
+
+        Ok(IndexStats {
+            document_count: paths.len(),
+            unique_terms,
+            corpus_size_bytes: corpus_size_bytes(paths.iter().map(PathBuf::as_path)),
+        })
+    }
+
+    fn document_info(&self, path: &Path) -> Result<Option<DocumentInfo>, SearchError> {
+        let query = "
+            SELECT Documents.term_count, COUNT(TermFreq.term) AS unique_terms
+            FROM Documents
+            LEFT JOIN TermFreq ON TermFreq.doc_id = Documents.id
+            WHERE Documents.path = :path
+            GROUP BY Documents.id
+        ";
+        self.read(|connection| {
+            let mut stmt = connection.prepare(query)?;
+            stmt.bind((":path", sqlite_path(path).as_str()))?;
+            match stmt.next()? {
+                sqlite::State::Row => Ok(Some(DocumentInfo {
+                    path: path.to_path_buf(),
+                    title: None,
+                    mime_type: None,
+                    term_count: stmt.read::<i64, _>("term_count")? as usize,
+                    unique_terms: stmt.read::<i64, _>("unique_terms")? as usize,
+                })),
+                sqlite::State::Done => Ok(None),
+            }
+        })
+    }
+
+    // The whole batch is one transaction, so it must not be called between `begin` and `commit`
+    fn add_document_batch(&mut self, docs: impl Iterator<Item = (PathBuf, Vec<char>)>) -> Result<usize, SearchError> {
+        self.begin()?;
+        match add_each_document(self, docs) {
+            Ok(added) => {
+                self.commit()?;
+                Ok(added)
+            }
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn add_document(&mut self, path: PathBuf, content: &[char], _mime_type: Option<&str>) -> Result<(), SearchError> {
+        let terms = Lexer::new(content).collect::<Vec<_>>();
+        let term_count = terms.len();
+
+        let mut tf = HashMap::<String, usize>::new();
+        for term in terms {
+            *tf.entry(term).or_insert(0) += 1;
+        }
+
+        self.add_term_freqs(&sqlite_path(&path), term_count, tf.iter().map(|(term, freq)| (term.as_str(), *freq)))
+    }
+
+    fn remove_document(&mut self, path: &Path) -> Result<bool, SearchError> {
+        let doc_id = {
+            let query = "SELECT id FROM Documents WHERE path = :path";
+            let mut stmt = self.connection.prepare(query)?;
+            stmt.bind((":path", sqlite_path(path).as_str()))?;
+            match stmt.next()? {
+                sqlite::State::Row => stmt.read::<i64, _>("id")?,
+                sqlite::State::Done => return Ok(false),
+            }
+        };
+
+        let queries = [
+            "UPDATE DocFreq SET freq = freq - 1 WHERE term IN (SELECT term FROM TermFreq WHERE doc_id = :doc_id)",
+            "DELETE FROM TermFreq WHERE doc_id = :doc_id",
+            "DELETE FROM Documents WHERE id = :doc_id",
+        ];
+        for query in queries {
+            let mut stmt = self.connection.prepare(query)?;
+            stmt.bind((":doc_id", doc_id))?;
+            stmt.next()?;
+        }
+        // The terms that only this document had
+        self.execute("DELETE FROM DocFreq WHERE freq <= 0")?;
+
+        Ok(true)
+    }
+
+    fn suggest_terms(&self, prefix: &str, limit: usize) -> Result<Vec<String>, SearchError> {
+        // Terms are stored the way the Lexer normalizes them; '%' and '_' in the prefix are no wildcards
+        let prefix = prefix.nfc().map(|x| x.to_ascii_uppercase()).collect::<String>();
+        let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let query = "SELECT term FROM DocFreq WHERE term LIKE :pattern ESCAPE '\\' ORDER BY freq DESC, term LIMIT :limit";
+        self.read(|connection| {
+            let mut stmt = connection.prepare(query)?;
+            stmt.bind_iter::<_, (_, sqlite::Value)>([
+                (":pattern", pattern.into()),
+                (":limit", (limit.min(i64::MAX as usize) as i64).into()),
+            ])?;
+            let mut terms = Vec::new();
+            while let sqlite::State::Row = stmt.next()? {
+                terms.push(stmt.read::<String, _>("term")?);
+            }
+            Ok(terms)
+        })
+    }
+}
+
+// Tokens around the match in the snippets of an FTS5 index
+const FTS5_SNIPPET_TOKENS: usize = 20;
+
+/// Index kept in an SQLite FTS5 table, which tokenizes, stems and ranks (with BM25) by itself.
+/// Its queries are FTS5 expressions built from the words of the query, so it knows nothing of the
+/// tokenizer settings of the other models.
+pub struct SqliteFts5Model {
+    // Serialized by SQLite itself, like the connection of SqliteModel
+    connection: sqlite::ConnectionWithFullMutex,
+}
+
+// A word of the query as an FTS5 string, so that characters of the FTS5 syntax in it are taken literally
+fn fts5_string(word: &str) -> String {
+    format!("\"{}\"", word.replace('"', "\"\""))
+}
+
+// The documents containing any word of the query, like the other models rank them
+fn fts5_any_word(query: &[char]) -> Option<String> {
+    let query = query.iter().collect::<String>();
+    let words = query.split_whitespace().map(fts5_string).collect::<Vec<_>>();
+    (!words.is_empty()).then(|| words.join(" OR "))
+}
+
+// FTS5 only knows NOT between two operands, `a NOT b`; a query that negates anything else has no expression
+fn fts5_boolean(query: &Query) -> Option<String> {
+    Some(match query {
+        Query::Term(term) => fts5_string(term),
+        Query::And(lhs, rhs) => match (lhs.as_ref(), rhs.as_ref()) {
+            (lhs, Query::Not(rhs)) | (Query::Not(rhs), lhs) => format!("({} NOT {})", fts5_boolean(lhs)?, fts5_boolean(rhs)?),
+            (lhs, rhs) => format!("({} AND {})", fts5_boolean(lhs)?, fts5_boolean(rhs)?),
+        },
+        Query::Or(lhs, rhs) => format!("({} OR {})", fts5_boolean(lhs)?, fts5_boolean(rhs)?),
+        Query::Not(_) => return None,
+    })
+}
+
+impl SqliteFts5Model {
+    fn execute(&self, statement: &str) -> Result<(), SearchError> {
+        Ok(self.connection.execute(statement)?)
+    }
+
+    pub fn begin(&self) -> Result<(), SearchError> {
+        self.execute("BEGIN IMMEDIATE;")
+    }
+
+    pub fn commit(&self) -> Result<(), SearchError> {
+        self.execute("COMMIT;")
+    }
+
+    pub fn rollback(&self) -> Result<(), SearchError> {
+        self.execute("ROLLBACK;")
+    }
+
+    pub fn open(path: &Path) -> Result<Self, SearchError> {
+        let mut connection = sqlite::Connection::open_with_full_mutex(path)?;
+        connection.set_busy_timeout(SQLITE_BUSY_TIMEOUT_MS)?;
+        let this = Self { connection };
+
+        this.execute("
+            CREATE VIRTUAL TABLE IF NOT EXISTS docs USING fts5(path UNINDEXED, content, title, tokenize='porter ascii');
+        ")?;
+
+        // The terms of the index: one row per term, and one per occurrence of a term in a document
+        this.execute("
+            CREATE VIRTUAL TABLE IF NOT EXISTS docs_terms USING fts5vocab(docs, 'row');
+            CREATE VIRTUAL TABLE IF NOT EXISTS docs_instances USING fts5vocab(docs, 'instance');
+        ")?;
+
+        Ok(this)
+    }
+
+    // The documents matching the FTS5 expression, best first. The BM25 rank of FTS5 is lower
+    // for better matches, so it is negated into a score.
+    fn search_expression(&self, expression: &str) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let query = "SELECT path, rank FROM docs WHERE docs MATCH :expression ORDER BY rank";
+        let mut stmt = self.connection.prepare(query)?;
+        stmt.bind((":expression", expression))?;
+        let mut results = Vec::new();
+        while let sqlite::State::Row = stmt.next()? {
+            let path = stmt.read::<String, _>("path")?;
+            let rank = stmt.read::<f64, _>("rank")?;
+            results.push((PathBuf::from(path), -rank as f32));
+        }
+        Ok(results)
+    }
+}
+
+impl Model for SqliteFts5Model {
+    fn search_query(&self, query: &[char], offset: usize, limit: usize) -> Result<SearchPage, SearchError> {
+        let results = match fts5_any_word(query) {
+            Some(expression) => self.search_expression(&expression)?,
+            None => Vec::new(),
+        };
+        Ok(SearchPage::paginate(results, offset, limit))
+    }
+
+    fn document_count(&self) -> Result<usize, SearchError> {
+        let mut stmt = self.connection.prepare("SELECT COUNT(*) FROM docs")?;
+        stmt.next()?;
+        Ok(stmt.read::<i64, _>(0)? as usize)
+    }
+
+    fn stats(&self) -> Result<IndexStats, SearchError> {
+        let unique_terms = {
+            let mut stmt = self.connection.prepare("SELECT COUNT(*) FROM docs_terms")?;
+            stmt.next()?;
+            stmt.read::<i64, _>(0)? as usize
+        };
+
+        let mut stmt = self.connection.prepare("SELECT path FROM docs")?;
+        let mut paths = Vec::new();
+        while let sqlite::State::Row = stmt.next()? {
+            paths.push(PathBuf::from(stmt.read::<String, _>("path")?));
+        }
+
+        Ok(IndexStats {
+            document_count: paths.len(),
+            unique_terms,
+            corpus_size_bytes: corpus_size_bytes(paths.iter().map(PathBuf::as_path)),
+        })
+    }
+
+    fn document_info(&self, path: &Path) -> Result<Option<DocumentInfo>, SearchError> {
+        let query = "
+            SELECT docs.title, COUNT(docs_instances.term) AS term_count, COUNT(DISTINCT docs_instances.term) AS unique_terms
+            FROM docs
+            LEFT JOIN docs_instances ON docs_instances.doc = docs.rowid AND docs_instances.col = 'content'
+            WHERE docs.path = :path
+            GROUP BY docs.rowid
+        ";
+        let mut stmt = self.connection.prepare(query)?;
+        stmt.bind((":path", sqlite_path(path).as_str()))?;
+        match stmt.next()? {
+            sqlite::State::Row => Ok(Some(DocumentInfo {
+                path: path.to_path_buf(),
+                title: stmt.read::<Option<String>, _>("title")?,
+                mime_type: None,
+                term_count: stmt.read::<i64, _>("term_count")? as usize,
+                unique_terms: stmt.read::<i64, _>("unique_terms")? as usize,
+            })),
+            sqlite::State::Done => Ok(None),
+        }
+    }
+
+    // The whole batch is one transaction, so it must not be called between `begin` and `commit`
+    fn add_document_batch(&mut self, docs: impl Iterator<Item = (PathBuf, Vec<char>)>) -> Result<usize, SearchError> {
+        self.begin()?;
+        match add_each_document(self, docs) {
+            Ok(added) => {
+                self.commit()?;
+                Ok(added)
+            }
+            Err(err) => {
+                self.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    // A document added again replaces the one of the same path, FTS5 tables have no unique columns
+    fn add_document(&mut self, path: PathBuf, content: &[char], _mime_type: Option<&str>) -> Result<(), SearchError> {
+        self.remove_document(&path)?;
+        let query = "INSERT INTO docs (path, content, title) VALUES (:path, :content, NULL)";
+        let mut stmt = self.connection.prepare(query)?;
+        stmt.bind_iter::<_, (_, sqlite::Value)>([
+            (":path", sqlite_path(&path).into()),
+            (":content", content.iter().collect::<String>().into()),
+        ])?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    fn remove_document(&mut self, path: &Path) -> Result<bool, SearchError> {
+        let mut stmt = self.connection.prepare("DELETE FROM docs WHERE path = :path")?;
+        stmt.bind((":path", sqlite_path(path).as_str()))?;
+        stmt.next()?;
+        Ok(self.connection.change_count() > 0)
+    }
+
+    fn set_document_title(&mut self, path: &Path, title: String) -> Result<(), SearchError> {
+        let mut stmt = self.connection.prepare("UPDATE docs SET title = :title WHERE path = :path")?;
+        stmt.bind_iter::<_, (_, sqlite::Value)>([
+            (":title", title.into()),
+            (":path", sqlite_path(path).into()),
+        ])?;
+        stmt.next()?;
+        Ok(())
+    }
+
+    fn document_title(&self, path: &Path) -> Option<String> {
+        let title = || -> Result<Option<String>, SearchError> {
+            let mut stmt = self.connection.prepare("SELECT title FROM docs WHERE path = :path")?;
+            stmt.bind((":path", sqlite_path(path).as_str()))?;
+            match stmt.next()? {
+                sqlite::State::Row => Ok(stmt.read::<Option<String>, _>("title")?),
+                sqlite::State::Done => Ok(None),
+            }
+        };
+        title().map_err(|err| tracing::error!(path = %path.display(), error = %err, "could not read title")).ok().flatten()
+    }
+
+    // Cut by FTS5 from the stored content, without marks: SQLite doesn't escape the text around them,
+    // so the caller highlights the snippet like any other
+    fn match_snippet(&self, path: &Path, query: &[char]) -> Option<String> {
+        let expression = fts5_any_word(query)?;
+        let snippet = || -> Result<Option<String>, SearchError> {
+            let query = format!("SELECT snippet(docs, 1, '', '', '…', {FTS5_SNIPPET_TOKENS}) AS snippet FROM docs WHERE docs MATCH :expression AND path = :path");
+            let mut stmt = self.connection.prepare(query)?;
+            stmt.bind_iter::<_, (_, sqlite::Value)>([
+                (":expression", expression.into()),
+                (":path", sqlite_path(path).into()),
+            ])?;
+            match stmt.next()? {
+                sqlite::State::Row => Ok(Some(stmt.read::<String, _>("snippet")?)),
+                sqlite::State::Done => Ok(None),
+            }
+        };
+        snippet().map_err(|err| tracing::error!(path = %path.display(), error = %err, "could not cut snippet")).ok().flatten()
+    }
+
+    fn search_phrase_query(&self, phrase: &[char]) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let phrase = phrase.iter().collect::<String>();
+        if phrase.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        self.search_expression(&fts5_string(&phrase))
+    }
+
+    fn search_boolean_query(&self, query: &Query) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let expression = fts5_boolean(query).ok_or(SearchError::Unsupported("NOT without a term to exclude from"))?;
+        self.search_expression(&expression)
+    }
+
+    fn suggest_terms(&self, prefix: &str, limit: usize) -> Result<Vec<String>, SearchError> {
+        // The ascii tokenizer only folds ASCII letters to lowercase; '%' and '_' in the prefix are no wildcards
+        let prefix = prefix.to_ascii_lowercase();
+        let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        let query = "SELECT term FROM docs_terms WHERE term LIKE :pattern ESCAPE '\\' ORDER BY doc DESC, term LIMIT :limit";
+        let mut stmt = self.connection.prepare(query)?;
+        stmt.bind_iter::<_, (_, sqlite::Value)>([
+            (":pattern", pattern.into()),
+            (":limit", (limit.min(i64::MAX as usize) as i64).into()),
+        ])?;
+        let mut terms = Vec::new();
+        while let sqlite::State::Row = stmt.next()? {
+            terms.push(stmt.read::<String, _>("term")?);
+        }
+        Ok(terms)
+    }
+
+    // FTS5 only ranks with BM25, with the usual k1 and b
+    fn default_ranking(&self) -> RankingAlgorithm {
+        RankingAlgorithm::bm25()
+    }
+
+    fn search_query_ranked(&self, query: &[char], ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        match ranking {
+            RankingAlgorithm::TfIdf => Err(SearchError::Unsupported("TF-IDF ranking")),
+            RankingAlgorithm::Bm25 { .. } => self.search_query(query, 0, usize::MAX).map(|page| page.results),
+        }
+    }
+}
+
+/// Combine two indexes of disjoint sets of documents, e.g. built on different machines.
+/// A document in both indexes is reported and kept as it is in `a`. The settings of `a` are kept as well,
+/// so `b` should be built with the same ones, otherwise its documents are tokenized differently from the queries.
+pub fn merge_models(mut a: InMemoryModel, b: InMemoryModel) -> InMemoryModel {
+    if a.config != b.config {
+        tracing::warn!("the indexes were built with different settings, the merged index uses those of the first one");
+    }
+
+    let InMemoryModel { tfpd, mut mime_types, mut file_mtimes, .. } = b;
+    for (path, entry) in tfpd {
+        if a.tfpd.contains_key(&path) {
+            tracing::warn!(path = %path.display(), "document is in both indexes, keeping it from the first one");
+            continue;
+        }
+        if let Some(mime_type) = mime_types.remove(&path) {
+            a.mime_types.insert(path.clone(), mime_type);
+        }
+        if let Some(mtime) = file_mtimes.remove(&path) {
+            a.file_mtimes.insert(path.clone(), mtime);
+        }
+        a.insert_entry(path, entry);
+    }
+    a
+}
+
+/// Sorted, so that the terms sharing a prefix are next to each other
+pub type DocFreq = BTreeMap<String, usize>;
+/// For every term of a document: its raw count and the sorted token positions where it occurs
+pub type TermFreq = HashMap<String, (usize, Vec<u32>)>;
+/// For every term of a document: the character offsets of its first occurrences in the content
+pub type TermPositions = HashMap<String, Vec<u32>>;
+pub type TermFreqPerDoc = HashMap<PathBuf, DocumentEntry>;
+/// For every term: the TF-IDF score of each document it occurs in, by document ID, sorted by ID.
+/// IDs are given to the documents in path order when the model is finalized.
+pub type InvertedIndex = HashMap<String, Vec<(u32, f32)>>;
+/// For every term: the other terms of its synonym classes
+pub type SynonymMap = HashMap<String, Vec<String>>;
+
+// Matches of a synonym of a query term count this much of a match of the term itself
+const SYNONYM_WEIGHT: f32 = 0.5;
+
+// Only the offsets of the first occurrences of a term are kept, snippets show the first one anyway
+const MAX_TERM_POSITIONS: usize = 100;
+
+pub const DEFAULT_FRESHNESS_HALF_LIFE_DAYS: f32 = 365.0;
+
+/// How much relevance counts against novelty in mmr_rerank when the index doesn't say
+pub const DEFAULT_MMR_LAMBDA: f32 = 0.7;
+
+// Weights of the query, of the top results and of the bottom results in expand_query_rocchio,
+// the values of the SMART system
+const ROCCHIO_ALPHA: f32 = 1.0;
+const ROCCHIO_BETA: f32 = 0.75;
+const ROCCHIO_GAMMA: f32 = 0.15;
+
+// How fast the proximity score of two occurrences falls with the number of characters between their starts,
+// about 0.5 a word
+const PROXIMITY_DECAY: f32 = 0.1;
+
+// Pairs of occurrences so far apart that they would add less than this to a proximity score are skipped
+const PROXIMITY_EPSILON: f32 = 1e-4;
+
+/// Sum of exp(-decay * gap) over every pair of an occurrence of `term_a` and one of `term_b`, where the gap is
+/// the distance between their character positions: the higher, the more often the terms are close together.
+/// A term is not close to itself, so the score of a term with itself is 0. The positions must be sorted, as
+/// they are in TermPositions, which the model takes them from.
+pub fn proximity_score(term_a: &str, term_b: &str, positions_a: &[u32], positions_b: &[u32], decay: f32) -> f32 {
+    if term_a == term_b {
+        return 0f32;
+    }
+    let max_gap = if decay > 0f32 { (-PROXIMITY_EPSILON.ln() / decay).ceil() as u32 } else { u32::MAX };
+    positions_a.iter().map(|&a| {
+        let start = positions_b.partition_point(|&b| b < a.saturating_sub(max_gap));
+        positions_b[start..].iter()
+            .take_while(|&&b| b <= a.saturating_add(max_gap))
+            .map(|&b| (-decay * a.abs_diff(b) as f32).exp())
+            .sum::<f32>()
+    }).sum()
+}
+
+/// How much of a file its content hash covers, so that checking a big file costs no more than a small one
+pub const CONTENT_HASH_BYTES: u64 = 64 * 1024;
+
+/// SHA-256 of the first CONTENT_HASH_BYTES of the file
+pub fn content_hash(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?.take(CONTENT_HASH_BYTES), &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+// Seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+/// 2^(-age / half-life): 1 for a file modified now, 0.5 for one as old as the half-life, 0.25 for twice as old.
+/// Files modified after `now` count as new.
+pub fn freshness_factor(last_modified: u64, now: u64, half_life_days: f32) -> f32 {
+    let age_seconds = now.saturating_sub(last_modified) as f32;
+    (-age_seconds / (half_life_days * 86_400.0)).exp2()
+}
+
+/// Everything an InMemoryModel keeps about one document
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DocumentEntry {
+    /// Number of terms of the document, i.e. the sum of the counts in `tf`
+    pub term_count: usize,
+    pub tf: TermFreq,
+    /// e.g. the `<title>` or first `<h1>` of HTML pages
+    #[serde(default)]
+    pub title: Option<String>,
+    /// When the document was added, in seconds since the Unix epoch
+    #[serde(default)]
+    pub indexed_at: u64,
+    /// When its file was last modified, in seconds since the Unix epoch; None for documents that are not files
+    #[serde(default)]
+    pub last_modified: Option<u64>,
+    #[serde(default)]
+    pub file_size_bytes: u64,
+    /// Count of the most frequent term, needed by TfNormalization::AugmentedFreq
+    #[serde(default)]
+    pub max_term_freq: usize,
+    /// Where snippets of the document are taken from, and the gaps between query terms are measured for proximity
+    #[serde(default)]
+    pub term_positions: TermPositions,
+    #[serde(default)]
+    pub fields: DocumentFields,
+    /// See content_hash; only kept for files indexed with hash checks, see InMemoryModel::set_hash_check
+    #[serde(default)]
+    pub content_hash: Option<[u8; 32]>,
+}
+
+/// The terms of the title and of the metadata of a document (e.g. the description of an HTML page),
+/// which weigh differently from the terms of its body, see FieldWeights. The body is DocumentEntry::tf.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DocumentFields {
+    pub title: TermFreq,
+    pub title_term_count: usize,
+    pub metadata: TermFreq,
+    pub metadata_term_count: usize,
+}
+
+/// How much the TF of a term in each field of a document counts in TF-IDF ranking.
+/// BM25 ranks by the body only.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FieldWeights {
+    pub title: f32,
+    pub body: f32,
+    pub metadata: f32,
+}
+
+impl Default for FieldWeights {
+    fn default() -> Self {
+        Self { title: 3.0, body: 1.0, metadata: 0.5 }
+    }
+}
+
+impl FieldWeights {
+    /// e.g. "title=3,body=1"; the fields that are not listed keep their default weight
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut weights = Self::default();
+        for pair in value.split(',') {
+            let (field, weight) = pair.split_once('=')?;
+            let weight = weight.trim().parse::<f32>().ok().filter(|weight| weight.is_finite() && *weight >= 0.0)?;
+            match field.trim() {
+                "title" => weights.title = weight,
+                "body" => weights.body = weight,
+                "metadata" => weights.metadata = weight,
+                _ => return None,
+            }
+        }
+        Some(weights)
+    }
+}
+
+/// A document tokenized by InMemoryModel::analyze, to be added with merge_document
+pub struct AnalyzedDocument {
+    pub tf: TermFreq,
+    pub term_positions: TermPositions,
+    pub term_count: usize,
+    pub fields: DocumentFields,
+}
+
+fn max_term_freq(tf: &TermFreq) -> usize {
+    tf.values().map(|(freq, _)| *freq).max().unwrap_or(0)
+}
+
+impl DocumentEntry {
+    /// Terms of the body, the title and the metadata, each one once
+    pub fn terms(&self) -> impl Iterator<Item = &String> {
+        let Self { tf, fields, .. } = self;
+        tf.keys()
+            .chain(fields.title.keys().filter(|term| !tf.contains_key(*term)))
+            .chain(fields.metadata.keys().filter(|term| !tf.contains_key(*term) && !fields.title.contains_key(*term)))
+    }
+
+    pub fn contains_term(&self, term: &str) -> bool {
+        self.tf.contains_key(term) || self.fields.title.contains_key(term) || self.fields.metadata.contains_key(term)
+    }
+
+    pub fn to_summary(&self, path: &Path) -> DocumentSummary {
+        DocumentSummary {
+            path: path.to_path_buf(),
+            term_count: self.term_count,
+            title: self.title.clone(),
+            file_size_bytes: self.file_size_bytes,
+        }
+    }
+}
+
+/// What one term of a query adds to the score of a document
+#[derive(Debug, Clone, Serialize)]
+pub struct TermScore {
+    pub term: String,
+    /// 1 for a term of the query, less for a synonym it brought in
+    pub weight: f32,
+    /// Occurrences of the term in the document
+    pub raw_count: usize,
+    /// Documents of the corpus containing the term
+    pub document_frequency: usize,
+    pub tf: f32,
+    pub idf: f32,
+    /// weight * tf * idf
+    pub contribution: f32,
+}
+
+/// Why a document has the score it has for a query, as returned by `explain_score`
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreExplanation {
+    pub path: PathBuf,
+    pub ranking: RankingAlgorithm,
+    /// Sum of the contributions of the terms and of the proximity score
+    pub total_score: f32,
+    /// Added to TF-IDF scores for query terms close to each other, see ModelConfig::proximity_weight
+    pub proximity_score: f32,
+    /// Terms in the document
+    pub document_length: usize,
+    /// Documents in the corpus
+    pub document_count: usize,
+    /// Only taken into account by BM25
+    pub average_document_length: f32,
+    /// Highest contribution first
+    pub term_scores: Vec<TermScore>,
+}
+
+/// A query expanded by pseudo-relevance feedback, as returned by `expand_query`
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryExpansion {
+    /// The terms of the query, reweighted
+    pub original_terms: Vec<(String, f32)>,
+    /// The terms of the top results that were added, highest weight first
+    pub expanded_terms: Vec<(String, f32)>,
+}
+
+/// One line of `serux list`
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentSummary {
+    pub path: PathBuf,
+    pub term_count: usize,
+    pub title: Option<String>,
+    pub file_size_bytes: u64,
+}
+
+/// How an InMemoryModel tokenizes and ranks, saved in the index file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ModelConfig {
+    /// Whether terms are reduced to their stems, both at index and query time
+    pub stemming: bool,
+    /// Terms that are dropped both at index and query time
+    pub stop_words: HashSet<String>,
+    /// Where `stop_words` were read from, for reference only: the words are saved with the index
+    #[serde(default)]
+    pub stop_words_file: Option<PathBuf>,
+    /// Query terms also match their synonyms. They are expanded at query time only, so the index stays the same size.
+    #[serde(default)]
+    pub synonyms: SynonymMap,
+    /// Ranking used when the search does not ask for a specific one
+    pub ranking: RankingAlgorithm,
+    /// Terms after the first `max_tokens_per_doc` ones of a document are not indexed
+    pub max_tokens_per_doc: Option<usize>,
+    /// Character n-grams of this length are indexed next to every word, see Lexer::with_ngrams
+    pub ngram_size: Option<usize>,
+    /// Accents are stripped from Latin letters, see Lexer::with_ascii_folding
+    #[serde(default)]
+    pub ascii_folding: bool,
+    /// Diacritics are stripped from Latin letters, see fold_diacritics
+    #[serde(default)]
+    pub diacritic_folding: bool,
+    /// How term counts are turned into TF for TF-IDF ranking
+    #[serde(default)]
+    pub tf_normalization: TfNormalization,
+    /// How document frequencies are turned into IDF for TF-IDF ranking
+    #[serde(default)]
+    pub idf: IdfVariant,
+    /// Words keep their case, so that "Config" and "config" are different terms, see Lexer::case_sensitive
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Hyphenated words are also indexed whole, see Lexer::compound_len
+    #[serde(default)]
+    pub hyphen_compounds: bool,
+    /// URLs are indexed whole instead of word by word, see Lexer::next_url
+    #[serde(default)]
+    pub tokenize_urls: bool,
+    #[serde(default)]
+    pub field_weights: FieldWeights,
+    /// Chinese, Japanese and Korean text is indexed character by character, see Lexer::next_cjk
+    #[serde(default)]
+    pub cjk_ngram: bool,
+    /// TF-IDF scores decay with the age of the files, see freshness_factor. The ages are those at the time the
+    /// model is finalized, and stay so until it is finalized again, as a server does when it reloads the index.
+    /// Getting older meanwhile would scale the scores of all the files by the same factor, so the order of the
+    /// results doesn't go stale, only the scores are higher than they would be now.
+    #[serde(default)]
+    pub freshness_boost: bool,
+    /// Age at which the score of a file is halved, DEFAULT_FRESHNESS_HALF_LIFE_DAYS if not set
+    #[serde(default)]
+    pub freshness_half_life_days: Option<f32>,
+    /// Tokens shorter or longer than this many characters are not indexed, see Lexer::with_length_bounds
+    #[serde(default)]
+    pub min_token_len: Option<usize>,
+    #[serde(default)]
+    pub max_token_len: Option<usize>,
+    /// The server reorders results by Maximal Marginal Relevance unless the search asks otherwise, see mmr_rerank
+    #[serde(default)]
+    pub mmr: bool,
+    /// Weight of relevance against novelty, DEFAULT_MMR_LAMBDA if not set
+    #[serde(default)]
+    pub mmr_lambda: Option<f32>,
+    /// TF-IDF scores go up by this times the proximity_score of every two consecutive query terms; 0 to rank terms
+    /// as if they were independent
+    #[serde(default)]
+    pub proximity_weight: f32,
+    /// TF-IDF searches only score the documents that can still make the page, see InMemoryModel::search_topk.
+    /// total_hits and the facets of a page still count every matching document, without scoring them.
+    #[serde(default)]
+    pub use_wand: bool,
+}
+
+#[derive(Default)]
+pub struct InMemoryModelBuilder {
+    config: ModelConfig,
+}
+
+impl InMemoryModelBuilder {
+    pub fn stemming(mut self, stemming: bool) -> Self {
+        self.config.stemming = stemming;
+        self
+    }
+
+    pub fn stop_words(mut self, stop_words: HashSet<String>) -> Self {
+        self.config.stop_words = stop_words;
+        self
+    }
+
+    pub fn stop_words_file(mut self, stop_words_file: PathBuf) -> Self {
+        self.config.stop_words_file = Some(stop_words_file);
+        self
+    }
+
+    pub fn synonyms(mut self, synonyms: SynonymMap) -> Self {
+        self.config.synonyms = synonyms;
+        self
+    }
+
+    pub fn ranking(mut self, ranking: RankingAlgorithm) -> Self {
+        self.config.ranking = ranking;
+        self
+    }
+
+    pub fn max_tokens_per_doc(mut self, max_tokens_per_doc: usize) -> Self {
+        self.config.max_tokens_per_doc = Some(max_tokens_per_doc);
+        self
+    }
+
+    pub fn ascii_folding(mut self, ascii_folding: bool) -> Self {
+        self.config.ascii_folding = ascii_folding;
+        self
+    }
+
+    pub fn ngram_size(mut self, ngram_size: usize) -> Self {
+        self.config.ngram_size = Some(ngram_size);
+        self
+    }
+
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.config.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn hyphen_compounds(mut self, hyphen_compounds: bool) -> Self {
+        self.config.hyphen_compounds = hyphen_compounds;
+        self
+    }
+
+    pub fn tokenize_urls(mut self, tokenize_urls: bool) -> Self {
+        self.config.tokenize_urls = tokenize_urls;
+        self
+    }
+
+    pub fn min_token_len(mut self, min_token_len: usize) -> Self {
+        self.config.min_token_len = Some(min_token_len);
+        self
+    }
+
+    pub fn max_token_len(mut self, max_token_len: usize) -> Self {
+        self.config.max_token_len = Some(max_token_len);
+        self
+    }
+
+    pub fn freshness_boost(mut self, freshness_boost: bool) -> Self {
+        self.config.freshness_boost = freshness_boost;
+        self
+    }
+
+    pub fn freshness_half_life_days(mut self, half_life_days: f32) -> Self {
+        self.config.freshness_half_life_days = Some(half_life_days);
+        self
+    }
+
+    pub fn mmr(mut self, mmr: bool) -> Self {
+        self.config.mmr = mmr;
+        self
+    }
+
+    pub fn mmr_lambda(mut self, lambda: f32) -> Self {
+        self.config.mmr_lambda = Some(lambda);
+        self
+    }
+
+    pub fn proximity_weight(mut self, proximity_weight: f32) -> Self {
+        self.config.proximity_weight = proximity_weight;
+        self
+    }
+
+    pub fn use_wand(mut self, use_wand: bool) -> Self {
+        self.config.use_wand = use_wand;
+        self
+    }
+
+    pub fn cjk_ngram(mut self, cjk_ngram: bool) -> Self {
+        self.config.cjk_ngram = cjk_ngram;
+        self
+    }
+
+    pub fn diacritic_folding(mut self, diacritic_folding: bool) -> Self {
+        self.config.diacritic_folding = diacritic_folding;
+        self
+    }
+
+    pub fn field_weights(mut self, field_weights: FieldWeights) -> Self {
+        self.config.field_weights = field_weights;
+        self
+    }
+
+    pub fn tf_normalization(mut self, tf_normalization: TfNormalization) -> Self {
+        self.config.tf_normalization = tf_normalization;
+        self
+    }
+
+    pub fn build(mut self) -> InMemoryModel {
+        // Tokens are compared with the stop words after their accents are stripped
+        if self.config.ascii_folding {
+            self.config.stop_words = self.config.stop_words.iter()
+                .map(|word| word.chars().map(|x| fold_to_ascii(x).to_ascii_uppercase()).collect())
+                .collect();
+        }
+        if self.config.diacritic_folding {
+            self.config.stop_words = self.config.stop_words.iter().map(|word| fold_diacritics(word).to_ascii_uppercase()).collect();
+        }
+        // Synonyms are looked up with query terms, so they are stemmed and folded like them.
+        // They ignore case, a case sensitive model looks them up by the uppercase term.
+        let normalize = |word: &String| {
+            let word = word.chars().collect::<Vec<_>>();
+            let mut lexer = Lexer {
+                stem: self.config.stemming,
+                hyphen_compounds: self.config.hyphen_compounds,
+                fold_diacritics: self.config.diacritic_folding,
+                ..Lexer::with_ascii_folding(&word, self.config.ascii_folding)
+            };
+            lexer.next_token()
+        };
+        let mut synonyms = SynonymMap::new();
+        for (term, term_synonyms) in &self.config.synonyms {
+            if let Some(term) = normalize(term) {
+                let entry = synonyms.entry(term.clone()).or_default();
+                entry.extend(term_synonyms.iter().filter_map(normalize).filter(|synonym| *synonym != term));
+                entry.sort();
+                entry.dedup();
+            }
+        }
+        self.config.synonyms = synonyms;
+        InMemoryModel { config: self.config, ..InMemoryModel::default() }
+    }
+}
+
+/// Index kept in memory and saved as JSON. Documents are added with `Model::add_document`,
+/// then `InMemoryModel::finalize` must be called before searching.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::{InMemoryModel, Model, RankingAlgorithm};
+///
+/// let mut model = InMemoryModel::builder().stemming(true).build();
+/// model.add_document("a.txt".into(), &"running foxes".chars().collect::<Vec<_>>(), None)?;
+/// model.add_document("b.txt".into(), &"a sleeping dog".chars().collect::<Vec<_>>(), None)?;
+/// model.finalize();
+///
+/// let results = model.search_query_ranked(&"fox".chars().collect::<Vec<_>>(), RankingAlgorithm::bm25())?;
+/// assert_eq!(results.len(), 1);
+/// # Ok::<(), serux_lib::SearchError>(())
+/// ```
+#[derive(Deserialize, Serialize)]
+pub struct InMemoryModel {
+    // Always SCHEMA_VERSION, older indexes are migrated when they are loaded.
+    // First, so that it comes right after the magic header of binary indexes.
+    schema_version: u32,
+    pub tfpd: TermFreqPerDoc,
+    pub df: DocFreq,
+    /// Average number of terms per document, needed by BM25
+    #[serde(default)]
+    pub avg_doc_len: f32,
+    /// Settings the index was built with, which queries have to use as well
+    #[serde(default)]
+    pub config: ModelConfig,
+    /// Format each document was extracted from, when known
+    #[serde(default)]
+    pub mime_types: HashMap<PathBuf, String>,
+    /// Modification time of every indexed file, used to skip unchanged files when reindexing
+    #[serde(default)]
+    pub file_mtimes: HashMap<PathBuf, SystemTime>,
+    // IDF of every term of df, dropped whenever the documents change. Saved with the index
+    // so that loading it doesn't compute them again.
+    #[serde(default)]
+    idf_cache: Option<HashMap<String, f32>>,
+    // Bloom filter of the terms of df, dropped and saved like idf_cache
+    #[serde(default)]
+    term_filter: Option<TermBloomFilter>,
+    // Derived from tfpd and df by finalize(), so it is not saved with the index
+    #[serde(skip)]
+    inverted_index: InvertedIndex,
+    // Path of every document ID of inverted_index, derived along with it
+    #[serde(skip)]
+    doc_paths: Vec<PathBuf>,
+    // Highest score of every term of inverted_index in any document, the bound search_topk skips documents by
+    #[serde(skip)]
+    max_scores: HashMap<String, f32>,
+    #[serde(skip)]
+    finalized: bool,
+    // Whether files are hashed when they are added and compared by hash when they are reindexed
+    #[serde(skip)]
+    hash_check: bool,
+    // Recent fuzzy expansions, keyed by term and edit distance
+    #[serde(skip, default = "fuzzy_cache")]
+    fuzzy_cache: Mutex<LruCache<(String, usize), Vec<String>>>,
+}
+
+const FUZZY_CACHE_CAPACITY: usize = 1000;
+
+fn fuzzy_cache() -> Mutex<LruCache<(String, usize), Vec<String>>> {
+    Mutex::new(LruCache::new(FUZZY_CACHE_CAPACITY))
+}
+
+// The fuzzy cache is not copied, the copy fills its own
+impl Clone for InMemoryModel {
+    fn clone(&self) -> Self {
+        Self {
+            schema_version: self.schema_version,
+            tfpd: self.tfpd.clone(),
+            df: self.df.clone(),
+            avg_doc_len: self.avg_doc_len,
+            config: self.config.clone(),
+            mime_types: self.mime_types.clone(),
+            file_mtimes: self.file_mtimes.clone(),
+            idf_cache: self.idf_cache.clone(),
+            term_filter: self.term_filter.clone(),
+            inverted_index: self.inverted_index.clone(),
+            doc_paths: self.doc_paths.clone(),
+            max_scores: self.max_scores.clone(),
+            finalized: self.finalized,
+            hash_check: self.hash_check,
+            fuzzy_cache: fuzzy_cache(),
+        }
+    }
+}
+
+impl Default for InMemoryModel {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            tfpd: TermFreqPerDoc::default(),
+            df: DocFreq::default(),
+            avg_doc_len: 0f32,
+            config: ModelConfig::default(),
+            mime_types: HashMap::default(),
+            file_mtimes: HashMap::default(),
+            idf_cache: None,
+            term_filter: None,
+            inverted_index: InvertedIndex::default(),
+            doc_paths: Vec::new(),
+            max_scores: HashMap::default(),
+            finalized: false,
+            hash_check: false,
+            fuzzy_cache: fuzzy_cache(),
+        }
+    }
+}
+
+impl InMemoryModel {
+    pub fn builder() -> InMemoryModelBuilder {
+        InMemoryModelBuilder::default()
+    }
+
+    /// Model of documents given as path and content, with the default settings, ready to be searched
+    pub fn from_documents(docs: impl Iterator<Item = (PathBuf, String)>) -> Result<Self, SearchError> {
+        let mut model = Self::default();
+        model.add_document_batch(docs.map(|(path, content)| (path, content.chars().collect())))?;
+        model.finalize();
+        Ok(model)
+    }
+
+    // Undo the contribution of a document to the term statistics. Returns false if it was not indexed.
+    fn forget_terms(&mut self, file_path: &Path) -> bool {
+        let Some(entry) = self.tfpd.remove(file_path) else {
+            return false;
+        };
+        let n = entry.term_count;
+
+        for t in entry.terms() {
+            if let Some(freq) = self.df.get_mut(t) {
+                *freq -= 1;
+                if *freq == 0 {
+                    self.df.remove(t);
+                }
+            }
+        }
+
+        let total_len = self.avg_doc_len * (self.tfpd.len() + 1) as f32 - n as f32;
+        self.avg_doc_len = if self.tfpd.is_empty() { 0f32 } else { total_len / self.tfpd.len() as f32 };
+        true
+    }
+
+    /// Tokenize the body, the title and the metadata of a document.
+    /// Only reads the model, so that documents can be tokenized in parallel before they are merged.
+    pub fn analyze(&self, content: &[char], title: Option<&str>, metadata: Option<&str>) -> AnalyzedDocument {
+        let (tf, term_positions, term_count) = self.term_freq(content);
+        let field_freq = |text: Option<&str>| {
+            let chars = text.unwrap_or_default().chars().collect::<Vec<_>>();
+            let (tf, _, n) = self.term_freq(&chars);
+            (tf, n)
+        };
+        let (title, title_term_count) = field_freq(title);
+        let (metadata, metadata_term_count) = field_freq(metadata);
+        AnalyzedDocument { tf, term_positions, term_count, fields: DocumentFields { title, title_term_count, metadata, metadata_term_count } }
+    }
+
+    // Term frequencies of the content, where its terms first occur and its number of terms
+    fn term_freq(&self, content: &[char]) -> (TermFreq, TermPositions, usize) {
+        let mut tf = TermFreq::new();
+        let mut term_positions = TermPositions::new();
+        let mut n = 0;
+        let max_tokens = self.config.max_tokens_per_doc.unwrap_or(usize::MAX);
+        for token in self.lexer(content).positions().take(max_tokens) {
+            let offsets = term_positions.entry(token.text.clone()).or_default();
+            if offsets.len() < MAX_TERM_POSITIONS {
+                offsets.push(token.char_start as u32);
+            }
+            let (freq, positions) = tf.entry(token.text).or_default();
+            *freq += 1;
+            positions.push(n as u32);
+            n += 1;
+        }
+        (tf, term_positions, n)
+    }
+
+    /// Add a document tokenized by `analyze` to the index
+    pub fn merge_document(&mut self, file_path: PathBuf, document: AnalyzedDocument, title: Option<String>, mime_type: Option<&str>) {
+        if let Some(mime_type) = mime_type {
+            self.mime_types.insert(file_path.clone(), mime_type.to_string());
+        }
+
+        let indexed_at = unix_now();
+        // The size is informational only, a file that is gone by now is not an error
+        let metadata = fs::metadata(&file_path).ok();
+        let file_size_bytes = metadata.as_ref().map_or(0, |metadata| metadata.len());
+        let last_modified = metadata.and_then(|metadata| metadata.modified().ok())
+            .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs());
+
+        // A file that can't be read anymore is simply indexed again next time
+        let content_hash = if self.hash_check { content_hash(&file_path).ok() } else { None };
+
+        let AnalyzedDocument { tf, term_positions, term_count, fields } = document;
+        let max_term_freq = max_term_freq(&tf);
+        self.insert_entry(file_path, DocumentEntry {
+            term_count, tf, title, indexed_at, last_modified, file_size_bytes, max_term_freq, term_positions, fields, content_hash,
+        });
+    }
+
+    fn insert_entry(&mut self, file_path: PathBuf, entry: DocumentEntry) {
+        // A document that is indexed again replaces its previous version
+        self.forget_terms(&file_path);
+        self.finalized = false;
+        self.idf_cache = None;
+        self.term_filter = None;
+
+        // Every term is listed once by terms(), so each one counts the document exactly once
+        for t in entry.terms() {
+            if let Some(freq) = self.df.get_mut(t) {
+                *freq += 1;
+            } else {
+                self.df.insert(t.into(), 1);
+            }
+        }
+
+        let total_len = self.avg_doc_len * self.tfpd.len() as f32;
+        let n = entry.term_count;
+        self.tfpd.insert(file_path, entry);
+        self.avg_doc_len = (total_len + n as f32) / self.tfpd.len() as f32;
+    }
+
+    /// Score every document for every term it contains. The IDF depends on the whole corpus,
+    /// so this can only happen once all the documents are added.
+    pub fn finalize(&mut self) {
+        self.cache_idf();
+        self.cache_term_filter();
+        let mut inverted_index = InvertedIndex::new();
+        // Visiting the documents in ID order keeps every posting list sorted
+        let mut documents = self.tfpd.iter().collect::<Vec<_>>();
+        documents.sort_by_key(|(path, _)| *path);
+        for (id, (_, entry)) in documents.iter().enumerate() {
+            for term in entry.terms() {
+                let tf = self.tf(term, entry);
+                inverted_index.entry(term.clone()).or_default().push((id as u32, tf));
+            }
+        }
+        for (term, postings) in inverted_index.iter_mut() {
+            let idf = self.idf(term);
+            for (_, score) in postings.iter_mut() {
+                *score *= idf;
+            }
+        }
+        self.doc_paths = documents.into_iter().map(|(path, _)| path.clone()).collect();
+        self.max_scores = inverted_index.iter()
+            .map(|(term, postings)| (term.clone(), postings.iter().map(|(_, score)| *score).fold(0f32, f32::max)))
+            .collect();
+        self.inverted_index = inverted_index;
+        self.finalized = true;
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.finalized
+    }
+
+    // TF of `term` in the document, normalized the way the model is configured to,
+    // with the TF in its title and its metadata added according to the FieldWeights.
+    // With freshness_boost, it decays with the age of the document now, which the scores of the inverted
+    // index keep from the last call of finalize.
+    fn tf(&self, term: &str, entry: &DocumentEntry) -> f32 {
+        let norm = self.config.tf_normalization;
+        let FieldWeights { title, body, metadata } = self.config.field_weights;
+        let DocumentFields { title: title_tf, title_term_count, metadata: metadata_tf, metadata_term_count } = &entry.fields;
+        let field_tf = |tf: &TermFreq, n: usize| if tf.contains_key(term) {
+            compute_tf(term, n, max_term_freq(tf), tf, norm)
+        } else {
+            0f32
+        };
+        let tf = body * compute_tf(term, entry.term_count, entry.max_term_freq, &entry.tf, norm)
+            + title * field_tf(title_tf, *title_term_count)
+            + metadata * field_tf(metadata_tf, *metadata_term_count);
+        match entry.last_modified {
+            Some(last_modified) if self.config.freshness_boost && tf > 0f32 => {
+                let half_life_days = self.config.freshness_half_life_days.unwrap_or(DEFAULT_FRESHNESS_HALF_LIFE_DAYS);
+                tf * freshness_factor(last_modified, unix_now(), half_life_days)
+            }
+            _ => tf,
+        }
+    }
+
+    // Computed only for terms that are not cached, e.g. while documents are being added.
+    // `cargo bench -p serux-lib --bench idf_cache` compares ranking with and without the cache.
+    fn idf(&self, term: &str) -> f32 {
+        if let Some(&idf) = self.idf_cache.as_ref().and_then(|idf_cache| idf_cache.get(term)) {
+            return idf;
+        }
+        compute_idf(term, self.tfpd.len(), &self.df, self.config.idf)
+    }
+
+    /// IDF of every indexed term, computed with the current documents
+    pub fn build_idf_cache(&self) -> HashMap<String, f32> {
+        self.df.keys().map(|term| (term.clone(), compute_idf(term, self.tfpd.len(), &self.df, self.config.idf))).collect()
+    }
+
+    /// Keep the IDF of every term until the documents change. finalize() does it as well.
+    pub fn cache_idf(&mut self) {
+        if self.idf_cache.is_none() {
+            self.idf_cache = Some(self.build_idf_cache());
+        }
+    }
+
+    /// Keep a Bloom filter of the terms until the documents change. finalize() does it as well.
+    pub fn cache_term_filter(&mut self) {
+        if self.term_filter.is_none() {
+            self.term_filter = Some(TermBloomFilter::from_terms(self.df.keys()));
+        }
+    }
+
+    // False if `term` is certainly not indexed. Without a filter every term may be.
+    fn may_contain_term(&self, term: &str) -> bool {
+        self.term_filter.as_ref().is_none_or(|term_filter| term_filter.might_contain(term))
+    }
+
+    /// Hash the first CONTENT_HASH_BYTES of the files added from now on, and don't index a file again if they
+    /// are unchanged even though it was modified, e.g. restored from a backup or touched by a build.
+    /// Costs reading every file whose modification time changed.
+    pub fn set_hash_check(&mut self, hash_check: bool) {
+        self.hash_check = hash_check;
+    }
+
+    /// Rank with another IDF variant than the model was built with
+    pub fn set_idf(&mut self, idf: IdfVariant) {
+        if self.config.idf != idf {
+            self.config.idf = idf;
+            self.idf_cache = None;
+        }
+    }
+
+    /// Documents where `query_terms` occur as consecutive tokens, ranked by the TF-IDF of the terms.
+    /// The terms must already be normalized by the Lexer of this model.
+    pub fn search_phrase(&self, query_terms: &[String]) -> Vec<(PathBuf, f32)> {
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut results: Vec<(PathBuf, f32)> = self.tfpd.iter().filter_map(|(path, entry)| {
+            let positions = query_terms.iter()
+                .map(|term| entry.tf.get(term).map(|(_, positions)| positions))
+                .collect::<Option<Vec<_>>>()?;
+            let phrase_found = positions[0].iter().any(|start| {
+                positions.iter().enumerate().skip(1).all(|(i, term_positions)| {
+                    term_positions.binary_search(&(start + i as u32)).is_ok()
+                })
+            });
+            if !phrase_found {
+                return None;
+            }
+
+            let mut rank = 0f32;
+            for term in query_terms {
+                rank += self.tf(term, entry) * self.idf(term);
+            }
+            Some((path.clone(), rank))
+        }).collect();
+        results.sort_by(|(_, rank1), (_, rank2)| rank2.partial_cmp(rank1).unwrap());
+        results
+    }
+
+    // Documents matching a boolean query. A term matches the documents containing all of its tokens;
+    // a term without tokens (e.g. a stop word) matches every document.
+    fn boolean_matches(&self, query: &Query) -> HashSet<&PathBuf> {
+        match query {
+            Query::Term(term) => {
+                let chars = term.chars().collect::<Vec<_>>();
+                let tokens = self.lexer(&chars).collect::<Vec<_>>();
+                self.tfpd.iter()
+                    .filter(|(_, entry)| tokens.iter().all(|token| entry.contains_term(token)))
+                    .map(|(path, _)| path)
+                    .collect()
+            }
+            Query::And(lhs, rhs) => {
+                let rhs = self.boolean_matches(rhs);
+                self.boolean_matches(lhs).into_iter().filter(|path| rhs.contains(path)).collect()
+            }
+            Query::Or(lhs, rhs) => {
+                let mut matches = self.boolean_matches(lhs);
+                matches.extend(self.boolean_matches(rhs));
+                matches
+            }
+            Query::Not(query) => {
+                let excluded = self.boolean_matches(query);
+                self.tfpd.keys().filter(|path| !excluded.contains(path)).collect()
+            }
+        }
+    }
+
+    /// AND narrows the candidates, OR unions them and NOT excludes documents. The candidates are
+    /// ranked by the TF-IDF of the terms that are not negated, so a document matched only through
+    /// NOT is returned with a rank of zero.
+    pub fn search_boolean(&self, query: &Query) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let terms = query.positive_terms().into_iter().flat_map(|term| {
+            let chars = term.chars().collect::<Vec<_>>();
+            self.lexer(&chars).collect::<Vec<_>>()
+        }).collect::<HashSet<String>>();
+        let mut results: Vec<(PathBuf, f32)> = self.boolean_matches(query).into_iter().map(|path| {
+            let entry = &self.tfpd[path];
+            let mut rank = 0f32;
+            for term in &terms {
+                rank += self.tf(term, entry) * self.idf(term);
+            }
+            (path.clone(), rank)
+        }).collect();
+        results.sort_by(|(_, rank1), (_, rank2)| rank2.partial_cmp(rank1).unwrap());
+        Ok(results)
+    }
+
+    /// Like search_query, but every query token is replaced by the indexed terms within
+    /// `max_distance` edits of it
+    pub fn search_fuzzy(&self, query: &[char], max_distance: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let mut terms = HashSet::<String>::new();
+        for token in self.lexer(query) {
+            let key = (token, max_distance);
+            let mut cache = self.fuzzy_cache.lock().unwrap();
+            if let Some(expansion) = cache.get(&key) {
+                terms.extend(expansion.iter().cloned());
+                continue;
+            }
+            let expansion = fuzzy_expand(&key.0, &self.df, max_distance);
+            terms.extend(expansion.iter().cloned());
+            cache.insert(key, expansion);
+        }
+
+        let mut results: Vec::<(PathBuf, f32)> = self.tfpd.iter().map(|(path, entry)| {
+            let mut rank = 0f32;
+            for term in &terms {
+                rank += self.tf(term, entry) * self.idf(term);
+            }
+            (path.clone(), rank)
+        }).filter(|(_, rank)| *rank > 0f32).collect();
+        results.sort_by(|(_, rank1), (_, rank2)| rank2.partial_cmp(rank1).unwrap());
+        Ok(results)
+    }
+
+    /// Each word ending with '*' is expanded to at most `max_expansions` indexed terms starting with it.
+    /// The prefix itself is neither stemmed nor checked against the stop words, since it is not a whole word.
+    pub fn search_with_wildcards(&self, query: &[char], max_expansions: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let mut terms = HashSet::<String>::new();
+        for word in query.split(|x| x.is_whitespace()).filter(|word| !word.is_empty()) {
+            match word.strip_suffix(&['*']) {
+                Some(word) => {
+                    let mut tokens = Lexer { case_sensitive: self.config.case_sensitive, ..Lexer::new(word) }.collect::<Vec<_>>();
+                    let prefix = tokens.pop().unwrap_or_default();
+                    terms.extend(tokens);
+                    terms.extend(prefix_expand(&prefix, &self.df, max_expansions));
+                }
+                None => terms.extend(self.lexer(word)),
+            }
+        }
+
+        let mut results: Vec::<(PathBuf, f32)> = self.tfpd.iter().map(|(path, entry)| {
+            let mut rank = 0f32;
+            for term in &terms {
+                rank += self.tf(term, entry) * self.idf(term);
+            }
+            (path.clone(), rank)
+        }).filter(|(_, rank)| *rank > 0f32).collect();
+        results.sort_by(|(_, rank1), (_, rank2)| rank2.partial_cmp(rank1).unwrap());
+        Ok(results)
+    }
+
+    // Terms of the query with the weight of their matches: the query's own terms count fully,
+    // the synonyms they bring in count SYNONYM_WEIGHT unless the query has them too
+    fn weighted_query_terms(&self, query: &[char]) -> HashMap<String, f32> {
+        let mut terms = HashMap::new();
+        let mut synonyms = HashMap::new();
+        for token in self.lexer(query) {
+            let key = if self.config.case_sensitive { token.to_ascii_uppercase() } else { token.clone() };
+            for synonym in self.config.synonyms.get(&key).into_iter().flatten() {
+                synonyms.entry(synonym.clone()).or_insert(SYNONYM_WEIGHT);
+            }
+            terms.insert(token, 1f32);
+        }
+        // Synonyms are uppercase, in a case sensitive model they match the indexed terms in any case
+        if self.config.case_sensitive {
+            synonyms = self.any_case_terms(&synonyms);
+        }
+        for (synonym, weight) in synonyms {
+            terms.entry(synonym).or_insert(weight);
+        }
+        terms
+    }
+
+    // The indexed terms that only differ in case from `terms`, with the weight of the term they match
+    fn any_case_terms(&self, terms: &HashMap<String, f32>) -> HashMap<String, f32> {
+        let mut uppercase = HashMap::<String, f32>::new();
+        for (term, weight) in terms {
+            let entry = uppercase.entry(term.to_ascii_uppercase()).or_default();
+            *entry = entry.max(*weight);
+        }
+        self.df.keys()
+            .filter_map(|term| uppercase.get(&term.to_ascii_uppercase()).map(|weight| (term.clone(), *weight)))
+            .collect()
+    }
+
+    // TF-IDF of every term of the document
+    fn tf_idf_vector<'a>(&self, entry: &'a DocumentEntry) -> HashMap<&'a str, f32> {
+        entry.terms().map(|term| (term.as_str(), self.tf(term, entry) * self.idf(term))).collect()
+    }
+
+    /// Cosine of the angle between the TF-IDF vectors of the two documents: 1 if they use their terms
+    /// in the same proportions, 0 if they have no term with a positive IDF in common.
+    /// The CLI and the server only compare documents through find_similar and mmr_rerank.
+    pub fn cosine_similarity(&self, path_a: &Path, path_b: &Path) -> Result<f32, SearchError> {
+        let entry_a = self.tfpd.get(path_a).ok_or_else(|| SearchError::DocumentNotFound(path_a.to_path_buf()))?;
+        let entry_b = self.tfpd.get(path_b).ok_or_else(|| SearchError::DocumentNotFound(path_b.to_path_buf()))?;
+        Ok(cosine(&self.tf_idf_vector(entry_a), &self.tf_idf_vector(entry_b)))
+    }
+
+    /// Rocchio query expansion by pseudo-relevance feedback: the `top_k` first results of `original_query` are taken
+    /// as relevant and as many last ones as not, and the query vector moves towards the centroid of the TF-IDF vectors
+    /// of the former and away from the one of the latter:
+    ///     alpha * query + beta * centroid(top) - gamma * centroid(bottom)
+    /// Returns the terms of the query with a positive weight, then the `expand_terms` other terms of highest weight.
+    pub fn expand_query_rocchio(&self, original_query: &[char], top_k: usize, alpha: f32, beta: f32, gamma: f32, expand_terms: usize) -> Vec<(String, f32)> {
+        let query_terms = self.weighted_query_terms(original_query);
+        let results = self.rank_tf_idf(&query_terms, |_| true);
+        let relevant = &results[..top_k.min(results.len())];
+        let non_relevant = &results[results.len().saturating_sub(top_k).max(relevant.len())..];
+
+        let mut weights = HashMap::<&str, f32>::new();
+        for (term, weight) in &query_terms {
+            *weights.entry(term).or_default() += alpha * weight;
+        }
+        for (documents, factor) in [(relevant, beta), (non_relevant, -gamma)] {
+            for (path, _) in documents {
+                let Some(entry) = self.tfpd.get(path) else {
+                    continue;
+                };
+                for (term, tf_idf) in self.tf_idf_vector(entry) {
+                    *weights.entry(term).or_default() += factor * tf_idf / documents.len() as f32;
+                }
+            }
+        }
+
+        let mut weights = weights.into_iter().filter(|(_, weight)| *weight > 0f32).collect::<Vec<_>>();
+        weights.sort_by(|(term_a, weight_a), (term_b, weight_b)| weight_b.total_cmp(weight_a).then_with(|| term_a.cmp(term_b)));
+        let (mut expanded, new_terms): (Vec<_>, Vec<_>) = weights.into_iter().partition(|(term, _)| query_terms.contains_key(*term));
+        expanded.extend(new_terms.into_iter().take(expand_terms));
+        expanded.into_iter().map(|(term, weight)| (term.to_string(), weight)).collect()
+    }
+
+    /// The `limit` other documents most similar to the one at `path` by cosine_similarity, most similar first.
+    /// Documents with nothing in common with it are left out.
+    pub fn find_similar(&self, path: &Path, limit: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let entry = self.tfpd.get(path).ok_or_else(|| SearchError::DocumentNotFound(path.to_path_buf()))?;
+        let vector = self.tf_idf_vector(entry);
+        let mut results = self.tfpd.iter()
+            .filter(|(other_path, _)| other_path.as_path() != path)
+            .map(|(other_path, other_entry)| (other_path.clone(), cosine(&vector, &self.tf_idf_vector(other_entry))))
+            .filter(|(_, similarity)| *similarity > 0f32)
+            .collect::<Vec<_>>();
+        results.sort_by(|(path_a, similarity_a), (path_b, similarity_b)| {
+            similarity_b.partial_cmp(similarity_a).unwrap().then_with(|| path_a.cmp(path_b))
+        });
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// The score of the document at `path` for `query`, term by term, with the values it is computed from.
+    /// Terms of the query that are not in the document are listed with a contribution of 0.
+    pub fn explain_score(&self, path: &Path, query: &[char], ranking: RankingAlgorithm) -> Option<ScoreExplanation> {
+        let entry = self.tfpd.get(path)?;
+        let DocumentEntry { term_count: n, tf: tf_table, .. } = entry;
+        let document_count = self.tfpd.len();
+
+        let mut term_scores = self.weighted_query_terms(query).into_iter().map(|(term, weight)| {
+            let (tf, idf) = match ranking {
+                RankingAlgorithm::TfIdf => (self.tf(&term, entry), self.idf(&term)),
+                RankingAlgorithm::Bm25 { k1, b } => (
+                    compute_bm25(&term, *n, tf_table, self.avg_doc_len, k1, b),
+                    compute_bm25_idf(&term, document_count, &self.df),
+                ),
+            };
+            TermScore {
+                raw_count: tf_table.get(&term).map_or(0, |(freq, _)| *freq),
+                document_frequency: self.df.get(&term).copied().unwrap_or(0),
+                contribution: weight * tf * idf,
+                term,
+                weight,
+                tf,
+                idf,
+            }
+        }).collect::<Vec<_>>();
+        term_scores.sort_by(|a, b| b.contribution.total_cmp(&a.contribution).then_with(|| a.term.cmp(&b.term)));
+        let proximity_score = match ranking {
+            RankingAlgorithm::TfIdf => self.proximity_bonus(&self.lexer(query).collect::<Vec<_>>(), entry),
+            RankingAlgorithm::Bm25 { .. } => 0f32,
+        };
+
+        Some(ScoreExplanation {
+            path: path.to_path_buf(),
+            ranking,
+            total_score: term_scores.iter().map(|term_score| term_score.contribution).sum::<f32>() + proximity_score,
+            proximity_score,
+            document_length: *n,
+            document_count,
+            average_document_length: self.avg_doc_len,
+            term_scores,
+        })
+    }
+
+    // What the query terms `terms`, in their order in the query, add to the TF-IDF score of the document
+    // for being close to each other
+    fn proximity_bonus(&self, terms: &[String], entry: &DocumentEntry) -> f32 {
+        if self.config.proximity_weight == 0f32 {
+            return 0f32;
+        }
+        let score = terms.windows(2)
+            .map(|pair| match (entry.term_positions.get(&pair[0]), entry.term_positions.get(&pair[1])) {
+                (Some(positions_a), Some(positions_b)) => proximity_score(&pair[0], &pair[1], positions_a, positions_b, PROXIMITY_DECAY),
+                _ => 0f32,
+            })
+            .sum::<f32>();
+        score * self.config.proximity_weight
+    }
+
+    /// The page of `limit` results of the query, ranked the default way, that follows `after`,
+    /// or the first page without a cursor; see SearchPage::after
+    pub fn search_after(&self, query: &[char], after: Option<SearchCursor>, limit: usize) -> Result<SearchPage, SearchError> {
+        let results = self.search_query_ranked(query, self.default_ranking())?;
+        Ok(SearchPage::after(results, after.as_ref(), limit))
+    }
+
+    /// Documents containing terms matching the regular expression `pattern`, at most `limit` different terms,
+    /// ranked by the sum of the TF-IDF of the terms they contain
+    pub fn search_regex(&self, pattern: &str, limit: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let regex = term_regex(pattern, self.config.case_sensitive)?;
+        self.search_regex_query(&regex, limit)
+    }
+
+    /// TF-IDF ranking like search_query, with the contribution of every term multiplied by its boost.
+    /// Documents containing any of the excluded terms, or fewer than min_should_match words of the query, are left out.
+    pub fn search_query_parsed(&self, query: &ParsedQuery) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let (terms, matches) = self.parsed_query_terms(query);
+        if query.terms.len() > 1 && query.min_should_match == query.terms.len() {
+            return Ok(self.rank_tf_idf_all_words(query, &terms, matches));
+        }
+        Ok(self.rank_tf_idf(&terms, matches))
+    }
+
+    // The terms to rank the documents by, see boosted_query_terms, and whether a document matches the query.
+    // A term that is both searched for and excluded is only excluded.
+    fn parsed_query_terms(&self, query: &ParsedQuery) -> (HashMap<String, f32>, impl Fn(&DocumentEntry) -> bool) {
+        let mut terms = self.boosted_query_terms(query);
+        let mut excluded = HashSet::new();
+        for word in &query.must_not {
+            excluded.extend(self.lexer(&word.chars().collect::<Vec<_>>()));
+        }
+        for term in &excluded {
+            if terms.remove(term).is_some() {
+                tracing::warn!(term, "term is both searched for and excluded, leaving out the documents containing it");
+            }
+        }
+
+        // A word of the query is in a document if any of its terms or of their synonyms is. Any document
+        // ranked at all has one of them, so they are only counted when more than one must be there.
+        let min_should_match = query.min_should_match;
+        let words = if min_should_match > 1 {
+            query.terms.iter()
+                .map(|(word, _)| self.weighted_query_terms(&word.chars().collect::<Vec<_>>()).into_keys().filter(|term| !excluded.contains(term)).collect())
+                .collect()
+        } else {
+            Vec::<HashSet<String>>::new()
+        };
+        let matches = move |entry: &DocumentEntry| {
+            !excluded.iter().any(|term| entry.contains_term(term))
+                && (min_should_match <= 1 || words.iter().filter(|terms| terms.iter().any(|term| entry.contains_term(term))).count() >= min_should_match)
+        };
+        (terms, matches)
+    }
+
+    // The weighted terms of every word of the query, see weighted_query_terms, with their weight multiplied
+    // by the boost of the word. A term found in several words keeps its highest weight.
+    fn boosted_query_terms(&self, query: &ParsedQuery) -> HashMap<String, f32> {
+        let mut terms = HashMap::<String, f32>::new();
+        for (word, boost) in &query.terms {
+            for (term, weight) in self.weighted_query_terms(&word.chars().collect::<Vec<_>>()) {
+                let entry = terms.entry(term).or_default();
+                *entry = entry.max(weight * boost);
+            }
+        }
+        terms
+    }
+
+    pub fn search_query_bm25(&self, query: &[char], k1: f32, b: f32) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Ok(self.rank_bm25(&self.weighted_query_terms(query), |_| true, k1, b))
+    }
+
+    // BM25 ranking of the documents that `matches` accepts
+    fn rank_bm25(&self, terms: &HashMap<String, f32>, matches: impl Fn(&DocumentEntry) -> bool, k1: f32, b: f32) -> Vec<(PathBuf, f32)> {
+        // Terms that are not indexed score nothing in any document
+        let terms = terms.iter().filter(|(term, _)| self.may_contain_term(term)).collect::<Vec<_>>();
+        let documents = self.tfpd.iter().filter(|(_, entry)| matches(entry));
+        let mut results: Vec::<(PathBuf, f32)> = documents.map(|(path, DocumentEntry { term_count: n, tf: tf_table, .. })| {
+            let mut rank = 0f32;
+            for &(term, weight) in &terms {
+                rank += weight * compute_bm25(term, *n, tf_table, self.avg_doc_len, k1, b) * compute_bm25_idf(term, self.tfpd.len(), &self.df);
+            }
+            (path.clone(), rank)
+        }).filter(|(_, rank)| *rank > 0f32).collect();
+        results.sort_by(|(_, rank1), (_, rank2)| rank2.partial_cmp(rank1).unwrap());
+        results
+    }
+
+    // TF-IDF ranking from the posting lists of `terms`, of the documents that `matches` accepts
+    fn rank_tf_idf(&self, terms: &HashMap<String, f32>, matches: impl Fn(&DocumentEntry) -> bool) -> Vec<(PathBuf, f32)> {
+        assert!(self.is_finalized(), "InMemoryModel::finalize() must be called before searching");
+
+        // Documents matching any of the terms, with the scores of all the terms they contain summed up.
+        // The terms are added in the order of their names, as in search_topk, so that both sum the same floats.
+        let mut terms = terms.iter().collect::<Vec<_>>();
+        terms.sort_unstable_by_key(|&(term, _)| term);
+        let mut results: Vec<(u32, f32)> = Vec::new();
+        for (term, &weight) in terms {
+            if !self.may_contain_term(term) {
+                continue;
+            }
+            let Some(postings) = self.inverted_index.get(term) else {
+                continue;
+            };
+            if weight == 1f32 {
+                results = merge_postings(&results, postings);
+            } else {
+                let weighted = postings.iter().map(|&(id, rank)| (id, rank * weight)).collect::<Vec<_>>();
+                results = merge_postings(&results, &weighted);
+            }
+        }
+        self.ranked_paths(results, matches)
+    }
+
+    // TF-IDF ranking like rank_tf_idf of the documents containing every word of the query. The posting lists
+    // of the terms of each word are merged, and only then intersected, so that the documents missing a word
+    // are skipped over instead of being scored and looked up.
+    fn rank_tf_idf_all_words(&self, query: &ParsedQuery, terms: &HashMap<String, f32>, matches: impl Fn(&DocumentEntry) -> bool) -> Vec<(PathBuf, f32)> {
+        assert!(self.is_finalized(), "InMemoryModel::finalize() must be called before searching");
+
+        // A term of several words, e.g. a synonym of both, is scored with the first one only
+        let mut scored = HashSet::new();
+        let word_postings = query.terms.iter().map(|(word, _)| {
+            let mut postings = Vec::new();
+            for term in self.weighted_query_terms(&word.chars().collect::<Vec<_>>()).into_keys() {
+                // Excluded terms are not among the terms, and never make a document match
+                let Some(&weight) = terms.get(&term) else {
+                    continue;
+                };
+                let Some(term_postings) = self.inverted_index.get(&term).filter(|_| self.may_contain_term(&term)) else {
+                    continue;
+                };
+                let weight = if scored.insert(term) { weight } else { 0f32 };
+                let weighted = term_postings.iter().map(|&(id, rank)| (id, rank * weight)).collect::<Vec<_>>();
+                postings = merge_postings(&postings, &weighted);
+            }
+            postings
+        }).collect::<Vec<_>>();
+        let lists = word_postings.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        self.ranked_paths(intersect_posting_lists(&lists), matches)
+    }
+
+    /// The `k` documents ranked first by search_query when it leaves out proximity, in the same order, found
+    /// with WAND (Weak AND). Documents are visited in ID order, and those whose terms could not add up to more
+    /// than the k-th best score so far, even with the highest score each term has anywhere, are skipped
+    /// without being scored. The rarer the terms that lift a document into the top `k`, the more are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serux_lib::{InMemoryModel, Model};
+    ///
+    /// let documents = ["rust", "rust rust web", "web", "go web", "rust web"];
+    /// let model = InMemoryModel::from_documents(documents.iter().enumerate().map(|(i, content)| (format!("{i}.txt").into(), content.to_string())))?;
+    /// let query = "rust web".chars().collect::<Vec<_>>();
+    /// assert_eq!(model.search_topk(&query, 2)?, model.search_query(&query, 0, 2)?.results);
+    /// # Ok::<(), serux_lib::SearchError>(())
+    /// ```
+    pub fn search_topk(&self, query: &[char], k: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        assert!(self.is_finalized(), "InMemoryModel::finalize() must be called before searching");
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        // In the order of their names, as in rank_tf_idf
+        let mut terms = self.weighted_query_terms(query).into_iter().collect::<Vec<_>>();
+        terms.sort_unstable_by(|(term1, _), (term2, _)| term1.cmp(term2));
+        let mut cursors = terms.into_iter()
+            .filter(|(term, _)| self.may_contain_term(term))
+            .filter_map(|(term, weight)| {
+                let postings = self.inverted_index.get(&term)?;
+                Some(TermCursor { postings, position: 0, weight, max_score: weight * self.max_scores.get(&term).copied().unwrap_or_default() })
+            })
+            .collect::<Vec<_>>();
+
+        // The k best documents so far, the worst on top
+        let mut top = BinaryHeap::<WorstFirst>::with_capacity(k.min(self.doc_paths.len()));
+        let mut by_document = (0..cursors.len()).collect::<Vec<_>>();
+        let mut scored = 0;
+        loop {
+            by_document.retain(|&i| cursors[i].document().is_some());
+            by_document.sort_by_key(|&i| cursors[i].document());
+            // The first document that the terms up to it could score above the k-th best score, with
+            // the documents of all the terms before it skipped
+            let threshold = top.peek().filter(|_| top.len() == k).map(|worst| worst.score);
+            let mut upper_bound = 0f32;
+            let Some(pivot) = by_document.iter().position(|&i| {
+                upper_bound += cursors[i].max_score;
+                threshold.is_none_or(|threshold| upper_bound > threshold)
+            }) else {
+                break;
+            };
+            let Some(pivot_document) = cursors[by_document[pivot]].document() else {
+                break;
+            };
+
+            if cursors[by_document[0]].document() == Some(pivot_document) {
+                let mut score = 0f32;
+                for cursor in &mut cursors {
+                    if let Some(rank) = cursor.rank_of(pivot_document) {
+                        score += rank;
+                        cursor.position += 1;
+                    }
+                }
+                scored += 1;
+                let candidate = WorstFirst { score, document: pivot_document };
+                if score > 0f32 && (top.len() < k || top.peek().is_some_and(|worst| candidate < *worst)) {
+                    if top.len() == k {
+                        top.pop();
+                    }
+                    top.push(candidate);
+                }
+            } else {
+                for &i in &by_document[..pivot] {
+                    cursors[i].skip_to(pivot_document);
+                }
+            }
+        }
+        tracing::debug!(scored, documents = self.doc_paths.len(), "WAND search");
+
+        let top = top.into_iter().map(|WorstFirst { score, document }| (document, score)).collect();
+        Ok(self.ranked_paths(top, |_| true))
+    }
+
+    // Number of documents search_query ranks for the query, and their facets, for search_topk that doesn't
+    // score them all: the documents in which any of the terms scores. Only the posting lists are walked,
+    // nothing is summed up or sorted.
+    fn count_matches(&self, query: &[char]) -> (usize, HashMap<String, usize>) {
+        let mut matching = vec![false; self.doc_paths.len()];
+        for (term, weight) in self.weighted_query_terms(query) {
+            if !self.may_contain_term(&term) {
+                continue;
+            }
+            let Some(postings) = self.inverted_index.get(&term) else {
+                continue;
+            };
+            for &(id, rank) in postings {
+                if rank * weight > 0f32 {
+                    matching[id as usize] = true;
+                }
+            }
+        }
+        let mut total_hits = 0;
+        let mut facets = HashMap::new();
+        for (path, _) in self.doc_paths.iter().zip(matching).filter(|(_, matching)| *matching) {
+            total_hits += 1;
+            *facets.entry(file_extension(path)).or_default() += 1;
+        }
+        (total_hits, facets)
+    }
+
+    // The documents of a posting list that score and that `matches` accepts, best first and, among equal
+    // scores, by document ID, which is the order of the paths
+    fn ranked_paths(&self, mut postings: Vec<(u32, f32)>, matches: impl Fn(&DocumentEntry) -> bool) -> Vec<(PathBuf, f32)> {
+        postings.sort_unstable_by(|(id1, rank1), (id2, rank2)| rank2.total_cmp(rank1).then(id1.cmp(id2)));
+        postings.into_iter()
+            .map(|(id, rank)| (&self.doc_paths[id as usize], rank))
+            .filter(|(path, rank)| *rank > 0f32 && self.tfpd.get(*path).is_some_and(&matches))
+            .map(|(path, rank)| (path.clone(), rank))
+            .collect()
+    }
+}
+
+impl Model for InMemoryModel {
+    fn search_query(&self, query: &[char], offset: usize, limit: usize) -> Result<SearchPage, SearchError> {
+        if self.config.use_wand && self.config.proximity_weight == 0f32 {
+            let results = self.search_topk(query, offset.saturating_add(limit))?;
+            let (total_hits, facets) = self.count_matches(query);
+            let results = results.into_iter().skip(offset).collect::<Vec<_>>();
+            let has_more = offset.saturating_add(results.len()) < total_hits;
+            return Ok(SearchPage { results, total_hits, has_more, facets });
+        }
+        let mut results = self.rank_tf_idf(&self.weighted_query_terms(query), |_| true);
+        if self.config.proximity_weight != 0f32 {
+            let terms = self.lexer(query).collect::<Vec<_>>();
+            if terms.len() > 1 {
+                for (path, score) in &mut results {
+                    *score += self.tfpd.get(path).map_or(0f32, |entry| self.proximity_bonus(&terms, entry));
+                }
+                results.sort_by(|(_, score1), (_, score2)| score2.total_cmp(score1));
+            }
+        }
+        Ok(SearchPage::paginate(results, offset, limit))
+    }
+
+    fn document_count(&self) -> Result<usize, SearchError> {
+        Ok(self.tfpd.len())
+    }
+
+    fn stats(&self) -> Result<IndexStats, SearchError> {
+        Ok(IndexStats {
+            document_count: self.tfpd.len(),
+            unique_terms: self.df.len(),
+            corpus_size_bytes: corpus_size_bytes(self.tfpd.keys().map(PathBuf::as_path)),
+        })
+    }
+
+    fn document_info(&self, path: &Path) -> Result<Option<DocumentInfo>, SearchError> {
+        Ok(self.tfpd.get(path).map(|entry| DocumentInfo {
+            path: path.to_path_buf(),
+            title: self.document_title(path),
+            mime_type: self.mime_types.get(path).cloned(),
+            term_count: entry.term_count,
+            unique_terms: entry.tf.len(),
+        }))
+    }
+
+    fn add_document(&mut self, file_path: PathBuf, content: &[char], mime_type: Option<&str>) -> Result<(), SearchError> {
+        let document = self.analyze(content, None, None);
+        self.merge_document(file_path, document, None, mime_type);
+        Ok(())
+    }
+
+    fn remove_document(&mut self, file_path: &Path) -> Result<bool, SearchError> {
+        self.mime_types.remove(file_path);
+        self.file_mtimes.remove(file_path);
+        if !self.forget_terms(file_path) {
+            return Ok(false);
+        }
+        self.finalized = false;
+        self.idf_cache = None;
+        self.term_filter = None;
+        Ok(true)
+    }
+
+    fn search_phrase_query(&self, phrase: &[char]) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let query_terms = self.lexer(phrase).collect::<Vec<_>>();
+        Ok(self.search_phrase(&query_terms))
+    }
+
+    fn search_boolean_query(&self, query: &Query) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        self.search_boolean(query)
+    }
+
+    fn search_parsed_query(&self, query: &ParsedQuery, ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        match ranking {
+            RankingAlgorithm::TfIdf => self.search_query_parsed(query),
+            RankingAlgorithm::Bm25 { k1, b } => {
+                let (terms, matches) = self.parsed_query_terms(query);
+                Ok(self.rank_bm25(&terms, matches, k1, b))
+            }
+        }
+    }
+
+    fn search_fuzzy_query(&self, query: &[char], max_distance: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        self.search_fuzzy(query, max_distance)
+    }
+
+    fn search_wildcard_query(&self, query: &[char], max_expansions: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        self.search_with_wildcards(query, max_expansions)
+    }
+
+    // Terms are visited in order, so the same ones are kept whenever there are more than `max_terms`
+    fn search_regex_query(&self, regex: &Regex, max_terms: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let terms = self.df.keys()
+            .filter(|term| regex.is_match(term))
+            .take(max_terms)
+            .map(|term| (term.clone(), 1f32))
+            .collect::<HashMap<_, _>>();
+        Ok(self.rank_tf_idf(&terms, |_| true))
+    }
+
+    fn explain_score(&self, path: &Path, query: &[char], ranking: RankingAlgorithm) -> Result<Option<ScoreExplanation>, SearchError> {
+        Ok(InMemoryModel::explain_score(self, path, query, ranking))
+    }
+
+    fn find_similar(&self, path: &Path, limit: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        InMemoryModel::find_similar(self, path, limit)
+    }
+
+    fn expand_query(&self, query: &[char], top_k: usize, expand_terms: usize) -> Result<QueryExpansion, SearchError> {
+        let query_terms = self.weighted_query_terms(query);
+        let (original_terms, expanded_terms) = self.expand_query_rocchio(query, top_k, ROCCHIO_ALPHA, ROCCHIO_BETA, ROCCHIO_GAMMA, expand_terms)
+            .into_iter()
+            .partition(|(term, _)| query_terms.contains_key(term));
+        Ok(QueryExpansion { original_terms, expanded_terms })
+    }
+
+    fn search_weighted_terms(&self, terms: &[(String, f32)], ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        let terms = terms.iter().cloned().collect::<HashMap<_, _>>();
+        Ok(match ranking {
+            RankingAlgorithm::TfIdf => self.rank_tf_idf(&terms, |_| true),
+            RankingAlgorithm::Bm25 { k1, b } => self.rank_bm25(&terms, |_| true, k1, b),
+        })
+    }
+
+    fn mmr_lambda(&self) -> Option<f32> {
+        self.config.mmr.then(|| self.config.mmr_lambda.unwrap_or(DEFAULT_MMR_LAMBDA))
+    }
+
+    fn mmr_rerank(&self, results: &[(PathBuf, f32)], lambda: f32, k: usize) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        Ok(mmr_rerank(results, self, lambda, k))
+    }
+
+    fn suggest_terms(&self, prefix: &str, limit: usize) -> Result<Vec<String>, SearchError> {
+        let prefix = if self.config.ascii_folding {
+            prefix.chars().map(fold_to_ascii).collect()
+        } else {
+            prefix.to_string()
+        };
+        let prefix = if self.config.diacritic_folding { fold_diacritics(&prefix) } else { prefix };
+        Ok(suggest_terms(&prefix, &self.df, limit, self.config.case_sensitive))
+    }
+
+    fn requires_reindexing(&mut self, path: &Path, last_modified: SystemTime) -> Result<bool, SearchError> {
+        let Some(entry) = self.tfpd.get(path) else {
+            self.file_mtimes.insert(path.to_path_buf(), last_modified);
+            return Ok(true);
+        };
+        if self.file_mtimes.get(path) == Some(&last_modified) {
+            return Ok(false);
+        }
+        self.file_mtimes.insert(path.to_path_buf(), last_modified);
+        // Unless the content is the same as when the file was indexed
+        if self.hash_check {
+            if let Some(hash) = entry.content_hash {
+                let current = content_hash(path).map_err(|err| SearchError::io_at(path, err))?;
+                return Ok(current != hash);
+            }
+        }
+        Ok(true)
+    }
+
+    fn set_document_title(&mut self, path: &Path, title: String) -> Result<(), SearchError> {
+        if let Some(entry) = self.tfpd.get_mut(path) {
+            entry.title = Some(title);
+        }
+        Ok(())
+    }
+
+    fn document_title(&self, path: &Path) -> Option<String> {
+        self.tfpd.get(path)?.title.clone()
+    }
+
+    fn match_positions(&self, path: &Path, query: &[char]) -> Vec<u32> {
+        // Only plain text is indexed as it is in the file, the offsets into the text of other documents mean nothing there
+        let (Some(entry), Some("text/plain")) = (self.tfpd.get(path), self.mime_types.get(path).map(String::as_str)) else {
+            return Vec::new();
+        };
+        let mut positions = self.lexer(query)
+            .filter_map(|term| entry.term_positions.get(&term))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+
+    fn default_ranking(&self) -> RankingAlgorithm {
+        self.config.ranking
+    }
+
+    fn search_query_ranked(&self, query: &[char], ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        match ranking {
+            RankingAlgorithm::TfIdf => self.search_query(query, 0, usize::MAX).map(|page| page.results),
+            RankingAlgorithm::Bm25 { k1, b } => self.search_query_bm25(query, k1, b),
+        }
+    }
+
+    // Every Lexer of the model goes through here so that documents and queries are tokenized identically
+    fn lexer<'a>(&'a self, content: &'a [char]) -> Lexer<'a> {
+        let stop_words = if self.config.stop_words.is_empty() { None } else { Some(&self.config.stop_words) };
+        let min_token_len = self.config.min_token_len.unwrap_or(1);
+        let max_token_len = self.config.max_token_len.unwrap_or(usize::MAX);
+        let lexer = match self.config.ngram_size {
+            Some(n) => Lexer { min_token_len, max_token_len, ..Lexer::with_ngrams(content, n) },
+            None => Lexer::with_length_bounds(content, min_token_len, max_token_len),
+        };
+        Lexer {
+            stem: self.config.stemming,
+            stop_words,
+            ascii_folding: self.config.ascii_folding,
+            fold_diacritics: self.config.diacritic_folding,
+            case_sensitive: self.config.case_sensitive,
+            hyphen_compounds: self.config.hyphen_compounds,
+            tokenize_urls: self.config.tokenize_urls,
+            cjk_ngram: self.config.cjk_ngram,
+            ..lexer
+        }
+    }
+
+    fn is_case_sensitive(&self) -> bool {
+        self.config.case_sensitive
+    }
+
+    fn is_ready(&self) -> bool {
+        self.is_finalized()
+    }
+
+    fn search_query_ignoring_case(&self, query: &[char], ranking: RankingAlgorithm) -> Result<Vec<(PathBuf, f32)>, SearchError> {
+        if !self.config.case_sensitive {
+            return self.search_query_ranked(query, ranking);
+        }
+        let terms = self.any_case_terms(&self.weighted_query_terms(query));
+        Ok(match ranking {
+            RankingAlgorithm::TfIdf => self.rank_tf_idf(&terms, |_| true),
+            RankingAlgorithm::Bm25 { k1, b } => self.rank_bm25(&terms, |_| true, k1, b),
+        })
+    }
+}
+
+/// Maximal Marginal Relevance: the `k` of `results` picked one by one, each time the one maximizing
+/// `lambda * relevance - (1 - lambda) * max_similarity_to_selected`, so that near duplicates of a document
+/// already picked come after documents that are less relevant but different. Relevance is the score scaled
+/// to the top one, similarity the cosine of the TF-IDF vectors; with a lambda of 1 the order is unchanged.
+/// The results keep their scores.
+pub fn mmr_rerank(results: &[(PathBuf, f32)], model: &InMemoryModel, lambda: f32, k: usize) -> Vec<(PathBuf, f32)> {
+    let top_score = results.iter().map(|(_, score)| *score).fold(0f32, f32::max);
+    let relevance = |score: f32| if top_score > 0f32 { score / top_score } else { 0f32 };
+    let vectors = results.iter()
+        .map(|(path, _)| model.tfpd.get(path).map(|entry| model.tf_idf_vector(entry)).unwrap_or_default())
+        .collect::<Vec<_>>();
+
+    // Indexes into `results` of the documents not picked yet, with their greatest similarity to the picked ones
+    let mut remaining = (0..results.len()).map(|i| (i, 0f32)).collect::<Vec<_>>();
+    let mut reranked = Vec::with_capacity(k.min(results.len()));
+    while reranked.len() < k && !remaining.is_empty() {
+        let mmr = |&(i, max_similarity): &(usize, f32)| lambda * relevance(results[i].1) - (1f32 - lambda) * max_similarity;
+        // The first of equal ones, i.e. the more relevant
+        let mut best = 0;
+        for candidate in 1..remaining.len() {
+            if mmr(&remaining[candidate]) > mmr(&remaining[best]) {
+                best = candidate;
+            }
+        }
+        let (picked, _) = remaining.remove(best);
+        for (i, max_similarity) in &mut remaining {
+            *max_similarity = max_similarity.max(cosine(&vectors[*i], &vectors[picked]));
+        }
+        reranked.push(results[picked].clone());
+    }
+    reranked
+}
+
+// Dot product over the terms both vectors have, divided by the product of their L2 norms; 0 if either is all zeros
+fn cosine(a: &HashMap<&str, f32>, b: &HashMap<&str, f32>) -> f32 {
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let dot = shorter.iter().filter_map(|(term, x)| longer.get(term).map(|y| x * y)).sum::<f32>();
+    let norm = |vector: &HashMap<&str, f32>| vector.values().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0f32 {
+        return 0f32;
+    }
+    // Rounding can take identical vectors a little past 1
+    (dot / norms).min(1f32)
+}
+
+/// Term frequency
+///  tf(t,d), is the relative frequency of term t within document d, as normalized by `norm`.
+///  `n` is the number of terms of the document and `max_f` the count of its most frequent one.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::{compute_tf, TfNormalization};
+/// use serux_lib::model::TermFreq;
+///
+/// // "FOX" 3 times in a document of 6 terms
+/// let tf = TermFreq::from([("FOX".to_string(), (3, vec![0, 2, 4]))]);
+/// assert_eq!(compute_tf("FOX", 6, 3, &tf, TfNormalization::Raw), 0.5);
+/// assert_eq!(compute_tf("FOX", 6, 3, &tf, TfNormalization::LogNorm), 1.0 + 3f32.ln());
+/// assert_eq!(compute_tf("DOG", 6, 3, &tf, TfNormalization::Raw), 0.0);
+/// ```
+pub fn compute_tf(t: &str, n: usize, max_f: usize, d: &TermFreq, norm: TfNormalization) -> f32 {
+    // m:  f(t,d) is the raw count of a term in a document
+    let m = d.get(t).map(|(freq, _)| *freq).unwrap_or(0) as f32;
+    if m == 0f32 {
+        return 0f32;
+    }
+    match norm {
+        // n: sum of  the raw count of a term in a document
+        TfNormalization::Raw => m / n as f32,
+        TfNormalization::LogNorm => 1f32 + m.ln(),
+        TfNormalization::AugmentedFreq => compute_tf_augmented(t, max_f, d),
+        TfNormalization::BooleanPresence => 1f32,
+    }
+}
+
+/// Augmented term frequency, 0.5 + 0.5 * f(t,d) / max_f, between 0.5 and 1 for the terms of the document and 0
+/// for the others. Unlike the raw count divided by the length of the document, it doesn't favor long documents
+/// that repeat a term only because they have more room to.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::compute_tf_augmented;
+/// use serux_lib::model::TermFreq;
+///
+/// // "FOX" once in a document where the most frequent term occurs 4 times
+/// let tf = TermFreq::from([("FOX".to_string(), (1, vec![0])), ("DOG".to_string(), (4, vec![1, 2, 3, 4]))]);
+/// assert_eq!(compute_tf_augmented("FOX", 4, &tf), 0.625);
+/// assert_eq!(compute_tf_augmented("DOG", 4, &tf), 1.0);
+/// ```
+pub fn compute_tf_augmented(t: &str, max_freq_in_doc: usize, d: &TermFreq) -> f32 {
+    let m = d.get(t).map(|(freq, _)| *freq).unwrap_or(0) as f32;
+    if m == 0f32 {
+        return 0f32;
+    }
+    0.5 + 0.5 * m / max_freq_in_doc.max(1) as f32
+}
+
+// Where search_topk is in the posting list of a term
+struct TermCursor<'a> {
+    postings: &'a [(u32, f32)],
+    position: usize,
+    weight: f32,
+    // Highest weighted score of the term in any document
+    max_score: f32,
+}
+
+impl TermCursor<'_> {
+    fn document(&self) -> Option<u32> {
+        self.postings.get(self.position).map(|(document, _)| *document)
+    }
+
+    // The weighted score of the document if the cursor is on it
+    fn rank_of(&self, document: u32) -> Option<f32> {
+        match self.postings.get(self.position) {
+            Some(&(id, rank)) if id == document => Some(if self.weight == 1f32 { rank } else { rank * self.weight }),
+            _ => None,
+        }
+    }
+
+    // Move to the first document at or after `document`
+    fn skip_to(&mut self, document: u32) {
+        self.position += self.postings[self.position..].partition_point(|(id, _)| *id < document);
+    }
+}
+
+// A document scored by search_topk, ordered so that a max-heap has the worst one on top: the lowest score,
+// and among equal scores the one with the highest ID, which ranked_paths ranks last as well
+#[derive(PartialEq)]
+struct WorstFirst {
+    score: f32,
+    document: u32,
+}
+
+impl Eq for WorstFirst {}
+
+impl Ord for WorstFirst {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.score.total_cmp(&self.score).then(self.document.cmp(&other.document))
+    }
+}
+
+impl PartialOrd for WorstFirst {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Union of two posting lists sorted by document ID, adding up the scores of the documents that are in both
+fn merge_postings(a: &[(u32, f32)], b: &[(u32, f32)]) -> Vec<(u32, f32)> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => {
+                merged.push(a[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                merged.push(b[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                merged.push((a[i].0, a[i].1 + b[j].1));
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    merged.extend_from_slice(&a[i..]);
+    merged.extend_from_slice(&b[j..]);
+    merged
+}
+
+/// Intersection of posting lists sorted by document ID: the documents that are in all of them, with their
+/// scores added up. The lists behind the furthest document skip ahead to it by binary search until they
+/// all agree, so a rare term bounds the work however common the others are.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::model::intersect_posting_lists;
+///
+/// let rust = [(1, 0.5), (4, 0.25), (7, 1.0)];
+/// let web = [(2, 0.5), (4, 0.5), (7, 0.5), (9, 0.5)];
+/// assert_eq!(intersect_posting_lists(&[&rust, &web]), vec![(4, 0.75), (7, 1.5)]);
+/// ```
+pub fn intersect_posting_lists(lists: &[&[(u32, f32)]]) -> Vec<(u32, f32)> {
+    let mut intersection = Vec::new();
+    if lists.is_empty() {
+        return intersection;
+    }
+    let mut cursors = vec![0; lists.len()];
+    loop {
+        let mut target = 0;
+        for (list, &cursor) in lists.iter().zip(&cursors) {
+            match list.get(cursor) {
+                Some(&(id, _)) => target = target.max(id),
+                None => return intersection,
+            }
+        }
+
+        let mut score = 0f32;
+        let mut all_on_target = true;
+        for (list, cursor) in lists.iter().zip(cursors.iter_mut()) {
+            *cursor += list[*cursor..].partition_point(|&(id, _)| id < target);
+            match list.get(*cursor) {
+                Some(&(id, rank)) if id == target => score += rank,
+                Some(_) => all_on_target = false,
+                None => return intersection,
+            }
+        }
+        if all_on_target {
+            intersection.push((target, score));
+            for cursor in &mut cursors {
+                *cursor += 1;
+            }
+        }
+    }
+}
+
+/// Inverse document frequency
+/// idf(t,D) is a measure of how much information the word provides, as computed by `variant`.
+/// `n` is the number of documents of the corpus.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::{compute_idf, IdfVariant};
+/// use serux_lib::model::DocFreq;
+///
+/// // "FOX" in 2 of 4 documents
+/// let df = DocFreq::from([("FOX".to_string(), 2)]);
+/// assert_eq!(compute_idf("FOX", 4, &df, IdfVariant::Standard), 2f32.ln());
+/// assert_eq!(compute_idf("FOX", 4, &df, IdfVariant::Smooth), (5f32 / 3f32).ln() + 1.0);
+/// ```
+pub fn compute_idf(t: &str, n: usize, df: &DocFreq, variant: IdfVariant) -> f32 {
+    // total number of documents in the corpus
+    let n = n as f32;
+    // number of documents where the term t appears
+    // tip: If the term is not in the corpus, this will lead to a division-by-zero
+    let m = df.get(t).cloned().unwrap_or(1) as f32;
+    match variant {
+        // Narrow down the range of values
+        IdfVariant::Standard => (n / m).ln(),
+        IdfVariant::Smooth => ((n + 1f32) / (m + 1f32)).ln() + 1f32,
+        // A term of every document would have ln(0)
+        IdfVariant::Probabilistic if m >= n => 0f32,
+        IdfVariant::Probabilistic => ((n - m) / m).ln().max(0f32),
+        IdfVariant::UnaryOne => 1f32,
+    }
+}
+
+/// Levenshtein distance
+///  the minimal number of single character insertions, deletions and substitutions turning a into b
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // Only two rows of the DP table are kept: distances from a[..i-1] and a[..i] to every prefix of b
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut curr = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let substitution = prev[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            curr[j] = substitution.min(prev[j] + 1).min(curr[j - 1] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// Terms of the corpus within `max_distance` edits of `term`, including the term itself
+pub fn fuzzy_expand(term: &str, df: &DocFreq, max_distance: usize) -> Vec<String> {
+    let len = term.chars().count();
+    df.keys()
+        // The distance is at least the difference in length, which is cheap to check first
+        .filter(|candidate| candidate.chars().count().abs_diff(len) <= max_distance)
+        .filter(|candidate| edit_distance(term, candidate) <= max_distance)
+        .cloned()
+        .collect()
+}
+
+/// The terms of the corpus starting with `prefix`, in lexicographic order. They are next to each other
+/// in the sorted DocFreq, so the scan starts at the first one and stops after the last one.
+pub fn terms_with_prefix<'a>(df: &'a DocFreq, prefix: &str) -> impl Iterator<Item = &'a str> {
+    let prefix = prefix.to_string();
+    df.range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+        .map(|(term, _)| term.as_str())
+        .take_while(move |term| term.starts_with(&prefix))
+}
+
+/// Up to `limit` terms of the corpus starting with `prefix`, in lexicographic order.
+/// An empty prefix matches every term.
+pub fn prefix_expand(prefix: &str, df: &DocFreq, limit: usize) -> Vec<String> {
+    terms_with_prefix(df, prefix).take(limit).map(str::to_string).collect()
+}
+
+/// Up to `limit` terms of the corpus starting with `prefix`, whatever its case, the ones in the most
+/// documents first. An empty prefix gives the `limit` most common terms.
+/// The terms of a `case_sensitive` corpus keep their case, so all of them are compared with the prefix.
+pub fn suggest_terms(prefix: &str, df: &DocFreq, limit: usize, case_sensitive: bool) -> Vec<String> {
+    let prefix = prefix.nfc().map(|x| x.to_ascii_uppercase()).collect::<String>();
+    let mut terms = if case_sensitive {
+        df.iter().filter(|(term, _)| term.to_ascii_uppercase().starts_with(&prefix)).map(|(term, df)| (term.as_str(), *df)).collect::<Vec<_>>()
+    } else {
+        terms_with_prefix(df, &prefix).map(|term| (term, df[term])).collect::<Vec<_>>()
+    };
+    terms.sort_by(|(term_a, df_a), (term_b, df_b)| df_b.cmp(df_a).then_with(|| term_a.cmp(term_b)));
+    terms.into_iter().take(limit).map(|(term, _)| term.to_string()).collect()
+}
+
+// Regular expressions come from queries, so they are kept small
+const MAX_REGEX_LEN: usize = 256;
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// How many different terms a regular expression search looks for unless it is told otherwise
+pub const DEFAULT_MAX_REGEX_TERMS: usize = 200;
+
+/// `pattern` compiled to match indexed terms, which are uppercase unless the index is `case_sensitive`,
+/// so the pattern ignores case then. Like any regular expression it matches anywhere in a term unless anchored.
+pub fn term_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, SearchError> {
+    if pattern.len() > MAX_REGEX_LEN {
+        return Err(SearchError::InvalidQuery(format!("regular expressions are limited to {MAX_REGEX_LEN} bytes")));
+    }
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|err| SearchError::InvalidQuery(err.to_string()))
+}
+
+/// BM25 term frequency
+///  the raw count saturates through k1 and is normalized by the document length through b
+pub fn compute_bm25(t: &str, n: usize, d: &TermFreq, avg_doc_len: f32, k1: f32, b: f32) -> f32 {
+    let f = d.get(t).map(|(freq, _)| *freq).unwrap_or(0) as f32;
+    // An index saved before avg_doc_len existed has no average, so skip length normalization
+    let len_norm = if avg_doc_len > 0f32 { n as f32 / avg_doc_len } else { 1f32 };
+    f * (k1 + 1f32) / (f + k1 * (1f32 - b + b * len_norm))
+}
+
+/// BM25 inverse document frequency
+///  the +1 inside the logarithm keeps it positive for terms present in most documents
+pub fn compute_bm25_idf(t: &str, n: usize, df: &DocFreq) -> f32 {
+    let n = n as f32;
+    let m = df.get(t).cloned().unwrap_or(0) as f32;
+    ((n - m + 0.5) / (m + 0.5) + 1f32).ln()
+}
+
+// The 153 apostrophe-free words of the NLTK English stop-word list
+// (the Lexer splits contractions like "don't" apart, so those forms never show up as tokens)
+const ENGLISH_STOP_WORDS: [&str; 153] = [
+    "i", "me", "my", "myself", "we", "our", "ours", "ourselves", "you", "your", "yours",
+    "yourself", "yourselves", "he", "him", "his", "himself", "she", "her", "hers", "herself",
+    "it", "its", "itself", "they", "them", "their", "theirs", "themselves", "what", "which",
+    "who", "whom", "this", "that", "these", "those", "am", "is", "are", "was", "were", "be",
+    "been", "being", "have", "has", "had", "having", "do", "does", "did", "doing", "a", "an",
+    "the", "and", "but", "if", "or", "because", "as", "until", "while", "of", "at", "by",
+    "for", "with", "about", "against", "between", "into", "through", "during", "before",
+    "after", "above", "below", "to", "from", "up", "down", "in", "out", "on", "off", "over",
+    "under", "again", "further", "then", "once", "here", "there", "when", "where", "why",
+    "how", "all", "any", "both", "each", "few", "more", "most", "other", "some", "such", "no",
+    "nor", "not", "only", "own", "same", "so", "than", "too", "very", "s", "t", "can",
+    "will", "just", "don", "should", "now", "d", "ll", "m", "o", "re", "ve", "y", "ain",
+    "aren", "couldn", "didn", "doesn", "hadn", "hasn", "haven", "isn", "ma", "mightn",
+    "mustn", "needn", "shan", "shouldn", "wasn", "weren", "won", "wouldn",
+];
+
+/// Built-in English stop words, uppercased like the tokens produced by the Lexer
+pub fn default_stop_words() -> HashSet<String> {
+    ENGLISH_STOP_WORDS.iter().map(|word| word.to_ascii_uppercase()).collect()
+}
+
+/// Read a UTF-8 stop-word list with one word per line. Blank lines and lines starting with '#' are skipped.
+/// The words are normalized like the tokens of the Lexer, so "The" and "THE" are the same stop word.
+pub fn load_stop_words(path: &Path) -> Result<HashSet<String>, SearchError> {
+    let content = fs::read_to_string(path).map_err(|err| SearchError::io_at(path, err))?;
+    Ok(content.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.nfc().map(|x| x.to_ascii_uppercase()).collect())
+        .collect())
+}
+
+/// Read a synonym dictionary with one class of equivalent words per line, separated by commas,
+/// e.g. `auto, car, automobile`. Blank lines and lines starting with '#' are skipped.
+/// A word in several classes is a synonym of the words of all of them.
+pub fn load_synonyms(path: &Path) -> Result<SynonymMap, SearchError> {
+    let content = fs::read_to_string(path).map_err(|err| SearchError::io_at(path, err))?;
+    let mut synonyms = HashMap::<String, BTreeSet<String>>::new();
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let class = line.split(',')
+            .map(|word| word.trim())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.nfc().map(|x| x.to_ascii_uppercase()).collect::<String>())
+            .collect::<BTreeSet<_>>();
+        for word in &class {
+            synonyms.entry(word.clone()).or_default().extend(class.iter().filter(|synonym| *synonym != word).cloned());
+        }
+    }
+    Ok(synonyms.into_iter().map(|(word, class)| (word, class.into_iter().collect())).collect())
+}
+
+/// Splits text into the terms that are indexed and searched. Words are uppercased and brought
+/// into Unicode NFC form, numbers are read whole and every other character that isn't whitespace
+/// is a token of its own.
+pub struct Lexer<'a> {
+    content: &'a [char],
+    // Number of characters consumed so far
+    offset: usize,
+    // Reduce alphabetic tokens to their Porter stem
+    stem: bool,
+    // Tokens in this set are skipped
+    stop_words: Option<&'a HashSet<String>>,
+    // Follow every alphabetic token with its character n-grams of this length
+    ngram_size: Option<usize>,
+    // Tokens derived from the last token that are yet to be returned, e.g. its n-grams, with the span of that token
+    pending: VecDeque<(String, Range<usize>)>,
+    // Strip the accents of Latin letters, see with_ascii_folding
+    ascii_folding: bool,
+    // Strip the diacritics of Latin letters, see fold_diacritics
+    fold_diacritics: bool,
+    // Keep the case of words instead of uppercasing them. Stop words still match in any case.
+    case_sensitive: bool,
+    // Return hyphenated words whole before their parts, see compound_len
+    hyphen_compounds: bool,
+    // Return URLs as one token, see next_url
+    tokenize_urls: bool,
+    // Return CJK characters and the pairs they start, see next_cjk
+    cjk_ngram: bool,
+    // Tokens with fewer or more characters than this are skipped
+    min_token_len: usize,
+    max_token_len: usize,
+    // Offset where the last compound returned ends; its parts are read as words of their own
+    compound_end: usize,
+}
+
+impl<'a> Lexer<'a> {
+    /// Lexer with none of the options: no stemming, no stop words and no n-grams
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use serux_lib::Lexer;
+    ///
+    /// let content = "Café, 42 times!".chars().collect::<Vec<_>>();
+    /// let tokens = Lexer::new(&content).collect::<Vec<_>>();
+    /// assert_eq!(tokens, ["CAFé", ",", "42", "TIMES", "!"]);
+    /// ```
+    pub fn new(content: &'a [char]) -> Self {
+        Self {
+            content,
+            offset: 0,
+            stem: false,
+            stop_words: None,
+            ngram_size: None,
+            pending: VecDeque::new(),
+            ascii_folding: false,
+            fold_diacritics: false,
+            case_sensitive: false,
+            hyphen_compounds: false,
+            tokenize_urls: false,
+            cjk_ngram: false,
+            min_token_len: 1,
+            max_token_len: usize::MAX,
+            compound_end: 0,
+        }
+    }
+
+    pub fn with_stemmer(content: &'a [char], stem: bool) -> Self {
+        Self { stem, ..Self::new(content) }
+    }
+
+    /// "Café" and "Über" become "CAFE" and "UBER". Only letters that are an ASCII letter plus accents
+    /// are folded, so other scripts (e.g. Cyrillic "й", which is "и" with a breve) are left alone.
+    pub fn with_ascii_folding(content: &'a [char], ascii_folding: bool) -> Self {
+        Self { ascii_folding, ..Self::new(content) }
+    }
+
+    /// "state-of-the-art" becomes "STATE-OF-THE-ART" followed by the tokens it is made of, see compound_len
+    pub fn with_hyphen_compounds(content: &'a [char], hyphen_compounds: bool) -> Self {
+        Self { hyphen_compounds, ..Self::new(content) }
+    }
+
+    /// "HELLO" with n = 3 is followed by "HEL", "ELL" and "LLO", so that a query for part of a word
+    /// finds the whole word. Tokens of n characters or less have no n-grams besides themselves.
+    /// N-grams make the index a lot bigger: a token of l > n characters is indexed as l - n + 2 terms instead of one.
+    pub fn with_ngrams(content: &'a [char], n: usize) -> Self {
+        assert!(n > 0, "n-grams must have at least one character");
+        Self { ngram_size: Some(n), ..Self::new(content) }
+    }
+
+    /// Tokens of fewer than `min` or more than `max` characters are skipped, e.g. "a" and "I" with
+    /// min = 2, or the long strings of base64 or minified code that no one searches for.
+    /// N-grams and URL hosts are bounded too.
+    pub fn with_length_bounds(content: &'a [char], min: usize, max: usize) -> Self {
+        assert!(min <= max, "the shortest token can't be longer than the longest one");
+        Self { min_token_len: min, max_token_len: max, ..Self::new(content) }
+    }
+
+    // Trim leading whitespace
+    fn trim_left(&mut self) {
+        self.chop_while(|x| x.is_whitespace());
+    }
+
+    // Remove n characters from the beginning of the content
+    fn chop(&mut self, n: usize) -> &'a [char] {
+        let token = &self.content[0..n];
+        self.content = &self.content[n..];
+        self.offset += n;
+        token
+    }
+
+    fn chop_while<P>(&mut self, mut predicate: P) -> &'a [char] where P: FnMut(&char) -> bool {
+        let mut n = 0;
+        while n < self.content.len() && predicate(&self.content[n]) {
+            n += 1;
+        }
+        self.chop(n)
+    }
+
+    pub fn next_token(&mut self) -> Option<String> {
+        self.next_token_span().map(|(token, _)| token)
+    }
+
+    /// Iterate over Tokens instead of Strings
+    pub fn positions(self) -> PositionLexer<'a> {
+        PositionLexer(self)
+    }
+
+    /// Like next_token, but also returns the range of characters of the content the token was read from
+    pub fn next_token_span(&mut self) -> Option<(String, Range<usize>)> {
+        loop {
+            let (token, span) = self.next_unbounded_token()?;
+            if (self.min_token_len..=self.max_token_len).contains(&token.chars().count()) {
+                return Some((token, span));
+            }
+        }
+    }
+
+    fn next_unbounded_token(&mut self) -> Option<(String, Range<usize>)> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
+        loop {
+            // URLs are neither stop words nor stemmed
+            if let Some(url) = self.next_url() {
+                return Some(url);
+            }
+            if let Some(cjk) = self.next_cjk() {
+                return Some(cjk);
+            }
+            let (token, span) = self.next_raw_token()?;
+            let is_stop_word = |stop_words: &HashSet<String>| if self.case_sensitive {
+                stop_words.contains(&token.to_ascii_uppercase())
+            } else {
+                stop_words.contains(&token)
+            };
+            if self.stop_words.is_some_and(is_stop_word) {
+                continue;
+            }
+            if !token.starts_with(|x: char| x.is_alphabetic()) {
+                return Some((token, span));
+            }
+            let token = if self.stem && self.case_sensitive {
+                with_case_of(&porter_stem(&token.to_ascii_lowercase()), &token)
+            } else if self.stem {
+                porter_stem(&token.to_ascii_lowercase()).to_ascii_uppercase()
+            } else {
+                token
+            };
+            if let Some(n) = self.ngram_size {
+                let chars = token.chars().collect::<Vec<_>>();
+                // The n-grams of a token too long to be indexed would fill the index all the same
+                if chars.len() > n && chars.len() <= self.max_token_len {
+                    self.pending.extend(chars.windows(n).map(|ngram| (ngram.iter().collect(), span.clone())));
+                }
+            }
+            return Some((token, span));
+        }
+    }
+
+    // "See https://Example.com/docs?page=2." gives "SEE", "https://example.com/docs?page=2" and "example.com":
+    // URLs starting with http://, https://, ftp:// or www. are lowercased and returned whole instead of
+    // word by word, followed by their host so that a query for the site finds all of its pages.
+    // Punctuation at the end of a URL is taken for the end of the sentence.
+    fn next_url(&mut self) -> Option<(String, Range<usize>)> {
+        if !self.tokenize_urls {
+            return None;
+        }
+        self.trim_left();
+        if !is_url_start(self.content) {
+            return None;
+        }
+        let start = self.offset;
+
+        let mut n = self.content.iter().position(|x| x.is_whitespace() || matches!(x, '"' | '\'' | '<' | '>')).unwrap_or(self.content.len());
+        while n > 0 && matches!(self.content[n - 1], '.' | ',' | ';' | ':' | '!' | '?' | ')' | ']' | '}') {
+            n -= 1;
+        }
+        let url = self.chop(n).iter().collect::<String>().to_lowercase();
+        let span = start..self.offset;
+
+        let host = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest)
+            .split(['/', '?', '#', ':'])
+            .next()
+            .unwrap_or_default();
+        if !host.is_empty() && host != url {
+            self.pending.push_back((host.to_string(), span.clone()));
+        }
+        Some((url, span))
+    }
+
+    // "北京大学" gives "北", "北京", "京", "京大", "大", "大学" and "学": CJK text has no spaces between its words,
+    // so every character is a token of its own, followed by the pair it starts, which matches two character
+    // words without a dictionary. They are neither stop words nor stemmed.
+    fn next_cjk(&mut self) -> Option<(String, Range<usize>)> {
+        if !self.cjk_ngram {
+            return None;
+        }
+        self.trim_left();
+        if !self.content.first().is_some_and(|x| is_cjk(*x)) {
+            return None;
+        }
+        let start = self.offset;
+        let unigram = self.chop(1)[0];
+        if let Some(&next) = self.content.first().filter(|x| is_cjk(**x)) {
+            self.pending.push_back(([unigram, next].iter().collect(), start..start + 2));
+        }
+        Some((unigram.to_string(), start..self.offset))
+    }
+
+    // The next token and the characters of the content it was read from, which can be more than
+    // the characters of the token since words are brought into Unicode NFC form
+    fn next_raw_token(&mut self) -> Option<(String, Range<usize>)> {
+        self.trim_left();
+        if self.content.is_empty() {
+            return None;
+        }
+        let start = self.offset;
+
+        let token = if self.content[0].is_numeric() {
+            self.chop_while(|x| x.is_numeric()).iter().collect()
+        } else if self.content[0].is_alphabetic() {
+            // The compound is not consumed, its parts are read next
+            let compound_len = if self.hyphen_compounds && self.offset >= self.compound_end { self.compound_len() } else { 0 };
+            if compound_len > 0 {
+                self.compound_end = start + compound_len;
+                return Some((self.normalize_word(&self.content[..compound_len]), start..self.compound_end));
+            }
+            // CJK characters are tokens of their own, see next_cjk
+            let cjk_ngram = self.cjk_ngram;
+            let word = self.chop_while(|x| is_word_char(*x) && !(cjk_ngram && is_cjk(*x)));
+            self.normalize_word(word)
+        } else {
+            self.chop(1).iter().collect()
+        };
+
+        Some((token, start..self.offset))
+    }
+
+    // Length of the hyphenated word at the start of the content, 0 if it has no hyphen between two letters.
+    // "state-of-the-art" is returned as "STATE-OF-THE-ART" followed by the tokens it is made of,
+    // "STATE", "-", "OF" and so on, so that a query for one of the parts still finds it.
+    // Only hyphens between two letters join words: "-art", "art-" and "art--deco" are not compounds.
+    fn compound_len(&self) -> usize {
+        let content = self.content;
+        let word_end = |mut n: usize| {
+            while n < content.len() && is_word_char(content[n]) {
+                n += 1;
+            }
+            n
+        };
+        let mut n = word_end(0);
+        let mut joined = false;
+        while n + 1 < content.len() && content[n] == '-' && content[n + 1].is_alphabetic() {
+            n = word_end(n + 1);
+            joined = true;
+        }
+        if joined { n } else { 0 }
+    }
+
+    fn normalize_word(&self, word: &[char]) -> String {
+        let word = word.iter().copied().nfc();
+        let word = word.map(|x| if self.ascii_folding { fold_to_ascii(x) } else { x });
+        let word = if self.case_sensitive {
+            word.collect::<String>()
+        } else {
+            word.map(|x| x.to_ascii_uppercase()).collect()
+        };
+        if !self.fold_diacritics {
+            return word;
+        }
+        // Only ASCII letters are uppercased, so "GARçON" folds to "GARcON": the letters the folding
+        // turned into ASCII ones are uppercased as well
+        let folded = fold_diacritics(&word);
+        if self.case_sensitive {
+            folded
+        } else {
+            folded.to_ascii_uppercase()
+        }
+    }
+}
+
+/// "Garçon" and "Dvořák" become "Garcon" and "Dvorak": the string is decomposed to NFD, the non-spacing marks
+/// (Unicode category Mn) of Latin letters are dropped and the rest is composed to NFC again. Greek, Cyrillic and
+/// the other scripts keep theirs, e.g. "й" is a letter of its own and not "и" with a breve.
+///
+/// # Examples
+///
+/// ```
+/// use serux_lib::model::fold_diacritics;
+///
+/// assert_eq!(fold_diacritics("Garçon à la crème"), "Garcon a la creme");
+/// assert_eq!(fold_diacritics("Łódź"), "Łodz");
+/// assert_eq!(fold_diacritics("ἄλφα йод"), "ἄλφα йод");
+/// ```
+pub fn fold_diacritics(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    let mut latin_base = false;
+    for c in s.nfd() {
+        if c.general_category() == GeneralCategory::NonspacingMark {
+            if !latin_base {
+                folded.push(c);
+            }
+            continue;
+        }
+        latin_base = is_latin(c);
+        folded.push(c);
+    }
+    folded.nfc().collect()
+}
+
+// Letters of the Latin blocks, which fold_diacritics strips the marks of
+fn is_latin(c: char) -> bool {
+    c.is_alphabetic() && matches!(c,
+        'A'..='Z' | 'a'..='z'
+        | '\u{00C0}'..='\u{024F}' // Latin-1 Supplement, Latin Extended-A and B
+        | '\u{1E00}'..='\u{1EFF}' // Latin Extended Additional
+        | '\u{2C60}'..='\u{2C7F}' // Latin Extended-C
+        | '\u{A720}'..='\u{A7FF}' // Latin Extended-D
+        | '\u{AB30}'..='\u{AB6F}' // Latin Extended-E
+    )
+}
+
+/// Whether the content starts with http://, https://, ftp:// or www., in any case, followed by more than that
+#[doc(hidden)]
+pub fn is_url_start(content: &[char]) -> bool {
+    ["http://", "https://", "ftp://", "www."].iter().any(|prefix| {
+        let n = prefix.chars().count();
+        content.len() > n
+            && content.iter().zip(prefix.chars()).all(|(x, y)| x.to_ascii_lowercase() == y)
+            && !content[n].is_whitespace()
+    })
+}
+
+/// CJK ideographs, Hiragana, Katakana and Hangul
+#[doc(hidden)]
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{4E00}'..='\u{9FFF}'     // CJK Unified Ideographs
+        | '\u{3400}'..='\u{4DBF}'   // CJK Unified Ideographs Extension A
+        | '\u{20000}'..='\u{2EBEF}' // CJK Unified Ideographs Extensions B to F
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{3040}'..='\u{309F}'   // Hiragana
+        | '\u{30A0}'..='\u{30FF}'   // Katakana
+        | '\u{1100}'..='\u{11FF}'   // Hangul Jamo
+        | '\u{3130}'..='\u{318F}'   // Hangul Compatibility Jamo
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul Syllables
+    )
+}
+
+// Combining accents belong to the letter before them, e.g. "cafe\u{301}" is one word
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_combining_mark(c)
+}
+
+// The lowercase `stem` of `word`, with the letters that are still those of the word in their original case,
+// e.g. "Running" gives "Run"
+fn with_case_of(stem: &str, word: &str) -> String {
+    let mut word = word.chars();
+    stem.chars().map(|x| match word.next() {
+        Some(original) if original.to_ascii_lowercase() == x => original,
+        _ => x,
+    }).collect()
+}
+
+// The ASCII letter that `c` is made of, if it is one with accents
+fn fold_to_ascii(c: char) -> char {
+    let mut base = None;
+    let mut accents_only = true;
+    decompose_canonical(c, |x| match base {
+        None => base = Some(x),
+        Some(_) => accents_only &= is_combining_mark(x),
+    });
+    match base {
+        Some(base) if base.is_ascii_alphabetic() && accents_only => base,
+        _ => c,
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+/// A token and the characters of the content it was read from, `content[char_start..char_end]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+/// The tokens of a Lexer with their position in the content, see Lexer::positions
+pub struct PositionLexer<'a>(Lexer<'a>);
+
+impl Iterator for PositionLexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_token_span().map(|(text, span)| Token { text, char_start: span.start, char_end: span.end })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::QueryParser;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn paths(results: &[(PathBuf, f32)]) -> Vec<String> {
+        results.iter().map(|(path, _)| path.display().to_string()).collect()
+    }
+
+    // Model with the default settings of documents given as path and content
+    fn model_of(docs: &[(&str, &str)]) -> InMemoryModel {
+        InMemoryModel::from_documents(docs.iter().map(|(path, content)| (PathBuf::from(path), content.to_string()))).unwrap()
+    }
+
+    // Empty directory of its own for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("serux-model-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+    }
+
+    fn term_freq_of(counts: &[(&str, usize)]) -> TermFreq {
+        counts.iter().map(|(term, count)| (term.to_string(), (*count, Vec::new()))).collect()
+    }
+
+    #[test]
+    fn augmented_tf_of_flat_skewed_and_single_term_documents() {
+        // Every term once: they all have the highest TF
+        let flat = term_freq_of(&[("RUST", 1), ("GO", 1), ("ZIG", 1)]);
+        for term in ["RUST", "GO", "ZIG"] {
+            assert_close(compute_tf_augmented(term, 1, &flat), 1.0);
+        }
+        assert_eq!(compute_tf_augmented("C", 1, &flat), 0.0);
+
+        // One term 1000 times: the others stay just above the 0.5 floor instead of dropping to almost nothing
+        let skewed = term_freq_of(&[("SPAM", 1000), ("EGGS", 1), ("HAM", 1)]);
+        assert_close(compute_tf_augmented("SPAM", 1000, &skewed), 1.0);
+        assert_close(compute_tf_augmented("EGGS", 1000, &skewed), 0.5005);
+        assert_close(compute_tf("EGGS", 1002, 1000, &skewed, TfVariant::AugmentedFreq), 0.5005);
+        assert!(compute_tf("EGGS", 1002, 1000, &skewed, TfVariant::Raw) < 0.001);
+
+        let single = term_freq_of(&[("ONLY", 7)]);
+        assert_close(compute_tf_augmented("ONLY", 7, &single), 1.0);
+        // A max_f of 0, as in documents saved before it was kept, is taken for 1
+        assert_close(compute_tf_augmented("ONLY", 0, &term_freq_of(&[("ONLY", 1)])), 1.0);
+    }
+
+    #[test]
+    fn augmented_tf_keeps_long_documents_from_dominating() {
+        let long = format!("rust {}", "filler ".repeat(200));
+        let mut model = InMemoryModel::builder().tf_normalization(TfVariant::AugmentedFreq).build();
+        for (path, content) in [("short.txt", "rust"), ("long.txt", long.as_str()), ("other.txt", "go")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+        assert_eq!(model.tfpd[Path::new("long.txt")].max_term_freq, 200);
+        assert_eq!(model.tfpd[Path::new("short.txt")].max_term_freq, 1);
+        // RUST has the highest count of short.txt, and only 1 of 200 in long.txt
+        let results = model.search_query(&chars("rust"), 0, 10).unwrap().results;
+        assert_eq!(paths(&results), ["short.txt", "long.txt"]);
+        assert_close(results[1].1 / results[0].1, 0.5025);
+    }
+
+    #[test]
+    fn diacritics_are_folded_for_latin_letters_only() {
+        // Latin Extended-A: the letters made of a base letter and marks lose the marks, the others (ł, ı, đ) stay
+        let latin = [
+            ("\u{105}\u{107}\u{119}\u{144}\u{15b}\u{17a}\u{17c}", "acenszz"),
+            ("\u{10d}\u{10f}\u{11b}\u{148}\u{159}\u{161}\u{165}\u{16f}\u{17e}", "cdenrstuz"),
+            ("\u{151}\u{171}\u{11f}\u{15f}\u{101}\u{12b}", "ougsai"),
+            ("\u{142}\u{131}\u{111}", "\u{142}\u{131}\u{111}"),
+            ("Gar\u{e7}on \u{c0} la cr\u{e8}me", "Garcon A la creme"),
+            // Already decomposed
+            ("cafe\u{301}", "cafe"),
+        ];
+        for (word, folded) in latin {
+            assert_eq!(fold_diacritics(word), folded, "{word}");
+        }
+        // Greek, Cyrillic and CJK keep their marks, including the dakuten of "\u{304c}" (\u{304b} + U+3099)
+        for word in [
+            "\u{3b1}\u{3b2}\u{3b3}", "\u{3ac}\u{3ad}\u{3ae}\u{3af}\u{3cc}\u{3cd}\u{3ce}\u{3ca}", "\u{1f04}\u{3bb}\u{3c6}\u{3b1}",
+            "\u{430}\u{431}\u{432}", "\u{439}\u{451}\u{457}\u{45e}",
+            "\u{65e5}\u{672c}\u{8a9e}", "\u{304c}\u{30d1}", "\u{d55c}\u{ad6d}\u{c5b4}",
+        ] {
+            assert_eq!(fold_diacritics(word), word, "{word}");
+        }
+    }
+
+    #[test]
+    fn queries_without_diacritics_find_words_with_them() {
+        let content = chars("Gar\u{e7}on Dvo\u{159}\u{e1}k \u{439}\u{43e}\u{433}\u{430}");
+        let lexer = Lexer { fold_diacritics: true, ..Lexer::new(&content) };
+        assert_eq!(lexer.collect::<Vec<_>>(), ["GARCON", "DVORAK", "\u{439}\u{43e}\u{433}\u{430}"]);
+        let lexer = Lexer { fold_diacritics: true, case_sensitive: true, ..Lexer::new(&content) };
+        assert_eq!(lexer.collect::<Vec<_>>(), ["Garcon", "Dvorak", "\u{439}\u{43e}\u{433}\u{430}"]);
+
+        let mut model = InMemoryModel::builder().diacritic_folding(true).stop_words(HashSet::from(["\u{e0}".to_string()])).build();
+        for (path, content) in [("fr.txt", "le gar\u{e7}on \u{e0} la plage"), ("cz.txt", "Anton\u{ed}n Dvo\u{159}\u{e1}k"), ("other.txt", "go")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+        assert_eq!(paths(&model.search_query(&chars("garcon"), 0, 10).unwrap().results), ["fr.txt"]);
+        assert_eq!(paths(&model.search_query(&chars("dvorak"), 0, 10).unwrap().results), ["cz.txt"]);
+        assert_eq!(paths(&model.search_query(&chars("Dvo\u{159}\u{e1}k"), 0, 10).unwrap().results), ["cz.txt"]);
+        // The stop word is folded like the tokens it is compared with
+        assert!(!model.df.contains_key("A"));
+        assert_eq!(model.suggest_terms("gar\u{e7}", 10).unwrap(), ["GARCON"]);
+    }
+
+    // a.txt has 3 terms, b.txt 2, so the average length is 2.5.
+    // APPLE:  idf = ln((2 - 1 + 0.5) / (1 + 0.5) + 1) = ln 2
+    //         a.txt: f = 2, |d|/avgdl = 1.2, 2 * 2.2 / (2 + 1.2 * (0.25 + 0.75 * 1.2)) = 4.4 / 3.38
+    // BANANA: idf = ln((2 - 2 + 0.5) / (2 + 0.5) + 1) = ln 1.2
+    //         a.txt: f = 1, 2.2 / (1 + 1.2 * 1.15) = 2.2 / 2.38
+    //         b.txt: f = 1, |d|/avgdl = 0.8, 2.2 / (1 + 1.2 * (0.25 + 0.75 * 0.8)) = 2.2 / 2.02
+    #[test]
+    fn bm25_scores_match_hand_computed_values() {
+        let model = model_of(&[("a.txt", "apple banana apple"), ("b.txt", "banana cherry")]);
+        assert_close(model.avg_doc_len, 2.5);
+
+        let results = model.search_query_bm25(&chars("apple"), 1.2, 0.75).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, PathBuf::from("a.txt"));
+        assert_close(results[0].1, 4.4 / 3.38 * 2f32.ln());
+
+        // The shorter document ranks first for a term both contain once
+        let results = model.search_query_bm25(&chars("banana"), 1.2, 0.75).unwrap();
+        assert_eq!(paths(&results), ["b.txt", "a.txt"]);
+        assert_close(results[0].1, 2.2 / 2.02 * 1.2f32.ln());
+        assert_close(results[1].1, 2.2 / 2.38 * 1.2f32.ln());
+
+        // Scores of the terms of a query add up
+        let results = model.search_query_bm25(&chars("apple banana"), 1.2, 0.75).unwrap();
+        assert_eq!(results[0].0, PathBuf::from("a.txt"));
+        assert_close(results[0].1, 4.4 / 3.38 * 2f32.ln() + 2.2 / 2.38 * 1.2f32.ln());
+    }
+
+    #[test]
+    fn bm25_without_length_normalization_ignores_document_length() {
+        let model = model_of(&[("a.txt", "apple banana apple"), ("b.txt", "banana cherry")]);
+        let results = model.search_query_bm25(&chars("banana"), 1.2, 0f32).unwrap();
+        assert_close(results[0].1, results[1].1);
+        assert_close(results[0].1, 2.2 / 2.2 * 1.2f32.ln());
+    }
+
+    #[test]
+    fn avg_doc_len_follows_added_and_removed_documents() {
+        let mut model = InMemoryModel::default();
+        model.add_document("a.txt".into(), &chars("one two three four"), None).unwrap();
+        assert_close(model.avg_doc_len, 4.0);
+        model.add_document("b.txt".into(), &chars("one two"), None).unwrap();
+        assert_close(model.avg_doc_len, 3.0);
+        model.remove_document(Path::new("a.txt")).unwrap();
+        assert_close(model.avg_doc_len, 2.0);
+    }
+
+    #[test]
+    fn stemmer_conflates_morphological_variants() {
+        let terms = Lexer::with_stemmer(&chars("running runs run"), true).collect::<Vec<_>>();
+        assert_eq!(terms, ["RUN", "RUN", "RUN"]);
+        assert_eq!(Lexer::with_stemmer(&chars("running runs"), false).collect::<Vec<_>>(), ["RUNNING", "RUNS"]);
+    }
+
+    #[test]
+    fn queries_are_stemmed_like_documents() {
+        let mut model = InMemoryModel::builder().stemming(true).build();
+        model.add_document("a.txt".into(), &chars("the runner was running"), None).unwrap();
+        model.add_document("b.txt".into(), &chars("a quiet afternoon"), None).unwrap();
+        model.finalize();
+        let results = model.search_query(&chars("runs"), 0, 10).unwrap().results;
+        assert_eq!(paths(&results), ["a.txt"]);
+    }
+
+    fn model_with_stop_words() -> InMemoryModel {
+        let mut model = InMemoryModel::builder().stop_words(default_stop_words()).build();
+        model.add_document("a.txt".into(), &chars("the cat and the hat"), None).unwrap();
+        model.add_document("b.txt".into(), &chars("of mice and men"), None).unwrap();
+        model.finalize();
+        model
+    }
+
+    #[test]
+    fn stop_words_are_not_indexed() {
+        let model = model_with_stop_words();
+        assert_eq!(model.df.keys().collect::<Vec<_>>(), ["CAT", "HAT", "MEN", "MICE"]);
+        assert_eq!(model.tfpd[Path::new("a.txt")].term_count, 2);
+    }
+
+    #[test]
+    fn query_of_stop_words_only_finds_nothing() {
+        let model = model_with_stop_words();
+        for query in ["the", "The AND of", ""] {
+            let page = model.search_query(&chars(query), 0, 10).unwrap();
+            assert!(page.results.is_empty(), "{query}");
+            assert_eq!(page.total_hits, 0);
+            assert!(model.search_query_bm25(&chars(query), 1.2, 0.75).unwrap().is_empty(), "{query}");
+        }
+        assert_eq!(paths(&model.search_query(&chars("the cat"), 0, 10).unwrap().results), ["a.txt"]);
+    }
+
+    #[test]
+    fn stop_words_are_read_one_per_line_and_normalized_like_tokens() {
+        let dir = temp_dir("stop-words");
+        let path = dir.join("stop-words.txt");
+        fs::write(&path, "# articles\nthe\n  An \n\ncafe\u{301}\n").unwrap();
+        let stop_words = load_stop_words(&path).unwrap();
+        assert_eq!(stop_words, HashSet::from(["THE".to_string(), "AN".to_string(), "CAF\u{e9}".to_string()]));
+        assert_eq!(Lexer { stop_words: Some(&stop_words), ..Lexer::new(&chars("the café is an inn")) }.collect::<Vec<_>>(), ["IS", "INN"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn default_stop_words_are_the_nltk_list() {
+        let stop_words = default_stop_words();
+        assert_eq!(stop_words.len(), 153);
+        assert!(["THE", "AND", "OF"].iter().all(|word| stop_words.contains(*word)));
+    }
+
+    #[test]
+    fn phrases_are_consecutive_terms() {
+        let model = model_of(&[("a.txt", "we study machine learning daily"), ("b.txt", "learning machine parts"), ("c.txt", "machine shop")]);
+        assert_eq!(paths(&model.search_phrase_query(&chars("machine learning")).unwrap()), ["a.txt"]);
+        assert_eq!(paths(&model.search_phrase_query(&chars("learning machine")).unwrap()), ["b.txt"]);
+        assert!(model.search_phrase_query(&chars("")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn phrases_do_not_span_sentence_boundaries() {
+        let model = model_of(&[("a.txt", "We built a machine. Learning came later."), ("b.txt", "machine learning")]);
+        assert_eq!(paths(&model.search_phrase_query(&chars("machine learning")).unwrap()), ["b.txt"]);
+        // The punctuation is a term of its own, so the phrase across the boundary is found with it
+        assert_eq!(paths(&model.search_phrase_query(&chars("machine. learning")).unwrap()), ["a.txt"]);
+    }
+
+    #[test]
+    fn stop_words_of_phrases_are_skipped_like_in_documents() {
+        let mut model = InMemoryModel::builder().stop_words(default_stop_words()).build();
+        model.add_document("a.txt".into(), &chars("the state of the art"), None).unwrap();
+        model.add_document("b.txt".into(), &chars("state their art"), None).unwrap();
+        model.add_document("c.txt".into(), &chars("state museum art"), None).unwrap();
+        model.finalize();
+        let mut results = paths(&model.search_phrase_query(&chars("state of the art")).unwrap());
+        results.sort();
+        assert_eq!(results, ["a.txt", "b.txt"]);
+        assert!(model.search_phrase_query(&chars("of the")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("kitten", "kitten"), 0);
+        // One substitution, insertion or deletion
+        assert_eq!(edit_distance("kitten", "sitten"), 1);
+        assert_eq!(edit_distance("kitten", "kittens"), 1);
+        assert_eq!(edit_distance("kitten", "kiten"), 1);
+        // Two of them
+        assert_eq!(edit_distance("kitten", "sittin"), 2);
+        assert_eq!(edit_distance("kitten", "kien"), 2);
+        // A transposition is two substitutions
+        assert_eq!(edit_distance("PROGRAMMING", "PROGRMAMING"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn fuzzy_expansion_within_the_distance() {
+        let df = doc_freq(&["PROGRAMMING", "PROGRAM", "PROGRAMS", "GRAMMAR"]);
+        assert_eq!(fuzzy_expand("PROGRAMMING", &df, 0), ["PROGRAMMING"]);
+        assert_eq!(fuzzy_expand("PROGRAMMIN", &df, 1), ["PROGRAMMING"]);
+        assert_eq!(fuzzy_expand("PROGRMAMING", &df, 1), Vec::<String>::new());
+        assert_eq!(fuzzy_expand("PROGRMAMING", &df, 2), ["PROGRAMMING"]);
+        assert_eq!(fuzzy_expand("PROGRAMX", &df, 1), ["PROGRAM", "PROGRAMS"]);
+        // A prefix is only as close as the letters it lacks
+        assert_eq!(fuzzy_expand("PROG", &df, 2), Vec::<String>::new());
+        assert_eq!(fuzzy_expand("PROGRA", &df, 2), ["PROGRAM", "PROGRAMS"]);
+    }
+
+    #[test]
+    fn fuzzy_search_finds_misspelled_terms() {
+        let model = model_of(&[("a.txt", "programming in rust"), ("b.txt", "grammar rules")]);
+        assert!(model.search_query(&chars("progrmaming"), 0, 10).unwrap().results.is_empty());
+        assert_eq!(paths(&model.search_fuzzy(&chars("progrmaming"), 2).unwrap()), ["a.txt"]);
+        assert!(model.search_fuzzy(&chars("progrmaming"), 1).unwrap().is_empty());
+        // Answered from the cache the second time
+        assert_eq!(paths(&model.search_fuzzy(&chars("progrmaming"), 2).unwrap()), ["a.txt"]);
+        assert!(model.fuzzy_cache.lock().unwrap().get(&("PROGRMAMING".to_string(), 2)).is_some());
+    }
+
+    fn doc_freq(terms: &[&str]) -> DocFreq {
+        terms.iter().map(|term| (term.to_string(), 1)).collect()
+    }
+
+    #[test]
+    fn prefix_expansion() {
+        let df = doc_freq(&["CAT", "CONFIG", "CONFIGURATION", "CONFIGURE", "CONSOLE", "DOG"]);
+        assert_eq!(prefix_expand("CONFIG", &df, 100), ["CONFIG", "CONFIGURATION", "CONFIGURE"]);
+        assert_eq!(prefix_expand("C", &df, 100), ["CAT", "CONFIG", "CONFIGURATION", "CONFIGURE", "CONSOLE"]);
+        assert_eq!(prefix_expand("", &df, 100).len(), df.len());
+        assert!(prefix_expand("X", &df, 100).is_empty());
+        assert!(prefix_expand("CONFIGS", &df, 100).is_empty());
+        assert_eq!(prefix_expand("C", &df, 2), ["CAT", "CONFIG"]);
+    }
+
+    #[test]
+    fn wildcards_match_terms_starting_with_the_word() {
+        let model = model_of(&[("a.txt", "configure the build"), ("b.txt", "configuration files"), ("c.txt", "console output")]);
+        let mut results = paths(&model.search_with_wildcards(&chars("config*"), 100).unwrap());
+        results.sort();
+        assert_eq!(results, ["a.txt", "b.txt"]);
+        assert_eq!(model.search_with_wildcards(&chars("c*"), 100).unwrap().len(), 3);
+        assert!(model.search_with_wildcards(&chars("zebra*"), 100).unwrap().is_empty());
+        // Expanded to CONFIGURATION only, the first of the terms in order
+        assert_eq!(paths(&model.search_with_wildcards(&chars("config*"), 1).unwrap()), ["b.txt"]);
+        // Whole words next to wildcards are searched as usual
+        assert_eq!(paths(&model.search_with_wildcards(&chars("output zebra*"), 100).unwrap()), ["c.txt"]);
+    }
+
+    #[test]
+    fn pages_cover_the_results_once() {
+        // 23 of the documents match, with a few different scores
+        let documents = (0..30)
+            .map(|i| (format!("{i:02}.txt"), if i < 23 { format!("{} filler", "word ".repeat(i % 5 + 1)) } else { "filler".to_string() }))
+            .collect::<Vec<_>>();
+        let model = model_of(&documents.iter().map(|(path, content)| (path.as_str(), content.as_str())).collect::<Vec<_>>());
+        let query = chars("word");
+        let all = model.search_query(&query, 0, usize::MAX).unwrap();
+        assert_eq!(all.total_hits, 23);
+        assert!(!all.has_more);
+
+        for limit in [1, 2, 5, 10, 23, 30] {
+            let mut seen = Vec::new();
+            let mut offset = 0;
+            loop {
+                let page = model.search_query(&query, offset, limit).unwrap();
+                assert_eq!(page.total_hits, 23, "offset {offset}, limit {limit}");
+                assert!(page.results.len() <= limit);
+                assert_eq!(page.has_more, offset + page.results.len() < 23, "offset {offset}, limit {limit}");
+                seen.extend(page.results);
+                offset += limit;
+                if !page.has_more {
+                    break;
+                }
+            }
+            assert_eq!(seen, all.results, "limit {limit}");
+        }
+
+        let past_the_end = model.search_query(&query, 40, 10).unwrap();
+        assert!(past_the_end.results.is_empty());
+        assert_eq!(past_the_end.total_hits, 23);
+        assert!(!past_the_end.has_more);
+        assert!(model.search_query(&query, 0, 0).unwrap().has_more);
+    }
+
+    #[test]
+    fn search_errors_display_what_went_wrong() {
+        let io = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        assert_eq!(SearchError::IoError(io).to_string(), "no such file");
+        let io = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        assert_eq!(SearchError::io_at(Path::new("docs/a.txt"), io).to_string(), "docs/a.txt: no such file");
+
+        let xml = SearchError::XmlParseError { path: PathBuf::from("docs/a.xml"), row: 3, col: 14, msg: "unexpected end of stream".to_string() };
+        assert_eq!(xml.to_string(), "docs/a.xml:3:14: unexpected end of stream");
+
+        let json: SearchError = serde_json::from_str::<u32>("x").unwrap_err().into();
+        assert_eq!(json.to_string(), "invalid JSON: expected value at line 1 column 1");
+
+        let sqlite: SearchError = sqlite::Error { code: Some(1), message: Some("no such table: documents".to_string()) }.into();
+        assert_eq!(sqlite.to_string(), "SQLite: no such table: documents (code 1)");
+
+        assert_eq!(SearchError::IndexCorrupted("df of RUST is 0".to_string()).to_string(), "index is corrupted: df of RUST is 0");
+        assert_eq!(SearchError::Unsupported("Phrase search").to_string(), "Phrase search is not supported by this model");
+        assert_eq!(SearchError::Usage("no index file given".to_string()).to_string(), "no index file given");
+        assert_eq!(SearchError::InvalidConfig("threads must be at least 1".to_string()).to_string(), "invalid configuration: threads must be at least 1");
+        assert_eq!(SearchError::DocumentNotFound(PathBuf::from("docs/b.txt")).to_string(), "docs/b.txt is not in the index");
+        assert_eq!(SearchError::InvalidQuery("unclosed group".to_string()).to_string(), "invalid query: unclosed group");
+        assert_eq!(
+            SearchError::SchemaVersionTooNew { found: 9, supported: 2 }.to_string(),
+            "the index has schema version 9, but this version of serux only reads up to 2; upgrade serux to use it"
+        );
+    }
+
+    #[test]
+    fn words_are_followed_by_their_ngrams() {
+        let content = chars("hello, hi 2024");
+        assert_eq!(Lexer::with_ngrams(&content, 3).collect::<Vec<_>>(), ["HELLO", "HEL", "ELL", "LLO", ",", "HI", "2024"]);
+    }
+
+    #[test]
+    fn ngrams_find_part_of_a_compound_word() {
+        let mut model = InMemoryModel::builder().ngram_size(3).build();
+        model.add_document("ship.txt".into(), &chars("Donaudampfschifffahrtsgesellschaft"), None).unwrap();
+        model.add_document("boat.txt".into(), &chars("a boat on the river"), None).unwrap();
+        model.finalize();
+        assert_eq!(paths(&model.search_query(&chars("dampf"), 0, 10).unwrap().results), ["ship.txt"]);
+        assert_eq!(paths(&model.search_query(&chars("gesellschaft"), 0, 10).unwrap().results), ["ship.txt"]);
+
+        // Without n-grams only whole words match
+        let model = model_of(&[("ship.txt", "Donaudampfschifffahrtsgesellschaft"), ("boat.txt", "a boat on the river")]);
+        assert!(model.search_query(&chars("dampf"), 0, 10).unwrap().results.is_empty());
+    }
+
+    #[test]
+    fn composed_and_decomposed_words_are_the_same_token() {
+        let composed = chars("caf\u{e9} na\u{ef}ve \u{c5}ngstr\u{f6}m");
+        let decomposed = chars("cafe\u{301} nai\u{308}ve A\u{30a}ngstro\u{308}m");
+        let tokens = Lexer::new(&composed).collect::<Vec<_>>();
+        assert_eq!(tokens, ["CAF\u{e9}", "NA\u{ef}VE", "\u{c5}NGSTR\u{f6}M"]);
+        assert_eq!(Lexer::new(&decomposed).collect::<Vec<_>>(), tokens);
+
+        let model = model_of(&[("nfc.txt", "caf\u{e9} au lait"), ("other.txt", "tea")]);
+        assert_eq!(paths(&model.search_query(&chars("cafe\u{301}"), 0, 10).unwrap().results), ["nfc.txt"]);
+    }
+
+    #[test]
+    fn ascii_folding_strips_the_accents_of_latin_letters_only() {
+        let content = chars("Caf\u{e9} \u{dc}ber cafe\u{301}");
+        assert_eq!(Lexer::with_ascii_folding(&content, true).collect::<Vec<_>>(), ["CAFE", "UBER", "CAFE"]);
+        assert_eq!(Lexer::with_ascii_folding(&content, false).collect::<Vec<_>>(), ["CAF\u{e9}", "\u{dc}BER", "CAF\u{e9}"]);
+
+        // Cyrillic "й" is "и" with a breve, and Greek "ά" is "α" with an accent; neither is an ASCII letter
+        let content = chars("\u{439}\u{43e}\u{433}\u{430} \u{3ac}\u{3bb}\u{3c6}\u{3b1} \u{6771}\u{4eac}");
+        assert_eq!(Lexer::with_ascii_folding(&content, true).collect::<Vec<_>>(), Lexer::new(&content).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn merged_document_frequencies_are_the_sums() {
+        let a = model_of(&[("a/1.txt", "rust and go"), ("a/2.txt", "rust only"), ("a/3.txt", "gardening tips")]);
+        let b = model_of(&[("b/1.txt", "rust and python"), ("b/2.txt", "python snakes")]);
+        let (a_df, b_df) = (a.df.clone(), b.df.clone());
+        let mut merged = merge_models(a, b);
+        merged.finalize();
+
+        assert_eq!(merged.df["RUST"], a_df["RUST"] + b_df["RUST"]);
+        assert_eq!(merged.df["RUST"], 3);
+        assert_eq!(merged.df["AND"], 2);
+        assert_eq!(merged.df["PYTHON"], b_df["PYTHON"]);
+        assert_eq!(merged.tfpd.len(), 5);
+        assert_close(merged.avg_doc_len, 12.0 / 5.0);
+
+        let mut found = paths(&merged.search_query(&chars("rust"), 0, 10).unwrap().results);
+        found.sort();
+        assert_eq!(found, ["a/1.txt", "a/2.txt", "b/1.txt"]);
+    }
+
+    #[test]
+    fn document_in_both_indexes_is_kept_from_the_first() {
+        let a = model_of(&[("same.txt", "rust"), ("a.txt", "go")]);
+        let b = model_of(&[("same.txt", "python"), ("b.txt", "java")]);
+        let mut merged = merge_models(a, b);
+        merged.finalize();
+
+        assert_eq!(merged.tfpd.len(), 3);
+        assert!(merged.tfpd[Path::new("same.txt")].tf.contains_key("RUST"));
+        assert!(!merged.df.contains_key("PYTHON"));
+        assert_eq!(merged.df["RUST"], 1);
+    }
+
+    const DELETE_CORPUS: [(&str, &str); 3] = [("a.txt", "rust and go and rust"), ("b.txt", "go gardening"), ("c.txt", "rust only")];
+
+    #[test]
+    fn deleting_a_document_is_like_indexing_without_it() {
+        let mut deleted = model_of(&DELETE_CORPUS);
+        assert!(deleted.remove_document(Path::new("b.txt")).unwrap());
+        assert!(!deleted.remove_document(Path::new("b.txt")).unwrap());
+        deleted.finalize();
+        let reindexed = model_of(&[DELETE_CORPUS[0], DELETE_CORPUS[2]]);
+
+        assert_eq!(deleted.df, reindexed.df);
+        assert!(!deleted.df.contains_key("GARDENING"));
+        assert_eq!(deleted.tfpd.keys().collect::<BTreeSet<_>>(), reindexed.tfpd.keys().collect::<BTreeSet<_>>());
+        for (path, entry) in &reindexed.tfpd {
+            assert_eq!(deleted.tfpd[path].term_count, entry.term_count, "{}", path.display());
+            assert_eq!(deleted.tfpd[path].tf, entry.tf, "{}", path.display());
+        }
+        assert_close(deleted.avg_doc_len, reindexed.avg_doc_len);
+        for query in ["rust", "go", "and", "rust go"] {
+            assert_eq!(deleted.search_query(&chars(query), 0, 10).unwrap().results, reindexed.search_query(&chars(query), 0, 10).unwrap().results, "{query}");
+        }
+    }
+
+    // Documents, term frequencies and document frequencies of an SQLite index, each in a set of rows
+    fn sqlite_rows(model: &SqliteModel) -> [BTreeSet<(String, String, i64)>; 3] {
+        let rows = |query: &str| {
+            let mut stmt = model.connection.prepare(query).unwrap();
+            let mut rows = BTreeSet::new();
+            while let sqlite::State::Row = stmt.next().unwrap() {
+                rows.insert((stmt.read::<String, _>(0).unwrap(), stmt.read::<String, _>(1).unwrap(), stmt.read::<i64, _>(2).unwrap()));
+            }
+            rows
+        };
+        [
+            rows("SELECT path, '', term_count FROM Documents"),
+            rows("SELECT path, term, freq FROM TermFreq JOIN Documents ON Documents.id = TermFreq.doc_id"),
+            rows("SELECT term, '', freq FROM DocFreq"),
+        ]
+    }
+
+    fn sqlite_model_of(dir: &Path, name: &str, docs: &[(&str, &str)]) -> SqliteModel {
+        let mut model = SqliteModel::open(&dir.join(name)).unwrap();
+        for (path, content) in docs {
+            model.add_document(PathBuf::from(path), &chars(content), None).unwrap();
+        }
+        model
+    }
+
+    #[test]
+    fn deleting_a_document_from_sqlite_is_like_indexing_without_it() {
+        let dir = temp_dir("sqlite-delete");
+        let mut deleted = sqlite_model_of(&dir, "deleted.db", &DELETE_CORPUS);
+        assert!(deleted.remove_document(Path::new("b.txt")).unwrap());
+        assert!(!deleted.remove_document(Path::new("b.txt")).unwrap());
+        let reindexed = sqlite_model_of(&dir, "reindexed.db", &[DELETE_CORPUS[0], DELETE_CORPUS[2]]);
+
+        assert_eq!(sqlite_rows(&deleted), sqlite_rows(&reindexed));
+        deleted.verify_integrity().unwrap();
+        drop((deleted, reindexed));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sqlite_models_index_paths_that_are_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = temp_dir("sqlite-not-utf8");
+        let path = Path::new(OsStr::from_bytes(b"caf\xe9.txt"));
+        let content = chars("rust and go");
+
+        let mut model = SqliteModel::open(&dir.join("index.db")).unwrap();
+        model.add_document(path.to_path_buf(), &content, None).unwrap();
+        model.add_document("other.txt".into(), &chars("python"), None).unwrap();
+        assert_eq!(model.document_info(path).unwrap().unwrap().term_count, 3);
+        assert_eq!(paths(&model.search_query(&chars("rust"), 0, 10).unwrap().results), ["caf%E9.txt"]);
+        assert!(model.remove_document(path).unwrap());
+
+        let mut fts5 = SqliteFts5Model::open(&dir.join("fts5.db")).unwrap();
+        fts5.add_document(path.to_path_buf(), &content, None).unwrap();
+        fts5.add_document("other.txt".into(), &chars("python"), None).unwrap();
+        fts5.set_document_title(path, "Caf\u{e9}".to_string()).unwrap();
+        assert_eq!(fts5.document_title(path).as_deref(), Some("Caf\u{e9}"));
+        assert!(fts5.document_info(path).unwrap().is_some());
+        assert!(fts5.match_snippet(path, &chars("rust")).is_some());
+        assert_eq!(paths(&fts5.search_query(&chars("rust"), 0, 10).unwrap().results), ["caf%E9.txt"]);
+        assert!(fts5.remove_document(path).unwrap());
+        drop((model, fts5));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn custom_stop_words_replace_the_built_in_ones_and_are_saved_with_the_index() {
+        let dir = temp_dir("custom-stop-words");
+        let path = dir.join("stop-words.txt");
+        fs::write(&path, "The\nTHE\n the \nrust\n#and\n").unwrap();
+        let stop_words = load_stop_words(&path).unwrap();
+        // "THE" is in the built-in list as well, and only there once
+        assert_eq!(stop_words, HashSet::from(["THE".to_string(), "RUST".to_string()]));
+        assert!(default_stop_words().contains("THE"));
+
+        let mut model = InMemoryModel::builder().stop_words(stop_words).stop_words_file(path.clone()).build();
+        model.add_document("a.txt".into(), &chars("the rust and the crab"), None).unwrap();
+        model.add_document("b.txt".into(), &chars("a crab"), None).unwrap();
+        let json = serde_json::to_vec(&model).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The words are in the index, the file is not needed to search it
+        let mut loaded = crate::schema::model_from_json(&json).unwrap();
+        loaded.finalize();
+        assert_eq!(loaded.config.stop_words_file, Some(path));
+        assert_eq!(loaded.df.keys().collect::<Vec<_>>(), ["A", "AND", "CRAB"]);
+        assert_eq!(paths(&loaded.search_query(&chars("and"), 0, 10).unwrap().results), ["a.txt"]);
+        assert!(loaded.search_query(&chars("the rust"), 0, 10).unwrap().results.is_empty());
+    }
+
+    #[test]
+    fn synonyms_are_read_as_classes_of_equivalent_words() {
+        let dir = temp_dir("synonyms");
+        let path = dir.join("synonyms.txt");
+        fs::write(&path, "# vehicles\nauto, Car , automobile\n\ncar, wagon\n").unwrap();
+        let synonyms = load_synonyms(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(synonyms["AUTO"], ["AUTOMOBILE", "CAR"]);
+        assert_eq!(synonyms["AUTOMOBILE"], ["AUTO", "CAR"]);
+        // In both classes
+        assert_eq!(synonyms["CAR"], ["AUTO", "AUTOMOBILE", "WAGON"]);
+        assert_eq!(synonyms["WAGON"], ["CAR"]);
+    }
+
+    #[test]
+    fn query_terms_match_their_synonyms_below_themselves() {
+        let synonyms = SynonymMap::from([
+            ("AUTO".to_string(), vec!["AUTOMOBILE".to_string()]),
+            ("AUTOMOBILE".to_string(), vec!["AUTO".to_string()]),
+        ]);
+        let mut model = InMemoryModel::builder().synonyms(synonyms).build();
+        for (path, content) in [("automobile.txt", "an automobile"), ("auto.txt", "an auto"), ("bike.txt", "a bike")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+
+        assert_eq!(paths(&model.search_query(&chars("auto"), 0, 10).unwrap().results), ["auto.txt", "automobile.txt"]);
+        assert_eq!(paths(&model.search_query(&chars("automobile"), 0, 10).unwrap().results), ["automobile.txt", "auto.txt"]);
+        // Synonyms are expanded at query time only
+        assert!(!model.tfpd[Path::new("automobile.txt")].tf.contains_key("AUTO"));
+    }
+
+    #[test]
+    fn suggestions_start_with_the_prefix_most_common_first() {
+        let df = DocFreq::from([("CONFIG", 3), ("CONFIGURE", 7), ("CONSOLE", 7), ("CAT", 9), ("DOG", 1)].map(|(term, df)| (term.to_string(), df)));
+        assert_eq!(suggest_terms("con", &df, 10, false), ["CONFIGURE", "CONSOLE", "CONFIG"]);
+        assert_eq!(suggest_terms("Config", &df, 10, false), ["CONFIGURE", "CONFIG"]);
+        assert_eq!(suggest_terms("con", &df, 2, false), ["CONFIGURE", "CONSOLE"]);
+        assert_eq!(suggest_terms("con", &df, 0, false), Vec::<String>::new());
+        assert_eq!(suggest_terms("x", &df, 10, false), Vec::<String>::new());
+        assert_eq!(suggest_terms("", &df, 3, false), ["CAT", "CONFIGURE", "CONSOLE"]);
+
+        let df = DocFreq::from([("Config", 2), ("config", 1), ("Cat", 1)].map(|(term, df)| (term.to_string(), df)));
+        assert_eq!(suggest_terms("CON", &df, 10, true), ["Config", "config"]);
+    }
+
+    #[test]
+    fn explained_contributions_add_up_to_the_score() {
+        let model = model_of(&[("a.txt", "apple banana apple"), ("b.txt", "banana cherry"), ("c.txt", "cherry pie")]);
+        for ranking in [RankingAlgorithm::TfIdf, RankingAlgorithm::bm25()] {
+            let results = model.search_query_ranked(&chars("apple banana kiwi"), ranking).unwrap();
+            for (path, score) in results {
+                let explanation = model.explain_score(&path, &chars("apple banana kiwi"), ranking).unwrap();
+                let sum = explanation.term_scores.iter().map(|term_score| term_score.contribution).sum::<f32>();
+                assert_close(sum + explanation.proximity_score, explanation.total_score);
+                assert_close(explanation.total_score, score);
+            }
+        }
+
+        let explanation = model.explain_score(Path::new("a.txt"), &chars("apple kiwi"), RankingAlgorithm::TfIdf).unwrap();
+        assert_eq!((explanation.document_length, explanation.document_count), (3, 3));
+        let [apple, kiwi] = &explanation.term_scores[..] else {
+            panic!("{:?} are not the scores of two terms", explanation.term_scores);
+        };
+        assert_eq!((apple.term.as_str(), apple.raw_count, apple.document_frequency), ("APPLE", 2, 1));
+        assert_close(apple.tf, 2.0 / 3.0);
+        assert_close(apple.idf, 3f32.ln());
+        assert_close(apple.contribution, apple.tf * apple.idf);
+        assert_eq!((kiwi.term.as_str(), kiwi.raw_count, kiwi.document_frequency, kiwi.contribution), ("KIWI", 0, 0, 0.0));
+
+        assert!(model.explain_score(Path::new("missing.txt"), &chars("apple"), RankingAlgorithm::TfIdf).is_none());
+    }
+
+    #[test]
+    fn sqlite_batch_is_like_adding_one_by_one() {
+        let dir = temp_dir("sqlite-batch");
+        let one_by_one = sqlite_model_of(&dir, "one-by-one.db", &DELETE_CORPUS);
+        let mut batch = SqliteModel::open(&dir.join("batch.db")).unwrap();
+        let docs = DELETE_CORPUS.iter().map(|(path, content)| (PathBuf::from(path), chars(content)));
+        assert_eq!(batch.add_document_batch(docs).unwrap(), 3);
+
+        assert_eq!(sqlite_rows(&batch), sqlite_rows(&one_by_one));
+        drop((batch, one_by_one));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tf_normalizations_at_their_bounds() {
+        // f = 0, f = 1 and f = max_f = 4, in a document of 5 terms
+        let d = term_freq_of(&[("ONCE", 1), ("MOST", 4)]);
+        let tf = |term, norm| compute_tf(term, 5, 4, &d, norm);
+        for norm in [TfNormalization::Raw, TfNormalization::LogNorm, TfNormalization::AugmentedFreq, TfNormalization::BooleanPresence] {
+            assert_eq!(tf("MISSING", norm), 0.0, "{norm:?}");
+        }
+        assert_close(tf("ONCE", TfNormalization::Raw), 0.2);
+        assert_close(tf("MOST", TfNormalization::Raw), 0.8);
+        assert_close(tf("ONCE", TfNormalization::LogNorm), 1.0);
+        assert_close(tf("MOST", TfNormalization::LogNorm), 1.0 + 4f32.ln());
+        assert_close(tf("ONCE", TfNormalization::AugmentedFreq), 0.625);
+        assert_close(tf("MOST", TfNormalization::AugmentedFreq), 1.0);
+        assert_close(tf("ONCE", TfNormalization::BooleanPresence), 1.0);
+        assert_close(tf("MOST", TfNormalization::BooleanPresence), 1.0);
+    }
+
+    // With an IDF of 1, a document scores the number of query terms it has, however often
+    #[test]
+    fn boolean_presence_counts_the_query_terms_of_a_document() {
+        let mut model = InMemoryModel::builder().tf_normalization(TfNormalization::BooleanPresence).build();
+        model.config.idf = IdfVariant::UnaryOne;
+        for (path, content) in [("a.txt", "rust rust rust go"), ("b.txt", "rust python java"), ("c.txt", "rust go python"), ("d.txt", "java")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+
+        let results = model.search_query(&chars("rust go python"), 0, 10).unwrap().results;
+        assert_eq!(paths(&results), ["c.txt", "a.txt", "b.txt"]);
+        for ((_, score), expected) in results.iter().zip([3.0, 2.0, 2.0]) {
+            assert_close(*score, expected);
+        }
+    }
+
+    #[test]
+    fn idf_variants_of_known_frequencies() {
+        let df = DocFreq::from([("RARE", 2), ("COMMON", 7), ("EVERYWHERE", 10)].map(|(term, df)| (term.to_string(), df)));
+        let idf = |term, variant| compute_idf(term, 10, &df, variant);
+        assert_close(idf("RARE", IdfVariant::Standard), 5f32.ln());
+        assert_close(idf("RARE", IdfVariant::Smooth), (11f32 / 3f32).ln() + 1.0);
+        assert_close(idf("RARE", IdfVariant::Probabilistic), 4f32.ln());
+        assert_close(idf("RARE", IdfVariant::UnaryOne), 1.0);
+
+        assert_close(idf("EVERYWHERE", IdfVariant::Standard), 0.0);
+        assert_close(idf("EVERYWHERE", IdfVariant::Smooth), 1.0);
+        assert_eq!(idf("EVERYWHERE", IdfVariant::Probabilistic), 0.0);
+        // ln(3 / 7) would be negative
+        assert_eq!(idf("COMMON", IdfVariant::Probabilistic), 0.0);
+        assert_close(idf("EVERYWHERE", IdfVariant::UnaryOne), 1.0);
+    }
+
+    #[test]
+    fn case_sensitive_terms_differ_by_case() {
+        let content = chars("Rust rust RUST");
+        assert_eq!(Lexer { case_sensitive: true, ..Lexer::new(&content) }.collect::<Vec<_>>(), ["Rust", "rust", "RUST"]);
+
+        let mut model = InMemoryModel::builder().case_sensitive(true).build();
+        for (path, content) in [("upper.txt", "Rust the language"), ("lower.txt", "rust on iron"), ("other.txt", "go")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+        assert_eq!((model.df["Rust"], model.df["rust"]), (1, 1));
+        assert!(!model.df.contains_key("RUST"));
+        assert_eq!(paths(&model.search_query(&chars("Rust"), 0, 10).unwrap().results), ["upper.txt"]);
+        assert_eq!(paths(&model.search_query(&chars("rust"), 0, 10).unwrap().results), ["lower.txt"]);
+        assert!(model.search_query(&chars("RUST"), 0, 10).unwrap().results.is_empty());
+
+        let mut ignoring_case = paths(&model.search_query_ignoring_case(&chars("RUST"), RankingAlgorithm::TfIdf).unwrap());
+        ignoring_case.sort();
+        assert_eq!(ignoring_case, ["lower.txt", "upper.txt"]);
+    }
+
+    #[test]
+    fn regular_expressions_match_indexed_terms() {
+        let model = model_of(&[
+            ("error.txt", "an error occurred"),
+            ("errar.txt", "errar humanum est"),
+            ("errors.txt", "many errors"),
+            ("year.txt", "released in 2024"),
+        ]);
+        let found = |pattern, limit| {
+            let mut found = paths(&model.search_regex(pattern, limit).unwrap());
+            found.sort();
+            found
+        };
+        assert_eq!(found("err[oa]r", 200), ["errar.txt", "error.txt", "errors.txt"]);
+        assert_eq!(found("^err[oa]r$", 200), ["errar.txt", "error.txt"]);
+        assert_eq!(found(r"^\d{4}$", 200), ["year.txt"]);
+        assert_eq!(found("^[0-9]+[a-z]", 200), Vec::<String>::new());
+        assert_eq!(found("^zzz", 200), Vec::<String>::new());
+        // Only the first matching terms count, in term order: ERRAR before ERROR and ERRORS
+        assert_eq!(found("^err", 1), ["errar.txt"]);
+
+        assert!(matches!(model.search_regex("err(", 200), Err(SearchError::InvalidQuery(_))));
+        assert!(matches!(model.search_regex(&"a".repeat(1000), 200), Err(SearchError::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn hyphenated_words_are_followed_by_their_parts() {
+        let tokens = |text: &str| Lexer::with_hyphen_compounds(&chars(text), true).collect::<Vec<_>>();
+        assert_eq!(tokens("e-mail"), ["E-MAIL", "E", "-", "MAIL"]);
+        assert_eq!(tokens("state-of-the-art design"), ["STATE-OF-THE-ART", "STATE", "-", "OF", "-", "THE", "-", "ART", "DESIGN"]);
+        // Only hyphens between two letters join words
+        assert_eq!(tokens("-leading"), ["-", "LEADING"]);
+        assert_eq!(tokens("trailing-"), ["TRAILING", "-"]);
+        assert_eq!(tokens("art--deco"), ["ART", "-", "-", "DECO"]);
+        assert_eq!(tokens("covid-19"), ["COVID", "-", "19"]);
+        assert_eq!(Lexer::with_hyphen_compounds(&chars("e-mail"), false).collect::<Vec<_>>(), ["E", "-", "MAIL"]);
+    }
+
+    #[test]
+    fn hyphenated_words_are_found_whole_and_by_their_parts() {
+        let mut model = InMemoryModel::builder().hyphen_compounds(true).build();
+        for (path, content) in [("art.txt", "a state-of-the-art compiler"), ("state.txt", "the state of the union"), ("other.txt", "go")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+        assert_eq!(model.df["STATE-OF-THE-ART"], 1);
+        assert_eq!(paths(&model.search_query(&chars("state-of-the-art"), 0, 10).unwrap().results)[0], "art.txt");
+        assert_eq!(paths(&model.search_query(&chars("art"), 0, 10).unwrap().results), ["art.txt"]);
+        let mut state = paths(&model.search_query(&chars("state"), 0, 10).unwrap().results);
+        state.sort();
+        assert_eq!(state, ["art.txt", "state.txt"]);
+    }
+
+    #[test]
+    fn urls_are_single_tokens_followed_by_their_host() {
+        let tokens = |text: &str| Lexer { tokenize_urls: true, ..Lexer::new(&chars(text)) }.collect::<Vec<_>>();
+        assert_eq!(tokens("http://example.com/a"), ["http://example.com/a", "example.com"]);
+        // Punctuation at the end is the end of the sentence
+        assert_eq!(tokens("See HTTPS://Example.com/Docs."), ["SEE", "https://example.com/docs", "example.com", "."]);
+        assert_eq!(tokens("ftp://files.example.org:21/pub/x.tar.gz"), ["ftp://files.example.org:21/pub/x.tar.gz", "files.example.org"]);
+        assert_eq!(tokens("(https://example.com/search?q=rust&page=2#top)"),
+            ["(", "https://example.com/search?q=rust&page=2#top", "example.com", ")"]);
+        assert_eq!(tokens("www.example.com/about, then"), ["www.example.com/about", "www.example.com", ",", "THEN"]);
+        // The host alone is not repeated
+        assert_eq!(tokens("www.example.com"), ["www.example.com"]);
+        assert_eq!(tokens("\"https://example.com\""), ["\"", "https://example.com", "example.com", "\""]);
+        // Not URLs: a scheme with nothing after it, and other schemes
+        assert_eq!(tokens("http:// mailto:me"), ["HTTP", ":", "/", "/", "MAILTO", ":", "ME"]);
+        assert_eq!(Lexer::new(&chars("https://example.com")).collect::<Vec<_>>(), ["HTTPS", ":", "/", "/", "EXAMPLE", ".", "COM"]);
+
+        assert!(is_url_start(&chars("WWW.example.com")));
+        assert!(!is_url_start(&chars("www.")));
+        assert!(!is_url_start(&chars("https:// example.com")));
+    }
+
+    #[test]
+    fn urls_are_found_by_their_host() {
+        let mut model = InMemoryModel::builder().tokenize_urls(true).build();
+        for (path, content) in [
+            ("docs.txt", "read https://example.com/docs first"),
+            ("blog.txt", "see https://example.com/blog?id=1"),
+            ("other.txt", "com example https"),
+        ] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.finalize();
+        // The host of the query URL matches the host of every page of the site
+        let mut host = paths(&model.search_query(&chars("https://example.com"), 0, 10).unwrap().results);
+        host.sort();
+        assert_eq!(host, ["blog.txt", "docs.txt"]);
+        assert_eq!(paths(&model.search_query(&chars("https://example.com/blog?id=1"), 0, 10).unwrap().results)[0], "blog.txt");
+        assert_eq!((model.df["example.com"], model.df["https://example.com/docs"], model.df["EXAMPLE"]), (2, 1, 1));
+    }
+
+    #[test]
+    fn tokens_outside_the_length_bounds_are_skipped() {
+        let content = chars("a I x go rust");
+        assert_eq!(Lexer::with_length_bounds(&content, 2, usize::MAX).collect::<Vec<_>>(), ["GO", "RUST"]);
+        assert_eq!(Lexer::with_length_bounds(&content, 1, 2).collect::<Vec<_>>(), ["A", "I", "X", "GO"]);
+
+        let mut model = InMemoryModel::builder().min_token_len(2).build();
+        model.add_document("letters.txt".into(), &chars("a b c I x y z"), None).unwrap();
+        model.finalize();
+        assert!(model.df.is_empty());
+        assert_eq!(model.tfpd[Path::new("letters.txt")].term_count, 0);
+
+        let long = "f".repeat(500);
+        let mut model = InMemoryModel::builder().max_token_len(100).build();
+        model.add_document("hex.txt".into(), &chars(&format!("sha {long} end")), None).unwrap();
+        model.finalize();
+        assert_eq!(model.df.keys().map(String::as_str).collect::<HashSet<_>>(), HashSet::from(["SHA", "END"]));
+        assert_eq!(model.config.max_token_len, Some(100));
+    }
+
+    #[test]
+    fn ngrams_of_tokens_too_long_to_index_are_skipped() {
+        let content = chars("go rust rustacean");
+        let lexer = Lexer { min_token_len: 3, max_token_len: 4, ..Lexer::with_ngrams(&content, 3) };
+        assert_eq!(lexer.collect::<Vec<_>>(), ["RUST", "RUS", "UST"]);
+    }
+
+    // Model of documents given as path, title and body
+    fn model_with_titles(builder: InMemoryModelBuilder, docs: &[(&str, &str, &str)]) -> InMemoryModel {
+        let mut model = builder.build();
+        for (path, title, body) in docs {
+            let document = model.analyze(&chars(body), Some(title), None);
+            model.merge_document(path.into(), document, Some(title.to_string()), None);
+        }
+        model.finalize();
+        model
+    }
+
+    #[test]
+    fn query_term_in_the_title_outranks_it_in_the_body() {
+        let docs = [
+            ("title.txt", "Rust guide", "a guide for programmers"),
+            ("body.txt", "Programming guide", "a guide for rust programmers"),
+            ("other.txt", "Gardening", "tomatoes"),
+        ];
+        let model = model_with_titles(InMemoryModel::builder(), &docs);
+        assert_eq!(model.tfpd[Path::new("title.txt")].fields.title_term_count, 2);
+        let results = model.search_query(&chars("rust"), 0, 10).unwrap().results;
+        assert_eq!(paths(&results), ["title.txt", "body.txt"]);
+
+        // Without weight, the title doesn't count at all
+        let weights = FieldWeights { title: 0.0, ..FieldWeights::default() };
+        let model = model_with_titles(InMemoryModel::builder().field_weights(weights), &docs);
+        assert_eq!(paths(&model.search_query(&chars("rust"), 0, 10).unwrap().results), ["body.txt"]);
+    }
+
+    #[test]
+    fn field_weights_are_parsed_from_a_list() {
+        assert_eq!(FieldWeights::default(), FieldWeights { title: 3.0, body: 1.0, metadata: 0.5 });
+        assert_eq!(FieldWeights::parse("title=3,body=1"), Some(FieldWeights::default()));
+        assert_eq!(FieldWeights::parse(" metadata = 2 "), Some(FieldWeights { metadata: 2.0, ..FieldWeights::default() }));
+        for invalid in ["", "title", "title=-1", "title=NaN", "author=1", "title=3;body=1"] {
+            assert_eq!(FieldWeights::parse(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn facets_count_the_results_by_extension() {
+        let results = ["a.md", "b.MD", "c.txt", "d", "dir.d/e.tar.gz"].map(|path| (PathBuf::from(path), 1.0));
+        let facets = facet_by_extension(&results);
+        assert_eq!(facets, HashMap::from([("md".to_string(), 2), ("txt".to_string(), 1), (String::new(), 1), ("gz".to_string(), 1)]));
+        assert_eq!(facets.values().sum::<usize>(), results.len());
+
+        let page = SearchPage::paginate(results.to_vec(), 0, 2);
+        assert_eq!((page.results.len(), page.total_hits), (2, 5));
+        assert_eq!(page.facets, facets);
+    }
+
+    #[test]
+    fn cjk_text_is_split_into_characters_and_pairs() {
+        let tokens = |text: &str| Lexer { cjk_ngram: true, ..Lexer::new(&chars(text)) }.collect::<Vec<_>>();
+        assert_eq!(tokens("\u{5317}\u{4eac}\u{5927}\u{5b66}"),
+            ["\u{5317}", "\u{5317}\u{4eac}", "\u{4eac}", "\u{4eac}\u{5927}", "\u{5927}", "\u{5927}\u{5b66}", "\u{5b66}"]);
+        // Script boundaries end the run: Latin words stay whole and pairs don't span spaces or punctuation
+        assert_eq!(tokens("Rust\u{662f}\u{597d} \u{597d}\u{3002}ok"),
+            ["RUST", "\u{662f}", "\u{662f}\u{597d}", "\u{597d}", "\u{597d}", "\u{3002}", "OK"]);
+        // Hiragana, Katakana and Hangul
+        assert_eq!(tokens("\u{3072}\u{30ab}\u{d55c}"), ["\u{3072}", "\u{3072}\u{30ab}", "\u{30ab}", "\u{30ab}\u{d55c}", "\u{d55c}"]);
+        // Without the flag, a run of CJK characters is a single word
+        assert_eq!(Lexer::new(&chars("\u{5317}\u{4eac}\u{5927}\u{5b66}")).collect::<Vec<_>>(), ["\u{5317}\u{4eac}\u{5927}\u{5b66}"]);
+
+        for c in ['\u{4e00}', '\u{9fff}', '\u{3400}', '\u{20000}', '\u{3041}', '\u{30a2}', '\u{ac00}'] {
+            assert!(is_cjk(c), "{c:?}");
+        }
+        for c in ['a', '\u{e9}', '\u{3002}', '\u{ff01}', '\u{416}'] {
+            assert!(!is_cjk(c), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn freshness_halves_every_half_life() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        assert_close(freshness_factor(now, now, 30.0), 1.0);
+        assert_close(freshness_factor(now - 30 * day, now, 30.0), 0.5);
+        assert_close(freshness_factor(now - 60 * day, now, 30.0), 0.25);
+        assert_close(freshness_factor(now + day, now, 30.0), 1.0);
+        assert!(freshness_factor(now - day, now, 30.0) > freshness_factor(now - 2 * day, now, 30.0));
+    }
+
+    #[test]
+    fn recently_modified_documents_outrank_old_ones_with_freshness() {
+        let day = 86_400;
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        let rank = |freshness_boost: bool| {
+            let mut model = InMemoryModel::builder().freshness_boost(freshness_boost).build();
+            for (path, content) in [("old.txt", "rust edition"), ("new.txt", "rust edition"), ("other.txt", "go")] {
+                model.add_document(path.into(), &chars(content), None).unwrap();
+            }
+            model.tfpd.get_mut(Path::new("old.txt")).unwrap().last_modified = Some(now - 365 * day);
+            model.tfpd.get_mut(Path::new("new.txt")).unwrap().last_modified = Some(now - day);
+            model.finalize();
+            model.search_query(&chars("rust"), 0, 10).unwrap().results
+        };
+
+        let results = rank(true);
+        assert_eq!(paths(&results), ["new.txt", "old.txt"]);
+        // With the default half-life of a year, a year old document scores half as much as a new one
+        assert_close(results[1].1 / results[0].1, 0.5 / freshness_factor(now - day, now, DEFAULT_FRESHNESS_HALF_LIFE_DAYS));
+
+        let results = rank(false);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].1, results[1].1);
+    }
+
+    #[test]
+    fn finalizing_again_takes_the_ages_of_the_documents_anew() {
+        let day = 86_400;
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+        let mut model = InMemoryModel::builder().freshness_boost(true).freshness_half_life_days(30.0).build();
+        for (path, content) in [("a.txt", "rust"), ("b.txt", "rust"), ("other.txt", "go")] {
+            model.add_document(path.into(), &chars(content), None).unwrap();
+        }
+        model.tfpd.get_mut(Path::new("a.txt")).unwrap().last_modified = Some(now - 60 * day);
+        model.tfpd.get_mut(Path::new("b.txt")).unwrap().last_modified = Some(now);
+        model.finalize();
+        assert_eq!(paths(&model.search_query(&chars("rust"), 0, 10).unwrap().results), ["b.txt", "a.txt"]);
+
+        // The ages are kept in the scores until the next finalize, like the one of a reload
+        model.tfpd.get_mut(Path::new("a.txt")).unwrap().last_modified = Some(now);
+        model.tfpd.get_mut(Path::new("b.txt")).unwrap().last_modified = Some(now - 60 * day);
+        assert_eq!(paths(&model.search_query(&chars("rust"), 0, 10).unwrap().results), ["b.txt", "a.txt"]);
+        model.finalize();
+        let results = model.search_query(&chars("rust"), 0, 10).unwrap().results;
+        assert_eq!(paths(&results), ["a.txt", "b.txt"]);
+        assert_close(results[1].1 / results[0].1, 0.25);
+    }
+
+    // a.txt and b.txt are the same, c.txt has none of their terms and d.txt shares RUST with them.
+    // With N = 4: idf(RUST) = ln(4/3), idf(GO) = idf(ZIG) = ln 2, and every TF is 1/2, so
+    // cos(a, d) = ln(4/3)^2 / (ln(4/3)^2 + ln(2)^2)
+    #[test]
+    fn cosine_similarity_of_identical_orthogonal_and_overlapping_documents() {
+        let model = model_of(&[("a.txt", "rust go"), ("b.txt", "rust go"), ("c.txt", "zig odin"), ("d.txt", "rust zig")]);
+        let similarity = |a: &str, b: &str| model.cosine_similarity(Path::new(a), Path::new(b)).unwrap();
+        assert_close(similarity("a.txt", "b.txt"), 1.0);
+        assert_close(similarity("a.txt", "a.txt"), 1.0);
+        assert_eq!(similarity("a.txt", "c.txt"), 0.0);
+        let (rust, other) = ((4f32 / 3.0).ln().powi(2), 2f32.ln().powi(2));
+        assert_close(similarity("a.txt", "d.txt"), rust / (rust + other));
+        assert_close(similarity("d.txt", "a.txt"), similarity("a.txt", "d.txt"));
+
+        assert!(matches!(model.cosine_similarity(Path::new("a.txt"), Path::new("missing.txt")), Err(SearchError::DocumentNotFound(_))));
+        assert!(matches!(model.find_similar(Path::new("missing.txt"), 10), Err(SearchError::DocumentNotFound(_))));
+
+        // The document itself and the ones with nothing in common are left out
+        let similar = model.find_similar(Path::new("a.txt"), 10).unwrap();
+        assert_eq!(paths(&similar), ["b.txt", "d.txt"]);
+        assert_eq!(paths(&model.find_similar(Path::new("a.txt"), 1).unwrap()), ["b.txt"]);
+    }
+
+    #[test]
+    fn min_score_drops_the_results_below_the_threshold() {
+        let model = model_of(&[
+            ("a.txt", "rust rust rust rust"),
+            ("b.txt", "rust rust rust snake"),
+            ("c.txt", "rust snake snake snake"),
+            ("d.txt", "snake snake snake snake"),
+        ]);
+        let results = model.search_query(&chars("rust"), 0, 10).unwrap().results;
+        assert_eq!(results.len(), 3);
+        let top = results.iter().map(|(_, score)| *score).fold(f32::NEG_INFINITY, f32::max);
+
+        let mut half = results.clone();
+        MinScoreStrategy::FractionOfTop(0.5).retain(&mut half);
+        assert_eq!(paths(&half), ["a.txt", "b.txt"]);
+        assert!(half.iter().all(|(_, score)| *score >= top / 2.0));
+
+        let mut everything = results.clone();
+        MinScoreStrategy::default().retain(&mut everything);
+        assert_eq!(everything, results);
+
+        let mut nothing = results.clone();
+        MinScoreStrategy::Absolute(f32::MAX).retain(&mut nothing);
+        assert!(nothing.is_empty());
+    }
+
+    #[test]
+    fn negative_min_scores_are_fractions_of_the_top_score() {
+        assert_eq!(MinScoreStrategy::from_min_score(0.001), Some(MinScoreStrategy::Absolute(0.001)));
+        assert_eq!(MinScoreStrategy::from_min_score(0.0), Some(MinScoreStrategy::Absolute(0.0)));
+        assert_eq!(MinScoreStrategy::from_min_score(-0.1), Some(MinScoreStrategy::FractionOfTop(0.1)));
+        assert_eq!(MinScoreStrategy::from_min_score(-1.0), Some(MinScoreStrategy::FractionOfTop(1.0)));
+        assert_eq!(MinScoreStrategy::from_min_score(-1.5), None);
+        assert_eq!(MinScoreStrategy::from_min_score(f32::NAN), None);
+    }
+
+    #[test]
+    fn boosts_multiply_the_score_of_their_term() {
+        let model = model_of(&[("a.txt", "rust"), ("b.txt", "python"), ("c.txt", "other")]);
+        let plain = model.search_query(&chars("rust python"), 0, 10).unwrap().results;
+        let score = |results: &[(PathBuf, f32)], path: &str| results.iter().find(|(p, _)| p == Path::new(path)).map(|(_, score)| *score).unwrap();
+        assert_close(score(&plain, "a.txt"), score(&plain, "b.txt"));
+
+        let boosted = model.search_query_parsed(&QueryParser::parse("rust^2 OR python^0.5").unwrap()).unwrap();
+        assert_eq!(paths(&boosted), ["a.txt", "b.txt"]);
+        assert_close(score(&boosted, "a.txt"), 2.0 * score(&plain, "a.txt"));
+        assert_close(score(&boosted, "b.txt"), 0.5 * score(&plain, "b.txt"));
+
+        // A boost of 1 is the same as none
+        let unboosted = model.search_query_parsed(&QueryParser::parse("rust^1 python^1").unwrap()).unwrap();
+        for (path, score_of) in &plain {
+            assert_close(score(&unboosted, &path.display().to_string()), *score_of);
+        }
+    }
+
+    #[test]
+    fn documents_containing_an_excluded_term_do_not_match() {
+        let model = model_of(&[("a.txt", "python snake python"), ("b.txt", "python code"), ("c.txt", "snake"), ("d.txt", "other")]);
+        assert_eq!(paths(&model.search_query(&chars("python"), 0, 10).unwrap().results), ["a.txt", "b.txt"]);
+        let search = |query: &str| paths(&model.search_query_parsed(&QueryParser::parse(query).unwrap()).unwrap());
+        assert_eq!(search("python -snake"), ["b.txt"]);
+        assert_eq!(search("python -SNAKE"), ["b.txt"]);
+        assert_eq!(search("python -missing"), ["a.txt", "b.txt"]);
+        // Excluding wins over searching for the same term
+        assert!(search("python -python").is_empty());
+        assert!(search("python code -python").is_empty());
+        assert!(search("-snake").is_empty());
+    }
+
+    #[test]
+    fn documents_with_an_excluded_term_in_the_title_only_do_not_match() {
+        let docs = [
+            ("snake.txt", "Snake care", "feeding a python"),
+            ("code.txt", "Scripting", "python code"),
+            ("other.txt", "Gardening", "tomatoes"),
+        ];
+        let model = model_with_titles(InMemoryModel::builder(), &docs);
+        assert!(!model.tfpd[Path::new("snake.txt")].tf.contains_key("SNAKE"));
+        let search = |query: &str| paths(&model.search_query_parsed(&QueryParser::parse(query).unwrap()).unwrap());
+        assert_eq!(search("python -snake"), ["code.txt"]);
+        assert_eq!(search("python -scripting"), ["snake.txt"]);
+    }
+
+    #[test]
+    fn documents_must_contain_at_least_min_should_match_of_the_terms() {
+        let model = model_of(&[("a.txt", "rust go zig"), ("b.txt", "rust go"), ("c.txt", "rust"), ("d.txt", "other")]);
+        let search = |min_should_match: usize| {
+            let query = ParsedQuery { min_should_match, ..QueryParser::parse("rust go zig").unwrap() };
+            let mut results = paths(&model.search_query_parsed(&query).unwrap());
+            results.sort();
+            results
+        };
+        assert_eq!(search(1), ["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(search(2), ["a.txt", "b.txt"]);
+        // As many as there are terms is AND
+        assert_eq!(search(3), ["a.txt"]);
+        assert!(search(4).is_empty());
+        assert!(search(usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn words_of_the_query_in_the_title_count_towards_min_should_match() {
+        let docs = [
+            ("title.txt", "Rust and Go", "zig"),
+            ("body.txt", "Languages", "rust go zig"),
+            ("one.txt", "Languages", "rust"),
+            ("other.txt", "Gardening", "tomatoes"),
+        ];
+        let model = model_with_titles(InMemoryModel::builder(), &docs);
+        let search = |min_should_match: usize| {
+            let query = ParsedQuery { min_should_match, ..QueryParser::parse("rust go zig").unwrap() };
+            let mut results = paths(&model.search_query_parsed(&query).unwrap());
+            results.sort();
+            results
+        };
+        assert_eq!(search(2), ["body.txt", "title.txt"]);
+        // Every word, which intersects the posting lists instead
+        assert_eq!(search(3), ["body.txt", "title.txt"]);
+    }
+
+    #[test]
+    fn mmr_keeps_near_duplicates_out_of_the_top_results() {
+        let model = model_of(&[
+            ("dup1.txt", "rust rust rust async tokio"),
+            ("dup2.txt", "rust rust rust async tokio"),
+            ("go.txt", "rust go"),
+            ("nim.txt", "rust nim"),
+            ("odin.txt", "rust odin"),
+            ("zig.txt", "rust zig"),
+            ("c.txt", "rust c"),
+            ("other.txt", "nothing here"),
+        ]);
+        let results = model.search_query(&chars("rust"), 0, 10).unwrap().results;
+        assert_eq!(paths(&results[..2]), ["dup1.txt", "dup2.txt"]);
+
+        let reranked = mmr_rerank(&results, &model, 0.7, 5);
+        assert_eq!(reranked.len(), 5);
+        assert_eq!(paths(&reranked[..1]), ["dup1.txt"]);
+        assert!(!paths(&reranked).contains(&"dup2.txt".to_string()), "{:?}", paths(&reranked));
+        // The results keep their scores
+        assert!(reranked.iter().all(|result| results.contains(result)));
+
+        // With a lambda of 1 only relevance counts
+        assert_eq!(mmr_rerank(&results, &model, 1.0, 5), results[..5]);
+        assert_eq!(mmr_rerank(&results, &model, 0.7, 100).len(), results.len());
+        assert!(mmr_rerank(&[], &model, 0.7, 5).is_empty());
+    }
+
+    // CAR is only in three documents, all of which also have AUTOMOBILE, which is in three more
+    const CAR_DOCUMENTS: [(&str, &str); 10] = [
+        ("a.txt", "car automobile automobile engine"),
+        ("b.txt", "car automobile automobile wheels"),
+        ("c.txt", "car automobile automobile brakes"),
+        ("d.txt", "automobile engine road"),
+        ("e.txt", "automobile wheels highway"),
+        ("f.txt", "automobile brakes garage"),
+        ("g.txt", "cooking recipe pasta"),
+        ("h.txt", "garden flowers soil"),
+        ("i.txt", "music guitar song"),
+        ("j.txt", "ocean waves sand"),
+    ];
+
+    #[test]
+    fn rocchio_expansion_adds_the_term_that_co_occurs_with_the_query() {
+        let model = model_of(&CAR_DOCUMENTS);
+        let car = |expand_terms: usize| model.expand_query_rocchio(&chars("car"), 3, ROCCHIO_ALPHA, ROCCHIO_BETA, ROCCHIO_GAMMA, expand_terms);
+        let expanded = car(1);
+        assert_eq!(expanded.iter().map(|(term, _)| term.as_str()).collect::<Vec<_>>(), ["CAR", "AUTOMOBILE"]);
+        assert!(expanded.iter().all(|(_, weight)| *weight > 0f32));
+        assert_eq!(car(0).len(), 1);
+
+        let expansion = model.expand_query(&chars("car"), 3, 1).unwrap();
+        assert_eq!(expansion.original_terms.iter().map(|(term, _)| term.as_str()).collect::<Vec<_>>(), ["CAR"]);
+        assert_eq!(expansion.expanded_terms.iter().map(|(term, _)| term.as_str()).collect::<Vec<_>>(), ["AUTOMOBILE"]);
+
+        // The expanded query finds the documents with the synonym only
+        assert_eq!(model.search_query(&chars("car"), 0, 10).unwrap().results.len(), 3);
+        let mut found = paths(&model.search_weighted_terms(&expanded, RankingAlgorithm::TfIdf).unwrap());
+        found.sort();
+        assert_eq!(found, ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt", "f.txt"]);
+    }
+
+    // Pages of `limit` results, each after the encoded cursor of the one before, until there is no next one
+    fn pages_after(model: &InMemoryModel, query: &str, limit: usize) -> Vec<(PathBuf, f32)> {
+        let mut results = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = model.search_after(&chars(query), cursor, limit).unwrap();
+            assert!(page.results.len() <= limit);
+            results.extend(page.results.iter().cloned());
+            match page.next_cursor() {
+                Some(next) => cursor = Some(SearchCursor::decode(&next.encode()).expect("cursor decodes")),
+                None => return results,
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_pages_add_up_to_all_results() {
+        // Groups of documents with the same score, so that the path has to tell them apart
+        let model = model_of(&[
+            ("a.txt", "rust rust"), ("b.txt", "rust rust"), ("c.txt", "rust rust"),
+            ("d.txt", "rust go"), ("e.txt", "rust go"), ("f.txt", "rust zig"),
+            ("g.txt", "rust go zig"), ("h.txt", "rust go zig odin"), ("i.txt", "rust go zig odin"),
+            ("j.txt", "rust go zig odin nim"), ("k.txt", "nothing here"),
+        ]);
+        let mut all = model.search_query(&chars("rust"), 0, usize::MAX).unwrap().results;
+        sort_for_cursors(&mut all);
+        assert_eq!(all.len(), 10);
+        for limit in 1..=11 {
+            assert_eq!(pages_after(&model, "rust", limit), all, "{limit}");
+        }
+        assert!(pages_after(&model, "missing", 3).is_empty());
+
+        let first = model.search_after(&chars("rust"), None, 4).unwrap();
+        assert_eq!(first.total_hits, 10);
+        assert!(first.has_more);
+        let last = model.search_after(&chars("rust"), Some(SearchCursor { score: all[9].1, path: all[9].0.clone() }), 4).unwrap();
+        assert!(last.results.is_empty() && !last.has_more && last.next_cursor().is_none());
+    }
+
+    #[test]
+    fn cursors_that_were_not_handed_out_do_not_decode() {
+        let cursor = SearchCursor { score: 0.25, path: PathBuf::from("docs/a b.txt") };
+        assert_eq!(SearchCursor::decode(&cursor.encode()), Some(cursor));
+        for garbage in ["", "not base64!", &URL_SAFE_NO_PAD.encode("[1.0]"), &URL_SAFE_NO_PAD.encode("{}"), &URL_SAFE_NO_PAD.encode("[null, \"a.txt\"]")] {
+            assert_eq!(SearchCursor::decode(garbage), None, "{garbage}");
+        }
+    }
+
+    #[test]
+    fn proximity_score_falls_with_the_gap_in_characters() {
+        assert_close(proximity_score("RUST", "GO", &[3], &[8], 0.1), (-0.5f32).exp());
+        assert_close(proximity_score("RUST", "GO", &[8], &[3], 0.1), (-0.5f32).exp());
+        assert_close(proximity_score("RUST", "GO", &[0, 50], &[5, 60], 0.1), (-0.5f32).exp() + (-1f32).exp() + (-4.5f32).exp() + (-6f32).exp());
+        assert_eq!(proximity_score("RUST", "GO", &[0], &[1000], 0.1), 0f32);
+        assert_eq!(proximity_score("RUST", "GO", &[], &[1], 0.1), 0f32);
+        // The same term twice in a row is not close to itself
+        assert_eq!(proximity_score("RUST", "RUST", &[0, 5], &[0, 5], 0.1), 0f32);
+    }
+
+    #[test]
+    fn co_located_terms_outrank_terms_at_opposite_ends() {
+        let filler = vec!["filler"; 50].join(" ");
+        let documents = [
+            ("far.txt", format!("alpha {filler} beta")),
+            ("near.txt", format!("alpha beta {filler}")),
+            ("other.txt", "nothing in common".to_string()),
+        ];
+        let query = chars("alpha beta");
+        let ranked = |proximity_weight: f32| {
+            let mut model = InMemoryModel::builder().proximity_weight(proximity_weight).build();
+            for (path, content) in &documents {
+                model.add_document(PathBuf::from(path), &chars(content), None).unwrap();
+            }
+            model.finalize();
+            model.search_query_ranked(&query, RankingAlgorithm::TfIdf).unwrap()
+        };
+
+        // Same terms as often in documents as long, nothing to tell them apart without proximity
+        let independent = ranked(0f32);
+        assert_eq!(independent.len(), 2);
+        assert_eq!(independent[0].1, independent[1].1);
+
+        let proximity = ranked(1f32);
+        assert_eq!(paths(&proximity), ["near.txt", "far.txt"]);
+        assert!(proximity[0].1 > proximity[1].1);
+        // BETA starts 6 characters after ALPHA
+        assert_close(proximity[0].1 - independent[0].1, (-0.6f32).exp());
+        assert_eq!(proximity[1].1, independent[1].1);
+    }
+
+    #[test]
+    fn equal_scores_are_ranked_by_path() {
+        let model = model_of(&[
+            ("d.txt", "rust web"), ("b.txt", "rust web"), ("c.txt", "rust"), ("a.txt", "web rust"), ("e.txt", "go"),
+        ]);
+        let query = chars("web rust");
+        let results = model.search_query(&query, 0, 10).unwrap().results;
+        assert_eq!(paths(&results), ["a.txt", "b.txt", "d.txt", "c.txt"]);
+        assert_eq!(results[0].1, results[2].1);
+    }
+
+    #[test]
+    fn search_topk_ranks_like_search_query_with_equal_scores() {
+        // Many documents with the same terms as often as each other, in every order
+        let words = ["rust", "web", "go", "zig"];
+        let documents = (0..60).map(|i| {
+            let content = (0..=i % 5).map(|j| words[(i + j) % words.len()]).collect::<Vec<_>>().join(" ");
+            (format!("{:02}.txt", (i * 37) % 60), content)
+        }).collect::<Vec<_>>();
+        let model = model_of(&documents.iter().map(|(path, content)| (path.as_str(), content.as_str())).collect::<Vec<_>>());
+
+        for query in ["rust", "rust web", "web rust", "rust web go", "zig go web rust", "rust unknown"] {
+            let query = chars(query);
+            let all = model.search_query(&query, 0, usize::MAX).unwrap().results;
+            assert!(all.windows(2).any(|pair| pair[0].1 == pair[1].1), "no equal scores for {query:?}");
+            for k in [1, 2, 5, 10, 30, all.len(), all.len() + 1] {
+                assert_eq!(model.search_topk(&query, k).unwrap(), all[..k.min(all.len())], "top {k} of {query:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn wand_pages_count_every_matching_document() {
+        let documents = (0..40).map(|i| {
+            let content = match i % 4 {
+                0 => "rust web",
+                1 => "rust",
+                2 => "web go",
+                _ => "zig",
+            };
+            (format!("{i:02}.{}", ["txt", "md", "html"][i % 3]), content)
+        }).collect::<Vec<_>>();
+        let documents = documents.iter().map(|(path, content)| (path.as_str(), *content)).collect::<Vec<_>>();
+        let exhaustive = model_of(&documents);
+        let mut wand = InMemoryModel::builder().use_wand(true).build();
+        for (path, content) in &documents {
+            wand.add_document(PathBuf::from(path), &chars(content), None).unwrap();
+        }
+        wand.finalize();
+
+        for query in ["rust", "rust web", "go", "unknown"] {
+            let query = chars(query);
+            for (offset, limit) in [(0, 3), (5, 10), (25, 10), (100, 10)] {
+                let expected = exhaustive.search_query(&query, offset, limit).unwrap();
+                let page = wand.search_query(&query, offset, limit).unwrap();
+                assert_eq!(page.results, expected.results, "{query:?} from {offset}");
+                assert_eq!(page.total_hits, expected.total_hits, "{query:?} from {offset}");
+                assert_eq!(page.has_more, expected.has_more, "{query:?} from {offset}");
+                assert_eq!(page.facets, expected.facets, "{query:?} from {offset}");
+            }
+        }
+        assert_eq!(wand.search_query(&chars("rust web"), 0, 3).unwrap().total_hits, 30);
+    }
+}
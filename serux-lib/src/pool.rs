@@ -0,0 +1,50 @@
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// SQLite connections to the same database, each used by one thread at a time.
+/// Threads wait for a connection when all of them are in use.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    connections: Arc<Mutex<Vec<sqlite::Connection>>>,
+    returned: Arc<Condvar>,
+}
+
+impl ConnectionPool {
+    pub fn new(connections: Vec<sqlite::Connection>) -> Self {
+        assert!(!connections.is_empty(), "a connection pool needs at least one connection");
+        Self { connections: Arc::new(Mutex::new(connections)), returned: Arc::new(Condvar::new()) }
+    }
+
+    pub fn acquire(&self) -> PooledConnection {
+        let mut connections = self.connections.lock().unwrap_or_else(|err| err.into_inner());
+        loop {
+            if let Some(connection) = connections.pop() {
+                return PooledConnection { connection: Some(connection), pool: self.clone() };
+            }
+            connections = self.returned.wait(connections).unwrap_or_else(|err| err.into_inner());
+        }
+    }
+}
+
+/// Goes back to its pool when dropped
+pub struct PooledConnection {
+    connection: Option<sqlite::Connection>,
+    pool: ConnectionPool,
+}
+
+impl Deref for PooledConnection {
+    type Target = sqlite::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        self.connection.as_ref().expect("connection is only taken when dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.connections.lock().unwrap_or_else(|err| err.into_inner()).push(connection);
+            self.pool.returned.notify_one();
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+
+/// Boosts above this would drown out every other term of the query
+pub const MAX_BOOST: f32 = 100f32;
+
+/// Documents match a query of terms if they contain any of them
+pub const DEFAULT_MIN_SHOULD_MATCH: usize = 1;
+
+/// Boolean query, e.g. `rust AND (lang OR programming) NOT java`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+impl Query {
+    /// Terms that contribute to the relevance score, i.e. the ones that are not negated
+    pub fn positive_terms(&self) -> Vec<&str> {
+        match self {
+            Query::Term(term) => vec![term.as_str()],
+            Query::And(lhs, rhs) | Query::Or(lhs, rhs) => {
+                let mut terms = lhs.positive_terms();
+                terms.extend(rhs.positive_terms());
+                terms
+            }
+            Query::Not(_) => Vec::new(),
+        }
+    }
+}
+
+/// Query of terms weighted with `term^boost`, e.g. `rust^2.5 programming`, and of terms excluded
+/// with a leading minus, e.g. `python -snake`. A term without a boost has a boost of 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedQuery {
+    pub terms: Vec<(String, f32)>,
+    /// Documents containing any of these terms don't match
+    pub must_not: Vec<String>,
+    /// Documents must contain at least this many of `terms`; as many as there are means all of them
+    pub min_should_match: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryParseError {
+    EmptyQuery,
+    // An operator or an opening parenthesis is not followed by an operand
+    UnexpectedEnd,
+    UnexpectedToken { token: String, position: usize },
+    UnclosedParen { position: usize },
+    // The boost of a term is not a number above 0 and at most MAX_BOOST
+    InvalidBoost { boost: String, position: usize },
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryParseError::EmptyQuery => write!(f, "query is empty"),
+            QueryParseError::UnexpectedEnd => write!(f, "query ends where a term was expected"),
+            QueryParseError::UnexpectedToken { token, position } => write!(f, "unexpected `{token}` at position {position}"),
+            QueryParseError::UnclosedParen { position } => write!(f, "parenthesis opened at position {position} is never closed"),
+            QueryParseError::InvalidBoost { boost, position } => {
+                write!(f, "boost `{boost}` at position {position} is not a number above 0 and at most {MAX_BOOST}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+/// Recursive descent parser. Precedence from loosest to tightest: OR, AND, NOT.
+/// Two operands next to each other are joined with an implicit AND, so `a NOT b` means `a AND NOT b`.
+pub struct QueryParser {
+    // Every token together with its character position in the input
+    tokens: Vec<(Token, usize)>,
+    cursor: usize,
+}
+
+impl QueryParser {
+    fn new(input: &str) -> Self {
+        let chars = input.chars().collect::<Vec<_>>();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                c if c.is_whitespace() => i += 1,
+                '(' => {
+                    tokens.push((Token::LParen, i));
+                    i += 1;
+                }
+                ')' => {
+                    tokens.push((Token::RParen, i));
+                    i += 1;
+                }
+                _ => {
+                    let start = i;
+                    while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '(' && chars[i] != ')' {
+                        i += 1;
+                    }
+                    let word = chars[start..i].iter().collect::<String>();
+                    let token = match word.as_str() {
+                        "AND" => Token::And,
+                        "OR" => Token::Or,
+                        "NOT" => Token::Not,
+                        _ => Token::Word(word),
+                    };
+                    tokens.push((token, start));
+                }
+            }
+        }
+        Self { tokens, cursor: 0 }
+    }
+
+    /// Whether the input uses any boolean operator, which are only recognized in uppercase
+    pub fn is_boolean(input: &str) -> bool {
+        input.split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .any(|word| matches!(word, "AND" | "OR" | "NOT"))
+    }
+
+    /// Whether any word of the input has a boost, e.g. `rust^2`, or is excluded, e.g. `-snake`
+    pub fn is_weighted(input: &str) -> bool {
+        input.split(|c: char| c.is_whitespace() || c == '(' || c == ')').any(|word| {
+            word.rsplit_once('^').is_some_and(|(term, _)| !term.is_empty()) || word.len() > 1 && word.starts_with('-')
+        })
+    }
+
+    /// Parse a query of weighted and excluded terms. Terms are alternatives anyway, so OR may separate them,
+    /// but boosts and exclusions can't be combined with the other boolean operators.
+    pub fn parse(input: &str) -> Result<ParsedQuery, QueryParseError> {
+        let parser = Self::new(input);
+        let mut terms = Vec::new();
+        let mut must_not = Vec::new();
+        for (token, position) in &parser.tokens {
+            match token {
+                Token::Or => {}
+                // An excluded term has nothing to boost
+                Token::Word(word) if word.len() > 1 && word.starts_with('-') && !word.contains('^') => must_not.push(word[1..].to_string()),
+                Token::Word(word) => match word.rsplit_once('^') {
+                    Some((term, boost)) if !term.is_empty() && !term.starts_with('-') => match boost.parse::<f32>() {
+                        Ok(boost_value) if boost_value > 0f32 && boost_value <= MAX_BOOST => terms.push((term.to_string(), boost_value)),
+                        _ => return Err(QueryParseError::InvalidBoost { boost: boost.to_string(), position: position + term.chars().count() + 1 }),
+                    },
+                    Some(_) => return Err(parser.unexpected(token, *position)),
+                    None if word.starts_with('-') => return Err(parser.unexpected(token, *position)),
+                    None => terms.push((word.clone(), 1f32)),
+                },
+                token => return Err(parser.unexpected(token, *position)),
+            }
+        }
+        if terms.is_empty() && must_not.is_empty() {
+            return Err(QueryParseError::EmptyQuery);
+        }
+        Ok(ParsedQuery { terms, must_not, min_should_match: DEFAULT_MIN_SHOULD_MATCH })
+    }
+
+    pub fn parse_boolean(input: &str) -> Result<Query, QueryParseError> {
+        let mut parser = Self::new(input);
+        if parser.tokens.is_empty() {
+            return Err(QueryParseError::EmptyQuery);
+        }
+        let query = parser.parse_or()?;
+        match parser.tokens.get(parser.cursor) {
+            Some((token, position)) => Err(parser.unexpected(token, *position)),
+            None => Ok(query),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor).map(|(token, _)| token)
+    }
+
+    fn unexpected(&self, token: &Token, position: usize) -> QueryParseError {
+        let token = match token {
+            Token::Word(word) => word.clone(),
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
+            Token::LParen => "(".to_string(),
+            Token::RParen => ")".to_string(),
+        };
+        QueryParseError::UnexpectedToken { token, position }
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryParseError> {
+        let mut query = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.cursor += 1;
+            query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+        }
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryParseError> {
+        let mut query = self.parse_not()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.cursor += 1,
+                Some(Token::Word(_) | Token::Not | Token::LParen) => {}
+                _ => return Ok(query),
+            }
+            query = Query::And(Box::new(query), Box::new(self.parse_not()?));
+        }
+    }
+
+    fn parse_not(&mut self) -> Result<Query, QueryParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.cursor += 1;
+            return Ok(Query::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, QueryParseError> {
+        let Some((token, position)) = self.tokens.get(self.cursor).cloned() else {
+            return Err(QueryParseError::UnexpectedEnd);
+        };
+        self.cursor += 1;
+        match token {
+            Token::Word(word) => Ok(Query::Term(word)),
+            Token::LParen => {
+                let query = self.parse_or()?;
+                match self.tokens.get(self.cursor) {
+                    Some((Token::RParen, _)) => {
+                        self.cursor += 1;
+                        Ok(query)
+                    }
+                    Some((token, position)) => Err(self.unexpected(token, *position)),
+                    None => Err(QueryParseError::UnclosedParen { position }),
+                }
+            }
+            token => Err(self.unexpected(&token, position)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(query: &ParsedQuery) -> Vec<(&str, f32)> {
+        query.terms.iter().map(|(term, boost)| (term.as_str(), *boost)).collect()
+    }
+
+    #[test]
+    fn boosts_follow_a_caret() {
+        let query = QueryParser::parse("rust^2 OR python^0.5").unwrap();
+        assert_eq!(terms(&query), [("rust", 2f32), ("python", 0.5)]);
+        assert!(query.must_not.is_empty());
+        assert_eq!(query.min_should_match, DEFAULT_MIN_SHOULD_MATCH);
+
+        assert_eq!(terms(&QueryParser::parse("rust^2.5 programming").unwrap()), [("rust", 2.5f32), ("programming", 1.0)]);
+        assert_eq!(QueryParser::parse("rust^1").unwrap(), QueryParser::parse("rust").unwrap());
+        assert_eq!(terms(&QueryParser::parse("rust^100").unwrap()), [("rust", MAX_BOOST)]);
+    }
+
+    #[test]
+    fn boosts_must_be_above_0_and_at_most_the_maximum() {
+        for (input, boost) in [("rust^0", "0"), ("rust^-1", "-1"), ("rust^100.5", "100.5"), ("rust^abc", "abc"), ("rust^", ""), ("rust^NaN", "NaN")] {
+            assert_eq!(QueryParser::parse(input), Err(QueryParseError::InvalidBoost { boost: boost.to_string(), position: 5 }), "{input}");
+        }
+        // The position is the one of the boost, in characters
+        assert_eq!(QueryParser::parse("café rust^0"), Err(QueryParseError::InvalidBoost { boost: "0".to_string(), position: 10 }));
+        assert!(QueryParser::is_weighted("rust^2 programming"));
+        assert!(!QueryParser::is_weighted("rust programming"));
+    }
+
+    #[test]
+    fn boosts_are_not_combined_with_boolean_operators() {
+        assert!(matches!(QueryParser::parse("rust^2 AND python"), Err(QueryParseError::UnexpectedToken { position: 7, .. })));
+        assert!(matches!(QueryParser::parse("^2"), Err(QueryParseError::UnexpectedToken { .. })));
+        assert_eq!(QueryParser::parse("   "), Err(QueryParseError::EmptyQuery));
+    }
+
+    #[test]
+    fn a_leading_minus_excludes_a_term() {
+        let query = QueryParser::parse("python -snake -reptile").unwrap();
+        assert_eq!(terms(&query), [("python", 1f32)]);
+        assert_eq!(query.must_not, ["snake", "reptile"]);
+        assert!(QueryParser::is_weighted("python -snake"));
+        // A lone minus or one inside a word excludes nothing
+        assert!(!QueryParser::is_weighted("python - snake"));
+        assert!(!QueryParser::is_weighted("sous-chef"));
+        // An excluded term has nothing to boost
+        assert!(matches!(QueryParser::parse("python -snake^2"), Err(QueryParseError::UnexpectedToken { position: 7, .. })));
+    }
+}
@@ -0,0 +1,224 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::model::{InMemoryModel, ModelConfig, SearchError};
+
+/// Version of the layout InMemoryModel is saved in. Indexes saved before there were versions are version 0.
+/// Bump it only when older JSON indexes can't be read as they are, and add the migration from the previous one.
+/// New fields with a `#[serde(default)]` don't need one.
+pub const SCHEMA_VERSION: u32 = 1;
+
+type Migration = fn(Value) -> Result<Value, SearchError>;
+
+// MIGRATIONS[n] turns a JSON index of version n into one of version n + 1
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [migrate_v0_to_v1];
+
+#[derive(Deserialize)]
+struct Versioned {
+    #[serde(default)]
+    schema_version: u32,
+}
+
+/// Version of a JSON index, read without building the model
+pub fn schema_version(json: &[u8]) -> Result<u32, SearchError> {
+    Ok(serde_json::from_slice::<Versioned>(json)?.schema_version)
+}
+
+pub fn check_schema_version(version: u32) -> Result<(), SearchError> {
+    if version > SCHEMA_VERSION {
+        return Err(SearchError::SchemaVersionTooNew { found: version, supported: SCHEMA_VERSION });
+    }
+    Ok(())
+}
+
+/// Parse a JSON index of any version. Current indexes are parsed straight into the model,
+/// older ones go through a serde_json::Value that the migrations bring up to date.
+pub fn model_from_json(json: &[u8]) -> Result<InMemoryModel, SearchError> {
+    let version = schema_version(json)?;
+    if version == SCHEMA_VERSION {
+        return Ok(serde_json::from_slice(json)?);
+    }
+    let index = migrate(serde_json::from_slice(json)?, version)?;
+    Ok(serde_json::from_value(index)?)
+}
+
+/// Apply the migrations from `version` on, one after the other
+pub fn migrate(mut index: Value, version: u32) -> Result<Value, SearchError> {
+    check_schema_version(version)?;
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        index = migration(index)?;
+        index_object(&mut index)?.insert("schema_version".to_string(), Value::from(from as u32 + 1));
+    }
+    Ok(index)
+}
+
+fn index_object(index: &mut Value) -> Result<&mut Map<String, Value>, SearchError> {
+    index.as_object_mut().ok_or_else(|| SearchError::IndexCorrupted("the index is not a JSON object".to_string()))
+}
+
+// Before version 1:
+// - documents saved before they had metadata are `[term_count, tf]` instead of an object
+// - titles were kept in a map of their own, `titles`, next to the documents
+// - documents saved before max_term_freq was kept don't have it
+// - `stemming` and `stop_words` were fields of the index itself, before there was a `config`
+fn migrate_v0_to_v1(mut index: Value) -> Result<Value, SearchError> {
+    let index_map = index_object(&mut index)?;
+    let stemming = index_map.remove("stemming");
+    let stop_words = index_map.remove("stop_words");
+    if stemming.is_some() || stop_words.is_some() {
+        let config = match index_map.remove("config") {
+            Some(config) => config,
+            None => serde_json::to_value(ModelConfig::default())?,
+        };
+        let Value::Object(mut config) = config else {
+            return Err(SearchError::IndexCorrupted("the config is not an object".to_string()));
+        };
+        config.extend(stemming.map(|stemming| ("stemming".to_string(), stemming)));
+        config.extend(stop_words.map(|stop_words| ("stop_words".to_string(), stop_words)));
+        index_map.insert("config".to_string(), Value::Object(config));
+    }
+
+    let titles = match index_map.remove("titles") {
+        Some(Value::Object(titles)) => titles,
+        _ => Map::new(),
+    };
+    let Some(Value::Object(tfpd)) = index_map.get_mut("tfpd") else {
+        return Err(SearchError::IndexCorrupted("the index has no documents".to_string()));
+    };
+
+    for (path, entry) in tfpd.iter_mut() {
+        if let Value::Array(fields) = entry {
+            let [term_count, tf] = <[Value; 2]>::try_from(std::mem::take(fields)).map_err(|_| {
+                SearchError::IndexCorrupted(format!("the entry of {path} is not [term_count, tf]"))
+            })?;
+            *entry = Value::Object(Map::from_iter([("term_count".to_string(), term_count), ("tf".to_string(), tf)]));
+        }
+        let Value::Object(entry) = entry else {
+            return Err(SearchError::IndexCorrupted(format!("the entry of {path} is not an object")));
+        };
+
+        if let Some(title) = titles.get(path) {
+            if entry.get("title").is_none_or(Value::is_null) {
+                entry.insert("title".to_string(), title.clone());
+            }
+        }
+
+        if !entry.contains_key("max_term_freq") {
+            // Every term maps to [count, positions]
+            let max_term_freq = entry.get("tf").and_then(Value::as_object).into_iter()
+                .flat_map(|tf| tf.values())
+                .filter_map(|freq| freq.get(0).and_then(Value::as_u64))
+                .max()
+                .unwrap_or(0);
+            entry.insert("max_term_freq".to_string(), Value::from(max_term_freq));
+        }
+    }
+
+    Ok(index)
+}
+
+// Fields added since version 1, which indexes saved before them are read without:
+// - `idf_cache` and `term_filter`, computed when they are missing
+// - `term_positions`; documents without them have their snippets found by reading them again
+// - `fields` of the title and the metadata; documents without them rank by their body as before
+// - `last_modified`, so older documents are not boosted by freshness
+// - `content_hash`; documents without one are indexed again whenever they are modified, as before
+// - the `hyphen_compounds`, `tokenize_urls`, `min_token_len`, `max_token_len`, `field_weights`, `cjk_ngram`,
+//   `freshness_boost`, `freshness_half_life_days`, `mmr`, `mmr_lambda`, `proximity_weight` and `use_wand` settings
+//   of the config, which take the value older indexes were built with
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn version_0_settings_move_into_the_config() {
+        let index = br#"{
+            "tfpd": {"a.txt": [2, {"RUST": [1, [0]], "CRAB": [1, [1]]}]},
+            "df": {"RUST": 1, "CRAB": 1},
+            "stemming": true,
+            "stop_words": ["THE", "OF"],
+            "titles": {"a.txt": "Rust"}
+        }"#;
+        let model = model_from_json(index).unwrap();
+        assert!(model.config.stemming);
+        assert_eq!(model.config.stop_words, HashSet::from(["THE".to_string(), "OF".to_string()]));
+        assert_eq!(model.config.ranking, ModelConfig::default().ranking);
+
+        let document = &model.tfpd[Path::new("a.txt")];
+        assert_eq!(document.term_count, 2);
+        assert_eq!(document.max_term_freq, 1);
+        assert_eq!(document.title.as_deref(), Some("Rust"));
+    }
+
+    #[test]
+    fn version_0_index_without_settings_gets_the_default_config() {
+        let index = br#"{"tfpd": {"a.txt": [1, {"RUST": [1, [0]]}]}, "df": {"RUST": 1}}"#;
+        let model = model_from_json(index).unwrap();
+        assert_eq!(model.config, ModelConfig::default());
+    }
+
+    #[test]
+    fn documents_saved_without_metadata_have_none() {
+        let index = br#"{
+            "schema_version": 1,
+            "tfpd": {"a.txt": {"term_count": 1, "tf": {"RUST": [1, [0]]}}},
+            "df": {"RUST": 1}
+        }"#;
+        let model = model_from_json(index).unwrap();
+        let document = &model.tfpd[Path::new("a.txt")];
+        assert_eq!(document.title, None);
+        assert_eq!(document.indexed_at, 0);
+        assert_eq!(document.last_modified, None);
+        assert_eq!(document.file_size_bytes, 0);
+        assert_eq!(document.content_hash, None);
+    }
+
+    #[test]
+    fn migrations_stamp_the_current_version() {
+        let index = serde_json::json!({"tfpd": {"a.txt": [1, {"RUST": [1, [0]]}]}, "df": {"RUST": 1}});
+        let index = migrate(index, 0).unwrap();
+        assert_eq!(index["schema_version"], SCHEMA_VERSION);
+        assert_eq!(index["tfpd"]["a.txt"], serde_json::json!({"term_count": 1, "tf": {"RUST": [1, [0]]}, "max_term_freq": 1}));
+    }
+
+    #[test]
+    fn version_0_entries_that_are_not_term_count_and_tf_are_corrupted() {
+        for index in [
+            br#"{"tfpd": {"a.txt": [1]}, "df": {}}"#.as_slice(),
+            br#"{"tfpd": {"a.txt": 1}, "df": {}}"#,
+            br#"{"df": {}}"#,
+            br#"[]"#,
+        ] {
+            assert!(matches!(model_from_json(index), Err(SearchError::IndexCorrupted(_))), "{}", String::from_utf8_lossy(index));
+        }
+    }
+
+    #[test]
+    fn version_1_index_reads_the_fields_added_since_with_their_defaults() {
+        let index = serde_json::json!({
+            "schema_version": 1,
+            "tfpd": {"a.txt": {"term_count": 2, "tf": {"RUST": [2, [0, 1]]}, "max_term_freq": 2}},
+            "df": {"RUST": 1},
+            "config": {"stemming": true, "stop_words": [], "ranking": ModelConfig::default().ranking, "max_tokens_per_doc": null, "ngram_size": null}
+        });
+        let model = model_from_json(&serde_json::to_vec(&index).unwrap()).unwrap();
+        assert_eq!(model.config, ModelConfig { stemming: true, ..ModelConfig::default() });
+        let document = &model.tfpd[Path::new("a.txt")];
+        assert!(document.term_positions.is_empty());
+        assert!(document.fields.title.is_empty() && document.fields.metadata.is_empty());
+        assert_eq!(document.content_hash, None);
+    }
+
+    #[test]
+    fn indexes_of_newer_versions_are_rejected() {
+        let index = format!(r#"{{"schema_version": {}, "tfpd": {{}}, "df": {{}}}}"#, SCHEMA_VERSION + 1);
+        assert!(matches!(
+            model_from_json(index.as_bytes()),
+            Err(SearchError::SchemaVersionTooNew { found, supported: SCHEMA_VERSION }) if found == SCHEMA_VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::Serialize;
+
+use super::model::{DocumentEntry, TermFreqPerDoc};
+
+pub const TOP_TERMS: usize = 20;
+const HISTOGRAM_BUCKETS: usize = 10;
+const HISTOGRAM_WIDTH: usize = 60;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TermCount {
+    pub term: String,
+    pub count: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DocumentLength {
+    pub path: PathBuf,
+    pub length: usize,
+}
+
+/// Documents with at least `min` and at most `max` tokens
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LengthBucket {
+    pub min: usize,
+    pub max: usize,
+    pub count: usize,
+}
+
+/// What the corpus of an index is made of, as printed by `serux stats`.
+/// Lengths are counted in tokens, after stop words are dropped.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CorpusStats {
+    pub document_count: usize,
+    pub unique_terms: usize,
+    /// Terms occurring in the most documents
+    pub top_terms_by_document_frequency: Vec<TermCount>,
+    /// Terms occurring most often across all documents
+    pub top_terms_by_frequency: Vec<TermCount>,
+    pub average_length: f32,
+    pub median_length: f32,
+    pub shortest: Option<DocumentLength>,
+    pub longest: Option<DocumentLength>,
+    pub length_histogram: Vec<LengthBucket>,
+    /// Size of the index file, if it was read from one
+    pub index_size_bytes: Option<u64>,
+}
+
+pub fn corpus_stats(tfpd: &TermFreqPerDoc) -> CorpusStats {
+    // Document frequency and raw frequency of every term, and the length of every document
+    let mut terms = HashMap::<&str, (usize, usize)>::new();
+    let mut lengths = Vec::with_capacity(tfpd.len());
+    for (path, DocumentEntry { term_count: n, tf, .. }) in tfpd {
+        for (term, (freq, _)) in tf {
+            let (df, total) = terms.entry(term).or_default();
+            *df += 1;
+            *total += freq;
+        }
+        lengths.push((path.clone(), *n));
+    }
+
+    corpus_stats_of(
+        terms.len(),
+        top_terms(&terms, |(df, _)| df),
+        top_terms(&terms, |(_, total)| total),
+        lengths,
+    )
+}
+
+/// Statistics of a corpus whose terms were already counted, e.g. by the queries of a SQLite index,
+/// from the length of every document
+pub fn corpus_stats_of(
+    unique_terms: usize,
+    top_terms_by_document_frequency: Vec<TermCount>,
+    top_terms_by_frequency: Vec<TermCount>,
+    mut lengths: Vec<(PathBuf, usize)>,
+) -> CorpusStats {
+    lengths.sort_by(|(path_a, n_a), (path_b, n_b)| n_a.cmp(n_b).then(path_a.cmp(path_b)));
+
+    let document_count = lengths.len();
+    let shortest = lengths.first().map(|(path, length)| DocumentLength { path: path.clone(), length: *length });
+    let longest = lengths.last().map(|(path, length)| DocumentLength { path: path.clone(), length: *length });
+    let lengths = lengths.into_iter().map(|(_, n)| n).collect::<Vec<_>>();
+
+    CorpusStats {
+        document_count,
+        unique_terms,
+        top_terms_by_document_frequency,
+        top_terms_by_frequency,
+        average_length: average(&lengths),
+        median_length: median(&lengths),
+        shortest,
+        longest,
+        length_histogram: histogram(&lengths, HISTOGRAM_BUCKETS),
+        index_size_bytes: None,
+    }
+}
+
+// The TOP_TERMS terms with the highest count, ties broken alphabetically so that the output is stable
+fn top_terms(terms: &HashMap<&str, (usize, usize)>, count: impl Fn((usize, usize)) -> usize) -> Vec<TermCount> {
+    let mut top = terms.iter()
+        .map(|(term, counts)| TermCount { term: term.to_string(), count: count(*counts) })
+        .collect::<Vec<_>>();
+    top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    top.truncate(TOP_TERMS);
+    top
+}
+
+fn average(lengths: &[usize]) -> f32 {
+    if lengths.is_empty() {
+        return 0f32;
+    }
+    lengths.iter().sum::<usize>() as f32 / lengths.len() as f32
+}
+
+// `lengths` must be sorted
+fn median(lengths: &[usize]) -> f32 {
+    match lengths.len() {
+        0 => 0f32,
+        len if len % 2 == 1 => lengths[len / 2] as f32,
+        len => (lengths[len / 2 - 1] + lengths[len / 2]) as f32 / 2f32,
+    }
+}
+
+// Split the range from the shortest to the longest of the sorted `lengths` into `buckets` ranges of equal width.
+// There are fewer buckets if the range is narrower than `buckets` tokens.
+fn histogram(lengths: &[usize], buckets: usize) -> Vec<LengthBucket> {
+    let (Some(&min), Some(&max)) = (lengths.first(), lengths.last()) else {
+        return Vec::new();
+    };
+    let width = (max - min + 1).div_ceil(buckets);
+    let mut histogram = (min..=max).step_by(width)
+        .map(|start| LengthBucket { min: start, max: (start + width - 1).min(max), count: 0 })
+        .collect::<Vec<_>>();
+    for length in lengths {
+        histogram[(length - min) / width].count += 1;
+    }
+    histogram
+}
+
+#[doc(hidden)]
+pub fn print_corpus_stats(stats: &CorpusStats) {
+    println!("Documents:      {}", stats.document_count);
+    println!("Unique terms:   {}", stats.unique_terms);
+    if let Some(index_size_bytes) = stats.index_size_bytes {
+        println!("Index size:     {index_size_bytes} bytes");
+    }
+    println!("Average length: {:.1} tokens", stats.average_length);
+    println!("Median length:  {:.1} tokens", stats.median_length);
+    if let Some(DocumentLength { path, length }) = &stats.shortest {
+        println!("Shortest:       {path} ({length} tokens)", path = path.display());
+    }
+    if let Some(DocumentLength { path, length }) = &stats.longest {
+        println!("Longest:        {path} ({length} tokens)", path = path.display());
+    }
+
+    println!();
+    println!("Top terms by document frequency:");
+    print_term_counts(&stats.top_terms_by_document_frequency);
+    println!();
+    println!("Top terms by frequency:");
+    print_term_counts(&stats.top_terms_by_frequency);
+
+    println!();
+    println!("Document lengths:");
+    let largest = stats.length_histogram.iter().map(|bucket| bucket.count).max().unwrap_or(0);
+    let label_width = stats.length_histogram.iter()
+        .map(|bucket| format!("{}-{}", bucket.min, bucket.max).len())
+        .max()
+        .unwrap_or(0);
+    for bucket in &stats.length_histogram {
+        let bar = "#".repeat(bucket.count * HISTOGRAM_WIDTH / largest.max(1));
+        let label = format!("{}-{}", bucket.min, bucket.max);
+        println!("    {label:>label_width$} | {bar} {count}", count = bucket.count);
+    }
+}
+
+fn print_term_counts(terms: &[TermCount]) {
+    let term_width = terms.iter().map(|term| term.term.chars().count()).max().unwrap_or(0);
+    for TermCount { term, count } in terms {
+        println!("    {term:<term_width$} {count}");
+    }
+}
@@ -0,0 +1,323 @@
+// The Porter stemming algorithm
+// M.F. Porter, 1980, An algorithm for suffix stripping, Program, 14(3) pp 130-137
+//
+// A word is seen as [C](VC){m}[V] where C is a run of consonants and V a run of vowels;
+// m is the "measure" of the stem and guards most of the rules below.
+
+struct Stemmer {
+    b: Vec<u8>,
+}
+
+impl Stemmer {
+    fn is_consonant(&self, i: usize) -> bool {
+        match self.b[i] {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => i == 0 || !self.is_consonant(i - 1),
+            _ => true,
+        }
+    }
+
+    // m() of the first `len` letters
+    fn measure(&self, len: usize) -> usize {
+        let mut i = 0;
+        while i < len && self.is_consonant(i) {
+            i += 1;
+        }
+        let mut m = 0;
+        loop {
+            while i < len && !self.is_consonant(i) {
+                i += 1;
+            }
+            if i >= len {
+                return m;
+            }
+            while i < len && self.is_consonant(i) {
+                i += 1;
+            }
+            m += 1;
+        }
+    }
+
+    // *v* - the first `len` letters contain a vowel
+    fn contains_vowel(&self, len: usize) -> bool {
+        (0..len).any(|i| !self.is_consonant(i))
+    }
+
+    // *d - the first `len` letters end with a double consonant
+    fn ends_double_consonant(&self, len: usize) -> bool {
+        len >= 2 && self.b[len - 1] == self.b[len - 2] && self.is_consonant(len - 1)
+    }
+
+    // *o - the first `len` letters end cvc, where the second c is not w, x or y
+    fn ends_cvc(&self, len: usize) -> bool {
+        len >= 3
+            && self.is_consonant(len - 3)
+            && !self.is_consonant(len - 2)
+            && self.is_consonant(len - 1)
+            && !matches!(self.b[len - 1], b'w' | b'x' | b'y')
+    }
+
+    fn ends_with(&self, suffix: &str) -> bool {
+        self.b.ends_with(suffix.as_bytes())
+    }
+
+    // Length of the stem left after removing `suffix`
+    fn stem_len(&self, suffix: &str) -> usize {
+        self.b.len() - suffix.len()
+    }
+
+    fn replace_suffix(&mut self, suffix: &str, replacement: &str) {
+        let len = self.stem_len(suffix);
+        self.b.truncate(len);
+        self.b.extend_from_slice(replacement.as_bytes());
+    }
+
+    // Find the first (longest) matching suffix and replace it if the stem has measure > `min_measure`
+    fn replace_rules(&mut self, rules: &[(&str, &str)], min_measure: usize) {
+        if let Some((suffix, replacement)) = rules.iter().find(|(suffix, _)| self.ends_with(suffix)) {
+            if self.measure(self.stem_len(suffix)) > min_measure {
+                self.replace_suffix(suffix, replacement);
+            }
+        }
+    }
+
+    fn step1a(&mut self) {
+        if self.ends_with("sses") {
+            self.replace_suffix("sses", "ss");
+        } else if self.ends_with("ies") {
+            self.replace_suffix("ies", "i");
+        } else if self.ends_with("ss") {
+            // ss -> ss
+        } else if self.ends_with("s") {
+            self.replace_suffix("s", "");
+        }
+    }
+
+    fn step1b(&mut self) {
+        if self.ends_with("eed") {
+            if self.measure(self.stem_len("eed")) > 0 {
+                self.replace_suffix("eed", "ee");
+            }
+            return;
+        }
+
+        let suffix = if self.ends_with("ed") {
+            "ed"
+        } else if self.ends_with("ing") {
+            "ing"
+        } else {
+            return;
+        };
+        if !self.contains_vowel(self.stem_len(suffix)) {
+            return;
+        }
+        self.replace_suffix(suffix, "");
+
+        if self.ends_with("at") || self.ends_with("bl") || self.ends_with("iz") {
+            self.b.push(b'e');
+        } else if self.ends_double_consonant(self.b.len()) {
+            if !matches!(self.b[self.b.len() - 1], b'l' | b's' | b'z') {
+                self.b.pop();
+            }
+        } else if self.measure(self.b.len()) == 1 && self.ends_cvc(self.b.len()) {
+            self.b.push(b'e');
+        }
+    }
+
+    fn step1c(&mut self) {
+        if self.ends_with("y") && self.contains_vowel(self.stem_len("y")) {
+            self.replace_suffix("y", "i");
+        }
+    }
+
+    fn step2(&mut self) {
+        self.replace_rules(&[
+            ("ational", "ate"),
+            ("tional", "tion"),
+            ("enci", "ence"),
+            ("anci", "ance"),
+            ("izer", "ize"),
+            ("abli", "able"),
+            ("alli", "al"),
+            ("entli", "ent"),
+            ("eli", "e"),
+            ("ousli", "ous"),
+            ("ization", "ize"),
+            ("ation", "ate"),
+            ("ator", "ate"),
+            ("alism", "al"),
+            ("iveness", "ive"),
+            ("fulness", "ful"),
+            ("ousness", "ous"),
+            ("aliti", "al"),
+            ("iviti", "ive"),
+            ("biliti", "ble"),
+        ], 0);
+    }
+
+    fn step3(&mut self) {
+        self.replace_rules(&[
+            ("icate", "ic"),
+            ("ative", ""),
+            ("alize", "al"),
+            ("iciti", "ic"),
+            ("ical", "ic"),
+            ("ful", ""),
+            ("ness", ""),
+        ], 0);
+    }
+
+    fn step4(&mut self) {
+        const SUFFIXES: [&str; 19] = [
+            "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement",
+            "ment", "ent", "ion", "ou", "ism", "ate", "iti", "ous", "ive", "ize",
+        ];
+        // Prefer the longest suffix, e.g. "ement" over "ment" over "ent"
+        let suffix = SUFFIXES.iter()
+            .filter(|suffix| self.ends_with(suffix))
+            .max_by_key(|suffix| suffix.len());
+        if let Some(suffix) = suffix {
+            let len = self.stem_len(suffix);
+            if *suffix == "ion" && !(len > 0 && matches!(self.b[len - 1], b's' | b't')) {
+                return;
+            }
+            if self.measure(len) > 1 {
+                self.b.truncate(len);
+            }
+        }
+    }
+
+    fn step5a(&mut self) {
+        if self.ends_with("e") {
+            let len = self.stem_len("e");
+            let m = self.measure(len);
+            if m > 1 || (m == 1 && !self.ends_cvc(len)) {
+                self.b.truncate(len);
+            }
+        }
+    }
+
+    fn step5b(&mut self) {
+        let len = self.b.len();
+        if self.measure(len) > 1 && self.ends_double_consonant(len) && self.b[len - 1] == b'l' {
+            self.b.pop();
+        }
+    }
+}
+
+/// Reduce an English word to its stem, e.g. "caresses" -> "caress", "relational" -> "relat".
+/// The word is expected in lowercase; words that are not plain ASCII letters are returned unchanged.
+pub fn porter_stem(word: &str) -> String {
+    if word.len() <= 2 || !word.bytes().all(|c| c.is_ascii_lowercase()) {
+        return word.to_string();
+    }
+
+    let mut stemmer = Stemmer { b: word.as_bytes().to_vec() };
+    stemmer.step1a();
+    stemmer.step1b();
+    stemmer.step1c();
+    stemmer.step2();
+    stemmer.step3();
+    stemmer.step4();
+    stemmer.step5a();
+    stemmer.step5b();
+    String::from_utf8(stemmer.b).expect("the stemmer only works with ASCII letters")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Apply a single step of the algorithm, the way the examples of the paper are given
+    fn apply(step: fn(&mut Stemmer), word: &str) -> String {
+        let mut stemmer = Stemmer { b: word.as_bytes().to_vec() };
+        step(&mut stemmer);
+        String::from_utf8(stemmer.b).unwrap()
+    }
+
+    fn assert_step(step: fn(&mut Stemmer), pairs: &[(&str, &str)]) {
+        for (word, stem) in pairs {
+            assert_eq!(apply(step, word), *stem, "{word}");
+        }
+    }
+
+    #[test]
+    fn step1a() {
+        assert_step(Stemmer::step1a, &[("caresses", "caress"), ("ponies", "poni"), ("ties", "ti"), ("caress", "caress"), ("cats", "cat")]);
+    }
+
+    #[test]
+    fn step1b() {
+        assert_step(Stemmer::step1b, &[
+            ("feed", "feed"), ("agreed", "agree"), ("plastered", "plaster"), ("bled", "bled"), ("motoring", "motor"), ("sing", "sing"),
+            ("conflated", "conflate"), ("troubled", "trouble"), ("sized", "size"), ("hopping", "hop"), ("tanned", "tan"),
+            ("falling", "fall"), ("hissing", "hiss"), ("fizzed", "fizz"), ("failing", "fail"), ("filing", "file"),
+        ]);
+    }
+
+    #[test]
+    fn step1c() {
+        assert_step(Stemmer::step1c, &[("happy", "happi"), ("sky", "sky")]);
+    }
+
+    #[test]
+    fn step2() {
+        assert_step(Stemmer::step2, &[
+            ("relational", "relate"), ("conditional", "condition"), ("rational", "rational"), ("valenci", "valence"),
+            ("hesitanci", "hesitance"), ("digitizer", "digitize"), ("conformabli", "conformable"), ("radicalli", "radical"),
+            ("differentli", "different"), ("vileli", "vile"), ("analogousli", "analogous"), ("vietnamization", "vietnamize"),
+            ("predication", "predicate"), ("operator", "operate"), ("feudalism", "feudal"), ("decisiveness", "decisive"),
+            ("hopefulness", "hopeful"), ("callousness", "callous"), ("formaliti", "formal"), ("sensitiviti", "sensitive"),
+            ("sensibiliti", "sensible"),
+        ]);
+    }
+
+    #[test]
+    fn step3() {
+        assert_step(Stemmer::step3, &[
+            ("triplicate", "triplic"), ("formative", "form"), ("formalize", "formal"), ("electriciti", "electric"),
+            ("electrical", "electric"), ("hopeful", "hope"), ("goodness", "good"),
+        ]);
+    }
+
+    #[test]
+    fn step4() {
+        assert_step(Stemmer::step4, &[
+            ("revival", "reviv"), ("allowance", "allow"), ("inference", "infer"), ("airliner", "airlin"), ("gyroscopic", "gyroscop"),
+            ("adjustable", "adjust"), ("defensible", "defens"), ("irritant", "irrit"), ("replacement", "replac"),
+            ("adjustment", "adjust"), ("dependent", "depend"), ("adoption", "adopt"), ("homologou", "homolog"), ("communism", "commun"),
+            ("activate", "activ"), ("angulariti", "angular"), ("homologous", "homolog"), ("effective", "effect"), ("bowdlerize", "bowdler"),
+        ]);
+    }
+
+    #[test]
+    fn step5a() {
+        assert_step(Stemmer::step5a, &[("probate", "probat"), ("rate", "rate"), ("cease", "ceas")]);
+    }
+
+    #[test]
+    fn step5b() {
+        assert_step(Stemmer::step5b, &[("controll", "control"), ("roll", "roll")]);
+    }
+
+    // The walk-throughs of the paper, through every step
+    #[test]
+    fn whole_words() {
+        assert_eq!(apply(Stemmer::step1a, "generalizations"), "generalization");
+        assert_eq!(apply(Stemmer::step2, "generalization"), "generalize");
+        assert_eq!(apply(Stemmer::step3, "generalize"), "general");
+        assert_eq!(apply(Stemmer::step4, "general"), "gener");
+        assert_eq!(porter_stem("generalizations"), "gener");
+        assert_eq!(porter_stem("oscillators"), "oscil");
+        assert_eq!(porter_stem("caresses"), "caress");
+        assert_eq!(porter_stem("relational"), "relat");
+    }
+
+    #[test]
+    fn words_that_are_not_stemmed() {
+        assert_eq!(porter_stem("is"), "is");
+        assert_eq!(porter_stem("Running"), "Running");
+        assert_eq!(porter_stem("naïve"), "naïve");
+        assert_eq!(porter_stem("x86"), "x86");
+    }
+}
@@ -0,0 +1,76 @@
+use proptest::prelude::*;
+use proptest::string::string_regex;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::model::Lexer;
+
+fn tokens(text: &str) -> Vec<String> {
+    Lexer::new(&text.chars().collect::<Vec<_>>()).collect()
+}
+
+// Text of letters, numbers, combining marks, punctuation, symbols, separators and control characters,
+// e.g. accents that NFC composes with the letter before them, Hangul jamo and Roman numerals
+fn unicode_text() -> impl Strategy<Value = String> {
+    string_regex(r"[\p{L}\p{M}\p{N}\p{P}\p{S}\p{Z}\p{Cc}\t\n]{0,40}").expect("the regex is valid")
+}
+
+// Words and numbers in NFC, so that every token is all letters or all digits. The Lexer brings words
+// into NFC, which turns a few letters into two, e.g. "\u{FB1D}" into "\u{5D9}\u{5B4}".
+fn alphanumeric_text() -> impl Strategy<Value = String> {
+    string_regex(r"([\p{L}]+|[\p{Nd}]+)( [\p{L}]+| [\p{Nd}]+){0,8}").expect("the regex is valid")
+        .prop_map(|text| text.nfc().collect())
+}
+
+fn alphanumeric_count(text: &str) -> usize {
+    text.chars().filter(|x| x.is_alphanumeric()).count()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(10_000))]
+
+    #[test]
+    fn tokens_contain_no_whitespace(text in unicode_text()) {
+        prop_assert!(!tokens(&text).concat().contains(char::is_whitespace));
+    }
+
+    #[test]
+    fn no_token_is_empty(text in unicode_text()) {
+        prop_assert!(tokens(&text).iter().all(|token| !token.is_empty()));
+    }
+
+    // A mark with no letter before it starts a token, and NFC can put another mark before it
+    // that is then a token of its own, e.g. "\u{1E01B}\u{10A39}", so those tokens are left out
+    #[test]
+    fn tokens_lex_to_themselves(text in unicode_text()) {
+        for token in tokens(&text).into_iter().filter(|token| !token.starts_with(is_combining_mark)) {
+            prop_assert_eq!(tokens(&token), vec![token.clone()]);
+        }
+    }
+
+    // Uppercasing never adds letters or digits, and every other character is a token of its own
+    #[test]
+    fn tokens_have_at_most_the_alphanumerics_of_the_text(text in alphanumeric_text()) {
+        let token_len = tokens(&text).iter().map(|token| alphanumeric_count(token)).sum::<usize>();
+        prop_assert!(alphanumeric_count(&text) >= token_len);
+    }
+}
+
+#[test]
+fn empty_content_has_no_tokens() {
+    assert_eq!(Lexer::new(&[]).next(), None);
+}
+
+#[test]
+fn nul_is_a_token_of_its_own() {
+    assert_eq!(tokens("\0"), ["\0"]);
+    assert_eq!(tokens("ab\0cd"), ["AB", "\0", "CD"]);
+}
+
+// A char can't be a surrogate, so text decoded from UTF-16 with lone surrogates has U+FFFD in their place
+#[test]
+fn lone_surrogates_are_replacement_characters() {
+    assert_eq!(char::from_u32(0xD800), None);
+    let text = String::from_utf16_lossy(&[0x61, 0xD800, 0x62, 0xDC00]);
+    assert_eq!(tokens(&text), ["A", "\u{FFFD}", "B", "\u{FFFD}"]);
+}
@@ -0,0 +1,2 @@
+mod lexer_props;
+mod posting_list_props;
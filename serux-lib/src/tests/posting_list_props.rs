@@ -0,0 +1,83 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+
+use crate::model::{intersect_posting_lists, InMemoryModel, Model};
+use crate::query::ParsedQuery;
+
+const WORDS: [&str; 5] = ["alpha", "beta", "gamma", "delta", "omega"];
+
+// Posting lists of up to 4 terms over 50 documents, sorted by document ID as in the inverted index
+fn posting_lists() -> impl Strategy<Value = Vec<Vec<(u32, f32)>>> {
+    prop::collection::vec(prop::collection::btree_map(0u32..50, 0.125f32..4.0, 0..30), 0..4)
+        .prop_map(|lists| lists.into_iter().map(|list| list.into_iter().collect()).collect())
+}
+
+// Documents of a few of WORDS each
+fn documents() -> impl Strategy<Value = Vec<Vec<&'static str>>> {
+    prop::collection::vec(prop::collection::vec(prop::sample::select(&WORDS[..]), 1..10), 1..20)
+}
+
+// Two or three different words of WORDS
+fn query_words() -> impl Strategy<Value = Vec<&'static str>> {
+    prop::sample::subsequence(&WORDS[..], 2..=3)
+}
+
+// The documents of the first list that all the others have too, with their scores added up in list order
+fn naive_intersection(lists: &[&[(u32, f32)]]) -> Vec<(u32, f32)> {
+    let Some((first, others)) = lists.split_first() else {
+        return Vec::new();
+    };
+    first.iter().filter_map(|&(id, rank)| {
+        others.iter().try_fold(rank, |score, list| list.iter().find(|&&(other, _)| other == id).map(|&(_, rank)| score + rank))
+            .map(|score| (id, score))
+    }).collect()
+}
+
+fn model_of(documents: &[Vec<&str>]) -> InMemoryModel {
+    InMemoryModel::from_documents(documents.iter().enumerate()
+        .map(|(i, content)| (PathBuf::from(format!("{i}.txt")), content.join(" ")))).expect("documents are indexed")
+}
+
+fn search(model: &InMemoryModel, words: &[&str], min_should_match: usize) -> BTreeMap<PathBuf, f32> {
+    let query = ParsedQuery { terms: words.iter().map(|word| (word.to_string(), 1f32)).collect(), must_not: Vec::new(), min_should_match };
+    model.search_query_parsed(&query).expect("search runs").into_iter().collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1_000))]
+
+    #[test]
+    fn intersection_is_the_naive_intersection(lists in posting_lists()) {
+        let lists = lists.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        prop_assert_eq!(intersect_posting_lists(&lists), naive_intersection(&lists));
+    }
+
+    // The documents of a search for any of the words that contain all of them, with the same scores
+    #[test]
+    fn searches_for_all_words_match_the_naive_filter(documents in documents(), words in query_words()) {
+        let model = model_of(&documents);
+        let all_words = search(&model, &words, words.len());
+        let any_word = search(&model, &words, 1);
+
+        let containing_all = documents.iter().enumerate()
+            .filter(|(_, content)| words.iter().all(|word| content.contains(word)))
+            .map(|(i, _)| PathBuf::from(format!("{i}.txt")))
+            .collect::<BTreeSet<_>>();
+        let expected = any_word.into_iter().filter(|(path, _)| containing_all.contains(path)).collect::<BTreeMap<_, _>>();
+        prop_assert_eq!(all_words.keys().collect::<Vec<_>>(), expected.keys().collect::<Vec<_>>());
+        for (path, score) in &all_words {
+            prop_assert!((score - expected[path]).abs() < 1e-5, "{}: {} != {}", path.display(), score, expected[path]);
+        }
+    }
+
+    // Documents of a few words of five often score the same
+    #[test]
+    fn wand_top_k_is_the_top_k_of_the_full_ranking(documents in documents(), words in query_words(), k in 1usize..25) {
+        let model = model_of(&documents);
+        let query = words.join(" ").chars().collect::<Vec<_>>();
+        let all = model.search_query(&query, 0, usize::MAX).expect("search runs").results;
+        prop_assert_eq!(model.search_topk(&query, k).expect("search runs"), &all[..k.min(all.len())]);
+    }
+}
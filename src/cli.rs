@@ -0,0 +1,424 @@
+use std::path::PathBuf;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{value_parser, Args, ColorChoice, Parser, Subcommand};
+use clap_complete::Shell;
+
+use super::config::CONFIG_FILE;
+use super::export::OutputFormat;
+use super::logging::{self, LogFormat};
+use super::model::{FieldWeights, IdfVariant, TfNormalization, DEFAULT_FRESHNESS_HALF_LIFE_DAYS, DEFAULT_MMR_LAMBDA};
+use super::parsers;
+use super::server::DEFAULT_RELOAD_INTERVAL;
+use super::tls::TLS_PASSWORD_VAR;
+use super::watch::DEFAULT_DEBOUNCE_MS;
+
+#[derive(Clone, Copy)]
+pub enum IndexFormat {
+    Json,
+    Binary,
+}
+
+impl IndexFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(IndexFormat::Json),
+            "binary" => Some(IndexFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+// Where the index is kept; --sqlite is short for --backend sqlite
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    InMemory,
+    Sqlite,
+    // An SQLite FTS5 table, see SqliteFts5Model
+    Fts5,
+}
+
+impl Backend {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "inmemory" => Some(Backend::InMemory),
+            "sqlite" => Some(Backend::Sqlite),
+            "fts5" => Some(Backend::Fts5),
+            _ => None,
+        }
+    }
+}
+
+// Order of the documents printed by `list`
+#[derive(Clone, Copy, PartialEq)]
+pub enum ListOrder {
+    Alphabetical,
+    // Largest file first
+    Size,
+    // Most terms first, as a rough measure of how much a document has to say
+    Score,
+}
+
+impl ListOrder {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "alpha" => Some(ListOrder::Alphabetical),
+            "size" => Some(ListOrder::Size),
+            "score" => Some(ListOrder::Score),
+            _ => None,
+        }
+    }
+}
+
+// Values of an option that `parse` reads, listed by the help and the shell completions
+fn one_of<T: Clone + Send + Sync + 'static>(names: &'static [&'static str], parse: fn(&str) -> Option<T>) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(names).map(move |name| parse(&name).expect("every possible value is parsed"))
+}
+
+const BACKENDS: &[&str] = &["inmemory", "sqlite", "fts5"];
+const IDF_VARIANTS: &[&str] = &["standard", "smooth", "probabilistic", "unary"];
+
+fn positive(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err("expected a positive number".to_string()),
+    }
+}
+
+fn field_weights(value: &str) -> Result<FieldWeights, String> {
+    FieldWeights::parse(value).ok_or_else(|| "expected e.g. title=3,body=1,metadata=0.5".to_string())
+}
+
+fn csv_delimiter(value: &str) -> Result<char, String> {
+    parsers::csv::parse_delimiter(value).ok_or_else(|| "expected one ASCII character, or tab".to_string())
+}
+
+/// Full-text search of the documents of a folder
+#[derive(Parser)]
+#[command(name = "serux", after_help = format!(
+    "Settings are read from {CONFIG_FILE} in the current directory if it exists; options override them.\n\
+     Log events are filtered with the {var} environment variable, e.g. {var}=debug; the default is info.",
+    var = logging::LOG_VAR,
+))]
+pub struct Cli {
+    /// Use the SQLite index instead of the JSON one
+    #[arg(long, global = true)]
+    pub sqlite: bool,
+    /// Rank results with Okapi BM25 instead of TF-IDF; an index built with it ranks with BM25 by default
+    #[arg(long, global = true)]
+    pub bm25: bool,
+    /// Reduce indexed terms to their Porter stems (index only)
+    #[arg(long, global = true)]
+    pub stem: bool,
+    /// Skip the words listed in <FILE>, one per line (index only)
+    #[arg(long, global = true, value_name = "FILE")]
+    pub stop_words: Option<PathBuf>,
+    /// Strip accents from Latin letters, so that "cafe" finds "café" (index only)
+    #[arg(long, global = true)]
+    pub ascii_folding: bool,
+    /// Do not skip any words, not even the built-in English list (index only)
+    #[arg(long, global = true)]
+    pub no_stop_words: bool,
+    /// Only index the first <N> terms of every document (index only)
+    #[arg(long, global = true, value_name = "N")]
+    pub max_tokens_per_doc: Option<usize>,
+    /// Also index the character n-grams of every word, so that parts of words match (index only)
+    #[arg(long = "ngrams", global = true, value_name = "N", value_parser = positive)]
+    pub ngram_size: Option<usize>,
+    /// How TF-IDF weighs the count of a term in a document: relative to its length (raw, the default),
+    /// 1 + ln(count), relative to its most frequent term, or only whether the term is there (index only)
+    #[arg(long = "tf", global = true, value_name = "MODE", value_parser = one_of(&["raw", "log", "augmented", "boolean"], TfNormalization::parse))]
+    pub tf_normalization: Option<TfNormalization>,
+    /// Highlight matched terms of search results; auto does it when printing to a terminal
+    #[arg(long, global = true, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Also log how long loading the index takes and how well it compresses
+    #[arg(long, global = true)]
+    pub verbose: bool,
+    /// Don't draw a progress bar while indexing; it is never drawn unless stdout is a terminal
+    #[arg(long, global = true)]
+    pub no_progress: bool,
+    /// Write log events as text lines or as JSON objects, to stderr
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = one_of(&["text", "json"], LogFormat::parse))]
+    pub log_format: Option<LogFormat>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Index the <FOLDER> and save the index to index.json (or index.bin)
+    Index(IndexArgs),
+    /// Index the <FOLDER> into <INDEX_FILE> (index.json by default) and keep it up to date as files change
+    Watch {
+        folder: String,
+        index_file: Option<String>,
+        /// Save the index once no change was seen for <N> ms
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_DEBOUNCE_MS)]
+        debounce_ms: u64,
+    },
+    /// Combine two indexes of different documents into <OUTPUT>
+    Merge {
+        index_a: String,
+        index_b: String,
+        output: String,
+    },
+    /// Remove the <DOCUMENT> from the <INDEX_FILE>, by the path the index lists it with
+    Delete {
+        index_file: String,
+        document: PathBuf,
+    },
+    /// Write the default settings to serux.toml
+    Init,
+    /// Search <QUERY> within the <INDEX_FILE>, or the index_path of serux.toml
+    #[command(allow_missing_positional = true)]
+    Search {
+        index_file: Option<String>,
+        query: String,
+        /// json, csv and tsv list the rank, path, score, title and snippet of every result
+        #[arg(long, value_parser = one_of(&["text", "json", "csv", "tsv"], OutputFormat::parse), default_value = "text")]
+        output_format: OutputFormat,
+        /// <QUERY> is a regular expression matching indexed terms
+        #[arg(long)]
+        regex: bool,
+    },
+    /// Print the path, size and title of every document of the <INDEX_FILE>
+    List {
+        index_file: Option<String>,
+        #[arg(long)]
+        json: bool,
+        /// Only print how many documents there are
+        #[arg(long)]
+        count_only: bool,
+        /// By path, largest first or with the most terms first
+        #[arg(long, value_parser = one_of(&["alpha", "size", "score"], ListOrder::parse), default_value = "alpha")]
+        sort: ListOrder,
+        /// Only the documents with the extension <EXT>
+        #[arg(long, value_name = "EXT")]
+        filter_ext: Option<String>,
+    },
+    /// Print what the corpus of the <INDEX_FILE> is made of
+    Stats {
+        index_file: Option<String>,
+        #[arg(long)]
+        json: bool,
+        #[arg(long, value_parser = one_of(&["inmemory", "sqlite"], Backend::parse))]
+        backend: Option<Backend>,
+    },
+    /// Check that the <INDEX_FILE> is consistent and its files still exist
+    Verify {
+        index_file: Option<String>,
+    },
+    /// Rewrite a JSON <INDEX_FILE> saved by an older version of serux in the current format,
+    /// keeping the original as <INDEX_FILE>.v<VERSION>.bak
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Migrate {
+        #[arg(required = true)]
+        index_file: Option<String>,
+        #[command(subcommand)]
+        command: Option<MigrateCommand>,
+    },
+    /// Save the changes logged for the <INDEX_FILE> into it and empty its write-ahead log
+    Checkpoint {
+        index_file: String,
+    },
+    /// Show how every term of <QUERY> adds to the score of the <DOCUMENT>
+    Explain {
+        index_file: String,
+        query: String,
+        document: String,
+    },
+    /// Inspect the settings of an index
+    Model {
+        #[command(subcommand)]
+        command: ModelCommand,
+    },
+    /// Start a local HTTP server with a web interface
+    Serve(ServeArgs),
+    /// Print the completion script of a shell
+    Completions {
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Copy the documents of the <INDEX_FILE> into a new SQLite index <DB_FILE>, for the --sqlite backend
+    JsonToSqlite {
+        index_file: String,
+        db_file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ModelCommand {
+    /// Settings the index was built with
+    Config {
+        #[command(subcommand)]
+        command: ModelConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ModelConfigCommand {
+    /// Print the settings the <INDEX_FILE> was built with
+    Show {
+        index_file: String,
+    },
+}
+
+#[derive(Args)]
+pub struct IndexArgs {
+    pub folder: String,
+    #[arg(long, value_parser = one_of(&["json", "binary"], IndexFormat::parse), default_value = "json")]
+    pub format: IndexFormat,
+    /// Compress the JSON index with zstd into index.json.zst
+    #[arg(long)]
+    pub compress: bool,
+    /// zstd level, 3 by default; implies --compress
+    #[arg(long, value_name = "LEVEL", value_parser = value_parser!(i32).range(1..=22))]
+    pub compress_level: Option<i32>,
+    /// Skip the files unchanged since the existing index was built; if there is no index file yet, everything is indexed
+    #[arg(long)]
+    pub incremental: bool,
+    /// Don't index a file again if its first 64 KB are the same as when it was indexed, even though it was modified;
+    /// costs reading them
+    #[arg(long)]
+    pub hash_check: bool,
+    /// Only index files up to <N> folders deep: 1 for the files right in <FOLDER>; all of them by default
+    #[arg(long, value_name = "N")]
+    pub depth: Option<usize>,
+    /// Index files on <N> threads, one per CPU by default
+    #[arg(long, value_name = "N")]
+    pub threads: Option<usize>,
+    /// Only index the files with these extensions; all of them by default
+    #[arg(long, value_name = "EXT,...")]
+    pub include_ext: Option<String>,
+    /// Don't index the files with these extensions
+    #[arg(long, value_name = "EXT,...")]
+    pub exclude_ext: Option<String>,
+    /// Don't index the files and folders whose path matches the glob <PATTERN>, e.g. "**/node_modules/**";
+    /// can be given several times. Patterns listed in the .seruxignore file of the folder apply as well
+    #[arg(long = "exclude-pattern", value_name = "PATTERN", value_parser = glob::Pattern::new)]
+    pub exclude_patterns: Vec<glob::Pattern>,
+    /// Queries also match the synonyms listed in <FILE>, one class per line, e.g. "auto, car, automobile"
+    #[arg(long, value_name = "FILE")]
+    pub synonyms: Option<PathBuf>,
+    /// How TF-IDF weighs rare terms: standard ln(N/df), smooth, probabilistic ln((N-df)/df) or unary 1
+    #[arg(long, value_parser = one_of(IDF_VARIANTS, IdfVariant::parse))]
+    pub idf: Option<IdfVariant>,
+    /// Words keep their case, so that "Config" and "config" differ
+    #[arg(long)]
+    pub case_sensitive: bool,
+    /// "state-of-the-art" is indexed whole as well as word by word
+    #[arg(long)]
+    pub hyphen_compounds: bool,
+    /// URLs are indexed whole, in lowercase, and so is their host
+    #[arg(long)]
+    pub tokenize_urls: bool,
+    /// Chinese, Japanese and Korean characters are indexed one by one and two by two,
+    /// since their words are not separated by spaces
+    #[arg(long)]
+    pub cjk_ngram: bool,
+    /// Diacritics are stripped from Latin letters, so that "garcon" finds "garçon" and "Dvorak" finds "Dvořák";
+    /// Greek and Cyrillic letters keep theirs
+    #[arg(long)]
+    pub diacritic_folding: bool,
+    /// Separates the fields of .csv and .tsv files ("tab" for tabs); guessed by default
+    #[arg(long, value_name = "CHAR", value_parser = csv_delimiter)]
+    pub csv_delimiter: Option<char>,
+    /// Tokens shorter than <N> characters are skipped
+    #[arg(long, value_name = "N")]
+    pub min_token_len: Option<usize>,
+    /// Tokens longer than <N> characters are skipped
+    #[arg(long, value_name = "N")]
+    pub max_token_len: Option<usize>,
+    /// How much a term counts in the title, the body and the metadata (description and keywords)
+    /// of documents for TF-IDF: title=3,body=1,metadata=0.5 by default
+    #[arg(long, value_name = "FIELD=WEIGHT,...", value_parser = field_weights)]
+    pub field_weights: Option<FieldWeights>,
+    /// Halve the TF-IDF score of a file every <DAYS> days since it was last modified
+    #[arg(long)]
+    pub freshness_boost: bool,
+    #[arg(long, value_name = "DAYS", help = format!("{DEFAULT_FRESHNESS_HALF_LIFE_DAYS} by default; implies --freshness-boost"))]
+    pub freshness_half_life_days: Option<f32>,
+    /// The server shows near duplicates after less relevant but different documents
+    #[arg(long)]
+    pub mmr: bool,
+    #[arg(long, value_name = "LAMBDA", help = format!(
+        "How much relevance counts against novelty, from 0 to 1, {DEFAULT_MMR_LAMBDA} by default; implies --mmr"
+    ))]
+    pub mmr_lambda: Option<f32>,
+    /// TF-IDF scores go up by <WEIGHT> times how close consecutive terms of the query are in the document
+    #[arg(long, value_name = "WEIGHT")]
+    pub proximity_weight: Option<f32>,
+    /// Skip scoring the documents that cannot make the page of results (TF-IDF only), at the cost of
+    /// the number of matching documents only counting those up to the next page
+    #[arg(long)]
+    pub wand: bool,
+    /// sqlite is the same as --sqlite; fts5 keeps the documents in an SQLite full-text table,
+    /// which tokenizes and ranks (with BM25) by itself, in index.db
+    #[arg(long, value_parser = one_of(BACKENDS, Backend::parse))]
+    pub backend: Option<Backend>,
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    pub index_file: Option<String>,
+    pub address: Option<String>,
+    /// Map the index file into memory instead of reading it
+    #[arg(long)]
+    pub mmap: bool,
+    /// Rank with another IDF variant than the index was built with
+    #[arg(long, value_parser = one_of(IDF_VARIANTS, IdfVariant::parse))]
+    pub idf: Option<IdfVariant>,
+    /// Queries match the case of the terms of a case sensitive index unless they ask with case=0;
+    /// otherwise they ask with case=1
+    #[arg(long)]
+    pub case_sensitive: bool,
+    #[arg(long, value_name = "FILE", requires = "tls_key", help = format!(
+        "Serve HTTPS with the PEM certificate chain and key; an encrypted key is decrypted with the password in ${TLS_PASSWORD_VAR}"
+    ))]
+    pub tls_cert: Option<String>,
+    #[arg(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<String>,
+    /// Every request needs these Basic credentials
+    #[arg(long, value_name = "USER", requires = "auth_password")]
+    pub auth_user: Option<String>,
+    #[arg(long, value_name = "PASSWORD", requires = "auth_user")]
+    pub auth_password: Option<String>,
+    /// Append every search to <FILE> as a line of JSON; the most frequent queries are listed by /api/logs/top-queries
+    #[arg(long, value_name = "FILE")]
+    pub query_log: Option<PathBuf>,
+    /// Read the index the way it was built with index --backend
+    #[arg(long, value_parser = one_of(BACKENDS, Backend::parse))]
+    pub backend: Option<Backend>,
+    /// Pages of that origin (any with *) may call the API from a browser
+    #[arg(long, value_name = "ORIGIN")]
+    pub cors_origin: Option<String>,
+    /// Load the index file again whenever it changes
+    #[arg(long)]
+    pub watch_index: bool,
+    #[arg(long, value_name = "N", value_parser = value_parser!(u64).range(1..), help = format!(
+        "Check whether the index file changed every <N> seconds, {} by default; implies --watch-index",
+        DEFAULT_RELOAD_INTERVAL.as_secs(),
+    ))]
+    pub reload_interval_secs: Option<u64>,
+    /// Load the index file again when the server gets a SIGHUP; the default on Linux for in-memory indexes
+    #[arg(long, overrides_with = "no_reload_on_sighup")]
+    pub reload_on_sighup: bool,
+    /// Let a SIGHUP stop the server as usual
+    #[arg(long, overrides_with = "reload_on_sighup")]
+    pub no_reload_on_sighup: bool,
+    /// Answer 408 to requests that take longer than <MS> milliseconds, 5000 by default
+    #[arg(long, value_name = "MS", value_parser = value_parser!(u64).range(1..))]
+    pub request_timeout_ms: Option<u64>,
+    /// Close connections that take longer than <MS> milliseconds to send a request, 30000 by default
+    #[arg(long, value_name = "MS", value_parser = value_parser!(u64).range(1..))]
+    pub read_timeout_ms: Option<u64>,
+    /// Serve at most <N> connections at once, 128 by default; more wait a second for one to close, then get 503
+    #[arg(long, value_name = "N")]
+    pub max_connections: Option<usize>,
+    /// Documents must contain at least <N> terms of a query unless it asks with msm=<N>
+    #[arg(long = "default-msm", value_name = "N")]
+    pub default_min_should_match: Option<usize>,
+}
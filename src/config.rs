@@ -0,0 +1,313 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::model::{FieldWeights, RankingAlgorithm, SearchError};
+use super::query::DEFAULT_MIN_SHOULD_MATCH;
+
+// Looked up in the current directory
+pub const CONFIG_FILE: &str = "serux.toml";
+
+// Looked up in the folder being indexed, see ignore_file_patterns
+pub const IGNORE_FILE: &str = ".seruxignore";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RankingName {
+    Tfidf,
+    Bm25,
+}
+
+impl RankingName {
+    pub fn algorithm(self) -> RankingAlgorithm {
+        match self {
+            RankingName::Tfidf => RankingAlgorithm::TfIdf,
+            RankingName::Bm25 => RankingAlgorithm::bm25(),
+        }
+    }
+}
+
+/// Settings of the indexer and the server. Every field can also be set from the command line,
+/// which takes precedence over the configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Index written by `index` and read by `search` and `serve` when no index file is given
+    pub index_path: Option<PathBuf>,
+    pub serve_address: String,
+    pub stemming: bool,
+    // Index words in their case, see ModelConfig::case_sensitive; for `serve`, search case sensitively by default
+    pub case_sensitive: bool,
+    // Index hyphenated words whole as well as their parts, see ModelConfig::hyphen_compounds
+    pub hyphen_compounds: bool,
+    // Index URLs whole, see ModelConfig::tokenize_urls
+    pub tokenize_urls: bool,
+    // Index Chinese, Japanese and Korean text character by character, see ModelConfig::cjk_ngram
+    pub cjk_ngram: bool,
+    // Strip the diacritics of Latin letters, see ModelConfig::diacritic_folding
+    pub diacritic_folding: bool,
+    // Tokens shorter or longer than this many characters are not indexed, see ModelConfig::min_token_len
+    pub min_token_len: Option<usize>,
+    pub max_token_len: Option<usize>,
+    // How much the title, the body and the metadata of documents count in TF-IDF ranking, see model::FieldWeights
+    pub field_weights: Option<FieldWeights>,
+    // Rank recently modified files higher, see model::freshness_factor
+    pub freshness_boost: bool,
+    pub freshness_half_life_days: Option<f32>,
+    // Reorder search results of the server so that near duplicates don't follow each other, see model::mmr_rerank
+    pub mmr: bool,
+    pub mmr_lambda: Option<f32>,
+    // Rank documents where query terms are close together higher, see model::proximity_score
+    pub proximity_weight: Option<f32>,
+    // Only score the documents that can make the page of results, see model::InMemoryModel::search_topk
+    pub use_wand: bool,
+    // Replaces the built-in English stop words
+    pub stop_words_file: Option<PathBuf>,
+    // Classes of equivalent words, see model::load_synonyms
+    pub synonyms_file: Option<PathBuf>,
+    // Ranking used for searching; the one the index was built with if not set
+    pub ranking_algorithm: Option<RankingName>,
+    // Indexing threads, one per CPU if not set
+    pub thread_count: Option<usize>,
+    // Most results printed by `search` or returned in one page by the server
+    pub max_results: usize,
+    // Shortest prefix the server suggests terms for, so that one letter doesn't list half the dictionary
+    pub suggest_min_prefix_len: usize,
+    // How many terms of a query documents must contain for the server when the search doesn't say, see query::ParsedQuery
+    pub default_min_should_match: usize,
+    // Only files with these extensions are indexed; all files if empty
+    pub file_extensions: Vec<String>,
+    // Files with these extensions are never indexed, even if file_extensions lists them
+    pub exclude_extensions: Vec<String>,
+    // Files and folders whose path matches any of these globs are not indexed, e.g. "**/node_modules/**"
+    #[serde(with = "glob_patterns")]
+    pub exclude_patterns: Vec<Pattern>,
+    // Separates the fields of .csv and .tsv files; guessed from their first line if not set
+    pub csv_delimiter: Option<char>,
+    // The server appends every search to this file, see query_log::QueryLog
+    pub query_log_path: Option<PathBuf>,
+    // Origin allowed to call the API from another site, or "*" for any; no CORS headers are sent if not set
+    pub cors_origin: Option<String>,
+    // The server answers 408 to requests it takes longer than this to answer
+    pub request_timeout_ms: u64,
+    // Connections that take longer than this to send the headers or the body of a request are closed
+    pub read_timeout_ms: u64,
+    // Connections the server serves at once; more wait for one to close and are answered 503 after a second
+    pub max_connections: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            index_path: None,
+            serve_address: "127.0.0.1:8383".to_string(),
+            stemming: false,
+            case_sensitive: false,
+            hyphen_compounds: false,
+            tokenize_urls: false,
+            cjk_ngram: false,
+            diacritic_folding: false,
+            min_token_len: None,
+            max_token_len: None,
+            field_weights: None,
+            freshness_boost: false,
+            freshness_half_life_days: None,
+            mmr: false,
+            mmr_lambda: None,
+            proximity_weight: None,
+            use_wand: false,
+            stop_words_file: None,
+            synonyms_file: None,
+            ranking_algorithm: None,
+            thread_count: None,
+            max_results: 20,
+            suggest_min_prefix_len: 2,
+            default_min_should_match: DEFAULT_MIN_SHOULD_MATCH,
+            file_extensions: Vec::new(),
+            exclude_extensions: Vec::new(),
+            exclude_patterns: Vec::new(),
+            csv_delimiter: None,
+            query_log_path: None,
+            cors_origin: None,
+            request_timeout_ms: 5000,
+            read_timeout_ms: 30_000,
+            max_connections: 128,
+        }
+    }
+}
+
+impl Config {
+    // The configuration in `path`, or the default one if there is no such file
+    pub fn load_or_default(path: &Path) -> Result<Self, SearchError> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(SearchError::io_at(path, err)),
+        };
+        let config: Self = toml::from_str(&source).map_err(|err| {
+            SearchError::InvalidConfig(format!("{path}: {err}", path = path.display()))
+        })?;
+        config.validate().map_err(|err| match err {
+            SearchError::InvalidConfig(msg) => SearchError::InvalidConfig(format!("{path}: {msg}", path = path.display())),
+            err => err,
+        })?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), SearchError> {
+        let port = self.serve_address.rsplit_once(':').map(|(_, port)| port.parse::<u16>());
+        if !matches!(port, Some(Ok(_))) {
+            return Err(SearchError::InvalidConfig(format!("serve_address must be <host>:<port>, got {:?}", self.serve_address)));
+        }
+        if self.thread_count == Some(0) {
+            return Err(SearchError::InvalidConfig("thread_count must be at least 1".to_string()));
+        }
+        if self.min_token_len == Some(0) || self.max_token_len == Some(0) {
+            return Err(SearchError::InvalidConfig("min_token_len and max_token_len must be at least 1".to_string()));
+        }
+        if let (Some(min), Some(max)) = (self.min_token_len, self.max_token_len) {
+            if min > max {
+                return Err(SearchError::InvalidConfig(format!("min_token_len must be at most max_token_len, got {min} and {max}")));
+            }
+        }
+        if let Some(FieldWeights { title, body, metadata }) = self.field_weights {
+            if [title, body, metadata].iter().any(|weight| !weight.is_finite() || *weight < 0.0) {
+                return Err(SearchError::InvalidConfig(format!("field_weights must be positive numbers, got {title}, {body} and {metadata}")));
+            }
+        }
+        if let Some(half_life_days) = self.freshness_half_life_days {
+            if !half_life_days.is_finite() || half_life_days <= 0.0 {
+                return Err(SearchError::InvalidConfig(format!("freshness_half_life_days must be a positive number, got {half_life_days}")));
+            }
+        }
+        if let Some(lambda) = self.mmr_lambda {
+            if !(0.0..=1.0).contains(&lambda) {
+                return Err(SearchError::InvalidConfig(format!("mmr_lambda must be between 0 and 1, got {lambda}")));
+            }
+        }
+        if self.request_timeout_ms == 0 {
+            return Err(SearchError::InvalidConfig("request_timeout_ms must be at least 1".to_string()));
+        }
+        if self.read_timeout_ms == 0 {
+            return Err(SearchError::InvalidConfig("read_timeout_ms must be at least 1".to_string()));
+        }
+        if let Some(weight) = self.proximity_weight {
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(SearchError::InvalidConfig(format!("proximity_weight must be a positive number, got {weight}")));
+            }
+        }
+        if self.max_connections == 0 {
+            return Err(SearchError::InvalidConfig("max_connections must be at least 1".to_string()));
+        }
+        if self.max_results == 0 {
+            return Err(SearchError::InvalidConfig("max_results must be at least 1".to_string()));
+        }
+        if self.default_min_should_match == 0 {
+            return Err(SearchError::InvalidConfig("default_min_should_match must be at least 1".to_string()));
+        }
+        for (name, extensions) in [("file_extensions", &self.file_extensions), ("exclude_extensions", &self.exclude_extensions)] {
+            if let Some(extension) = extensions.iter().find(|extension| extension.is_empty() || extension.starts_with('.')) {
+                return Err(SearchError::InvalidConfig(format!("{name} must be given without the leading dot, got {extension:?}")));
+            }
+        }
+        if let Some(origin) = &self.cors_origin {
+            if origin.is_empty() || !origin.bytes().all(|byte| byte.is_ascii_graphic()) {
+                return Err(SearchError::InvalidConfig(format!("cors_origin must be an origin like https://example.com or *, got {origin:?}")));
+            }
+        }
+        if self.csv_delimiter.is_some_and(|delimiter| !delimiter.is_ascii()) {
+            return Err(SearchError::InvalidConfig(format!("csv_delimiter must be an ASCII character, got {:?}", self.csv_delimiter.unwrap())));
+        }
+        Ok(())
+    }
+
+    // Whether the file at `path` should be indexed according to `file_extensions` and `exclude_extensions`
+    pub fn indexes_file(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        if self.exclude_extensions.iter().any(|excluded| excluded.eq_ignore_ascii_case(extension)) {
+            return false;
+        }
+        self.file_extensions.is_empty() || self.file_extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(extension))
+    }
+
+    // Whether `path` matches one of `exclude_patterns`. A folder also matches the patterns of everything in it,
+    // e.g. "**/node_modules/**", so that it is not walked at all.
+    pub fn excludes_path(&self, path: &Path, is_dir: bool) -> bool {
+        let contents = is_dir.then(|| path.join(""));
+        self.exclude_patterns.iter().any(|pattern| {
+            pattern.matches_path(path) || contents.as_deref().is_some_and(|contents| pattern.matches_path(contents))
+        })
+    }
+
+    // Write the default configuration to `path`, refusing to overwrite an existing file
+    pub fn write_default(path: &Path) -> Result<(), SearchError> {
+        if path.exists() {
+            return Err(SearchError::InvalidConfig(format!("{path} already exists", path = path.display())));
+        }
+        let source = toml::to_string_pretty(&Self::default())
+            .map_err(|err| SearchError::InvalidConfig(err.to_string()))?;
+        fs::write(path, source).map_err(|err| SearchError::io_at(path, err))
+    }
+}
+
+// Exclude patterns are written as the globs they were parsed from
+mod glob_patterns {
+    use glob::Pattern;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(patterns: &[Pattern], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(patterns.iter().map(Pattern::as_str))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Pattern>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?.iter()
+            .map(|pattern| Pattern::new(pattern).map_err(|err| de::Error::custom(format!("invalid pattern {pattern:?}: {err}"))))
+            .collect()
+    }
+}
+
+// The exclude patterns of the IGNORE_FILE of the folder `dir`, if it has one. Lines are read like those of a
+// .gitignore: a name without a slash matches at any depth, one with a slash matches from `dir`, one ending
+// with a slash only matches folders. Blank lines and lines starting with # are skipped; negations with !
+// are not supported.
+pub fn ignore_file_patterns(dir: &Path) -> Result<Vec<Pattern>, SearchError> {
+    let path = dir.join(IGNORE_FILE);
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(SearchError::io_at(&path, err)),
+    };
+
+    let root = Pattern::escape(&dir.join("").to_string_lossy());
+    let mut patterns = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('!') {
+            warn!(path = %path.display(), line = number + 1, "negated patterns are not supported, ignoring");
+            continue;
+        }
+        let (line, folders_only) = match line.strip_suffix('/') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let glob = match line.strip_prefix('/') {
+            Some(line) => format!("{root}{line}"),
+            None if line.contains('/') => format!("{root}{line}"),
+            None => format!("{root}**/{line}"),
+        };
+        let globs = if folders_only { vec![format!("{glob}/**")] } else { vec![format!("{glob}/**"), glob] };
+        for glob in globs {
+            let pattern = Pattern::new(&glob).map_err(|err| {
+                SearchError::InvalidConfig(format!("{path}:{line}: {err}", path = path.display(), line = number + 1))
+            })?;
+            patterns.push(pattern);
+        }
+    }
+    Ok(patterns)
+}
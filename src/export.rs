@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    // One line per result, for people
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+/// One search result as it is exported, `rank` starts at 1
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedResult {
+    pub rank: usize,
+    pub path: PathBuf,
+    pub score: f32,
+    pub title: Option<String>,
+    // Plain text, without any highlighting
+    pub snippet: Option<String>,
+}
+
+impl ExportedResult {
+    fn fields(&self) -> [String; 5] {
+        [
+            self.rank.to_string(),
+            self.path.to_string_lossy().into_owned(),
+            self.score.to_string(),
+            self.title.clone().unwrap_or_default(),
+            self.snippet.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+const COLUMNS: [&str; 5] = ["rank", "path", "score", "title", "snippet"];
+
+// RFC 4180: fields with a comma, a quote or a line break are quoted, with their quotes doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// TSV has no quoting, so tabs and line breaks become spaces
+fn tsv_field(field: &str) -> String {
+    field.replace(['\t', '\r', '\n'], " ")
+}
+
+/// `results` as CSV with a header line, lines end with CRLF as RFC 4180 asks
+pub fn write_results_csv<W: Write>(results: &[ExportedResult], mut writer: W) -> io::Result<()> {
+    write!(writer, "{}\r\n", COLUMNS.join(","))?;
+    for result in results {
+        let fields = result.fields().map(|field| csv_field(&field));
+        write!(writer, "{}\r\n", fields.join(","))?;
+    }
+    writer.flush()
+}
+
+/// `results` as tab separated values with a header line
+pub fn write_results_tsv<W: Write>(results: &[ExportedResult], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "{}", COLUMNS.join("\t"))?;
+    for result in results {
+        let fields = result.fields().map(|field| tsv_field(&field));
+        writeln!(writer, "{}", fields.join("\t"))?;
+    }
+    writer.flush()
+}
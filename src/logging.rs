@@ -0,0 +1,61 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use tracing_subscriber::EnvFilter;
+
+use super::progress;
+
+// Filter directives, e.g. `debug` or `serux=debug,hyper=warn`
+pub const LOG_VAR: &str = "SERUX_LOG";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // One JSON object per event, for log collectors
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// Writes to stderr without mixing into a progress bar being drawn there
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        progress::suspend(|| io::stderr().write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+// Logs go to stderr, stdout is left to the output of the subcommands.
+// SERUX_LOG decides what is logged, `info` unless it is set; `verbose` adds the debug events of serux.
+// Only the first call takes effect, so that `main` can make sure errors are logged no matter how far `entry` got.
+pub fn init(format: LogFormat, verbose: bool) {
+    let default_directives = if verbose { "info,serux=debug" } else { "info" };
+    let (filter, invalid) = match EnvFilter::try_from_env(LOG_VAR) {
+        Ok(filter) => (filter, None),
+        Err(err) => (EnvFilter::new(default_directives), env::var_os(LOG_VAR).map(|_| err)),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(|| LogWriter)
+        .with_ansi(io::stderr().is_terminal());
+    let initialized = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    if let (Ok(()), Some(err)) = (initialized, invalid) {
+        tracing::warn!(error = %err, "ignoring invalid {LOG_VAR}, logging at {default_directives}");
+    }
+}
@@ -1,220 +1,1385 @@
-use std::env;
 use std::fs::{self, File};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
 use std::process::ExitCode;
+use std::time::{Duration, Instant};
 use std::result::Result;
 use std::str;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
 use xml::reader::{EventReader, XmlEvent};
 use xml::common::{Position, TextPosition};
+use scraper::{ElementRef, Html, Node, Selector};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use rayon::prelude::*;
+use tracing::{debug, error, field, info, info_span, warn};
 
 mod snowball;
 
-mod model;
-use model::*;
+use serux_lib::{cache, query, schema, stats};
+use serux_lib::model::{self, *};
+
+mod parsers;
+use parsers::csv::parse_csv_file;
+use parsers::epub::parse_epub_file;
+use parsers::json::parse_json_file;
 
 mod server;
+mod tls;
+
+mod snippet;
+use snippet::HighlightMode;
+
+mod config;
+use config::{ignore_file_patterns, Config, RankingName, CONFIG_FILE};
+
+mod watch;
+
+mod verify;
+
+mod export;
+use export::{ExportedResult, OutputFormat};
+
+mod logging;
+use logging::LogFormat;
+
+mod progress;
+use progress::IndexingProgress;
+
+mod query_log;
+mod metrics;
+mod wal;
+
+mod cli;
+
+#[cfg(test)]
+mod tests;
+use cli::{Backend, Cli, Command, IndexFormat, ListOrder, MigrateCommand, ModelCommand, ModelConfigCommand};
+use clap::{ColorChoice, CommandFactory, Parser as _};
 
-fn parse_xml_file(file_path: &Path) -> Result<String, ()> {
-    let file = File::open(file_path).map_err(|err| {
-        eprintln!("ERROR: could not open file {file_path}: {err}", file_path = file_path.display());
-    })?;
+// Returns the text of the document together with its title; the text of <title> elements is only indexed as the title
+fn parse_xml_file(file_path: &Path) -> Result<(String, Option<String>, Option<String>), SearchError> {
+    let file = File::open(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
     let er = EventReader::new(BufReader::new(file));
     let mut content = String::new();
+    let mut title: Option<String> = None;
+    // Text of the <title> or <h1> element being read, if the document has no title yet
+    let mut title_text: Option<String> = None;
+    // Number of <title> elements the text being read is in
+    let mut title_depth = 0usize;
     for event in er.into_iter() {
         let event = event.map_err(|err| {
             let TextPosition { row, column } = err.position();
-            let msg = err.msg();
-            eprintln!("{file_path}:{row}:{column}: ERROR: {msg}", file_path = file_path.display());
+            SearchError::XmlParseError { path: file_path.to_path_buf(), row, col: column, msg: err.msg().to_string() }
         })?;
 
-        if let XmlEvent::Characters(text) = event {
-            content.push_str(&text);
-            content.push(' ');
+        match event {
+            XmlEvent::StartElement { name, .. } if matches!(name.local_name.as_str(), "title" | "h1") => {
+                if name.local_name == "title" {
+                    title_depth += 1;
+                }
+                if title.is_none() {
+                    title_text = Some(String::new());
+                }
+            }
+            XmlEvent::EndElement { name } if matches!(name.local_name.as_str(), "title" | "h1") => {
+                if name.local_name == "title" {
+                    title_depth = title_depth.saturating_sub(1);
+                }
+                title = title.or(title_text.take().map(|text| text.trim().to_string()).filter(|text| !text.is_empty()));
+            }
+            XmlEvent::Characters(text) => {
+                if let Some(title_text) = title_text.as_mut() {
+                    title_text.push_str(&text);
+                }
+                if title_depth == 0 {
+                    content.push_str(&text);
+                    content.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((content, title, None))
+}
+
+// Collect the visible text below `element`, leaving out subtrees that are never rendered as page content
+fn push_html_text(element: ElementRef, content: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                content.push_str(text);
+                content.push(' ');
+            }
+            Node::Element(_) => {
+                let child = ElementRef::wrap(child).expect("element nodes wrap into ElementRef");
+                if !matches!(child.value().name(), "script" | "style" | "head" | "noscript" | "template") {
+                    push_html_text(child, content);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_html_file(file_path: &Path) -> Result<(String, Option<String>, Option<String>), SearchError> {
+    let source = fs::read_to_string(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
+    Ok(html_to_text(&source))
+}
+
+// Returns the visible text of the page together with its <title>, or its first <h1> if it has none,
+// and its metadata: the content of its description and keywords <meta> tags
+fn html_to_text(source: &str) -> (String, Option<String>, Option<String>) {
+    let document = Html::parse_document(source);
+
+    let mut content = String::new();
+    push_html_text(document.root_element(), &mut content);
+
+    let title = ["title", "h1"].iter().find_map(|selector| {
+        let selector = Selector::parse(selector).expect("title and h1 are valid selectors");
+        document.select(&selector).next()
+            .map(|title| title.text().collect::<String>().trim().to_string())
+            .filter(|title| !title.is_empty())
+    });
+
+    let selector = Selector::parse("meta[name][content]").expect("meta[name][content] is a valid selector");
+    let metadata = document.select(&selector)
+        .filter(|meta| meta.value().attr("name").is_some_and(|name| name.eq_ignore_ascii_case("description") || name.eq_ignore_ascii_case("keywords")))
+        .filter_map(|meta| meta.value().attr("content"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (content, title, Some(metadata).filter(|metadata| !metadata.trim().is_empty()))
+}
+
+fn parse_text_file(file_path: &Path) -> Result<String, SearchError> {
+    let bytes = fs::read(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// How many times heading text is repeated so that heading terms weigh more than body text
+const MARKDOWN_HEADING_REPEAT: usize = 3;
+
+// Keep only the text and inline code of the document. Link URLs, raw HTML, image alt text
+// and YAML/TOML front matter never reach the output.
+fn markdown_to_text(source: &str, heading_repeat: usize) -> String {
+    let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+    let mut content = String::new();
+    let mut heading: Option<String> = None;
+    let mut skip_depth = 0;
+
+    for event in Parser::new_ext(source, options) {
+        match event {
+            Event::Start(Tag::Image { .. } | Tag::MetadataBlock(_)) => skip_depth += 1,
+            Event::End(TagEnd::Image | TagEnd::MetadataBlock(_)) => skip_depth -= 1,
+            Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(heading) = heading.take() {
+                    for _ in 0..heading_repeat {
+                        content.push_str(&heading);
+                    }
+                }
+            }
+            Event::Text(text) | Event::Code(text) if skip_depth == 0 => {
+                let target = heading.as_mut().unwrap_or(&mut content);
+                target.push_str(&text);
+                target.push(' ');
+            }
+            Event::SoftBreak | Event::HardBreak => content.push(' '),
+            _ => {}
         }
     }
-    Ok(content)
+
+    content
 }
 
-fn save_model_as_json(model: &InMemoryModel, index_path: &str) -> Result<(), ()> {
-    println!("Saving {index_path}...");
+fn parse_markdown_file(file_path: &Path) -> Result<String, SearchError> {
+    let source = fs::read_to_string(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
+    Ok(markdown_to_text(&source, MARKDOWN_HEADING_REPEAT))
+}
+
+// The index is written here before it replaces index_path, see write_index
+fn temporary_index_path(index_path: &Path) -> PathBuf {
+    let mut path = index_path.as_os_str().to_owned();
+    path.push(".tmp");
+    PathBuf::from(path)
+}
 
-    let index_file = File::create(index_path).map_err(|err| {
-        eprintln!("ERROR: could not create index file {index_path}: {err}");
-    })?;
+// Write an index with `write` to a file of its own, and move it over `index_path` once it is on disk.
+// A crash while saving leaves the old index in place instead of half of the new one, and the changes
+// logged for the old index are only dropped once the new one replaced it.
+fn write_index<T>(index_path: &str, write: impl FnOnce(&File) -> Result<T, SearchError>) -> Result<T, SearchError> {
+    let path = Path::new(index_path);
+    let temporary_path = temporary_index_path(path);
+    let file = File::create(&temporary_path).map_err(|err| SearchError::io_at(&temporary_path, err))?;
+    let written = write(&file).and_then(|value| {
+        file.sync_all().map_err(|err| SearchError::io_at(&temporary_path, err))?;
+        Ok(value)
+    });
+    drop(file);
+    let value = match written {
+        Ok(value) => value,
+        Err(err) => {
+            let _ = fs::remove_file(&temporary_path);
+            return Err(err);
+        }
+    };
+    if let Err(err) = fs::rename(&temporary_path, path) {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(SearchError::io_at(path, err));
+    }
+    sync_folder(path)?;
+    Ok(value)
+}
 
-    serde_json::to_writer(BufWriter::new(index_file), &model).map_err(|err| {
-        eprintln!("ERROR: could not serialize index into file {index_path}: {err}")
-    })?;
+// Make the renaming of the file at `path` durable, which is up to the folder that lists it
+#[cfg(unix)]
+fn sync_folder(path: &Path) -> Result<(), SearchError> {
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    File::open(folder).and_then(|folder| folder.sync_all()).map_err(|err| SearchError::io_at(folder, err))
+}
 
+// Folders can't be opened to be synced elsewhere
+#[cfg(not(unix))]
+fn sync_folder(_path: &Path) -> Result<(), SearchError> {
     Ok(())
 }
 
-fn add_folder_to_model(dir_path: &Path, model: &mut dyn Model) -> Result<(), ()> {
-    let dir = fs::read_dir(dir_path).map_err(|err| {
-        eprintln!("ERROR: could not open directory {dir_path} for indexing: {err}",
-                  dir_path = dir_path.display());
-    })?;
+fn save_model_as_json(model: &InMemoryModel, index_path: &str) -> Result<(), SearchError> {
+    info!(path = %index_path, "saving index");
 
-    'next_file: for file in dir {
-        let file = file.map_err(|err| {
-            eprintln!("ERROR: could not read next file in directory {dir_path} during indexing: {err}",
-                      dir_path = dir_path.display());
+    write_index(index_path, |index_file| {
+        let mut writer = BufWriter::new(index_file);
+        serde_json::to_writer(&mut writer, &model)?;
+        Ok(writer.flush()?)
+    })
+}
+
+// First bytes of a binary index; anything else is read as JSON.
+// They are followed by the schema version, as a little endian u32.
+// Binary indexes don't name their fields, so every field added to the model changes their layout even when
+// SCHEMA_VERSION stays the same: bump the magic then, and move the old one to LEGACY_BINCODE_MAGICS.
+const BINCODE_MAGIC: &[u8; 4] = b"SRX4";
+
+// First bytes of binary indexes saved before documents had metadata (SRXB), before indexes had a schema version
+// (SRX2) and before the fields of documents were indexed on their own (SRX3), which can't be read anymore
+const LEGACY_BINCODE_MAGICS: [&[u8; 4]; 3] = [b"SRXB", b"SRX2", b"SRX3"];
+
+// First bytes of every zstd frame
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+// Passes everything through to `inner`, counting the bytes
+struct CountingWriter<W: Write> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Save the model as zstd compressed JSON. Returns the size of the JSON before compression.
+fn save_model_compressed(model: &InMemoryModel, index_path: &str, level: i32) -> Result<u64, SearchError> {
+    info!(path = %index_path, "saving index");
+
+    write_index(index_path, |index_file| {
+        let encoder = zstd::stream::write::Encoder::new(BufWriter::new(index_file), level)?;
+
+        let mut writer = CountingWriter { inner: BufWriter::new(encoder), count: 0 };
+        serde_json::to_writer(&mut writer, &model)?;
+
+        let encoder = writer.inner.into_inner().map_err(|err| err.into_error())?;
+        encoder.finish()?.flush()?;
+
+        Ok(writer.count)
+    })
+}
+
+// Decompression costs a bit of load time when the file is in the page cache, in exchange for less I/O.
+// benches/load_index.rs compares the size and load time with those of index.json.
+fn load_model_compressed(index_path: &str) -> Result<InMemoryModel, SearchError> {
+    let index_file = File::open(index_path).map_err(|err| SearchError::io_at(Path::new(index_path), err))?;
+    let json = zstd::stream::decode_all(BufReader::new(index_file))?;
+    schema::model_from_json(&json)
+}
+
+fn bincode_config() -> impl bincode::config::Config {
+    bincode::config::standard().with_little_endian().with_fixed_int_encoding()
+}
+
+fn save_model_as_bincode(model: &InMemoryModel, index_path: &str) -> Result<(), SearchError> {
+    info!(path = %index_path, "saving index");
+
+    write_index(index_path, |index_file| {
+        let mut writer = BufWriter::new(index_file);
+
+        writer.write_all(BINCODE_MAGIC)?;
+        bincode::serde::encode_into_std_write(model, &mut writer, bincode_config()).map_err(|err| match err {
+            bincode::error::EncodeError::Io { inner, .. } => SearchError::IoError(inner),
+            err => SearchError::IndexCorrupted(err.to_string()),
         })?;
 
+        Ok(writer.flush()?)
+    })
+}
+
+// The binary file is bigger than index.json because fixed-int encoding spends 8 bytes on every length and count.
+// benches/load_index.rs compares their size and load time.
+fn load_model_from_bincode(index_path: &str) -> Result<InMemoryModel, SearchError> {
+    let index_file = File::open(index_path).map_err(|err| SearchError::io_at(Path::new(index_path), err))?;
+    let mut reader = BufReader::new(index_file);
+
+    let mut magic = [0; BINCODE_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if LEGACY_BINCODE_MAGICS.contains(&&magic) {
+        return Err(legacy_bincode_error(index_path));
+    }
+    if &magic != BINCODE_MAGIC {
+        return Err(SearchError::IndexCorrupted(format!("{index_path} is not a binary index file")));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    check_bincode_version(version, index_path)?;
+
+    // The version is the first field of the model
+    bincode::serde::decode_from_std_read(&mut version.chain(reader), bincode_config())
+        .map_err(|err| SearchError::IndexCorrupted(err.to_string()))
+}
+
+fn legacy_bincode_error(index_path: &str) -> SearchError {
+    SearchError::IndexCorrupted(format!("{index_path} was saved by an older version of serux, index the folder again"))
+}
+
+// Binary indexes don't name their fields, so unlike JSON ones they can't be migrated
+fn check_bincode_version(version: [u8; 4], index_path: &str) -> Result<(), SearchError> {
+    let version = u32::from_le_bytes(version);
+    schema::check_schema_version(version)?;
+    if version < schema::SCHEMA_VERSION {
+        return Err(legacy_bincode_error(index_path));
+    }
+    Ok(())
+}
+
+// Load an index in any format, with the changes logged since it was saved
+fn load_model(index_path: &str) -> Result<InMemoryModel, SearchError> {
+    let mut model = read_model(index_path)?;
+    wal::replay(Path::new(index_path), &mut model, false)?;
+    Ok(model)
+}
+
+// Like load_model, for the process that logs the next changes: the operation a crash left half written
+// is cut off the log, see wal::replay
+fn load_model_to_log(index_path: &str) -> Result<InMemoryModel, SearchError> {
+    let mut model = read_model(index_path)?;
+    wal::replay(Path::new(index_path), &mut model, true)?;
+    Ok(model)
+}
+
+// Read an index file in any format, telling them apart by the magic header
+fn read_model(index_path: &str) -> Result<InMemoryModel, SearchError> {
+    let mut index_file = File::open(index_path).map_err(|err| SearchError::io_at(Path::new(index_path), err))?;
+
+    let mut magic = [0; BINCODE_MAGIC.len()];
+    if index_file.read_exact(&mut magic).is_ok() {
+        if &magic == BINCODE_MAGIC || LEGACY_BINCODE_MAGICS.contains(&&magic) {
+            return load_model_from_bincode(index_path);
+        }
+        if &magic == ZSTD_MAGIC {
+            return load_model_compressed(index_path);
+        }
+    }
+
+    // The schema version has to be read before the model, so the whole file is read at once
+    let json = fs::read(index_path).map_err(|err| SearchError::io_at(Path::new(index_path), err))?;
+    schema::model_from_json(&json)
+}
+
+// Save an index in the format its file name suggests: index.bin, index.json.zst or index.json.
+// The changes logged for it are in the file from then on, so the log is emptied once the file replaced the old one.
+fn save_model(model: &InMemoryModel, index_path: &str) -> Result<(), SearchError> {
+    if index_path.ends_with(".bin") {
+        save_model_as_bincode(model, index_path)?;
+    } else if index_path.ends_with(".zst") {
+        save_model_compressed(model, index_path, DEFAULT_COMPRESSION_LEVEL)?;
+    } else {
+        save_model_as_json(model, index_path)?;
+    }
+    wal::truncate(Path::new(index_path))
+}
+
+// Parse an index in any format from its bytes
+fn model_from_slice(bytes: &[u8]) -> Result<InMemoryModel, SearchError> {
+    if bytes.starts_with(ZSTD_MAGIC) {
+        return schema::model_from_json(&zstd::stream::decode_all(bytes)?);
+    }
+    if LEGACY_BINCODE_MAGICS.iter().any(|magic| bytes.starts_with(*magic)) {
+        return Err(legacy_bincode_error("the index"));
+    }
+    if let Some(encoded) = bytes.strip_prefix(BINCODE_MAGIC) {
+        let version = encoded.first_chunk::<4>().ok_or_else(|| SearchError::IndexCorrupted("the index ends after its header".to_string()))?;
+        check_bincode_version(*version, "the index")?;
+        return bincode::serde::decode_from_slice(encoded, bincode_config())
+            .map(|(model, _)| model)
+            .map_err(|err| SearchError::IndexCorrupted(err.to_string()));
+    }
+    schema::model_from_json(bytes)
+}
+
+// Rewrite the JSON index at `index_path` in the current schema, keeping a copy of the original next to it.
+// Returns the version it was migrated from, None if it was up to date.
+fn migrate_index(index_path: &str) -> Result<Option<u32>, SearchError> {
+    let bytes = fs::read(index_path).map_err(|err| SearchError::io_at(Path::new(index_path), err))?;
+    if LEGACY_BINCODE_MAGICS.iter().any(|magic| bytes.starts_with(*magic)) {
+        return Err(legacy_bincode_error(index_path));
+    }
+    if let Some(encoded) = bytes.strip_prefix(BINCODE_MAGIC) {
+        let version = encoded.first_chunk::<4>().ok_or_else(|| SearchError::IndexCorrupted(format!("{index_path} ends after its header")))?;
+        check_bincode_version(*version, index_path)?;
+        return Ok(None);
+    }
+
+    let compressed = bytes.starts_with(ZSTD_MAGIC);
+    let json = if compressed { zstd::stream::decode_all(bytes.as_slice())? } else { bytes };
+    let version = schema::schema_version(&json)?;
+    schema::check_schema_version(version)?;
+    if version == schema::SCHEMA_VERSION {
+        return Ok(None);
+    }
+    let mut model = schema::model_from_json(&json)?;
+    model.cache_idf();
+    model.cache_term_filter();
+
+    let backup_path = format!("{index_path}.v{version}.bak");
+    fs::copy(index_path, &backup_path).map_err(|err| SearchError::io_at(Path::new(&backup_path), err))?;
+    if compressed {
+        save_model_compressed(&model, index_path, DEFAULT_COMPRESSION_LEVEL)?;
+    } else {
+        save_model_as_json(&model, index_path)?;
+    }
+    Ok(Some(version))
+}
+
+// Documents migrated to SQLite between two progress lines
+const MIGRATION_PROGRESS_DOCS: usize = 1000;
+
+// Copy the documents of an index into a new SQLite index, in one transaction. The SQLite backend tokenizes
+// queries with the default Lexer, so the terms of the index must have been made the same way to be found.
+fn migrate_to_sqlite(index_path: &str, db_path: &Path) -> Result<usize, SearchError> {
+    // Adding to an existing database would mix up the documents of two indexes
+    if db_path.exists() {
+        return Err(SearchError::Usage(format!("{db_path} already exists", db_path = db_path.display())));
+    }
+    let model = load_model(index_path)?;
+    let config = &model.config;
+    if config.stemming || config.ngram_size.is_some() || config.ascii_folding || config.diacritic_folding || config.hyphen_compounds || config.tokenize_urls {
+        return Err(SearchError::Unsupported("migrating an index with stemming, n-grams, ASCII or diacritic folding, compounds or URLs to SQLite"));
+    }
+    if config.case_sensitive || config.cjk_ngram || config.min_token_len.is_some() || config.max_token_len.is_some() {
+        return Err(SearchError::Unsupported("migrating an index with case sensitive, CJK or length bounded tokens to SQLite"));
+    }
+
+    let mut sqlite_model = SqliteModel::open(db_path)?;
+    sqlite_model.begin()?;
+    let total = model.tfpd.len();
+    let migrated = model.tfpd.iter().enumerate().try_for_each(|(done, (path, entry))| {
+        let tf = entry.tf.iter().map(|(term, (freq, _))| (term.as_str(), *freq));
+        sqlite_model.add_term_freqs(&sqlite_path(path), entry.term_count, tf)?;
+        if (done + 1) % MIGRATION_PROGRESS_DOCS == 0 {
+            info!(done = done + 1, total, "migrating documents");
+        }
+        Ok(())
+    });
+    match migrated {
+        Ok(()) => sqlite_model.commit()?,
+        Err(err) => {
+            sqlite_model.rollback()?;
+            return Err(err);
+        }
+    }
+    sqlite_model.verify_integrity()?;
+    Ok(total)
+}
+
+// Same as load_model, but the file is mapped into memory and parsed straight from the mapping
+// instead of being copied through a read buffer
+#[cfg(not(target_arch = "wasm32"))]
+fn load_model_mmap(index_path: &Path) -> Result<InMemoryModel, SearchError> {
+    let index_file = File::open(index_path).map_err(|err| SearchError::io_at(index_path, err))?;
+
+    // SAFETY: the mapping is read-only and only lives until the model is parsed out of it.
+    // Mapping a file is undefined behaviour if the file is modified while it is mapped;
+    // the index files are only ever written by the index subcommand, which is not expected
+    // to run on the same file while it is being loaded.
+    let mmap = match unsafe { memmap2::Mmap::map(&index_file) } {
+        Ok(mmap) => mmap,
+        Err(err) => {
+            warn!(path = %index_path.display(), error = %err, "could not map index file into memory, reading it instead");
+            return load_model(index_path_str(index_path)?);
+        }
+    };
+    let mut model = model_from_slice(&mmap)?;
+    wal::replay(index_path, &mut model, false)?;
+    Ok(model)
+}
+
+// There is no mmap on WebAssembly
+#[cfg(target_arch = "wasm32")]
+fn load_model_mmap(index_path: &Path) -> Result<InMemoryModel, SearchError> {
+    load_model(index_path_str(index_path)?)
+}
+
+// load_model takes the path as text, which a path that is not UTF-8 can't be given as
+fn index_path_str(index_path: &Path) -> Result<&str, SearchError> {
+    index_path.to_str().ok_or_else(|| SearchError::Usage(format!("{} is not a UTF-8 path", index_path.display())))
+}
+
+// Files under `dir_path` that changed since they were last indexed, in the order they are listed.
+// With a `max_depth` of 1 only the files right in `dir_path` are listed, with 0 none at all.
+fn files_to_index(dir_path: &Path, model: &mut dyn Model, config: &Config, max_depth: Option<usize>, files: &mut Vec<PathBuf>) -> Result<(), SearchError> {
+    if max_depth == Some(0) {
+        return Ok(());
+    }
+    let dir = fs::read_dir(dir_path).map_err(|err| SearchError::io_at(dir_path, err))?;
+
+    'next_file: for file in dir {
+        let file = file.map_err(|err| SearchError::io_at(dir_path, err))?;
+
         let file_path = file.path();
 
-        let file_type = file.file_type().map_err(|err| {
-            eprintln!("ERROR: could not determine type of file {file_path}: {err}",
-                      file_path = file_path.display());
-        })?;
+        let file_type = file.file_type().map_err(|err| SearchError::io_at(&file_path, err))?;
+
+        if config.excludes_path(&file_path, file_type.is_dir()) {
+            debug!(path = %file_path.display(), "skipping, it matches an exclude pattern");
+            continue 'next_file;
+        }
 
         // Recursively index all files in the directory
         if file_type.is_dir() {
-            add_folder_to_model(&file_path, model)?;
+            files_to_index(&file_path, model, config, max_depth.map(|depth| depth - 1), files)?;
             continue 'next_file;
         }
 
-        // how does this work with symlinks?
-        println!("Indexing {:?}...", &file_path);
+        if !config.indexes_file(&file_path) {
+            debug!(path = %file_path.display(), "skipping file, its extension is filtered out");
+            continue 'next_file;
+        }
 
-        let content = match parse_xml_file(&file_path) {
-            Ok(content) => content.chars().collect::<Vec<_>>(),
-            Err(()) => continue 'next_file,
-        };
+        let last_modified = file.metadata().and_then(|metadata| metadata.modified())
+            .map_err(|err| SearchError::io_at(&file_path, err))?;
 
-        model.add_document(file_path, &content)?;
+        if !model.requires_reindexing(&file_path, last_modified)? {
+            debug!(path = %file_path.display(), "ignoring file, it is already indexed");
+            continue 'next_file;
+        }
+
+        files.push(file_path);
     }
 
     Ok(())
 }
 
-fn usage(program: &String) {
-    eprintln!("Usage: {program} [SUBCOMMAND] [OPTIONS]");
-    eprintln!("Subcommands:");
-    eprintln!("    index <folder>                  index the <folder> and save the index to index.json file");
-    eprintln!("    search <index-file> <query>     search <query> within the <index-file>");
-    eprintln!("    serve <index-file> [address]    start local HTTP server with Web Interface");
+struct ParsedFile {
+    content: Vec<char>,
+    title: Option<String>,
+    // Indexed apart from the content, see model::DocumentFields
+    metadata: Option<String>,
+    mime_type: &'static str,
 }
 
-// Entry point of the program
-fn entry() -> Result<(), ()> {
-    let mut args = env::args();
-    let program = args.next().expect("path to program is provided");
+fn parse_file(file_path: &Path, config: &Config) -> Result<ParsedFile, SearchError> {
+    // how does this work with symlinks?
+    debug!(path = %file_path.display(), "indexing file");
+
+    let extension = file_path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase());
+    let (parsed, mime_type) = match extension.as_deref() {
+        Some("txt") => (parse_text_file(file_path).map(|content| (content, None, None)), "text/plain"),
+        Some("md" | "markdown") => (parse_markdown_file(file_path).map(|content| (content, None, None)), "text/markdown"),
+        Some("json") => (parse_json_file(file_path).map(|content| (content, None, None)), "application/json"),
+        Some("csv") => (parse_csv_file(file_path, config.csv_delimiter).map(|content| (content, None, None)), "text/csv"),
+        Some("tsv") => (parse_csv_file(file_path, config.csv_delimiter).map(|content| (content, None, None)), "text/tab-separated-values"),
+        Some("epub") => (parse_epub_file(file_path).map(|(content, title)| (content, title, None)), "application/epub+zip"),
+        Some("html" | "htm") => (parse_html_file(file_path)
+            .or_else(|_| parse_xml_file(file_path)), "text/html"),
+        Some("xml" | "xhtml") => (parse_xml_file(file_path)
+            .or_else(|_| parse_html_file(file_path)), "application/xml"),
+        _ => (parse_xml_file(file_path), "application/xml"),
+    };
+    let (content, title, metadata) = parsed?;
+    Ok(ParsedFile { content: content.chars().collect(), title, metadata, mime_type })
+}
+
+// Files that can't be parsed are reported and left out of the index
+fn parse_file_or_report(file_path: &Path, config: &Config) -> Option<ParsedFile> {
+    parse_file(file_path, config).map_err(|err| {
+        error!(path = %file_path.display(), error = %err, "could not parse file");
+    }).ok()
+}
+
+// Name of the file shown next to the progress bar
+fn file_name(file_path: &Path) -> std::borrow::Cow<'_, str> {
+    file_path.file_name().unwrap_or(file_path.as_os_str()).to_string_lossy()
+}
+
+// Files are parsed in parallel and added to the model one by one
+fn add_folder_to_model(dir_path: &Path, model: &mut dyn Model, config: &Config, max_depth: Option<usize>, show_progress: bool) -> Result<(), SearchError> {
+    let span = info_span!("add_folder", path = %dir_path.display(), doc_count = field::Empty, elapsed_ms = field::Empty);
+    let _entered = span.enter();
+    let start = Instant::now();
 
-    let mut subcommand = None;
-    let mut use_sqlite_mode = false;
+    let mut files = Vec::new();
+    files_to_index(dir_path, model, config, max_depth, &mut files)?;
 
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "--sqlite" => use_sqlite_mode = true,
-            _=> {
-                subcommand = Some(arg);
-                break
+    let progress = IndexingProgress::new(files.len(), show_progress);
+    let parsed = files.into_par_iter()
+        .filter_map(|file_path| match parse_file_or_report(&file_path, config) {
+            Some(file) => Some((file_path, file)),
+            None => {
+                progress.advance(&file_name(&file_path));
+                None
             }
+        })
+        .collect::<Vec<_>>();
+
+    let doc_count = parsed.len();
+    for (file_path, file) in parsed {
+        model.add_document(file_path.clone(), &file.content, Some(file.mime_type))?;
+        if let Some(title) = file.title {
+            model.set_document_title(&file_path, title)?;
         }
+        progress.advance(&file_name(&file_path));
     }
 
-    let subcommand = subcommand.ok_or_else(|| {
-        usage(&program);
-        eprintln!("ERROR: no subcommand is provided");
-    })?;
+    progress.finish(doc_count, start.elapsed());
+    span.record("doc_count", doc_count);
+    span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+    info!("indexed folder");
+    Ok(())
+}
 
-    match subcommand.as_str() {
-        "index" => {
-            let dir_path = args.next().ok_or_else(|| {
-                usage(&program);
-                eprintln!("ERROR: no directory is provided for {subcommand} subcommand");
-            })?;
+// Same as add_folder_to_model, but the files are also tokenized in parallel.
+// Only merging the term frequencies into the model is serial, so that `df` needs no locking.
+fn add_folder_to_in_memory_model(dir_path: &Path, model: &mut InMemoryModel, config: &Config, max_depth: Option<usize>, show_progress: bool) -> Result<(), SearchError> {
+    let span = info_span!("add_folder", path = %dir_path.display(), doc_count = field::Empty, elapsed_ms = field::Empty);
+    let _entered = span.enter();
+    let start = Instant::now();
 
-            if use_sqlite_mode {
-                let index_path = "index.db";
+    let mut files = Vec::new();
+    files_to_index(dir_path, model, config, max_depth, &mut files)?;
+
+    let progress = IndexingProgress::new(files.len(), show_progress);
+    let analyzed = {
+        let model = &*model;
+        files.into_par_iter()
+            .filter_map(|file_path| {
+                let analyzed = parse_file_or_report(&file_path, config).map(|file| {
+                    let document = model.analyze(&file.content, file.title.as_deref(), file.metadata.as_deref());
+                    (file_path.clone(), document, file.title, file.mime_type)
+                });
+                progress.advance(&file_name(&file_path));
+                analyzed
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let doc_count = analyzed.len();
+    for (file_path, document, title, mime_type) in analyzed {
+        model.merge_document(file_path, document, title, Some(mime_type));
+    }
+
+    progress.finish(doc_count, start.elapsed());
+    span.record("doc_count", doc_count);
+    span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+    info!("indexed folder");
+    Ok(())
+}
+
+// Empty model with the tokenizer settings of the configuration and the command line
+fn new_model(config: &Config, no_stop_words: bool, ranking: Option<RankingAlgorithm>, max_tokens_per_doc: Option<usize>, ngram_size: Option<usize>, ascii_folding: bool, tf_normalization: TfNormalization) -> Result<InMemoryModel, SearchError> {
+    let (stop_words, stop_words_file) = match &config.stop_words_file {
+        _ if no_stop_words => (HashSet::new(), None),
+        Some(stop_words_file) => (load_stop_words(stop_words_file)?, Some(stop_words_file.clone())),
+        None => (default_stop_words(), None),
+    };
+    let mut builder = InMemoryModel::builder()
+        .stemming(config.stemming)
+        .case_sensitive(config.case_sensitive)
+        .hyphen_compounds(config.hyphen_compounds)
+        .tokenize_urls(config.tokenize_urls)
+        .cjk_ngram(config.cjk_ngram)
+        .diacritic_folding(config.diacritic_folding)
+        .freshness_boost(config.freshness_boost)
+        .mmr(config.mmr)
+        .use_wand(config.use_wand)
+        .ascii_folding(ascii_folding)
+        .tf_normalization(tf_normalization)
+        .stop_words(stop_words)
+        .ranking(ranking.unwrap_or_default());
+    if let Some(stop_words_file) = stop_words_file {
+        builder = builder.stop_words_file(stop_words_file);
+    }
+    if let Some(synonyms_file) = &config.synonyms_file {
+        builder = builder.synonyms(load_synonyms(synonyms_file)?);
+    }
+    if let Some(max_tokens_per_doc) = max_tokens_per_doc {
+        builder = builder.max_tokens_per_doc(max_tokens_per_doc);
+    }
+    if let Some(ngram_size) = ngram_size {
+        builder = builder.ngram_size(ngram_size);
+    }
+    if let Some(half_life_days) = config.freshness_half_life_days {
+        builder = builder.freshness_half_life_days(half_life_days);
+    }
+    if let Some(lambda) = config.mmr_lambda {
+        builder = builder.mmr_lambda(lambda);
+    }
+    if let Some(proximity_weight) = config.proximity_weight {
+        builder = builder.proximity_weight(proximity_weight);
+    }
+    if let Some(field_weights) = config.field_weights {
+        builder = builder.field_weights(field_weights);
+    }
+    if let Some(min_token_len) = config.min_token_len {
+        builder = builder.min_token_len(min_token_len);
+    }
+    if let Some(max_token_len) = config.max_token_len {
+        builder = builder.max_token_len(max_token_len);
+    }
+    Ok(builder.build())
+}
+
+// Snippets show where the terms of `prompt` occur, there are none for an empty prompt
+fn print_search_results(model: &impl Model, results: Vec<(PathBuf, f32)>, prompt: &[char], max_results: usize, highlight: HighlightMode, format: OutputFormat) -> Result<(), SearchError> {
+    let page = SearchPage::paginate(results, 0, max_results);
+    let tokens = snippet::snippet_tokens(model, prompt);
+    if format != OutputFormat::Text {
+        let results = page.results.into_iter().enumerate().map(|(i, (path, score))| {
+            let title = model.document_title(&path);
+            let snippet = snippet::snippet_for(model, &path, &tokens, &model.match_positions(&path, prompt), 80);
+            ExportedResult { rank: i + 1, path, score, title, snippet }
+        }).collect::<Vec<_>>();
+        let stdout = std::io::stdout().lock();
+        match format {
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut BufWriter::new(stdout), &results)?;
+                println!();
+            }
+            OutputFormat::Csv => export::write_results_csv(&results, BufWriter::new(stdout))?,
+            _ => export::write_results_tsv(&results, BufWriter::new(stdout))?,
+        }
+        return Ok(());
+    }
+
+    for (path, rank) in &page.results {
+        println!("{path} {rank}", path = path.display());
+        if let Some(snippet) = snippet::snippet_for(model, path, &tokens, &model.match_positions(path, prompt), 80) {
+            println!("    {}", snippet::highlight_snippet(model, &snippet, &tokens, highlight));
+        }
+    }
+    println!("{shown} of {total} matching documents, {count} documents indexed",
+             shown = page.results.len(), total = page.total_hits, count = model.document_count()?);
+    Ok(())
+}
+
+fn print_score_explanation(explanation: &ScoreExplanation) {
+    let ranking = match explanation.ranking {
+        RankingAlgorithm::TfIdf => "TF-IDF".to_string(),
+        RankingAlgorithm::Bm25 { k1, b } => format!("BM25 (k1 = {k1}, b = {b})"),
+    };
+    println!("Document:       {}", explanation.path.display());
+    println!("Score:          {}", explanation.total_score);
+    println!("Ranking:        {ranking}");
+    println!("Length:         {} terms", explanation.document_length);
+    println!("Corpus:         {} documents, {:.1} terms on average", explanation.document_count, explanation.average_document_length);
+
+    println!();
+    if explanation.term_scores.is_empty() {
+        println!("No term of the query is indexed");
+        return;
+    }
+    let term_width = explanation.term_scores.iter().map(|score| score.term.chars().count()).max().unwrap_or(0).max("term".len());
+    println!("    {:<term_width$} {:>6} {:>6} {:>6} {:>10} {:>10} {:>12}", "term", "weight", "count", "df", "tf", "idf", "contribution");
+    for TermScore { term, weight, raw_count, document_frequency, tf, idf, contribution } in &explanation.term_scores {
+        println!("    {term:<term_width$} {weight:>6.2} {raw_count:>6} {document_frequency:>6} {tf:>10.6} {idf:>10.6} {contribution:>12.6}");
+    }
+    if explanation.proximity_score != 0f32 {
+        println!();
+        println!("Proximity:      {:.6} for query terms close to each other", explanation.proximity_score);
+    }
+}
+
+// Comma separated list of extensions as given to --include-ext and --exclude-ext, with or without their dots
+fn parse_extensions(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|extension| extension.trim().trim_start_matches('.'))
+        .filter(|extension| !extension.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Index path given on the command line, or else the one of the configuration file
+fn index_path_or_config(index_path: Option<String>, config: &Config, subcommand: &str) -> Result<String, SearchError> {
+    index_path.or(config.index_path.as_ref().map(|index_path| index_path.to_string_lossy().into_owned())).ok_or_else(|| {
+        SearchError::Usage(format!("no path to index is provided for {subcommand} subcommand"))
+    })
+}
+
+// Command line flags that apply to every subcommand override the configuration file
+fn apply_global_flags(config: &mut Config, cli: &Cli) {
+    if cli.bm25 {
+        config.ranking_algorithm = Some(RankingName::Bm25);
+    }
+    if cli.stem {
+        config.stemming = true;
+    }
+    if let Some(stop_words_path) = &cli.stop_words {
+        config.stop_words_file = Some(stop_words_path.clone());
+    }
+}
+
+// Entry point of the program
+fn entry() -> Result<(), SearchError> {
+    let cli = Cli::parse();
+
+    let mut config = Config::load_or_default(Path::new(CONFIG_FILE))?;
+    apply_global_flags(&mut config, &cli);
+
+    let use_sqlite_mode = cli.sqlite;
+    let no_stop_words = cli.no_stop_words;
+    let ascii_folding = cli.ascii_folding;
+    let max_tokens_per_doc = cli.max_tokens_per_doc;
+    let ngram_size = cli.ngram_size;
+    let tf_normalization = cli.tf_normalization.unwrap_or_default();
+
+    logging::init(cli.log_format.unwrap_or_default(), cli.verbose);
+
+    let ranking = config.ranking_algorithm.map(RankingName::algorithm);
 
-                if let Err(err) = fs::remove_file(index_path) {
+    let show_progress = !cli.no_progress && std::io::stdout().is_terminal();
+
+    let highlight = match cli.color {
+        ColorChoice::Always => HighlightMode::Ansi,
+        ColorChoice::Never => HighlightMode::None,
+        ColorChoice::Auto if std::io::stdout().is_terminal() => HighlightMode::Ansi,
+        ColorChoice::Auto => HighlightMode::None,
+    };
+
+    match cli.command {
+        Command::Index(args) => {
+            let dir_path = args.folder;
+            let format = args.format;
+            let incremental = args.incremental;
+            let max_depth = args.depth;
+            let hash_check = args.hash_check;
+            let idf = args.idf;
+            let compression_level = args.compress_level.or(args.compress.then_some(DEFAULT_COMPRESSION_LEVEL));
+
+            if args.threads.is_some() {
+                config.thread_count = args.threads;
+            }
+            if let Some(include) = args.include_ext {
+                config.file_extensions = parse_extensions(&include);
+                if config.file_extensions.is_empty() {
+                    return Err(SearchError::Usage("no extension is provided for --include-ext flag".to_string()));
+                }
+            }
+            if let Some(exclude) = args.exclude_ext {
+                config.exclude_extensions = parse_extensions(&exclude);
+                if config.exclude_extensions.is_empty() {
+                    return Err(SearchError::Usage("no extension is provided for --exclude-ext flag".to_string()));
+                }
+            }
+            if !args.exclude_patterns.is_empty() {
+                config.exclude_patterns = args.exclude_patterns;
+            }
+            config.exclude_patterns.extend(ignore_file_patterns(Path::new(&dir_path))?);
+            if args.synonyms.is_some() {
+                config.synonyms_file = args.synonyms;
+            }
+            config.case_sensitive |= args.case_sensitive;
+            config.hyphen_compounds |= args.hyphen_compounds;
+            config.tokenize_urls |= args.tokenize_urls;
+            config.cjk_ngram |= args.cjk_ngram;
+            config.diacritic_folding |= args.diacritic_folding;
+            if args.csv_delimiter.is_some() {
+                config.csv_delimiter = args.csv_delimiter;
+            }
+            if args.min_token_len.is_some() {
+                config.min_token_len = args.min_token_len;
+            }
+            if args.max_token_len.is_some() {
+                config.max_token_len = args.max_token_len;
+            }
+            if args.field_weights.is_some() {
+                config.field_weights = args.field_weights;
+            }
+            config.freshness_boost |= args.freshness_boost || args.freshness_half_life_days.is_some();
+            if args.freshness_half_life_days.is_some() {
+                config.freshness_half_life_days = args.freshness_half_life_days;
+            }
+            config.mmr |= args.mmr || args.mmr_lambda.is_some();
+            if args.mmr_lambda.is_some() {
+                config.mmr_lambda = args.mmr_lambda;
+            }
+            if args.proximity_weight.is_some() {
+                config.proximity_weight = args.proximity_weight;
+            }
+            config.use_wand |= args.wand;
+
+            // The flags can contradict each other or the configuration file
+            config.validate()?;
+
+            // By default Rayon uses one thread per CPU
+            if let Some(threads) = config.thread_count {
+                rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()
+                    .map_err(std::io::Error::other)?;
+            }
+
+            let backend = args.backend.unwrap_or(if use_sqlite_mode { Backend::Sqlite } else { Backend::InMemory });
+            if backend != Backend::InMemory {
+                if idf.is_some() {
+                    return Err(SearchError::Unsupported("IDF variants with a SQLite index"));
+                }
+                if config.case_sensitive {
+                    return Err(SearchError::Unsupported("case sensitive SQLite indexes"));
+                }
+                if config.min_token_len.is_some() || config.max_token_len.is_some() {
+                    return Err(SearchError::Unsupported("token length bounds with a SQLite index"));
+                }
+                if config.cjk_ngram {
+                    return Err(SearchError::Unsupported("CJK n-grams with a SQLite index"));
+                }
+                if config.diacritic_folding {
+                    return Err(SearchError::Unsupported("diacritic folding with a SQLite index"));
+                }
+                if hash_check {
+                    return Err(SearchError::Unsupported("hash checks with a SQLite index"));
+                }
+                let index_path = config.index_path.clone().unwrap_or(PathBuf::from("index.db"));
+
+                if let Err(err) = fs::remove_file(&index_path) {
                     if err.kind() != std::io::ErrorKind::NotFound {
-                        eprintln!("ERROR: could not delete file {index_path}: {err}");
-                        return Err(())
+                        return Err(SearchError::io_at(&index_path, err));
                     }
                 }
 
-                let mut model = SqliteModel::open(Path::new(index_path))?;
-                model.begin()?;
-                add_folder_to_model(Path::new(&dir_path), &mut model)?;
-                model.commit()
+                if backend == Backend::Fts5 {
+                    let mut model = SqliteFts5Model::open(&index_path)?;
+                    model.begin()?;
+                    add_folder_to_model(Path::new(&dir_path), &mut model, &config, max_depth, show_progress)?;
+                    model.commit()
+                } else {
+                    let mut model = SqliteModel::open(&index_path)?;
+                    model.begin()?;
+                    add_folder_to_model(Path::new(&dir_path), &mut model, &config, max_depth, show_progress)?;
+                    model.commit()
+                }
             } else {
-                let index_path = "index.json";
-                let mut model = Default::default();
-                add_folder_to_model(Path::new(&dir_path), &mut model)?;
-                save_model_as_json(&model, index_path)
+                let default_index_path = match (format, compression_level) {
+                    (IndexFormat::Json, None) => "index.json",
+                    (IndexFormat::Json, Some(_)) => "index.json.zst",
+                    (IndexFormat::Binary, None) => "index.bin",
+                    (IndexFormat::Binary, Some(_)) => {
+                        return Err(SearchError::Usage("only JSON indexes can be compressed".to_string()));
+                    }
+                };
+                let index_path = match &config.index_path {
+                    Some(index_path) => index_path.to_string_lossy().into_owned(),
+                    None => default_index_path.to_string(),
+                };
+                let index_path = index_path.as_str();
+
+                // An incremental run keeps the tokenizer settings of the existing index
+                let mut model = if incremental && Path::new(index_path).exists() {
+                    load_model(index_path)?
+                } else {
+                    new_model(&config, no_stop_words, ranking, max_tokens_per_doc, ngram_size, ascii_folding, tf_normalization)?
+                };
+                if let Some(idf) = idf {
+                    model.set_idf(idf);
+                }
+                model.set_hash_check(hash_check);
+                add_folder_to_in_memory_model(Path::new(&dir_path), &mut model, &config, max_depth, show_progress)?;
+                model.cache_idf();
+                model.cache_term_filter();
+                match (format, compression_level) {
+                    (IndexFormat::Json, Some(level)) => {
+                        let uncompressed_size = save_model_compressed(&model, index_path, level)?;
+                        if tracing::enabled!(tracing::Level::DEBUG) {
+                            let compressed_size = fs::metadata(index_path)?.len();
+                            debug!(uncompressed_size, compressed_size, ratio = uncompressed_size as f64 / compressed_size as f64,
+                                   "compressed index");
+                        }
+                        Ok(())
+                    }
+                    (IndexFormat::Json, None) => save_model_as_json(&model, index_path),
+                    (IndexFormat::Binary, _) => save_model_as_bincode(&model, index_path),
+                }?;
+                // The changes logged for an incremental run are in the index now, and a fresh index has none
+                wal::truncate(Path::new(index_path))
             }
         },
-        "search" => {
-            let index_path = args.next().ok_or_else(|| {
-                usage(&program);
-                eprintln!("ERROR: no path to index is provided for {subcommand} subcommand");
-            })?;
-
-            let prompt = args.next().ok_or_else(|| {
-                usage(&program);
-                eprintln!("ERROR: no search query is provided {subcommand} subcommand");
-            })?.chars().collect::<Vec<_>>();
+        Command::Search { index_file, query: prompt, output_format: format, regex } => {
+            // The index file can be left out when the configuration names one
+            let index_path = index_path_or_config(index_file, &config, "search")?;
 
             if use_sqlite_mode {
+                if regex {
+                    return Err(SearchError::Unsupported("regex search"));
+                }
                 let model = SqliteModel::open(Path::new(&index_path))?;
-                for (path, rank) in model.search_query(&prompt)?.iter().take(20) {
-                    println!("{path} {rank}", path = path.display());
+                let prompt = prompt.chars().collect::<Vec<_>>();
+                let results = model.search_query_ranked(&prompt, ranking.unwrap_or(model.default_ranking()))?;
+                print_search_results(&model, results, &prompt, config.max_results, highlight, format)
+            } else {
+                let mut model = load_model(&index_path)?;
+                model.finalize();
+                if regex {
+                    let results = model.search_regex(&prompt, DEFAULT_MAX_REGEX_TERMS)?;
+                    return print_search_results(&model, results, &[], config.max_results, highlight, format);
+                }
+                let prompt = prompt.chars().collect::<Vec<_>>();
+                let results = model.search_query_ranked(&prompt, ranking.unwrap_or(model.default_ranking()))?;
+                print_search_results(&model, results, &prompt, config.max_results, highlight, format)
+            }
+        },
+        Command::Serve(args) => {
+            let mmap = args.mmap;
+            let case_sensitive = args.case_sensitive;
+            let idf = args.idf;
+            let reload_interval = match args.reload_interval_secs {
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => args.watch_index.then_some(server::DEFAULT_RELOAD_INTERVAL),
+            };
+            if args.query_log.is_some() {
+                config.query_log_path = args.query_log;
+            }
+            if args.cors_origin.is_some() {
+                config.cors_origin = args.cors_origin;
+            }
+            if let Some(request_timeout_ms) = args.request_timeout_ms {
+                config.request_timeout_ms = request_timeout_ms;
+            }
+            if let Some(read_timeout_ms) = args.read_timeout_ms {
+                config.read_timeout_ms = read_timeout_ms;
+            }
+            if let Some(max_connections) = args.max_connections {
+                config.max_connections = max_connections;
+            }
+            if let Some(min_should_match) = args.default_min_should_match {
+                config.default_min_should_match = min_should_match;
+            }
+            config.validate()?;
+
+            let index_path = index_path_or_config(args.index_file, &config, "serve")?;
+
+            let address = args.address.unwrap_or_else(|| config.serve_address.clone());
+
+            // Read before the index, a bad certificate should not have to wait for a big index to load.
+            // The arguments require each other, so both or neither are given.
+            let tls = match (args.tls_cert, args.tls_key) {
+                (Some(cert), Some(key)) => Some(tls::load_server_config(Path::new(&cert), Path::new(&key))?),
+                _ => None,
+            };
+            let auth = match (args.auth_user, args.auth_password) {
+                (Some(user), Some(password)) => Some(server::BasicAuth::new(user, password)?),
+                _ => None,
+            };
+
+            let backend = args.backend.unwrap_or(if use_sqlite_mode { Backend::Sqlite } else { Backend::InMemory });
+            // A SQLite index is searched as it changes, so only in-memory indexes reload on SIGHUP by default
+            let reload_on_sighup = args.reload_on_sighup
+                || (backend == Backend::InMemory && cfg!(target_os = "linux") && !args.no_reload_on_sighup);
+            if backend != Backend::InMemory && (reload_interval.is_some() || reload_on_sighup) {
+                return Err(SearchError::Usage(
+                    "a SQLite index is searched as it changes and never reloaded, leave out --reload-interval-secs, --watch-index and --reload-on-sighup".to_string(),
+                ));
+            }
+            if backend == Backend::Fts5 {
+                if idf.is_some() || ranking == Some(RankingAlgorithm::TfIdf) {
+                    return Err(SearchError::Unsupported("TF-IDF ranking with an FTS5 index"));
+                }
+                if case_sensitive {
+                    return Err(SearchError::Unsupported("case sensitive SQLite indexes"));
+                }
+                let model = SqliteFts5Model::open(Path::new(&index_path))?;
+                let ranking = ranking.unwrap_or(model.default_ranking());
+                server::start(&address, server::SharedModel::new(model), ranking, &config, tls, auth)
+            } else if backend == Backend::Sqlite {
+                if idf.is_some() {
+                    return Err(SearchError::Unsupported("IDF variants with a SQLite index"));
                 }
+                if case_sensitive {
+                    return Err(SearchError::Unsupported("case sensitive SQLite indexes"));
+                }
+                // One connection per thread that may be searching at the same time
+                let readers = config.thread_count.unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()));
+                let model = SqliteModel::open_pooled(Path::new(&index_path), readers)?;
+
+                let ranking = ranking.unwrap_or(model.default_ranking());
+                server::start(&address, server::SharedModel::new(model), ranking, &config, tls, auth)
             } else {
-                let index_file = File::open(&index_path).map_err(|err| {
-                    eprintln!("ERROR: could not open index file {index_path}: {err}");
-                })?;
+                // Also used to reload the index file when it changes
+                let load = move |index_path: &Path| {
+                    let mut model = if mmap {
+                        load_model_mmap(index_path)?
+                    } else {
+                        load_model(&index_path.to_string_lossy())?
+                    };
+                    // Only the ranking changes, the index file is left as it is
+                    if let Some(idf) = idf {
+                        model.set_idf(idf);
+                    }
+                    model.finalize();
+                    Ok(model)
+                };
 
-                let model = serde_json::from_reader::<_,InMemoryModel>(index_file).map_err(|err| {
-                    eprintln!("ERROR: could not parse index file {index_path}: {err}");
-                })?;
+                let start = Instant::now();
+                let model = load(Path::new(&index_path))?;
+                // Terms can only be told apart by case if they were indexed that way
+                if case_sensitive {
+                    if !model.config.case_sensitive {
+                        return Err(SearchError::Usage(format!("{index_path} was not indexed with --case-sensitive")));
+                    }
+                    config.case_sensitive = true;
+                }
+                debug!(path = %index_path, elapsed_ms = start.elapsed().as_millis() as u64, "loaded and finalized index");
+                let ranking = ranking.unwrap_or(model.default_ranking());
+                let model = server::SharedModel::new(model);
+                if reload_on_sighup {
+                    #[cfg(unix)]
+                    server::reload_on_sighup(PathBuf::from(&index_path), model.clone(), load)?;
+                    #[cfg(not(unix))]
+                    return Err(SearchError::Unsupported("reloading the index on SIGHUP without Unix signals"));
+                }
+                if let Some(interval) = reload_interval {
+                    server::reload_on_change(PathBuf::from(&index_path), model.clone(), interval, load);
+                }
+                server::start(&address, model, ranking, &config, tls, auth)
+            }
+        },
+        Command::Watch { folder: dir_path, index_file, debounce_ms } => {
+            let index_path = index_file
+                .or(config.index_path.as_ref().map(|index_path| index_path.to_string_lossy().into_owned()))
+                .unwrap_or("index.json".to_string());
 
-                for (path, rank) in model.search_query(&prompt)?.iter().take(20) {
-                    println!("{path} {rank}", path = path.display());
+            if use_sqlite_mode {
+                return Err(SearchError::Unsupported("watching a folder with a SQLite index"));
+            }
+            config.exclude_patterns.extend(ignore_file_patterns(Path::new(&dir_path))?);
+
+            // Catch up with the changes made while nothing was watching
+            let mut model = if Path::new(&index_path).exists() {
+                load_model_to_log(&index_path)?
+            } else {
+                new_model(&config, no_stop_words, ranking, max_tokens_per_doc, ngram_size, ascii_folding, tf_normalization)?
+            };
+            let deleted = model.tfpd.keys()
+                .filter(|file_path| file_path.starts_with(&dir_path) && !file_path.exists())
+                .cloned()
+                .collect::<Vec<_>>();
+            for file_path in deleted {
+                model.remove_document(&file_path)?;
+            }
+            add_folder_to_in_memory_model(Path::new(&dir_path), &mut model, &config, None, show_progress)?;
+            save_model(&model, &index_path)?;
+
+            watch::watch(Path::new(&dir_path), &index_path, &mut model, &config, Duration::from_millis(debounce_ms))
+        },
+        Command::Stats { index_file, json, backend } => {
+            let index_path = index_path_or_config(index_file, &config, "stats")?;
+
+            let mut stats = match backend.unwrap_or(if use_sqlite_mode { Backend::Sqlite } else { Backend::InMemory }) {
+                Backend::InMemory => stats::corpus_stats(&load_model(&index_path)?.tfpd),
+                Backend::Sqlite => {
+                    // Opening a SQLite index that doesn't exist would create an empty one
+                    if !Path::new(&index_path).exists() {
+                        return Err(SearchError::io_at(Path::new(&index_path), std::io::ErrorKind::NotFound.into()));
+                    }
+                    SqliteModel::open(Path::new(&index_path))?.corpus_stats()?
                 }
+                Backend::Fts5 => return Err(SearchError::Unsupported("corpus statistics of an FTS5 index")),
+            };
+            stats.index_size_bytes = Some(fs::metadata(&index_path).map_err(|err| SearchError::io_at(Path::new(&index_path), err))?.len());
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                stats::print_corpus_stats(&stats);
             }
+            Ok(())
+        },
+        Command::Delete { index_file: index_path, document } => {
+            let document_path = document.as_path();
 
+            let removed = if use_sqlite_mode {
+                // Opening a SQLite index that doesn't exist would create an empty one
+                if !Path::new(&index_path).exists() {
+                    return Err(SearchError::io_at(Path::new(&index_path), std::io::ErrorKind::NotFound.into()));
+                }
+                let mut model = SqliteModel::open(Path::new(&index_path))?;
+                model.begin()?;
+                let removed = model.remove_document(document_path)?;
+                model.commit()?;
+                removed
+            } else {
+                // Logging the removal is enough, the next save of the index makes it for good
+                let mut model = load_model_to_log(&index_path)?;
+                let removed = model.remove_document(document_path)?;
+                if removed {
+                    let mut wal = wal::WriteAheadLog::open(Path::new(&index_path))?;
+                    wal.append(&wal::IndexOp::RemoveDocument { path: document_path.to_path_buf() })?;
+                }
+                removed
+            };
+            if !removed {
+                return Err(SearchError::Usage(format!("{document_path} is not indexed in {index_path}", document_path = document_path.display())));
+            }
+            info!(path = %document_path.display(), "removed document");
             Ok(())
         },
-        "serve" => {
-            let index_path = args.next().ok_or_else(|| {
-                usage(&program);
-                eprintln!("ERROR: no path to index is provided for {subcommand} subcommand");
-            })?;
+        Command::Verify { index_file } => {
+            let index_path = index_path_or_config(index_file, &config, "verify")?;
+
+            if use_sqlite_mode {
+                return Err(SearchError::Unsupported("verification of a SQLite index"));
+            }
+
+            let model = load_model(&index_path)?;
+            let failed = verify::print_verification(&verify::verify(&model));
+            if failed > 0 {
+                return Err(SearchError::IndexCorrupted(format!("{failed} of the integrity checks of {index_path} failed")));
+            }
+            Ok(())
+        },
+        Command::Migrate { index_file: _, command: Some(MigrateCommand::JsonToSqlite { index_file: index_path, db_file: db_path }) } => {
+            let migrated = migrate_to_sqlite(&index_path, &db_path)?;
+            info!(path = %db_path.display(), doc_count = migrated, "migrated index to SQLite");
+            Ok(())
+        },
+        Command::Migrate { index_file, command: None } => {
+            // Required unless there is a subcommand
+            let index_path = index_file.unwrap_or_default();
 
-            let address = args.next().unwrap_or("127.0.0.1:8383".to_string());
+            if use_sqlite_mode {
+                return Err(SearchError::Unsupported("migration of a SQLite index"));
+            }
+
+            match migrate_index(&index_path)? {
+                Some(version) => info!(path = %index_path, from = version, to = schema::SCHEMA_VERSION, "migrated index"),
+                None => info!(path = %index_path, version = schema::SCHEMA_VERSION, "index is up to date"),
+            }
+            Ok(())
+        },
+        Command::Checkpoint { index_file: index_path } => {
 
             if use_sqlite_mode {
-                let model = SqliteModel::open(Path::new(&index_path))?;
+                return Err(SearchError::Unsupported("checkpointing a SQLite index"));
+            }
 
-                server::start(&address, &model)
-            } else {
-                let index_file = File::open(&index_path).map_err(|err| {
-                    eprintln!("ERROR: could not open index file {index_path}: {err}");
-                })?;
+            let mut model = load_model(&index_path)?;
+            model.cache_idf();
+            model.cache_term_filter();
+            save_model(&model, &index_path)?;
+            info!(path = %index_path, "checkpointed index");
+            Ok(())
+        },
+        Command::Explain { index_file: index_path, query, document: document_path } => {
 
-                let model: InMemoryModel = serde_json::from_reader(index_file).map_err(|err| {
-                    eprintln!("ERROR: could not parse index file {index_path}: {err}");
-                })?;
+            if use_sqlite_mode {
+                return Err(SearchError::Unsupported("explaining scores of a SQLite index"));
+            }
 
-                server::start(&address, &model)
+            let mut model = load_model(&index_path)?;
+            model.finalize();
+            let ranking = ranking.unwrap_or(model.default_ranking());
+            let prompt = query.chars().collect::<Vec<_>>();
+            let explanation = model.explain_score(Path::new(&document_path), &prompt, ranking).ok_or_else(|| {
+                SearchError::Usage(format!("{document_path} is not indexed in {index_path}"))
+            })?;
+            print_score_explanation(&explanation);
+            Ok(())
+        },
+        Command::List { index_file, json, count_only, sort: order, filter_ext } => {
+            let extension = filter_ext.map(|extension| extension.trim_start_matches('.').to_lowercase());
+            let index_path = index_path_or_config(index_file, &config, "list")?;
+
+            if use_sqlite_mode {
+                return Err(SearchError::Unsupported("listing the documents of a SQLite index"));
             }
+
+            let model = load_model(&index_path)?;
+            let mut summaries = model.tfpd.iter()
+                .filter(|(path, _)| extension.as_ref().is_none_or(|extension| file_extension(path) == *extension))
+                .map(|(path, entry)| entry.to_summary(path))
+                .collect::<Vec<_>>();
+            if count_only {
+                println!("{}", summaries.len());
+                return Ok(());
+            }
+            // Documents that rank the same are listed by path
+            match order {
+                ListOrder::Alphabetical => summaries.sort_by(|a, b| a.path.cmp(&b.path)),
+                ListOrder::Size => summaries.sort_by(|a, b| b.file_size_bytes.cmp(&a.file_size_bytes).then_with(|| a.path.cmp(&b.path))),
+                ListOrder::Score => summaries.sort_by(|a, b| b.term_count.cmp(&a.term_count).then_with(|| a.path.cmp(&b.path))),
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else {
+                for DocumentSummary { path, title, file_size_bytes, .. } in &summaries {
+                    match title {
+                        Some(title) => println!("{path} {file_size_bytes} bytes: {title}", path = path.display()),
+                        None => println!("{path} {file_size_bytes} bytes", path = path.display()),
+                    }
+                }
+            }
+            Ok(())
+        },
+        Command::Merge { index_a, index_b, output } => {
+
+            if use_sqlite_mode {
+                return Err(SearchError::Unsupported("merging SQLite indexes"));
+            }
+
+            let merged = merge_models(load_model(&index_a)?, load_model(&index_b)?);
+            info!(doc_count = merged.tfpd.len(), "merged indexes");
+            save_model(&merged, &output)
+        },
+        Command::Init => {
+            Config::write_default(Path::new(CONFIG_FILE))?;
+            info!(path = CONFIG_FILE, "created configuration file");
+            Ok(())
+        },
+        Command::Model { command: ModelCommand::Config { command: ModelConfigCommand::Show { index_file: index_path } } } => {
+            let model = load_model(&index_path)?;
+            println!("{}", serde_json::to_string_pretty(&model.config)?);
+            Ok(())
+        },
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "serux", &mut std::io::stdout());
+            Ok(())
         },
-        _ => {
-            usage(&program);
-            eprintln!("ERROR: unknown subcommand {subcommand}");
-            Err(())
-        }
     }
 }
 
 fn main() -> ExitCode {
     match entry() {
         Ok(()) => ExitCode::SUCCESS,
-        Err(()) => ExitCode::FAILURE,
+        Err(err) => {
+            // The options may not have been read that far
+            logging::init(LogFormat::default(), false);
+            error!("{err}");
+            ExitCode::FAILURE
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Upper bounds of the buckets of serux_search_duration_seconds, in seconds
+const SEARCH_DURATION_BUCKETS: [f64; 7] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+/// Counters of the server, exposed at /metrics in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    searches: AtomicU64,
+    search_errors: AtomicU64,
+    documents_indexed: AtomicU64,
+    // Searches that took at most the bound of the bucket but more than the one before; the last one is +Inf
+    search_duration_buckets: [AtomicU64; SEARCH_DURATION_BUCKETS.len() + 1],
+    search_duration_micros: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_search(&self, duration: Duration, error: bool) {
+        self.searches.fetch_add(1, Ordering::Relaxed);
+        if error {
+            self.search_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let seconds = duration.as_secs_f64();
+        let bucket = SEARCH_DURATION_BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(SEARCH_DURATION_BUCKETS.len());
+        self.search_duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.search_duration_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_indexing(&self, count: usize) {
+        self.documents_indexed.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    // Exposition in the Prometheus text format 0.0.4. The counters are read one by one,
+    // so a search recorded meanwhile may show in some of them only.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        self.write_exposition(&mut text).expect("writing to a String does not fail");
+        text
+    }
+
+    fn write_exposition(&self, text: &mut String) -> fmt::Result {
+        let counters = [
+            ("serux_searches_total", "Searches answered, including the failed ones.", &self.searches),
+            ("serux_search_errors_total", "Searches that failed.", &self.search_errors),
+            ("serux_documents_indexed_total", "Documents in the index the server loaded.", &self.documents_indexed),
+        ];
+        for (name, help, value) in counters {
+            writeln!(text, "# HELP {name} {help}")?;
+            writeln!(text, "# TYPE {name} counter")?;
+            writeln!(text, "{name} {}", value.load(Ordering::Relaxed))?;
+        }
+
+        let name = "serux_search_duration_seconds";
+        writeln!(text, "# HELP {name} Time taken to answer searches.")?;
+        writeln!(text, "# TYPE {name} histogram")?;
+        // Prometheus buckets are cumulative
+        let mut count = 0;
+        for (i, bucket) in self.search_duration_buckets.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            match SEARCH_DURATION_BUCKETS.get(i) {
+                Some(bound) => writeln!(text, "{name}_bucket{{le=\"{bound}\"}} {count}")?,
+                None => writeln!(text, "{name}_bucket{{le=\"+Inf\"}} {count}")?,
+            }
+        }
+        let sum = self.search_duration_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        writeln!(text, "{name}_sum {sum}")?;
+        writeln!(text, "{name}_count {count}")
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+use crate::model::SearchError;
+
+// How many times the column names are repeated so that they weigh more than the cells
+pub(crate) const CSV_HEADER_REPEAT: usize = 3;
+
+// Tried in this order when the delimiter is guessed
+const CANDIDATE_DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+// `--csv-delimiter` takes a single ASCII character, or `tab` / `\t` for a tab
+pub fn parse_delimiter(value: &str) -> Option<char> {
+    match value {
+        "tab" | "\\t" => Some('\t'),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(delimiter), None) if delimiter.is_ascii() => Some(delimiter),
+                _ => None,
+            }
+        }
+    }
+}
+
+// The candidate occurring most often in the first line, outside of quotes; tabs for .tsv files, commas otherwise
+fn guess_delimiter(file_path: &Path, content: &[u8]) -> u8 {
+    let is_tsv = file_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tsv"));
+    let default = if is_tsv { b'\t' } else { b',' };
+
+    let mut counts = [0usize; CANDIDATE_DELIMITERS.len()];
+    let mut quoted = false;
+    for &byte in content {
+        if byte == b'\n' && !quoted {
+            break;
+        }
+        if byte == b'"' {
+            quoted = !quoted;
+        } else if !quoted {
+            if let Some(i) = CANDIDATE_DELIMITERS.iter().position(|&delimiter| delimiter == byte) {
+                counts[i] += 1;
+            }
+        }
+    }
+    match counts.iter().enumerate().max_by_key(|&(i, count)| (count, std::cmp::Reverse(i))) {
+        Some((i, &count)) if count > 0 => CANDIDATE_DELIMITERS[i],
+        _ => default,
+    }
+}
+
+// A first row without empty or numeric fields is taken for the names of the columns
+fn is_header(record: &csv::StringRecord) -> bool {
+    !record.is_empty() && record.iter().all(|field| {
+        let field = field.trim();
+        !field.is_empty() && field.parse::<f64>().is_err()
+    })
+}
+
+// The text of every field of every record, separated by spaces. The `delimiter` is guessed if not given.
+// Rows with another number of fields than the first one are indexed anyway, with a warning.
+pub fn parse_csv_file(file_path: &Path, delimiter: Option<char>) -> Result<String, SearchError> {
+    let content = fs::read(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
+    // Checked by parse_delimiter and Config::validate
+    let delimiter = delimiter.map_or_else(|| guess_delimiter(file_path, &content), |delimiter| delimiter as u8);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_slice());
+
+    let mut text = String::new();
+    let mut field_count = None;
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|err| SearchError::io_at(file_path, err.into()))?;
+        let repeat = if i == 0 && is_header(&record) { CSV_HEADER_REPEAT } else { 1 };
+
+        match field_count {
+            None => field_count = Some(record.len()),
+            Some(count) if count != record.len() => {
+                let line = record.position().map_or(0, |position| position.line());
+                warn!(path = %file_path.display(), line, expected = count, found = record.len(), "row has an unexpected number of fields");
+            }
+            Some(_) => {}
+        }
+
+        for field in &record {
+            for _ in 0..repeat {
+                text.push_str(field);
+                text.push(' ');
+            }
+        }
+    }
+    Ok(text)
+}
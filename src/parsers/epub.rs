@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use tracing::warn;
+use xml::reader::{EventReader, XmlEvent};
+use xml::common::{Position, TextPosition};
+use zip::ZipArchive;
+
+use crate::html_to_text;
+use crate::model::SearchError;
+
+// Entries bigger than this are not read, so that a crafted archive can't fill the memory
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+// Media types of the items read as text; images, fonts, stylesheets and the like are skipped
+const CONTENT_MEDIA_TYPES: [&str; 2] = ["application/xhtml+xml", "text/html"];
+
+const DUBLIN_CORE_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+// What the OPF package file says about the book
+#[derive(Default)]
+struct Package {
+    title: Option<String>,
+    // Items by id: their path relative to the package file and their media type
+    manifest: HashMap<String, (String, String)>,
+    // Ids of the items in reading order
+    spine: Vec<String>,
+}
+
+fn read_entry(archive: &mut ZipArchive<BufReader<File>>, file_path: &Path, name: &str) -> Result<Vec<u8>, SearchError> {
+    let entry = archive.by_name(name).map_err(|err| SearchError::io_at(file_path, err.into()))?;
+    let mut bytes = Vec::new();
+    entry.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut bytes).map_err(|err| SearchError::io_at(file_path, err))?;
+    if bytes.len() as u64 > MAX_ENTRY_SIZE {
+        let err = io::Error::new(io::ErrorKind::InvalidData, format!("{name} is bigger than {MAX_ENTRY_SIZE} bytes"));
+        return Err(SearchError::io_at(file_path, err));
+    }
+    Ok(bytes)
+}
+
+// Feed the XML events of the entry `name` to `on_event`; errors point into the archive, e.g. book.epub/OEBPS/content.opf
+fn read_xml_entry(file_path: &Path, name: &str, bytes: &[u8], mut on_event: impl FnMut(XmlEvent)) -> Result<(), SearchError> {
+    for event in EventReader::new(bytes) {
+        let event = event.map_err(|err| {
+            let TextPosition { row, column } = err.position();
+            SearchError::XmlParseError { path: file_path.join(name), row, col: column, msg: err.msg().to_string() }
+        })?;
+        on_event(event);
+    }
+    Ok(())
+}
+
+// META-INF/container.xml names the package file in the full-path attribute of its first <rootfile>
+fn package_path(file_path: &Path, container: &[u8]) -> Result<String, SearchError> {
+    let mut package_path = None;
+    read_xml_entry(file_path, "META-INF/container.xml", container, |event| {
+        if let XmlEvent::StartElement { name, attributes, .. } = event {
+            if name.local_name == "rootfile" && package_path.is_none() {
+                package_path = attributes.into_iter().find(|attribute| attribute.name.local_name == "full-path").map(|attribute| attribute.value);
+            }
+        }
+    })?;
+    package_path.ok_or_else(|| SearchError::XmlParseError {
+        path: file_path.join("META-INF/container.xml"),
+        row: 0,
+        col: 0,
+        msg: "no <rootfile> with a full-path".to_string(),
+    })
+}
+
+fn parse_package(file_path: &Path, name: &str, bytes: &[u8]) -> Result<Package, SearchError> {
+    let mut package = Package::default();
+    // Text of the <dc:title> being read
+    let mut title_text: Option<String> = None;
+    read_xml_entry(file_path, name, bytes, |event| match event {
+        XmlEvent::StartElement { name, attributes, .. } => {
+            let attribute = |local_name: &str| attributes.iter()
+                .find(|attribute| attribute.name.local_name == local_name)
+                .map(|attribute| attribute.value.clone());
+            match name.local_name.as_str() {
+                "title" if package.title.is_none() && name.namespace.as_deref() == Some(DUBLIN_CORE_NAMESPACE) => {
+                    title_text = Some(String::new());
+                }
+                "item" => {
+                    if let (Some(id), Some(href), Some(media_type)) = (attribute("id"), attribute("href"), attribute("media-type")) {
+                        package.manifest.insert(id, (href, media_type));
+                    }
+                }
+                "itemref" => package.spine.extend(attribute("idref")),
+                _ => {}
+            }
+        }
+        XmlEvent::Characters(text) => {
+            if let Some(title_text) = title_text.as_mut() {
+                title_text.push_str(&text);
+            }
+        }
+        XmlEvent::EndElement { name } if name.local_name == "title" => {
+            package.title = package.title.take().or(title_text.take().map(|text| text.trim().to_string()).filter(|text| !text.is_empty()));
+        }
+        _ => {}
+    })?;
+    Ok(package)
+}
+
+// Path within the archive of `href`, which is relative to the directory of the package file
+fn resolve_href(package_path: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+    let mut segments = package_path.split('/').collect::<Vec<_>>();
+    segments.pop();
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+// Returns the text of the content documents of the book, in reading order, together with its <dc:title>
+pub fn parse_epub_file(file_path: &Path) -> Result<(String, Option<String>), SearchError> {
+    let file = File::open(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|err| SearchError::io_at(file_path, err.into()))?;
+
+    let container = read_entry(&mut archive, file_path, "META-INF/container.xml")?;
+    let package_path = package_path(file_path, &container)?;
+    let package = parse_package(file_path, &package_path, &read_entry(&mut archive, file_path, &package_path)?)?;
+
+    let mut content = String::new();
+    for id in &package.spine {
+        let Some((href, media_type)) = package.manifest.get(id) else {
+            warn!(path = %file_path.display(), id = %id, "skipping spine item missing from the manifest");
+            continue;
+        };
+        if !CONTENT_MEDIA_TYPES.contains(&media_type.as_str()) {
+            continue;
+        }
+        // One missing chapter should not keep the rest of the book out of the index
+        let name = resolve_href(&package_path, href);
+        let bytes = match read_entry(&mut archive, file_path, &name) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!(path = %file_path.display(), entry = %name, error = %err, "skipping content document");
+                continue;
+            }
+        };
+        let (text, _, _) = html_to_text(&String::from_utf8_lossy(&bytes));
+        content.push_str(&text);
+        content.push(' ');
+    }
+
+    Ok((content, package.title))
+}
@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use serde_json::Value;
+
+use crate::model::SearchError;
+
+// Every key and string value of the document, at any depth, separated by spaces.
+// Numbers, booleans and nulls are left out: they are rarely what is searched for.
+pub fn parse_json_file(file_path: &Path) -> Result<String, SearchError> {
+    let file = File::open(file_path).map_err(|err| SearchError::io_at(file_path, err))?;
+    let value: Value = serde_json::from_reader(BufReader::new(file))?;
+
+    let mut content = String::new();
+    push_json_strings(&value, &mut content);
+    Ok(content)
+}
+
+fn push_json_strings(value: &Value, content: &mut String) {
+    match value {
+        Value::String(string) => {
+            content.push_str(string);
+            content.push(' ');
+        }
+        Value::Array(values) => {
+            for value in values {
+                push_json_strings(value, content);
+            }
+        }
+        Value::Object(object) => {
+            for (key, value) in object {
+                content.push_str(key);
+                content.push(' ');
+                push_json_strings(value, content);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
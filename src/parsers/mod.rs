@@ -0,0 +1,3 @@
+pub mod csv;
+pub mod epub;
+pub mod json;
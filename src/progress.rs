@@ -0,0 +1,55 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use indicatif::{ProgressBar, ProgressStyle};
+
+const TEMPLATE: &str = "[{elapsed_precise}] {bar:40} {pos}/{len} {msg}";
+
+// The bar being drawn, if any. Log events hide it while they are written.
+static ACTIVE: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+// Run `f` with the active bar cleared from the terminal, so that what it writes to stderr doesn't mix with the bar
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    let active = ACTIVE.lock().unwrap_or_else(|err| err.into_inner()).clone();
+    match active {
+        Some(bar) => bar.suspend(f),
+        None => f(),
+    }
+}
+
+// Bar on stderr counting the files of a folder as they are indexed.
+// It is cleared if it is dropped before `finish`, e.g. when indexing fails.
+pub struct IndexingProgress {
+    bar: ProgressBar,
+}
+
+impl IndexingProgress {
+    // Nothing is drawn unless `show`
+    pub fn new(file_count: usize, show: bool) -> Self {
+        if !show {
+            return Self { bar: ProgressBar::hidden() };
+        }
+        let style = ProgressStyle::with_template(TEMPLATE).expect("progress bar template is valid");
+        let bar = ProgressBar::new(file_count as u64).with_style(style);
+        *ACTIVE.lock().unwrap_or_else(|err| err.into_inner()) = Some(bar.clone());
+        Self { bar }
+    }
+
+    // One more file of the folder is done with, `name` is shown next to the bar
+    pub fn advance(&self, name: &str) {
+        self.bar.set_message(name.to_string());
+        self.bar.inc(1);
+    }
+
+    pub fn finish(self, doc_count: usize, elapsed: Duration) {
+        self.bar.finish_with_message(format!("{doc_count} documents indexed in {:.1} s", elapsed.as_secs_f32()));
+    }
+}
+
+impl Drop for IndexingProgress {
+    fn drop(&mut self) {
+        *ACTIVE.lock().unwrap_or_else(|err| err.into_inner()) = None;
+        if !self.bar.is_finished() {
+            self.bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::model::SearchError;
+
+// How often the server appends the queries of the last interval to the log file
+pub const QUERY_LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// One search, as a line of the newline-delimited JSON log file
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct QueryLogEntry {
+    pub query: String,
+    pub result_count: usize,
+    // When the search was answered, in seconds since the Unix epoch
+    pub timestamp_unix: u64,
+    pub duration_ms: u64,
+}
+
+/// How often a query was searched, with the averages of its searches
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryStats {
+    pub query: String,
+    pub count: usize,
+    pub avg_result_count: f64,
+    pub avg_duration_ms: f64,
+}
+
+// Sums over the searches of one query
+#[derive(Default)]
+struct QueryTotals {
+    count: usize,
+    result_count: usize,
+    duration_ms: u64,
+}
+
+impl QueryTotals {
+    fn add(&mut self, entry: &QueryLogEntry) {
+        self.count += 1;
+        self.result_count += entry.result_count;
+        self.duration_ms += entry.duration_ms;
+    }
+}
+
+/// Searches kept in memory until they are appended to the log file, and statistics of every query
+/// of the file, so that the most frequent ones can be listed without reading it again
+pub struct QueryLog {
+    path: PathBuf,
+    // Recorded since the last flush
+    pending: Mutex<Vec<QueryLogEntry>>,
+    totals: Mutex<HashMap<String, QueryTotals>>,
+}
+
+impl QueryLog {
+    // The log at `path`, with the statistics of the queries it already has. Lines that are not
+    // entries are skipped with a warning, e.g. the last one if the server was killed while writing it.
+    pub fn open(path: &Path) -> Result<Self, SearchError> {
+        let mut totals = HashMap::<String, QueryTotals>::new();
+        match File::open(path) {
+            Ok(file) => {
+                for (i, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line.map_err(|err| SearchError::io_at(path, err))?;
+                    match serde_json::from_str::<QueryLogEntry>(&line) {
+                        Ok(entry) => totals.entry(entry.query.clone()).or_default().add(&entry),
+                        Err(err) => warn!(path = %path.display(), line = i + 1, error = %err, "skipping query log line"),
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(SearchError::io_at(path, err)),
+        }
+        Ok(Self { path: path.to_path_buf(), pending: Mutex::new(Vec::new()), totals: Mutex::new(totals) })
+    }
+
+    pub fn record(&self, query: &str, result_count: usize, duration_ms: u64) {
+        let timestamp_unix = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let entry = QueryLogEntry { query: query.to_string(), result_count, timestamp_unix, duration_ms };
+        self.totals.lock().unwrap_or_else(|err| err.into_inner()).entry(entry.query.clone()).or_default().add(&entry);
+        self.pending.lock().unwrap_or_else(|err| err.into_inner()).push(entry);
+    }
+
+    // Append the entries recorded since the last flush to the log file
+    pub fn flush(&self) -> Result<(), SearchError> {
+        self.flush_to_file(&self.path)
+    }
+
+    // Append the entries recorded since the last flush to the file at `path`, one JSON object per line.
+    // If they can't be written, they are kept for the next flush.
+    pub fn flush_to_file(&self, path: &Path) -> Result<(), SearchError> {
+        let entries = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|err| err.into_inner()));
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let written = append_entries(path, &entries);
+        if written.is_err() {
+            let mut pending = self.pending.lock().unwrap_or_else(|err| err.into_inner());
+            pending.splice(0..0, entries);
+        }
+        written
+    }
+
+    // The `limit` most searched queries, in alphabetical order among those searched as often
+    pub fn top_queries(&self, limit: usize) -> Vec<QueryStats> {
+        let totals = self.totals.lock().unwrap_or_else(|err| err.into_inner());
+        let mut top = totals.iter().map(|(query, totals)| QueryStats {
+            query: query.clone(),
+            count: totals.count,
+            avg_result_count: totals.result_count as f64 / totals.count as f64,
+            avg_duration_ms: totals.duration_ms as f64 / totals.count as f64,
+        }).collect::<Vec<_>>();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.query.cmp(&b.query)));
+        top.truncate(limit);
+        top
+    }
+}
+
+fn append_entries(path: &Path, entries: &[QueryLogEntry]) -> Result<(), SearchError> {
+    let file = OpenOptions::new().create(true).append(true).open(path).map_err(|err| SearchError::io_at(path, err))?;
+    let mut writer = BufWriter::new(file);
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n").map_err(|err| SearchError::io_at(path, err))?;
+    }
+    writer.flush().map_err(|err| SearchError::io_at(path, err))
+}
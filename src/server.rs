@@ -1,105 +1,1012 @@
-use std::io;
-use std::fs::File;
-use std::str;
-use tiny_http::{Header, Method, Request, Response, Server};
-
-use super::model::*;
-
-fn serve_404(request: Request) -> io::Result<()> {
-    request.respond(Response::from_string("404").with_status_code(404))
-}
-
-fn serve_500(request: Request) -> io::Result<()> {
-    request.respond(Response::from_string("500").with_status_code(500))
-}
-
-fn serve_400(request: Request, message: &str) -> io::Result<()> {
-    request.respond(Response::from_string(format!("400: {message}")).with_status_code(400))
-}
-
-fn serve_static_file(request: Request, file_path: &str, content_type: &str) -> io::Result<()> {
-    let content_type_header = Header::from_bytes("Content-Type", content_type)
-        .expect("That we didn't put any garbage in the headers");
-
-    let file = match File::open(file_path) {
-        Ok(file) => file,
-        Err(err) => {
-            eprintln!("ERROR: could not serve this {file_path}: {err}");
-            if err.kind() == io::ErrorKind::NotFound {
-                return serve_404(request);
-            }
-            return serve_500(request);
-        }
-    };
-
-    request.respond(Response::from_file(file).with_header(content_type_header))
-}
-
-fn serve_api_search(model: &impl Model, mut request: Request) -> io::Result<()> {
-    let mut buf = Vec::new();
-    if let Err(err) = request.as_reader().read_to_end(&mut buf) {
-        eprintln!("ERROR: could not read the body of request: {err}");
-        return serve_500(request);
-    }
-
-    let body = match str::from_utf8(&buf) {
-        Ok(body) => body.chars().collect::<Vec<_>>(),
-        Err(err) => {
-            eprintln!("ERROR: could not interpret body as UTF-8 string: {err}");
-            return serve_400(request, "Body must be a valid UTF-8 string");
-        }
-    };
-
-    let results = model.search_query(&body);
-
-    let json = match serde_json::to_string(&results.iter().take(20).collect::<Vec<_>>()) {
-        Ok(json) => json,
-        Err(err) => {
-            eprintln!("ERROR: could not convert search results to JSON: {err}");
-            return serve_500(request);
-        }
-    };
-
-    let content_type_header = Header::from_bytes("Content-Type", "application/json")
-        .expect("That we didn't put any garbage in the headers");
-    request.respond(Response::from_string(&json).with_header(content_type_header))
-}
-
-fn serve_request(model: &impl Model, request: Request) -> io::Result<()> {
-    println!("INFO: received request! method: {:?}, url: {:?}", request.method(), request.url());
-
-    match (request.method(), request.url()) {
-        (Method::Post, "/api/search") => {
-            serve_api_search(model, request)
-        }
-        (Method::Get, "/index.js") => {
-            serve_static_file(request, "static/index.js", "text/javascript; charset=utf-8")
-        }
-        (Method::Get, "/index.css") => {
-            serve_static_file(request, "static/index.css", "text/css; charset=utf-8")
-        }
-        (Method::Get, "/") | (Method::Get, "/index.html") => {
-            serve_static_file(request, "static/index.html", "text/html; charset=utf-8")
-        }
-        _ => {
-            serve_404(request)
-        }
-    }
-}
-
-pub fn start(address: &str, model: &impl Model) -> Result<(), ()> {
-    let server = Server::http(&address).map_err(|err| {
-        eprintln!("ERROR: could not start HTTP server at {address}: {err}");
-    })?;
-
-    println!("INFO: listening at http://{address}/");
-
-    for request in server.incoming_requests() {
-        serve_request(model, request).map_err(|err| {
-            eprintln!("ERROR: could not serve the response: {err}");
-        }).ok();
-    }
-
-    eprintln!("ERROR: the server socket has shutdown");
-    Err(())
-}
\ No newline at end of file
+use std::io;
+use std::future::Future;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHasher, PasswordVerifier, phc::PasswordHash};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use subtle::ConstantTimeEq;
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Query, Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use tokio::sync::{watch, Semaphore};
+use tokio_rustls::rustls::ServerConfig;
+use tower_http::compression::CompressionLayer;
+use tower_http::timeout::RequestBodyTimeoutLayer;
+use regex::Regex;
+use tracing::{debug, error, info, warn};
+
+use super::config::Config;
+use super::model::*;
+use super::query::{ParsedQuery, QueryParser};
+use super::cache::LruCache;
+use super::snippet::{highlight_snippet, snippet_of, snippet_tokens, HighlightMode};
+use super::tls::TlsListener;
+use super::export::{write_results_csv, ExportedResult, OutputFormat};
+use super::query_log::{QueryLog, QUERY_LOG_FLUSH_INTERVAL};
+use super::metrics::Metrics;
+
+// How long new connections wait for one of the `max_connections` to close before they are refused
+const CONNECTION_WAIT: Duration = Duration::from_secs(1);
+
+// Clients refused for too many connections are asked to come back after this many seconds
+const RETRY_AFTER_SECS: &str = "5";
+
+// Queries are short, anything bigger is not a query
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+// Results per page unless the request says otherwise
+const DEFAULT_PAGE_LIMIT: usize = 10;
+
+// Characters of the document shown around the first match
+const SNIPPET_WINDOW: usize = 160;
+
+// Raw content of the most recently shown documents, so that paging through results doesn't read them again
+const CONTENT_CACHE_CAPACITY: usize = 50;
+
+// How many indexed terms a wildcard may expand to unless the request says otherwise
+const MAX_WILDCARD_EXPANSIONS: usize = 100;
+
+// Edits a fuzzy search may allow, and allows unless the request says otherwise. Every edit more
+// expands a term to many more indexed ones, all of which are then ranked.
+const MAX_EDIT: usize = 2;
+
+// Regular expressions of the latest regex searches, compiling them again would take longer than the search
+const REGEX_CACHE_CAPACITY: usize = 50;
+
+// Suggested terms unless the request says otherwise
+const DEFAULT_SUGGEST_LIMIT: usize = 10;
+
+// Similar documents listed by /api/similar unless the request says otherwise
+const DEFAULT_SIMILAR_LIMIT: usize = 10;
+
+// Queries listed by /api/logs/top-queries unless the request says otherwise
+const DEFAULT_TOP_QUERIES_LIMIT: usize = 20;
+
+// Pseudo-relevance feedback takes the terms of this many of the first results unless the request says otherwise
+const DEFAULT_PRF_TOP: usize = 5;
+
+// Terms pseudo-relevance feedback adds to the query unless the request says otherwise
+const DEFAULT_PRF_EXPAND_TERMS: usize = 3;
+
+// MMR reranking picks the documents shown up to the requested page among at least this many of the top results
+const MMR_CANDIDATES: usize = 100;
+
+/// A model shared between the server and whoever else holds a clone, e.g. to add documents while it is served.
+/// Searches take the read lock and run concurrently, adding documents takes the write lock.
+pub struct SharedModel<M>(Arc<RwLock<M>>);
+
+impl<M> Clone for SharedModel<M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+// A panic while the model was written to may have left it half changed, it is not searched anymore after that
+fn poisoned_model() -> SearchError {
+    error!("the index was left inconsistent by a panic while it was changed, restart the server");
+    SearchError::IndexCorrupted("a change to the index was interrupted by a panic".to_string())
+}
+
+impl<M> SharedModel<M> {
+    pub fn new(model: M) -> Self {
+        Self(Arc::new(RwLock::new(model)))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, M>, SearchError> {
+        self.0.read().map_err(|_| poisoned_model())
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, M>, SearchError> {
+        self.0.write().map_err(|_| poisoned_model())
+    }
+}
+
+// How often the index file is checked for changes when the server reloads it
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+// Check the modification time of `index_path` every `interval` and, when it changes, replace the model
+// with the one `load` reads from the file. The searches go on with the old model while the new one loads.
+// A file that can't be loaded, e.g. one that is still being written, is reported and tried again once it
+// changes again. The time is compared for equality, an index copied with its original time counts as well.
+pub fn reload_on_change<M: Model + Send + Sync + 'static>(
+    index_path: PathBuf,
+    model: SharedModel<M>,
+    interval: Duration,
+    load: impl Fn(&Path) -> Result<M, SearchError> + Send + 'static,
+) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified());
+    let mut last_modified = modified(&index_path).ok();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        let mtime = match modified(&index_path) {
+            Ok(mtime) => mtime,
+            Err(err) => {
+                warn!(path = %index_path.display(), error = %err, "could not check index file");
+                continue;
+            }
+        };
+        if last_modified == Some(mtime) {
+            continue;
+        }
+        last_modified = Some(mtime);
+
+        if !reload(&index_path, &model, &load) {
+            return;
+        }
+    });
+}
+
+// Replace the model with the one `load` reads from `index_path` every time the process gets a SIGHUP,
+// e.g. from `kill -HUP` once a new index was moved into place. The handler is installed before this returns,
+// so a signal sent once the server started listening does not kill it.
+#[cfg(unix)]
+pub fn reload_on_sighup<M: Model + Send + Sync + 'static>(
+    index_path: PathBuf,
+    model: SharedModel<M>,
+    load: impl Fn(&Path) -> Result<M, SearchError> + Send + 'static,
+) -> Result<(), SearchError> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            info!(path = %index_path.display(), "got SIGHUP, reloading index");
+            if !reload(&index_path, &model, &load) {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+// Load the index again and swap it in, keeping the old model when that fails.
+// The searches go on with the old model while the new one loads, and those that already hold it finish with it.
+// Returns false once the model is poisoned, reloading it is pointless then.
+fn reload<M: Model>(index_path: &Path, model: &SharedModel<M>, load: impl Fn(&Path) -> Result<M, SearchError>) -> bool {
+    let start = Instant::now();
+    let loaded = load(index_path).and_then(|new_model| Ok((new_model.document_count()?, new_model)));
+    let (doc_count, new_model) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            error!(path = %index_path.display(), error = %err, "could not reload index, still serving the previous one");
+            return true;
+        }
+    };
+    let old_model = match model.write() {
+        Ok(mut guard) => std::mem::replace(&mut *guard, new_model),
+        Err(_) => return false,
+    };
+    // Freeing the old model can take a while, the lock is not held for it
+    drop(old_model);
+    info!(path = %index_path.display(), doc_count, elapsed_ms = start.elapsed().as_millis() as u64, "reloaded index");
+    true
+}
+
+// Shared by all the requests
+struct AppState<M> {
+    model: SharedModel<M>,
+    ranking: RankingAlgorithm,
+    max_results: usize,
+    suggest_min_prefix_len: usize,
+    // How many terms of a query documents must contain unless it says with `msm`
+    default_min_should_match: usize,
+    // Whether queries are case sensitive unless they say with `case`, only ever true for a case sensitive model
+    case_sensitive: bool,
+    contents: Mutex<LruCache<PathBuf, String>>,
+    regexes: Mutex<LruCache<String, Regex>>,
+    query_log: Option<Arc<QueryLog>>,
+    started: Instant,
+    metrics: Metrics,
+}
+
+// All API responses are JSON that must not be reused without asking the server again
+fn json_response(status_code: StatusCode, value: &impl Serialize) -> Response {
+    match serde_json::to_string(value) {
+        Ok(json) => (status_code, [(header::CONTENT_TYPE, "application/json"), (header::CACHE_CONTROL, "no-cache")], json).into_response(),
+        Err(err) => {
+            error!(error = %err, "could not convert response to JSON");
+            (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ApiError<'a> {
+    error: &'a str,
+}
+
+fn api_error(status_code: StatusCode, message: &str) -> Response {
+    json_response(status_code, &ApiError { error: message })
+}
+
+async fn serve_static_file(file_path: &str, content_type: &'static str) -> Response {
+    match tokio::fs::read(file_path).await {
+        Ok(content) => ([(header::CONTENT_TYPE, content_type)], content).into_response(),
+        Err(err) => {
+            error!(path = file_path, error = %err, "could not serve static file");
+            if err.kind() == io::ErrorKind::NotFound {
+                return (StatusCode::NOT_FOUND, "404").into_response();
+            }
+            (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    path: PathBuf,
+    score: f32,
+    title: Option<String>,
+    snippet: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    // Number of matching documents, of which `results` is one page
+    total: usize,
+    has_more: bool,
+    // Passed as `after` for the next page, null for the last one and for reranked results
+    next_cursor: Option<String>,
+    // Number of matching documents by file extension, counted before the `ext` filter
+    facets: HashMap<String, usize>,
+    results: Vec<SearchResult>,
+    // Only for searches with prf=1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query_expansion: Option<QueryExpansion>,
+}
+
+// The query comes from the `q` parameter, or from the request body for POST requests
+async fn serve_api_search<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    method: Method,
+    Query(params): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Response {
+    serve_search(state, method, params, body, OutputFormat::Json).await
+}
+
+// Same as /api/search, as a CSV file to download
+async fn serve_api_search_csv<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    method: Method,
+    Query(params): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Response {
+    serve_search(state, method, params, body, OutputFormat::Csv).await
+}
+
+async fn serve_search<M: Model + Send + Sync + 'static>(
+    state: Arc<AppState<M>>,
+    method: Method,
+    params: HashMap<String, String>,
+    body: Bytes,
+    format: OutputFormat,
+) -> Response {
+    let query = match params.get("q") {
+        Some(query) => query.clone(),
+        None if method != Method::POST => {
+            return api_error(StatusCode::BAD_REQUEST, "missing query parameter q");
+        }
+        None => match String::from_utf8(body.to_vec()) {
+            Ok(body) => body,
+            Err(err) => {
+                error!(error = %err, "could not interpret body as UTF-8 string");
+                return api_error(StatusCode::BAD_REQUEST, "body must be a valid UTF-8 string");
+            }
+        },
+    };
+
+    // Ranking is CPU bound, so it runs off the threads that serve the connections
+    let started = Instant::now();
+    let searched = Arc::clone(&state);
+    let response = tokio::task::spawn_blocking(move || search(&searched, &query, &params, format)).await
+        .unwrap_or_else(|err| {
+            error!(error = %err, "search failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response()
+        });
+    state.metrics.record_search(started.elapsed(), !response.status().is_success());
+    response
+}
+
+// Results as JSON, or as CSV when `format` asks for it
+fn search<M: Model>(state: &AppState<M>, query: &str, params: &HashMap<String, String>, format: OutputFormat) -> Response {
+    let started = Instant::now();
+    let offset = match params.get("offset").map(|offset| offset.parse::<usize>()) {
+        None => 0,
+        Some(Ok(offset)) => offset,
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "offset must be a non-negative integer"),
+    };
+    let limit = match params.get("limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_PAGE_LIMIT.min(state.max_results),
+        Some(Ok(limit)) => limit.min(state.max_results),
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "limit must be a non-negative integer"),
+    };
+    // The page after a next_cursor stays right while the index changes, an offset may skip or repeat results
+    let after = match params.get("after").map(|after| SearchCursor::decode(after)) {
+        None => None,
+        Some(Some(_)) if params.contains_key("offset") => {
+            return api_error(StatusCode::BAD_REQUEST, "after and offset can't be combined");
+        }
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => return api_error(StatusCode::BAD_REQUEST, "after must be the next_cursor of an earlier search"),
+    };
+
+    let Ok(model) = state.model.read() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response();
+    };
+    // Only the terms of a case sensitive index have a case to match
+    let case_sensitive = match params.get("case").map(String::as_str) {
+        None => state.case_sensitive,
+        Some("0") => false,
+        Some("1") if model.is_case_sensitive() => true,
+        Some("1") => return api_error(StatusCode::BAD_REQUEST, "the index was not built case sensitive"),
+        Some(_) => return api_error(StatusCode::BAD_REQUEST, "case must be 0 or 1"),
+    };
+
+    let max_expansions = match params.get("max_expansions").map(|max_expansions| max_expansions.parse::<usize>()) {
+        None => None,
+        Some(Ok(max_expansions)) => Some(max_expansions),
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "max_expansions must be a non-negative integer"),
+    };
+    let regex = params.get("regex").is_some_and(|regex| regex == "1");
+    let fuzzy = params.get("fuzzy").is_some_and(|fuzzy| fuzzy == "1");
+    let prf = params.get("prf").is_some_and(|prf| prf == "1");
+    let prf_top = match params.get("prf_top").map(|prf_top| prf_top.parse::<usize>()) {
+        None => DEFAULT_PRF_TOP,
+        Some(Ok(prf_top)) if prf_top > 0 => prf_top,
+        Some(_) => return api_error(StatusCode::BAD_REQUEST, "prf_top must be a positive integer"),
+    };
+    let expand = match params.get("expand").map(|expand| expand.parse::<usize>()) {
+        None => DEFAULT_PRF_EXPAND_TERMS,
+        Some(Ok(expand)) => expand,
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "expand must be a non-negative integer"),
+    };
+    // With mmr=1, near duplicates come after less relevant but different documents, see mmr_rerank
+    let mmr_lambda = match (params.get("mmr").map(String::as_str), params.get("lambda").map(|lambda| lambda.parse::<f32>())) {
+        (Some("0"), _) => None,
+        (Some("1") | None, Some(Ok(lambda))) if (0.0..=1.0).contains(&lambda) => Some(lambda),
+        (Some("1") | None, Some(_)) => return api_error(StatusCode::BAD_REQUEST, "lambda must be a number from 0 to 1"),
+        (Some("1"), None) => Some(model.mmr_lambda().unwrap_or(DEFAULT_MMR_LAMBDA)),
+        (None, None) => model.mmr_lambda(),
+        (Some(_), _) => return api_error(StatusCode::BAD_REQUEST, "mmr must be 0 or 1"),
+    };
+    let min_should_match = match params.get("msm").map(|msm| msm.parse::<usize>()) {
+        None => state.default_min_should_match,
+        Some(Ok(msm)) if msm > 0 => msm,
+        Some(_) => return api_error(StatusCode::BAD_REQUEST, "msm must be a positive integer"),
+    };
+    let min_score = match params.get("min_score").map(|min_score| min_score.parse::<f32>().ok().and_then(MinScoreStrategy::from_min_score)) {
+        None => MinScoreStrategy::default(),
+        Some(Some(min_score)) => min_score,
+        Some(None) => return api_error(StatusCode::BAD_REQUEST, "min_score must be a number, from -1 for a fraction of the top score"),
+    };
+
+    // With regex=1 the query is a regular expression matching indexed terms. Otherwise a query wrapped
+    // in double quotes is searched as a phrase, one with boosts like `rust^2` or exclusions like `-snake` as weighted terms,
+    // one with uppercase AND, OR or NOT as a boolean query. Plain terms are searched as weighted terms too
+    // when documents must contain more than one of them. With prf=1, plain terms are searched again
+    // with terms of their first results added, see Model::expand_query.
+    let query = query.trim();
+    let mut query_expansion = None;
+    let wildcard = query.split_whitespace().any(|word| word.ends_with('*'));
+    let weighted = QueryParser::is_weighted(query)
+        || min_should_match > 1 && !QueryParser::is_boolean(query) && !wildcard && !fuzzy;
+    let results = if regex {
+        let regex = match cached_regex(state, query, model.is_case_sensitive()) {
+            Ok(regex) => regex,
+            Err(err) => return api_error(StatusCode::BAD_REQUEST, &err.to_string()),
+        };
+        model.search_regex_query(&regex, max_expansions.unwrap_or(DEFAULT_MAX_REGEX_TERMS))
+    } else if let Some(phrase) = query.strip_prefix('"').and_then(|query| query.strip_suffix('"')) {
+        model.search_phrase_query(&phrase.chars().collect::<Vec<_>>())
+    } else if weighted {
+        match QueryParser::parse(query) {
+            Ok(query) => model.search_parsed_query(&ParsedQuery { min_should_match, ..query }, state.ranking),
+            Err(err) => return api_error(StatusCode::BAD_REQUEST, &format!("invalid query: {err}")),
+        }
+    } else if QueryParser::is_boolean(query) {
+        match QueryParser::parse_boolean(query) {
+            Ok(query) => model.search_boolean_query(&query),
+            Err(err) => return api_error(StatusCode::BAD_REQUEST, &format!("invalid query: {err}")),
+        }
+    } else if wildcard {
+        model.search_wildcard_query(&query.chars().collect::<Vec<_>>(), max_expansions.unwrap_or(MAX_WILDCARD_EXPANSIONS))
+    } else if fuzzy {
+        let max_edit = match params.get("max_edit").map(|max_edit| max_edit.parse::<usize>()) {
+            None => MAX_EDIT,
+            Some(Ok(max_edit)) if max_edit <= MAX_EDIT => max_edit,
+            Some(_) => return api_error(StatusCode::BAD_REQUEST, &format!("max_edit must be an integer from 0 to {MAX_EDIT}")),
+        };
+        model.search_fuzzy_query(&query.chars().collect::<Vec<_>>(), max_edit)
+    } else if prf {
+        model.expand_query(&query.chars().collect::<Vec<_>>(), prf_top, expand).and_then(|expansion| {
+            let terms = expansion.original_terms.iter().chain(&expansion.expanded_terms).cloned().collect::<Vec<_>>();
+            let results = model.search_weighted_terms(&terms, state.ranking);
+            query_expansion = Some(expansion);
+            results
+        })
+    } else if case_sensitive || !model.is_case_sensitive() {
+        model.search_query_ranked(&query.chars().collect::<Vec<_>>(), state.ranking)
+    } else {
+        model.search_query_ignoring_case(&query.chars().collect::<Vec<_>>(), state.ranking)
+    };
+    let mut results = match results {
+        Ok(results) => results,
+        Err(err) => {
+            error!(query, error = %err, "search failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response();
+        }
+    };
+    min_score.retain(&mut results);
+    // The score of a document doesn't depend on the other results, so they are filtered once ranked.
+    // The facets still count every extension, so that the client can switch to another one.
+    let facets = facet_by_extension(&results);
+    if let Some(ext) = params.get("ext") {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        results.retain(|(path, _)| file_extension(path) == ext);
+    }
+    if mmr_lambda.is_some() && after.is_some() {
+        return api_error(StatusCode::BAD_REQUEST, "reranked results can't be paged with after, only with offset");
+    }
+    if let Some(lambda) = mmr_lambda {
+        let shown = offset.saturating_add(limit).min(results.len());
+        let candidates = results.len().min(shown.max(MMR_CANDIDATES));
+        let reranked = match model.mmr_rerank(&results[..candidates], lambda, shown) {
+            Ok(reranked) => reranked,
+            Err(err @ SearchError::Unsupported(_)) => return api_error(StatusCode::NOT_IMPLEMENTED, &err.to_string()),
+            Err(err) => {
+                error!(query, error = %err, "reranking failed");
+                return (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response();
+            }
+        };
+        // The documents that were not picked follow in their order
+        let picked = reranked.iter().map(|(path, _)| path.clone()).collect::<HashSet<_>>();
+        results.retain(|(path, _)| !picked.contains(path));
+        results.splice(0..0, reranked);
+    }
+    let (page, next_cursor) = if mmr_lambda.is_some() {
+        (SearchPage::paginate(results, offset, limit), None)
+    } else {
+        // Offsets page in the same order as cursors, so that a next_cursor can follow either
+        sort_for_cursors(&mut results);
+        let page = match &after {
+            Some(after) => SearchPage::after(results, Some(after), limit),
+            None => SearchPage::paginate(results, offset, limit),
+        };
+        let next_cursor = page.next_cursor().map(|cursor| cursor.encode());
+        (page, next_cursor)
+    };
+    let page = SearchPage { facets, ..page };
+
+    // A regular expression has no words to show in snippets
+    let snippet_query = if regex { "" } else { query };
+    let words = snippet_query.split_whitespace()
+        .filter(|word| !matches!(*word, "AND" | "OR" | "NOT"))
+        // Excluded terms are in none of the results
+        .filter(|word| !word.starts_with('-'))
+        .map(|word| word.rsplit_once('^').map_or(word, |(term, _)| term))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let words = words.chars().collect::<Vec<_>>();
+    let tokens = snippet_tokens(&*model, &words);
+    let highlight = if format == OutputFormat::Csv { HighlightMode::None } else { HighlightMode::Html };
+    let mut contents = state.contents.lock().unwrap_or_else(|err| err.into_inner());
+    let results = page.results.into_iter().map(|(path, score)| {
+        let title = model.document_title(&path);
+        let snippet = model.match_snippet(&path, &words)
+            .or_else(|| document_content(&mut contents, &path)
+                .and_then(|content| snippet_of(&*model, content, &tokens, &model.match_positions(&path, &words), SNIPPET_WINDOW)))
+            .map(|snippet| highlight_snippet(&*model, &snippet, &tokens, highlight));
+        SearchResult { path, score, title, snippet }
+    }).collect::<Vec<_>>();
+    if let Some(query_log) = &state.query_log {
+        query_log.record(query, page.total_hits, started.elapsed().as_millis() as u64);
+    }
+
+    if format == OutputFormat::Csv {
+        let results = results.into_iter().enumerate()
+            .map(|(i, SearchResult { path, score, title, snippet })| ExportedResult { rank: offset + i + 1, path, score, title, snippet })
+            .collect::<Vec<_>>();
+        let mut csv = Vec::new();
+        if let Err(err) = write_results_csv(&results, &mut csv) {
+            error!(error = %err, "could not write CSV");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "500").into_response();
+        }
+        let headers = [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"results.csv\""),
+        ];
+        return (headers, csv).into_response();
+    }
+    json_response(StatusCode::OK, &SearchResponse {
+        total: page.total_hits, has_more: page.has_more, next_cursor, facets: page.facets, results, query_expansion,
+    })
+}
+
+fn cached_regex<M>(state: &AppState<M>, pattern: &str, case_sensitive: bool) -> Result<Regex, SearchError> {
+    let mut regexes = state.regexes.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(regex) = regexes.get(&pattern.to_string()) {
+        return Ok(regex.clone());
+    }
+    let regex = term_regex(pattern, case_sensitive)?;
+    regexes.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+fn document_content<'a>(contents: &'a mut LruCache<PathBuf, String>, path: &Path) -> Option<&'a str> {
+    if contents.get(&path.to_path_buf()).is_none() {
+        let content = std::fs::read(path).map_err(|err| {
+            error!(path = %path.display(), error = %err, "could not read file");
+        }).ok()?;
+        contents.insert(path.to_path_buf(), String::from_utf8_lossy(&content).into_owned());
+    }
+    contents.get(&path.to_path_buf()).map(String::as_str)
+}
+
+async fn serve_api_stats<M: Model + Send + Sync + 'static>(State(state): State<Arc<AppState<M>>>) -> Response {
+    let stats = tokio::task::spawn_blocking(move || {
+        state.model.read().and_then(|model| model.stats())
+    }).await;
+    match stats {
+        Ok(Ok(stats)) => json_response(StatusCode::OK, &stats),
+        Ok(Err(err)) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("could not compute index statistics: {err}")),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("could not compute index statistics: {err}")),
+    }
+}
+
+async fn serve_metrics<M: Model + Send + Sync + 'static>(State(state): State<Arc<AppState<M>>>) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render()).into_response()
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    doc_count: usize,
+    uptime_seconds: u64,
+}
+
+// Whether the server is alive, for orchestrators; only a model left inconsistent by a panic makes it fail
+async fn serve_health<M: Model + Send + Sync + 'static>(State(state): State<Arc<AppState<M>>>) -> Response {
+    match state.model.read().and_then(|model| model.document_count()) {
+        Ok(doc_count) => {
+            let uptime_seconds = state.started.elapsed().as_secs();
+            json_response(StatusCode::OK, &Health { status: "ok", doc_count, uptime_seconds })
+        }
+        Err(err) => api_error(StatusCode::SERVICE_UNAVAILABLE, &err.to_string()),
+    }
+}
+
+// Whether the model can be searched, i.e. it is loaded and finalized
+async fn serve_ready<M: Model + Send + Sync + 'static>(State(state): State<Arc<AppState<M>>>) -> Response {
+    let ready = state.model.read().map(|model| model.is_ready());
+    match ready {
+        Ok(true) => serve_health(State(state)).await,
+        Ok(false) => json_response(StatusCode::SERVICE_UNAVAILABLE, &serde_json::json!({ "status": "loading" })),
+        Err(err) => api_error(StatusCode::SERVICE_UNAVAILABLE, &err.to_string()),
+    }
+}
+
+// Documents are identified by their percent-encoded path, which the router decodes
+async fn serve_api_document<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    axum::extract::Path(path): axum::extract::Path<String>,
+) -> Response {
+    let info = state.model.read().and_then(|model| model.document_info(Path::new(&path)));
+    match info {
+        Ok(Some(info)) => json_response(StatusCode::OK, &info),
+        Ok(None) => api_error(StatusCode::NOT_FOUND, "document is not indexed"),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("could not read document metadata: {err}")),
+    }
+}
+
+// Credentials every request has to carry when the server is started with them.
+// Only an Argon2 hash of the password is kept.
+pub struct BasicAuth {
+    user: String,
+    password_hash: String,
+}
+
+impl BasicAuth {
+    pub fn new(user: String, password: String) -> Result<Self, SearchError> {
+        let password_hash = Argon2::default().hash_password(password.as_bytes())
+            .map_err(|err| SearchError::InvalidConfig(format!("could not hash password: {err}")))?
+            .to_string();
+        Ok(Self { user, password_hash })
+    }
+
+    fn accepts(&self, user: &str, password: &str) -> bool {
+        let Ok(password_hash) = PasswordHash::new(&self.password_hash) else {
+            return false;
+        };
+        // Both are checked whether the user matches or not, so that the time taken doesn't tell
+        let user_matches = user.as_bytes().ct_eq(self.user.as_bytes());
+        let password_matches = Argon2::default().verify_password(password.as_bytes(), &password_hash).is_ok();
+        bool::from(user_matches) & password_matches
+    }
+}
+
+// `None` lets the request through, otherwise the response asks for credentials
+fn require_basic_auth(request: &Request, auth: &BasicAuth) -> Option<Response> {
+    let credentials = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    if let Some((user, password)) = credentials.as_deref().and_then(|credentials| credentials.split_once(':')) {
+        if auth.accepts(user, password) {
+            return None;
+        }
+    }
+    Some((StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Basic realm=\"serux\"")], "401").into_response())
+}
+
+async fn basic_auth(State(auth): State<Arc<BasicAuth>>, request: Request, next: Next) -> Response {
+    // Verifying an Argon2 hash takes long enough to hold up the other requests on this thread
+    match tokio::task::block_in_place(|| require_basic_auth(&request, &auth)) {
+        Some(response) => response,
+        None => next.run(request).await,
+    }
+}
+
+// Allow pages of `origin` to read the response from a browser
+fn apply_cors(response: &mut Response, origin: &HeaderValue) {
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+    // The header doesn't depend on the origin of the request, but a cache shared with other sites should still not mix them up
+    if origin != "*" {
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+}
+
+// Answers the preflight requests of browsers itself, before they would be refused for their lack of credentials
+async fn cors(State(origin): State<Arc<HeaderValue>>, request: Request, next: Next) -> Response {
+    if !request.uri().path().starts_with("/api/") {
+        return next.run(request).await;
+    }
+    let mut response = if request.method() == Method::OPTIONS {
+        let headers = [
+            (header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST"),
+            (header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Authorization"),
+        ];
+        (StatusCode::NO_CONTENT, headers).into_response()
+    } else {
+        next.run(request).await
+    };
+    apply_cors(&mut response, &origin);
+    response
+}
+
+// How the document at the `path` parameter scores for the query in `q`
+async fn serve_api_explain<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let (Some(query), Some(path)) = (params.get("q"), params.get("path")) else {
+        return api_error(StatusCode::BAD_REQUEST, "missing query parameter q or path");
+    };
+
+    let prompt = query.chars().collect::<Vec<_>>();
+    let explanation = state.model.read().and_then(|model| model.explain_score(Path::new(path), &prompt, state.ranking));
+    match explanation {
+        Ok(Some(explanation)) => json_response(StatusCode::OK, &explanation),
+        Ok(None) => api_error(StatusCode::NOT_FOUND, "document is not indexed"),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("could not explain score: {err}")),
+    }
+}
+
+// Indexed terms starting with the `prefix` parameter, the most common ones first
+async fn serve_api_suggest<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let prefix = params.get("prefix").map(String::as_str).unwrap_or("");
+    let limit = match params.get("limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_SUGGEST_LIMIT.min(state.max_results),
+        Some(Ok(limit)) => limit.min(state.max_results),
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "limit must be a non-negative integer"),
+    };
+    if prefix.chars().count() < state.suggest_min_prefix_len {
+        return json_response(StatusCode::OK, &Vec::<String>::new());
+    }
+
+    let terms = state.model.read().and_then(|model| model.suggest_terms(prefix, limit));
+    match terms {
+        Ok(terms) => json_response(StatusCode::OK, &terms),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("could not suggest terms: {err}")),
+    }
+}
+
+// The documents most similar to the one at `path`, by the cosine similarity of their TF-IDF vectors
+async fn serve_api_similar<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let Some(path) = params.get("path") else {
+        return api_error(StatusCode::BAD_REQUEST, "path is missing");
+    };
+    let limit = match params.get("limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_SIMILAR_LIMIT.min(state.max_results),
+        Some(Ok(limit)) => limit.min(state.max_results),
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "limit must be a non-negative integer"),
+    };
+
+    let model = match state.model.read() {
+        Ok(model) => model,
+        Err(err) => return api_error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+    };
+    match model.find_similar(Path::new(path), limit) {
+        Ok(similar) => {
+            let results = similar.into_iter().map(|(path, score)| {
+                let title = model.document_title(&path);
+                SearchResult { path, score, title, snippet: None }
+            }).collect::<Vec<_>>();
+            json_response(StatusCode::OK, &results)
+        }
+        Err(SearchError::DocumentNotFound(_)) => api_error(StatusCode::NOT_FOUND, "document is not indexed"),
+        Err(err @ SearchError::Unsupported(_)) => api_error(StatusCode::NOT_IMPLEMENTED, &err.to_string()),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &format!("could not find similar documents: {err}")),
+    }
+}
+
+// The most frequent queries of the query log, with their average number of results and response time
+async fn serve_api_top_queries<M: Model + Send + Sync + 'static>(
+    State(state): State<Arc<AppState<M>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let Some(query_log) = &state.query_log else {
+        return api_error(StatusCode::NOT_FOUND, "the server keeps no query log, start it with --query-log");
+    };
+    let limit = match params.get("limit").map(|limit| limit.parse::<usize>()) {
+        None => DEFAULT_TOP_QUERIES_LIMIT,
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => return api_error(StatusCode::BAD_REQUEST, "limit must be a non-negative integer"),
+    };
+    json_response(StatusCode::OK, &query_log.top_queries(limit))
+}
+
+// The search may go on in the background, its result is dropped
+async fn request_deadline(State(timeout): State<Duration>, request: Request, next: Next) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!(timeout_ms = timeout.as_millis() as u64, "request timed out");
+            json_response(StatusCode::REQUEST_TIMEOUT, &serde_json::json!({"error": "timeout", "message": "request timed out"}))
+        }
+    }
+}
+
+async fn log_request(request: Request, next: Next) -> Response {
+    info!(method = %request.method(), uri = %request.uri(), "received request");
+    next.run(request).await
+}
+
+fn router<M: Model + Send + Sync + 'static>(state: Arc<AppState<M>>, auth: Option<BasicAuth>, cors_origin: Option<HeaderValue>, request_timeout: Duration, read_timeout: Duration) -> Router {
+    let router = Router::new()
+        .route("/api/search", get(serve_api_search::<M>).post(serve_api_search::<M>))
+        .route("/api/search.csv", get(serve_api_search_csv::<M>).post(serve_api_search_csv::<M>))
+        .route("/api/stats", get(serve_api_stats::<M>))
+        .route("/api/suggest", get(serve_api_suggest::<M>))
+        .route("/api/explain", get(serve_api_explain::<M>))
+        .route("/api/documents/{*path}", get(serve_api_document::<M>))
+        .route("/api/similar", get(serve_api_similar::<M>))
+        .route("/api/logs/top-queries", get(serve_api_top_queries::<M>))
+        .route("/metrics", get(serve_metrics::<M>))
+        .route("/index.js", get(|| serve_static_file("static/index.js", "text/javascript; charset=utf-8")))
+        .route("/index.css", get(|| serve_static_file("static/index.css", "text/css; charset=utf-8")))
+        .route("/", get(|| serve_static_file("static/index.html", "text/html; charset=utf-8")))
+        .route("/index.html", get(|| serve_static_file("static/index.html", "text/html; charset=utf-8")))
+        .fallback(|| async { (StatusCode::NOT_FOUND, "404") })
+        .with_state(Arc::clone(&state));
+    let router = match auth {
+        Some(auth) => router.layer(middleware::from_fn_with_state(Arc::new(auth), basic_auth)),
+        None => router,
+    };
+    // Orchestrators check these without credentials
+    let router = router.merge(Router::new()
+        .route("/health", get(serve_health::<M>))
+        .route("/ready", get(serve_ready::<M>))
+        .with_state(state));
+    let router = match cors_origin {
+        Some(origin) => router.layer(middleware::from_fn_with_state(Arc::new(origin), cors)),
+        None => router,
+    };
+    // Responses are gzipped for clients that accept it, with Vary: Accept-Encoding so that caches
+    // keep both versions; tiny ones and images are left as they are
+    router
+        .layer(CompressionLayer::new())
+        .layer(middleware::from_fn(log_request))
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .layer(RequestBodyTimeoutLayer::new(read_timeout))
+        .layer(middleware::from_fn_with_state(request_timeout, request_deadline))
+}
+
+// Resolves on Ctrl-C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!(error = %err, "could not listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                error!(error = %err, "could not listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+// Answer to the requests of connections beyond max_connections, which are closed right after
+fn too_many_connections() -> Response {
+    let mut response = api_error(StatusCode::SERVICE_UNAVAILABLE, "too many connections, try again later");
+    let headers = response.headers_mut();
+    headers.insert(header::RETRY_AFTER, HeaderValue::from_static(RETRY_AFTER_SECS));
+    headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+    response
+}
+
+// Like axum::serve, which gives hyper no timer to cut off connections that are slow to send their headers:
+// those that take longer than `read_timeout` are closed, so that clients sending them byte by byte can't
+// hold on to them.
+// Connections beyond `max_connections` are still read once they waited too long, so that their clients get a
+// 503 rather than a reset.
+// Once shut down, no connection is accepted anymore and the requests in flight are answered.
+async fn serve<L>(mut listener: L, router: Router, read_timeout: Duration, max_connections: usize, mut shutdown_receiver: watch::Receiver<bool>) -> io::Result<()>
+where
+    L: axum::serve::Listener,
+    L::Addr: std::fmt::Debug,
+{
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().timer(TokioTimer::new()).header_read_timeout(read_timeout);
+    let graceful = GracefulShutdown::new();
+    let connections = Arc::new(Semaphore::new(max_connections));
+    let busy = Router::new().fallback(|| async { too_many_connections() });
+    let mut refusing = false;
+    loop {
+        // Held until the connection is closed. New connections wait in the backlog for one to close, and
+        // are only refused once that takes too long; they are refused until one closes.
+        let permit = if refusing {
+            None
+        } else {
+            tokio::select! {
+                permit = tokio::time::timeout(CONNECTION_WAIT, Arc::clone(&connections).acquire_owned()) => permit.ok().and_then(Result::ok),
+                _ = shutdown_receiver.wait_for(|&shutdown| shutdown) => break,
+            }
+        };
+        let (io, addr) = tokio::select! {
+            connection = listener.accept() => connection,
+            _ = shutdown_receiver.wait_for(|&shutdown| shutdown) => break,
+        };
+        // One may have closed while waiting for this connection
+        let permit = permit.or_else(|| Arc::clone(&connections).try_acquire_owned().ok());
+        refusing = permit.is_none();
+        let service = if permit.is_some() {
+            router.clone()
+        } else {
+            warn!(?addr, max_connections, "too many connections, refusing one");
+            busy.clone()
+        };
+        let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), TowerToHyperService::new(service));
+        let connection = graceful.watch(connection.into_owned());
+        tokio::spawn(async move {
+            // Mostly clients that went away or were too slow
+            if let Err(err) = connection.await {
+                debug!(?addr, error = %err, "connection closed");
+            }
+            drop(permit);
+        });
+    }
+    drop(listener);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+// Pages are cut to `max_results` of the configuration no matter how many results the request asks for.
+// With `tls`, connections are HTTPS only. With `auth`, requests without these credentials are refused.
+// With `query_log_path` in the configuration, searches are appended to that file.
+// With `cors_origin`, the API answers browsers on pages of that origin.
+// Returns once the server is stopped with Ctrl-C or SIGTERM and the requests in flight are answered.
+pub fn start<M: Model + Send + Sync + 'static>(address: &str, model: SharedModel<M>, ranking: RankingAlgorithm, config: &Config, tls: Option<Arc<ServerConfig>>, auth: Option<BasicAuth>) -> Result<(), SearchError> {
+    start_until(address, model, ranking, config, tls, auth, shutdown_signal())
+}
+
+// Like start, but the server stops once `shutdown` resolves instead of on a signal
+pub fn start_until<M: Model + Send + Sync + 'static>(address: &str, model: SharedModel<M>, ranking: RankingAlgorithm, config: &Config, tls: Option<Arc<ServerConfig>>, auth: Option<BasicAuth>, shutdown: impl Future<Output = ()> + Send + 'static) -> Result<(), SearchError> {
+    let query_log = config.query_log_path.as_deref().map(QueryLog::open).transpose()?.map(Arc::new);
+    // Checked by Config::validate
+    let cors_origin = config.cors_origin.as_deref().map(HeaderValue::from_str).transpose()
+        .map_err(|err| SearchError::InvalidConfig(format!("cors_origin: {err}")))?;
+    let request_timeout = Duration::from_millis(config.request_timeout_ms);
+    let read_timeout = Duration::from_millis(config.read_timeout_ms);
+    let max_connections = config.max_connections;
+    let state = Arc::new(AppState {
+        ranking,
+        max_results: config.max_results,
+        suggest_min_prefix_len: config.suggest_min_prefix_len,
+        default_min_should_match: config.default_min_should_match,
+        case_sensitive: config.case_sensitive && model.read()?.is_case_sensitive(),
+        contents: Mutex::new(LruCache::new(CONTENT_CACHE_CAPACITY)),
+        regexes: Mutex::new(LruCache::new(REGEX_CACHE_CAPACITY)),
+        query_log: query_log.clone(),
+        started: Instant::now(),
+        metrics: Metrics::default(),
+        model,
+    });
+    state.metrics.record_indexing(state.model.read()?.document_count()?);
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(address).await.map_err(|err| {
+            io::Error::new(err.kind(), format!("could not start HTTP server at {address}: {err}"))
+        })?;
+
+        // Everything that has to stop with the server waits for this channel to change
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        tokio::spawn(async move {
+            shutdown.await;
+            info!("shutting down");
+            shutdown_sender.send_replace(true);
+        });
+
+        // Writing the file is blocking, it runs off the threads that serve the connections
+        if let Some(query_log) = query_log.clone() {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(QUERY_LOG_FLUSH_INTERVAL);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    let query_log = Arc::clone(&query_log);
+                    match tokio::task::spawn_blocking(move || query_log.flush()).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(err)) => error!(error = %err, "could not write the query log"),
+                        Err(err) => error!(error = %err, "could not write the query log"),
+                    }
+                }
+            });
+        }
+
+        match tls {
+            Some(config) => {
+                info!(url = %format_args!("https://{address}/"), "listening");
+                serve(TlsListener::new(listener, config)?, router(state, auth, cors_origin, request_timeout, read_timeout), read_timeout, max_connections, shutdown_receiver).await?;
+            }
+            None => {
+                warn!("serving over plain HTTP");
+                info!(url = %format_args!("http://{address}/"), "listening");
+                serve(listener, router(state, auth, cors_origin, request_timeout, read_timeout), read_timeout, max_connections, shutdown_receiver).await?;
+            }
+        }
+        Ok::<_, SearchError>(())
+    })?;
+
+    // The searches since the last flush
+    match query_log {
+        Some(query_log) => query_log.flush(),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::Path;
+
+use super::model::{Lexer, Model};
+
+// The query and the document are tokenized by the model like the indexed documents, so that the
+// snippet shows what matched: "running" finds "runs" if the index was built with stemming, and
+// stop words are not marked if it drops them. Punctuation like quotes or wildcards is dropped.
+pub fn snippet_tokens(model: &(impl Model + ?Sized), query: &[char]) -> Vec<String> {
+    model.lexer(query)
+        .filter(|token| token.starts_with(|x: char| x.is_alphanumeric()))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HighlightMode {
+    // Bold yellow on terminals
+    Ansi,
+    // <mark> tags, with the rest of the text HTML-escaped
+    Html,
+    None,
+}
+
+/// Excerpt of about `window` characters of the file around the first occurrence of any of the
+/// `query_tokens` (as returned by `snippet_tokens` for the `model`), with its whitespace collapsed into single spaces.
+/// The occurrence is taken from the `positions` of the query the index knows (see Model::match_positions)
+/// if there are any, otherwise the file is searched for it.
+/// None if the file can't be read or none of the tokens occur in it.
+pub fn snippet_for(model: &(impl Model + ?Sized), path: &Path, query_tokens: &[String], positions: &[u32], window: usize) -> Option<String> {
+    let content = fs::read(path).map_err(|err| {
+        tracing::error!(path = %path.display(), error = %err, "could not read file");
+    }).ok()?;
+    snippet_of(model, &String::from_utf8_lossy(&content), query_tokens, positions, window)
+}
+
+// Same as `snippet_for` for content that is already in memory
+pub fn snippet_of(model: &(impl Model + ?Sized), content: &str, query_tokens: &[String], positions: &[u32], window: usize) -> Option<String> {
+    let chars = content.chars().collect::<Vec<_>>();
+    // A position is only trusted if a token still starts there, the file may have changed since it was indexed
+    let indexed = positions.iter().find_map(|&position| {
+        let position = position as usize;
+        let token = Lexer::new(chars.get(position..)?).positions().next()?;
+        (token.char_start == 0).then_some(position..position + token.char_end)
+    });
+    let matched = match indexed {
+        Some(matched) => matched,
+        None => {
+            let token = model.lexer(&chars).positions().find(|token| query_tokens.contains(&token.text))?;
+            token.char_start..token.char_end
+        }
+    };
+
+    // Center the window on the occurrence, shifting it back inside the document near either end
+    let center = (matched.start + matched.end) / 2;
+    let start = center.saturating_sub(window / 2)
+        .min(chars.len().saturating_sub(window))
+        .min(matched.start);
+    let end = (start + window).max(matched.end).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.extend(chars[start..end].iter().map(|&c| if c.is_whitespace() { ' ' } else { c }));
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+/// Mark every occurrence of the `query_tokens` in the `snippet`.
+/// Occurrences are whole tokens of the snippet, compared the same way as by `snippet_of`,
+/// so a query with both "foo" and "foobar" marks "foobar" once rather than nesting the marks.
+pub fn highlight_snippet(model: &(impl Model + ?Sized), snippet: &str, query_tokens: &[String], mode: HighlightMode) -> String {
+    let (open, close) = match mode {
+        HighlightMode::Ansi => ("\x1b[1;33m", "\x1b[0m"),
+        HighlightMode::Html => ("<mark>", "</mark>"),
+        HighlightMode::None => return snippet.to_string(),
+    };
+    let push_text = |highlighted: &mut String, text: &[char]| {
+        for &c in text {
+            match c {
+                '&' if mode == HighlightMode::Html => highlighted.push_str("&amp;"),
+                '<' if mode == HighlightMode::Html => highlighted.push_str("&lt;"),
+                '>' if mode == HighlightMode::Html => highlighted.push_str("&gt;"),
+                '"' if mode == HighlightMode::Html => highlighted.push_str("&quot;"),
+                c => highlighted.push(c),
+            }
+        }
+    };
+
+    let chars = snippet.chars().collect::<Vec<_>>();
+    let mut highlighted = String::new();
+    let mut copied = 0;
+    for token in model.lexer(&chars).positions() {
+        if query_tokens.contains(&token.text) {
+            push_text(&mut highlighted, &chars[copied..token.char_start]);
+            highlighted.push_str(open);
+            push_text(&mut highlighted, &chars[token.char_start..token.char_end]);
+            highlighted.push_str(close);
+            copied = token.char_end;
+        }
+    }
+    push_text(&mut highlighted, &chars[copied..]);
+    highlighted
+}
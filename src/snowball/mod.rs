@@ -1,4 +1,5 @@
 #![allow(unused)]
+#![allow(clippy::all)]
 pub mod algorithms;
 mod among;
 mod snowball_env;
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::cli::{Cli, Command};
+use crate::config::{Config, RankingName};
+use crate::model::SearchError;
+
+use super::TestDir;
+
+fn cli(args: &[&str]) -> Cli {
+    Cli::try_parse_from([&["serux"], args].concat()).expect("arguments parse")
+}
+
+fn invalid_config(source: &str) -> String {
+    let dir = TestDir::new("config-invalid");
+    match Config::load_or_default(&dir.write("serux.toml", source)) {
+        Err(SearchError::InvalidConfig(msg)) => msg,
+        result => panic!("{source:?} is accepted: {result:?}"),
+    }
+}
+
+#[test]
+fn flags_override_the_config_file() {
+    let dir = TestDir::new("config-override");
+    let path = dir.write("serux.toml", "index_path = \"docs.json\"\nstemming = false\nmax_results = 5\nfile_extensions = [\"md\"]\n");
+    let mut config = Config::load_or_default(&path).unwrap();
+    assert_eq!(config.index_path, Some(PathBuf::from("docs.json")));
+    assert_eq!(config.max_results, 5);
+
+    crate::apply_global_flags(&mut config, &cli(&["--stem", "search", "rust"]));
+    let expected = Config {
+        index_path: Some(PathBuf::from("docs.json")),
+        stemming: true,
+        max_results: 5,
+        file_extensions: vec!["md".to_string()],
+        ..Config::default()
+    };
+    assert_eq!(config, expected);
+
+    // Flags that are not given leave the file alone
+    crate::apply_global_flags(&mut config, &cli(&["search", "rust"]));
+    assert_eq!(config, expected);
+
+    crate::apply_global_flags(&mut config, &cli(&["--bm25", "--stop-words", "words.txt", "search", "rust"]));
+    assert_eq!(config.ranking_algorithm, Some(RankingName::Bm25));
+    assert_eq!(config.stop_words_file, Some(PathBuf::from("words.txt")));
+}
+
+#[test]
+fn missing_config_file_is_the_default() {
+    let dir = TestDir::new("config-missing");
+    assert_eq!(Config::load_or_default(&dir.path().join("serux.toml")).unwrap(), Config::default());
+}
+
+#[test]
+fn default_config_file_reads_back() {
+    let dir = TestDir::new("config-init");
+    let path = dir.path().join("serux.toml");
+    Config::write_default(&path).unwrap();
+    assert_eq!(Config::load_or_default(&path).unwrap(), Config::default());
+    assert!(matches!(Config::write_default(&path), Err(SearchError::InvalidConfig(_))));
+}
+
+#[test]
+fn unknown_and_out_of_range_fields_are_rejected() {
+    assert!(invalid_config("stemmming = true\n").contains("unknown field `stemmming`"));
+    assert!(invalid_config("thread_count = 0\n").ends_with("thread_count must be at least 1"));
+    assert!(invalid_config("serve_address = \"localhost\"\n").contains("serve_address must be <host>:<port>"));
+    assert!(invalid_config("mmr_lambda = 1.5\n").ends_with("mmr_lambda must be between 0 and 1, got 1.5"));
+    assert!(invalid_config("file_extensions = [\".md\"]\n").contains("file_extensions must be given without the leading dot"));
+}
+
+#[test]
+fn token_length_bounds_are_checked() {
+    assert!(invalid_config("min_token_len = 0\n").ends_with("min_token_len and max_token_len must be at least 1"));
+    assert!(invalid_config("min_token_len = 5\nmax_token_len = 3\n").ends_with("min_token_len must be at most max_token_len, got 5 and 3"));
+
+    let dir = TestDir::new("config-token-len");
+    let config = Config::load_or_default(&dir.write("serux.toml", "min_token_len = 2\nmax_token_len = 100\n")).unwrap();
+    assert_eq!((config.min_token_len, config.max_token_len), (Some(2), Some(100)));
+    assert!(Cli::try_parse_from(["serux", "index", "docs", "--min-token-len", "2", "--max-token-len", "100"]).is_ok());
+    assert!(Cli::try_parse_from(["serux", "index", "docs", "--min-token-len", "-1"]).is_err());
+}
+
+#[test]
+fn default_min_should_match_is_at_least_1() {
+    assert!(invalid_config("default_min_should_match = 0\n").ends_with("default_min_should_match must be at least 1"));
+    let cli = cli(&["serve", "index.json", "--default-msm", "2"]);
+    let Command::Serve(args) = cli.command else { panic!("serve is parsed") };
+    assert_eq!(args.default_min_should_match, Some(2));
+}
+
+#[test]
+fn request_and_read_timeouts_are_at_least_1_ms() {
+    assert!(invalid_config("request_timeout_ms = 0\n").ends_with("request_timeout_ms must be at least 1"));
+    assert!(invalid_config("read_timeout_ms = 0\n").ends_with("read_timeout_ms must be at least 1"));
+    let cli = cli(&["serve", "index.json", "--read-timeout-ms", "500"]);
+    let Command::Serve(args) = cli.command else { panic!("serve is parsed") };
+    assert_eq!(args.read_timeout_ms, Some(500));
+    assert!(Cli::try_parse_from(["serux", "serve", "index.json", "--read-timeout-ms", "0"]).is_err());
+}
@@ -0,0 +1,431 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
+
+use crate::cli::{Cli, Command};
+use crate::config::{ignore_file_patterns, Config};
+use crate::model::{InMemoryModel, Model, TfNormalization};
+
+use super::TestDir;
+
+// Model of the files of the directory, indexed with the default configuration
+fn index_folder(dir: &TestDir) -> InMemoryModel {
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(dir.path(), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    model.finalize();
+    model
+}
+
+#[test]
+fn malformed_html_keeps_its_visible_text() {
+    let source = "<!DOCTYPE html><html><head><title>Broken &amp; page</title><style>p { color: red }</style></head>\
+        <body><p>unclosed <b>bold<div>less &lt;than&gt; &copy; 2024<script>var hidden = 1;</script><p>last";
+    let (content, title, _) = crate::html_to_text(source);
+    for text in ["unclosed", "bold", "less <than> \u{a9} 2024", "last"] {
+        assert!(content.contains(text), "{text:?} is not in {content:?}");
+    }
+    for text in ["hidden", "color", "Broken"] {
+        assert!(!content.contains(text), "{text:?} is in {content:?}");
+    }
+    assert_eq!(title.as_deref(), Some("Broken & page"));
+}
+
+#[test]
+fn malformed_html_files_are_all_indexed() {
+    let dir = TestDir::new("malformed-html");
+    let files = [
+        ("unclosed.html", "<html><body><p>unclosed paragraph <i>italic <b>nested"),
+        ("entities.htm", "<p>caf&eacute; &amp; cr&egrave;me &unknown; &#x41;&#66;"),
+        ("stray-end-tags.html", "</div></span>stray closing tags</p></body>"),
+        ("no-markup.html", "just words without any markup"),
+        ("title-only.html", "<!DOCTYPE html><title>Only a title</title>"),
+        ("empty.html", ""),
+    ];
+    for (name, content) in files {
+        dir.write(name, content);
+    }
+    let model = index_folder(&dir);
+
+    for (name, _) in files {
+        assert!(model.tfpd.contains_key(&dir.path().join(name)), "{name} is not indexed");
+    }
+    for term in ["UNCLOSED", "NESTED", "CAF\u{e9}", "CR\u{e8}ME", "STRAY", "MARKUP"] {
+        assert!(model.df.contains_key(term), "{term} is not indexed");
+    }
+    assert_eq!(model.document_title(&dir.path().join("title-only.html")).as_deref(), Some("Only a title"));
+    assert_eq!(model.mime_types[&dir.path().join("unclosed.html")], "text/html");
+}
+
+#[test]
+fn documents_keep_their_title_size_and_index_time() {
+    let dir = TestDir::new("document-entries");
+    let titled = dir.write("titled.html", "<html><head><title>The title</title></head><body><h1>A heading</h1>text</body></html>");
+    let heading = dir.write("heading.html", "<html><body><h1>Only a heading</h1><h1>Second heading</h1>text</body></html>");
+    let plain = dir.write("plain.txt", "no title here");
+    let before = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let model = index_folder(&dir);
+
+    assert_eq!(model.tfpd[&titled].title.as_deref(), Some("The title"));
+    assert_eq!(model.tfpd[&heading].title.as_deref(), Some("Only a heading"));
+    assert_eq!(model.tfpd[&plain].title, None);
+    for path in [&titled, &heading, &plain] {
+        let entry = &model.tfpd[path];
+        assert_eq!(entry.file_size_bytes, fs::metadata(path).unwrap().len());
+        assert!(entry.indexed_at >= before, "{} was indexed at {}", path.display(), entry.indexed_at);
+    }
+
+    let summary = model.tfpd[&heading].to_summary(&heading);
+    assert_eq!(summary.path, heading);
+    assert_eq!(summary.title.as_deref(), Some("Only a heading"));
+    assert_eq!(summary.term_count, model.tfpd[&heading].term_count);
+    assert_eq!(summary.file_size_bytes, fs::metadata(&heading).unwrap().len());
+}
+
+#[test]
+fn text_files_are_indexed_next_to_xml_files() {
+    let dir = TestDir::new("text-and-xml");
+    dir.write("page.xml", "<doc><p>shared xmlonly</p></doc>");
+    dir.write("notes.txt", "shared textonly plain prose");
+    let model = index_folder(&dir);
+
+    for term in ["TEXTONLY", "PROSE", "XMLONLY"] {
+        assert_eq!(model.df.get(term), Some(&1), "{term}");
+    }
+    assert_eq!(model.df.get("SHARED"), Some(&2));
+    assert_eq!(model.mime_types[&dir.path().join("notes.txt")], "text/plain");
+}
+
+#[test]
+fn invalid_utf8_in_text_files_is_replaced() {
+    let dir = TestDir::new("invalid-utf8");
+    let path = dir.write("latin1.txt", b"caf\xe9 au lait");
+    assert_eq!(crate::parse_text_file(&path).expect("text file is read"), "caf\u{fffd} au lait");
+    let model = index_folder(&dir);
+    assert!(model.df.contains_key("LAIT"));
+}
+
+#[test]
+fn documents_pushed_with_a_mime_type_keep_it() {
+    let mut model = InMemoryModel::default();
+    model.add_document("generated".into(), &"plain text".chars().collect::<Vec<_>>(), Some("text/plain")).expect("document is added");
+    assert_eq!(model.document_info(Path::new("generated")).expect("info is read").and_then(|info| info.mime_type).as_deref(), Some("text/plain"));
+}
+
+// Sorted terms of `text` and how often they occur
+fn terms(text: &str) -> Vec<(String, usize)> {
+    let mut model = InMemoryModel::default();
+    model.add_document("doc".into(), &text.chars().collect::<Vec<_>>(), None).expect("document is added");
+    let mut terms = model.tfpd[Path::new("doc")].tf.iter().map(|(term, (freq, _))| (term.clone(), *freq)).collect::<Vec<_>>();
+    terms.sort();
+    terms
+}
+
+#[test]
+fn markdown_is_indexed_like_its_plain_text() {
+    let markdown = "Some **bold** and _emphasized_ words, a [link](https://example.com/hidden-url) \
+        and `inline code`.\n\n```rust\nfn fenced() {}\n```\n";
+    let plain = "Some bold and emphasized words, a link and inline code. fn fenced() {}";
+    assert_eq!(terms(&crate::markdown_to_text(markdown, 1)), terms(plain));
+}
+
+#[test]
+fn markdown_drops_front_matter_images_and_html() {
+    let yaml = "---\ntitle: front matter\n---\nbody text";
+    let toml = "+++\ntitle = \"front matter\"\n+++\nbody text";
+    for source in [yaml, toml] {
+        assert_eq!(terms(&crate::markdown_to_text(source, 1)), terms("body text"));
+    }
+    // The text between inline tags is part of the paragraph, the tags are not
+    let source = "before ![alt text](image.png) <span class=\"tag\">inline</span>\n\n<div>\nblock html\n</div>\n\nafter";
+    assert_eq!(terms(&crate::markdown_to_text(source, 1)), terms("before inline after"));
+}
+
+#[test]
+fn markdown_headings_are_repeated() {
+    let source = "# Title words\n\nbody";
+    assert_eq!(terms(&crate::markdown_to_text(source, 3)), [("BODY".to_string(), 1), ("TITLE".to_string(), 3), ("WORDS".to_string(), 3)]);
+    assert_eq!(terms(&crate::markdown_to_text(source, 1)), terms("Title words body"));
+}
+
+// Content of the file replaced, with its modification time set to `modified`
+fn rewrite(path: &Path, content: &str, modified: SystemTime) {
+    fs::write(path, content).expect("file is written");
+    fs::File::options().write(true).open(path).and_then(|file| file.set_modified(modified)).expect("modification time is set");
+}
+
+#[test]
+fn incremental_indexing_only_updates_modified_files() {
+    let dir = TestDir::new("incremental");
+    let unchanged = dir.write("unchanged.txt", "original words");
+    let modified = dir.write("modified.txt", "outdated words");
+    let mut model = index_folder(&dir);
+    let mtime = fs::metadata(&unchanged).and_then(|metadata| metadata.modified()).expect("modification time is read");
+
+    // Same modification time, so the new content must not be indexed
+    rewrite(&unchanged, "sneaky replacement", mtime);
+    rewrite(&modified, "fresh words", mtime + Duration::from_secs(60));
+    crate::add_folder_to_in_memory_model(dir.path(), &mut model, &Config::default(), None, false).expect("folder is indexed again");
+    model.finalize();
+
+    assert!(model.tfpd[&unchanged].contains_term("ORIGINAL"));
+    assert!(!model.df.contains_key("SNEAKY"));
+    assert!(model.tfpd[&modified].contains_term("FRESH"));
+    assert!(!model.df.contains_key("OUTDATED"));
+    assert_eq!(model.df.get("WORDS"), Some(&2));
+    assert_eq!(model.file_mtimes[&modified], mtime + Duration::from_secs(60));
+}
+
+// Documents of random words out of a small vocabulary, the same for every run
+fn write_seeded_corpus(dir: &TestDir, documents: usize) {
+    const WORDS: [&str; 12] = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu"];
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    for i in 0..documents {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let len = 5 + (state % 40) as usize;
+        let words = (0..len).map(|j| WORDS[((state >> (j % 48)) as usize + j * 7) % WORDS.len()]).collect::<Vec<_>>();
+        dir.write(&format!("{}/{i}.xml", i % 7), format!("<doc><title>document {i}</title><p>{}</p></doc>", words.join(" ")));
+    }
+}
+
+#[test]
+fn parallel_indexing_equals_serial_indexing() {
+    let dir = TestDir::new("parallel");
+    write_seeded_corpus(&dir, 300);
+    let config = Config::default();
+    let parallel = index_folder(&dir);
+
+    let mut files = Vec::new();
+    crate::files_to_index(dir.path(), &mut InMemoryModel::default(), &config, None, &mut files).expect("files are listed");
+    files.sort();
+    let mut serial = InMemoryModel::default();
+    for path in files {
+        let file = crate::parse_file(&path, &config).expect("file is parsed");
+        let document = serial.analyze(&file.content, file.title.as_deref(), file.metadata.as_deref());
+        serial.merge_document(path, document, file.title, Some(file.mime_type));
+    }
+    serial.finalize();
+
+    assert_eq!(parallel.df, serial.df);
+    assert_eq!(parallel.tfpd.len(), 300);
+    for (path, entry) in &serial.tfpd {
+        let other = &parallel.tfpd[path];
+        assert_eq!((&other.tf, other.term_count, &other.title), (&entry.tf, entry.term_count, &entry.title), "{}", path.display());
+    }
+    assert!((parallel.avg_doc_len - serial.avg_doc_len).abs() < 1e-3);
+    // The scores of the terms may be added up in another order, so they can differ in the last bits
+    for query in ["alpha", "beta gamma", "mu lambda kappa"] {
+        let query = query.chars().collect::<Vec<_>>();
+        let scores = |model: &InMemoryModel| model.search_query(&query, 0, usize::MAX).expect("search runs").results.into_iter().collect::<BTreeMap<_, _>>();
+        let (parallel, serial) = (scores(&parallel), scores(&serial));
+        assert_eq!(parallel.keys().collect::<Vec<_>>(), serial.keys().collect::<Vec<_>>());
+        assert!(parallel.values().zip(serial.values()).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+}
+
+#[test]
+fn only_included_extensions_are_indexed() {
+    let dir = TestDir::new("extensions");
+    let xml = dir.write("doc.xml", "<doc>markup words</doc>");
+    let txt = dir.write("notes.TXT", "plain words");
+    let rs = dir.write("src/main.rs", "fn main() {}");
+
+    let index = |config: &Config| {
+        let mut model = InMemoryModel::default();
+        crate::add_folder_to_in_memory_model(dir.path(), &mut model, config, None, false).expect("folder is indexed");
+        model.tfpd.into_keys().collect::<Vec<_>>()
+    };
+    assert_eq!(index(&Config { file_extensions: vec!["xml".to_string()], ..Config::default() }), std::slice::from_ref(&xml));
+
+    let mut excluded = index(&Config { exclude_extensions: vec!["rs".to_string()], ..Config::default() });
+    excluded.sort();
+    assert_eq!(excluded, [xml.clone(), txt]);
+
+    // Excluding wins over including
+    let config = Config { file_extensions: vec!["xml".to_string(), "rs".to_string()], exclude_extensions: vec!["rs".to_string()], ..Config::default() };
+    assert_eq!(index(&config), [xml]);
+    assert!(!config.indexes_file(&rs));
+}
+
+#[test]
+fn documents_from_memory_are_indexed_like_files() {
+    let dir = TestDir::new("from-documents");
+    let docs = [("a.txt", "rust and go and rust"), ("b.txt", "go gardening"), ("sub/c.txt", "rust only")];
+    for (name, content) in docs {
+        dir.write(name, content);
+    }
+    let from_folder = index_folder(&dir);
+    let mut from_memory = InMemoryModel::from_documents(docs.iter().map(|(name, content)| (dir.path().join(name), content.to_string()))).unwrap();
+    let mut batch = InMemoryModel::default();
+    let added = batch.add_document_batch(docs.iter().map(|(name, content)| (dir.path().join(name), content.chars().collect()))).unwrap();
+    assert_eq!(added, 3);
+    batch.finalize();
+
+    for model in [&mut from_memory, &mut batch] {
+        assert_eq!(model.df, from_folder.df);
+        assert_eq!(model.tfpd.len(), from_folder.tfpd.len());
+        for (path, entry) in &from_folder.tfpd {
+            assert_eq!(model.tfpd[path].term_count, entry.term_count, "{}", path.display());
+            assert_eq!(model.tfpd[path].tf, entry.tf, "{}", path.display());
+        }
+        assert_eq!(model.avg_doc_len, from_folder.avg_doc_len);
+        let query = "rust go".chars().collect::<Vec<_>>();
+        assert_eq!(model.search_query(&query, 0, 10).unwrap().results, from_folder.search_query(&query, 0, 10).unwrap().results);
+    }
+}
+
+#[test]
+fn xml_titles_are_indexed_apart_from_the_body() {
+    let dir = TestDir::new("indexing-xml-title");
+    let titled = dir.write("titled.xml", "<doc><title>Rust</title><body>A guide for programmers</body></doc>");
+    let body = dir.write("body.xml", "<doc><title>Programming</title><body>A guide for rust programmers</body></doc>");
+    dir.write("other.xml", "<doc><body>tomatoes</body></doc>");
+
+    let (content, title, _) = crate::parse_xml_file(&titled).expect("XML is parsed");
+    assert_eq!(title.as_deref(), Some("Rust"));
+    assert!(!content.contains("Rust"), "{content:?}");
+
+    let model = index_folder(&dir);
+    let entry = &model.tfpd[&titled];
+    assert!(entry.fields.title.contains_key("RUST") && !entry.tf.contains_key("RUST"));
+    let results = model.search_query(&"rust".chars().collect::<Vec<_>>(), 0, 10).expect("search runs").results;
+    assert_eq!(results.into_iter().map(|(path, _)| path).collect::<Vec<_>>(), [titled, body]);
+}
+
+#[test]
+fn chinese_documents_are_found_by_two_character_words() {
+    let dir = TestDir::new("indexing-cjk");
+    // "Peking University is a university", "The University of Tokyo is in Japan" and "I like to eat apples"
+    let peking = dir.write("peking.txt", "北京大学是一所大学");
+    let tokyo = dir.write("tokyo.txt", "东京大学在日本");
+    let apples = dir.write("apples.txt", "我喜欢吃苹果");
+
+    let config = Config { cjk_ngram: true, ..Config::default() };
+    let mut model = crate::new_model(&config, false, None, None, None, false, TfNormalization::Raw).expect("model is created");
+    crate::add_folder_to_in_memory_model(dir.path(), &mut model, &config, None, false).expect("folder is indexed");
+    model.finalize();
+
+    let search = |query: &str| model.search_query(&query.chars().collect::<Vec<_>>(), 0, 10).expect("search runs")
+        .results.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+    // The characters of the query are terms as well, so Tokyo (东京) matches the 京 of Beijing (北京), below it
+    assert_eq!(search("北京"), [peking.clone(), tokyo.clone()]);
+    assert_eq!(search("日本"), std::slice::from_ref(&tokyo));
+    assert_eq!(search("大学"), [peking, tokyo]);
+    assert_eq!(search("苹果"), [apples]);
+
+    // Without n-grams, the sentences are single terms
+    let model = index_folder(&dir);
+    assert!(model.search_query(&"北京".chars().collect::<Vec<_>>(), 0, 10).expect("search runs").results.is_empty());
+}
+
+#[test]
+fn documents_keep_when_their_file_was_modified() {
+    let dir = TestDir::new("indexing-last-modified");
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    rewrite(&dir.write("old.txt", ""), "rust edition 2018", old);
+    dir.write("new.txt", "rust edition 2024");
+
+    let model = index_folder(&dir);
+    assert_eq!(model.tfpd[&dir.path().join("old.txt")].last_modified, Some(1_600_000_000));
+    let new = model.tfpd[&dir.path().join("new.txt")].last_modified.expect("modification time is kept");
+    assert!(new > 1_600_000_000);
+
+    let config = Config { freshness_half_life_days: Some(0.0), ..Config::default() };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn depth_limits_how_far_down_the_folder_is_indexed() {
+    let dir = TestDir::new("indexing-depth");
+    for (name, content) in [("root.txt", "root level"), ("one/first.txt", "first level"), ("one/two/second.txt", "second level")] {
+        dir.write(name, content);
+    }
+    let indexed = |max_depth: Option<usize>| {
+        let mut model = InMemoryModel::default();
+        crate::add_folder_to_in_memory_model(dir.path(), &mut model, &Config::default(), max_depth, false).expect("folder is indexed");
+        let mut paths = model.tfpd.keys().map(|path| path.strip_prefix(dir.path()).expect("documents are in the folder").to_path_buf()).collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+    let [root, first, second] = ["root.txt", "one/first.txt", "one/two/second.txt"].map(|name| Path::new(name).to_path_buf());
+    assert!(indexed(Some(0)).is_empty());
+    assert_eq!(indexed(Some(1)), std::slice::from_ref(&root));
+    assert_eq!(indexed(Some(2)), [first.clone(), root.clone()]);
+    assert_eq!(indexed(Some(3)), [first.clone(), second.clone(), root.clone()]);
+    assert_eq!(indexed(None), [first, second, root]);
+
+    let cli = Cli::try_parse_from(["serux", "index", "docs", "--depth", "1"]).expect("arguments parse");
+    let Command::Index(args) = cli.command else { panic!("index is parsed") };
+    assert_eq!(args.depth, Some(1));
+}
+
+#[test]
+fn hash_checks_skip_files_whose_content_did_not_change() {
+    let dir = TestDir::new("indexing-hash-check");
+    let file = dir.write("corpus/notes.txt", "backed up words");
+    let backup = dir.write("backup/notes.txt", "backed up words");
+    let mut model = InMemoryModel::default();
+    model.set_hash_check(true);
+    crate::add_folder_to_in_memory_model(&dir.path().join("corpus"), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    let hash = model.tfpd[&file].content_hash.expect("content is hashed");
+
+    // The hash is saved with the index
+    let index_path = dir.path().join("index.json");
+    crate::save_model(&model, index_path.to_str().expect("path is UTF-8")).expect("index is saved");
+    let mut model = crate::load_model(index_path.to_str().expect("path is UTF-8")).expect("index loads");
+    assert_eq!(model.tfpd[&file].content_hash, Some(hash));
+    model.set_hash_check(true);
+
+    // Restored from the backup, with a time in the past
+    fs::copy(&backup, &file).expect("file is restored");
+    fs::File::options().write(true).open(&file).and_then(|file| file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000))).expect("modification time is set");
+    let restored = fs::metadata(&file).and_then(|metadata| metadata.modified()).expect("modification time is read");
+    assert!(!model.requires_reindexing(&file, restored).unwrap());
+
+    // Modified for real
+    rewrite(&file, "rewritten words", restored + Duration::from_secs(60));
+    crate::add_folder_to_in_memory_model(&dir.path().join("corpus"), &mut model, &Config::default(), None, false).expect("folder is indexed again");
+    assert!(model.tfpd[&file].contains_term("REWRITTEN"));
+    assert_ne!(model.tfpd[&file].content_hash, Some(hash));
+
+    // Without hash checks any other time means indexing the file again
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(&dir.path().join("corpus"), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    assert_eq!(model.tfpd[&file].content_hash, None);
+    assert!(model.requires_reindexing(&file, restored + Duration::from_secs(120)).unwrap());
+}
+
+#[test]
+fn excluded_folders_and_ignored_files_are_left_out() {
+    let dir = TestDir::new("indexing-exclude");
+    for (name, content) in [
+        ("src/main.txt", "main code"), ("notes.txt", "project notes"), ("docs/notes.txt", "more notes"), ("docs/build-1.txt", "build output"),
+        ("node_modules/left-pad/readme.txt", "module docs"), ("web/node_modules/react/readme.txt", "nested module"),
+        (".git/HEAD.txt", "ref heads"), ("target/debug/out.txt", "compiled"), ("src/target.txt", "a file named target"),
+    ] {
+        dir.write(name, content);
+    }
+    let indexed = |config: &Config| {
+        let mut model = InMemoryModel::default();
+        crate::add_folder_to_in_memory_model(dir.path(), &mut model, config, None, false).expect("folder is indexed");
+        let mut paths = model.tfpd.keys().map(|path| path.strip_prefix(dir.path()).expect("documents are in the folder").display().to_string()).collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+
+    assert_eq!(indexed(&Config::default()).len(), 9);
+
+    let cli = Cli::try_parse_from(["serux", "index", "docs", "--exclude-pattern", "**/node_modules/**", "--exclude-pattern", "**/.git/**"]).expect("arguments parse");
+    let Command::Index(args) = cli.command else { panic!("index is parsed") };
+    let mut config = Config { exclude_patterns: args.exclude_patterns, ..Config::default() };
+    assert_eq!(indexed(&config), ["docs/build-1.txt", "docs/notes.txt", "notes.txt", "src/main.txt", "src/target.txt", "target/debug/out.txt"]);
+
+    // Folders only with a trailing slash, from the root only with a leading one, and at any depth otherwise
+    dir.write(".seruxignore", "# noise\ntarget/\n/notes.txt\nbuild-*\n\n");
+    config.exclude_patterns.extend(ignore_file_patterns(dir.path()).expect("ignore file is read"));
+    assert_eq!(indexed(&config), ["docs/notes.txt", "src/main.txt", "src/target.txt"]);
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod config;
+mod indexing;
+mod parsers;
+mod persistence;
+mod query_log;
+mod server_integration;
+mod snippet;
+mod verify;
+
+// Empty directory of its own for a test, removed when dropped
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("serux-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("temporary directory is created");
+        Self(dir)
+    }
+
+    pub fn write(&self, name: &str, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().expect("files are in the directory")).expect("parent directory is created");
+        fs::write(&path, content).expect("file is written");
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::model::{InMemoryModel, Model};
+use crate::parsers::csv::{parse_csv_file, CSV_HEADER_REPEAT};
+use crate::parsers::json::parse_json_file;
+
+use super::TestDir;
+
+// Distinct terms of `text`
+fn term_set(text: &str) -> BTreeSet<String> {
+    let mut model = InMemoryModel::default();
+    model.add_document("doc".into(), &text.chars().collect::<Vec<_>>(), None).expect("document is added");
+    model.tfpd[Path::new("doc")].tf.keys().cloned().collect()
+}
+
+#[test]
+fn nested_json_is_indexed_like_its_flat_strings() {
+    let dir = TestDir::new("parsers-json-nested");
+    let json = dir.write("nested.json", r#"{
+        "library": {
+            "name": "serux",
+            "authors": [{"name": "ferris"}, {"name": "corro", "roles": ["maintainer", ["reviewer"]]}],
+            "topics": {"search": {"ranking": "bm25 and tf-idf"}}
+        }
+    }"#);
+    let flat = "library name serux authors name ferris name corro roles maintainer reviewer \
+        topics search ranking bm25 and tf-idf";
+    assert_eq!(term_set(&parse_json_file(&json).expect("JSON is parsed")), term_set(flat));
+}
+
+#[test]
+fn json_nulls_booleans_and_numbers_are_skipped() {
+    let dir = TestDir::new("parsers-json-scalars");
+    let json = dir.write("scalars.json", r#"[{"title": "kept", "draft": false, "stars": 42, "ratio": 0.5, "parent": null}, true, 7, null]"#);
+    let content = parse_json_file(&json).expect("JSON is parsed");
+    assert_eq!(term_set(&content), term_set("title kept draft stars ratio parent"));
+    for skipped in ["FALSE", "TRUE", "NULL", "42", "0", "5", "7"] {
+        assert!(!term_set(&content).contains(skipped), "{skipped} is indexed");
+    }
+}
+
+#[test]
+fn json_files_are_indexed_with_their_mime_type() {
+    let dir = TestDir::new("parsers-json-folder");
+    dir.write("data.json", r#"{"greeting": "hello"}"#);
+    dir.write("broken.json", r#"{"greeting": "#);
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(dir.path(), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    model.finalize();
+
+    let data = dir.path().join("data.json");
+    assert_eq!(model.mime_types.get(&data).map(String::as_str), Some("application/json"));
+    assert!(model.tfpd[&data].tf.contains_key("HELLO"));
+    // Invalid JSON is reported and left out
+    assert!(!model.tfpd.contains_key(&dir.path().join("broken.json")));
+}
+
+// Terms of `text` and how often they occur
+fn term_counts(text: &str) -> Vec<(String, usize)> {
+    let mut model = InMemoryModel::default();
+    model.add_document("doc".into(), &text.chars().collect::<Vec<_>>(), None).expect("document is added");
+    let mut terms = model.tfpd[Path::new("doc")].tf.iter().map(|(term, (freq, _))| (term.clone(), *freq)).collect::<Vec<_>>();
+    terms.sort();
+    terms
+}
+
+#[test]
+fn csv_with_a_bom_unicode_and_quoted_fields_is_parsed() {
+    let dir = TestDir::new("parsers-csv-unicode");
+    let csv = dir.write("glossary.csv", "\u{feff}term,meaning\n\
+        Straße,\"street, road\"\n\
+        日本,\"Japan\nthe country\"\n\
+        café,\"a \"\"small\"\" restaurant\"\n");
+    let content = parse_csv_file(&csv, None).expect("CSV is parsed");
+    assert!(!content.contains('\u{feff}'), "{content:?}");
+    // The header is repeated so that the names of the columns weigh more than the cells
+    let header = format!("{}{}", "term ".repeat(CSV_HEADER_REPEAT), "meaning ".repeat(CSV_HEADER_REPEAT));
+    let cells = "Straße street, road 日本 Japan\nthe country café a \"small\" restaurant ";
+    assert_eq!(content, format!("{header}{cells}"));
+    assert_eq!(term_counts(&content).into_iter().find(|(term, _)| term == "TERM"), Some(("TERM".to_string(), CSV_HEADER_REPEAT)));
+}
+
+#[test]
+fn csv_delimiters_are_guessed_or_given() {
+    let dir = TestDir::new("parsers-csv-delimiters");
+    let expected = "1 alpha beta 2 gamma delta ";
+    for (name, content) in [
+        ("semicolons.csv", "1;alpha beta\n2;\"gamma delta\"\n"),
+        ("pipes.csv", "1|alpha beta\n2|gamma delta\n"),
+        ("tabs.tsv", "1\talpha beta\n2\tgamma delta\n"),
+    ] {
+        assert_eq!(parse_csv_file(&dir.write(name, content), None).expect("CSV is parsed"), expected, "{name}");
+    }
+    let given = dir.write("given.csv", "1:alpha beta,\n2:gamma delta,\n");
+    assert_eq!(parse_csv_file(&given, Some(':')).expect("CSV is parsed"), "1 alpha beta, 2 gamma delta, ");
+    assert_eq!(crate::parsers::csv::parse_delimiter("tab"), Some('\t'));
+    assert_eq!(crate::parsers::csv::parse_delimiter("é"), None);
+}
+
+#[test]
+fn csv_rows_with_missing_fields_are_still_indexed() {
+    let dir = TestDir::new("parsers-csv-ragged");
+    let csv = dir.write("ragged.csv", "name,colour\napple,red\nbanana\ncherry,dark,red\n");
+    let content = parse_csv_file(&csv, None).expect("CSV is parsed");
+    assert!(content.ends_with("apple red banana cherry dark red "), "{content:?}");
+}
+
+// EPUB archive of the content documents `chapters`, read in the order they are given, next to a stylesheet and
+// an image that are in the spine as well
+fn write_epub(dir: &TestDir, name: &str, title: &str, chapters: &[(&str, &str)]) -> std::path::PathBuf {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let mut manifest = String::from(r#"<item id="css" href="style.css" media-type="text/css"/><item id="cover" href="images/cover.png" media-type="image/png"/>"#);
+    let mut spine = String::from(r#"<itemref idref="cover"/>"#);
+    // Listed in the manifest in the reverse of the reading order, which the spine alone decides
+    for (i, (href, _)) in chapters.iter().enumerate().rev() {
+        manifest.push_str(&format!(r#"<item id="chapter{i}" href="{href}" media-type="application/xhtml+xml"/>"#));
+    }
+    for i in 0..chapters.len() {
+        spine.push_str(&format!(r#"<itemref idref="chapter{i}"/>"#));
+    }
+    let package = format!(r#"<?xml version="1.0"?>
+        <package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+            <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>{title}</dc:title></metadata>
+            <manifest>{manifest}</manifest>
+            <spine>{spine}</spine>
+        </package>"#);
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut entries = vec![
+        ("mimetype".to_string(), "application/epub+zip".to_string()),
+        ("META-INF/container.xml".to_string(), r#"<?xml version="1.0"?>
+            <container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+                <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+            </container>"#.to_string()),
+        ("OEBPS/content.opf".to_string(), package),
+        ("OEBPS/style.css".to_string(), "body { font-family: stylesheetfont; }".to_string()),
+        ("OEBPS/images/cover.png".to_string(), "imagebytes".to_string()),
+    ];
+    entries.extend(chapters.iter().map(|(href, body)| {
+        (format!("OEBPS/{href}"), format!(r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>{body}</body></html>"#))
+    }));
+    for (entry, content) in entries {
+        zip.start_file(entry, options).expect("entry is started");
+        zip.write_all(content.as_bytes()).expect("entry is written");
+    }
+    dir.write(name, zip.finish().expect("archive is finished").into_inner())
+}
+
+#[test]
+fn epub_chapters_are_read_in_spine_order_with_the_title() {
+    let dir = TestDir::new("parsers-epub");
+    let epub = write_epub(&dir, "book.epub", "A Tiny Book", &[
+        ("text/one.xhtml", "<h1>Departure</h1><p>The ship leaves the harbour.</p>"),
+        ("text/two.xhtml", "<p>A storm <em>rises</em> at sea.</p>"),
+    ]);
+    let (content, title) = crate::parsers::epub::parse_epub_file(&epub).expect("EPUB is parsed");
+    assert_eq!(title.as_deref(), Some("A Tiny Book"));
+    let departure = content.find("Departure").expect("first chapter is read");
+    let storm = content.find("storm").expect("second chapter is read");
+    assert!(departure < storm, "{content:?}");
+    assert!(!content.contains("stylesheetfont") && !content.contains("imagebytes"), "{content:?}");
+
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(dir.path(), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    let document = &model.tfpd[&epub];
+    assert_eq!(document.title.as_deref(), Some("A Tiny Book"));
+    assert!(document.tf.contains_key("HARBOUR") && document.tf.contains_key("RISES"));
+    assert_eq!(model.mime_types.get(&epub).map(String::as_str), Some("application/epub+zip"));
+}
+
+#[test]
+fn epub_without_a_package_file_is_an_error() {
+    let dir = TestDir::new("parsers-epub-broken");
+    let not_a_zip = dir.write("plain.epub", "just text");
+    assert!(crate::parsers::epub::parse_epub_file(&not_a_zip).is_err());
+
+    // container.xml points at a package file the archive doesn't have
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    zip.start_file("META-INF/container.xml", zip::write::SimpleFileOptions::default()).expect("entry is started");
+    std::io::Write::write_all(&mut zip, br#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#)
+        .expect("entry is written");
+    let epub = dir.write("book.epub", zip.finish().expect("archive is finished").into_inner());
+    assert!(crate::parsers::epub::parse_epub_file(&epub).is_err());
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::model::{InMemoryModel, Model, SearchError, SqliteModel};
+use crate::wal::{self, IndexOp, WriteAheadLog};
+
+use super::TestDir;
+
+// A small corpus indexed with the default configuration
+fn indexed_model(dir: &TestDir) -> InMemoryModel {
+    for (name, content) in [
+        ("rust.txt", "rust is a systems programming language"),
+        ("go.txt", "go is a programming language with garbage collection"),
+        ("garden.txt", "tomatoes and basil grow in the garden"),
+    ] {
+        dir.write(&format!("corpus/{name}"), content);
+    }
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(&dir.path().join("corpus"), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    model.finalize();
+    model
+}
+
+#[test]
+fn mapped_and_read_indexes_search_the_same() {
+    let dir = TestDir::new("persistence-mmap");
+    let model = indexed_model(&dir);
+    for file_name in ["index.json", "index.bin", "index.json.zst"] {
+        let index_path = dir.path().join(file_name);
+        crate::save_model(&model, index_path.to_str().expect("path is UTF-8")).expect("index is saved");
+        let mut read = crate::load_model(index_path.to_str().expect("path is UTF-8")).expect("index is read");
+        let mut mapped = crate::load_model_mmap(&index_path).expect("index is mapped");
+        read.finalize();
+        mapped.finalize();
+        for query in ["programming language", "garden", "rust go", "nothing"] {
+            let query = query.chars().collect::<Vec<_>>();
+            let expected = model.search_query(&query, 0, 10).expect("search runs");
+            assert_eq!(read.search_query(&query, 0, 10).expect("search runs"), expected, "{file_name}");
+            assert_eq!(mapped.search_query(&query, 0, 10).expect("search runs"), expected, "{file_name}");
+        }
+    }
+}
+
+#[test]
+fn binary_indexes_of_older_layouts_ask_to_index_again() {
+    let dir = TestDir::new("persistence-legacy-bincode");
+    let index_path = dir.path().join("index.bin");
+    let index_path = index_path.to_str().expect("path is UTF-8");
+    let mut older_version = crate::BINCODE_MAGIC.to_vec();
+    older_version.extend_from_slice(&0u32.to_le_bytes());
+    for bytes in [b"SRX3\x01\0\0\0".to_vec(), older_version] {
+        std::fs::write(index_path, &bytes).expect("index is written");
+        for err in [crate::load_model(index_path).err(), crate::model_from_slice(&bytes).err(), crate::migrate_index(index_path).err()] {
+            let message = err.expect("older binary index is rejected").to_string();
+            assert!(message.contains("index the folder again"), "{message}");
+        }
+    }
+}
+
+#[test]
+fn binary_indexes_of_newer_versions_are_rejected() {
+    let mut bytes = crate::BINCODE_MAGIC.to_vec();
+    bytes.extend_from_slice(&(crate::schema::SCHEMA_VERSION + 1).to_le_bytes());
+    assert!(matches!(crate::model_from_slice(&bytes), Err(crate::model::SearchError::SchemaVersionTooNew { .. })));
+}
+
+// Word of letters only that no other `n` gives, so that the Lexer keeps it as one term
+fn word(mut n: usize) -> String {
+    let mut word = "DOC".to_string();
+    loop {
+        word.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 {
+            return word;
+        }
+    }
+}
+
+// Operation adding a document that contains word(n)
+fn add_op(model: &InMemoryModel, dir: &TestDir, n: usize) -> IndexOp {
+    let content = format!("logged document {}", word(n)).chars().collect::<Vec<_>>();
+    IndexOp::add_document(dir.path().join(format!("{n}.txt")), model.analyze(&content, None, None), None, Some("text/plain"), SystemTime::now())
+}
+
+// Empty index saved to `file_name`, with nothing logged for it
+fn empty_index(dir: &TestDir, file_name: &str) -> (InMemoryModel, PathBuf) {
+    let model = InMemoryModel::default();
+    let index_path = dir.path().join(file_name);
+    crate::save_model(&model, index_path.to_str().expect("path is UTF-8")).expect("index is saved");
+    (model, index_path)
+}
+
+fn wal_len(index_path: &Path) -> u64 {
+    fs::metadata(wal::wal_path(index_path)).expect("log exists").len()
+}
+
+#[test]
+fn logged_documents_survive_a_crash_before_the_checkpoint() {
+    let dir = TestDir::new("persistence-wal-crash");
+    for file_name in ["index.json", "index.bin", "index.json.zst"] {
+        let (model, index_path) = empty_index(&dir, file_name);
+        let mut log = WriteAheadLog::open(&index_path).expect("log opens");
+        for n in 0..100 {
+            log.append(&add_op(&model, &dir, n)).expect("operation is logged");
+        }
+        // The process is gone before the index was saved again
+        drop(log);
+
+        let mut reloaded = crate::load_model(index_path.to_str().expect("path is UTF-8")).expect("index loads");
+        reloaded.finalize();
+        assert_eq!(reloaded.document_count().expect("documents are counted"), 100, "{file_name}");
+        for n in 0..100 {
+            let results = reloaded.search_query(&word(n).chars().collect::<Vec<_>>(), 0, 10).expect("search runs").results;
+            assert_eq!(results.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>(), [dir.path().join(format!("{n}.txt"))], "{file_name}");
+        }
+    }
+}
+
+#[test]
+fn a_torn_last_operation_is_cut_off_before_more_are_logged() {
+    let dir = TestDir::new("persistence-wal-torn");
+    let (model, index_path) = empty_index(&dir, "index.json");
+    let index_path_str = index_path.to_str().expect("path is UTF-8");
+    let mut log = WriteAheadLog::open(&index_path).expect("log opens");
+    log.append(&add_op(&model, &dir, 0)).expect("operation is logged");
+    log.append(&add_op(&model, &dir, 1)).expect("operation is logged");
+    drop(log);
+    let complete_len = wal_len(&index_path);
+    // Killed halfway through writing the third operation
+    let mut line = serde_json::to_vec(&add_op(&model, &dir, 2)).expect("operation serializes");
+    line.truncate(line.len() / 2);
+    fs::OpenOptions::new().append(true).open(wal::wal_path(&index_path)).and_then(|mut file| file.write_all(&line)).expect("log is appended to");
+
+    // Searches leave the log alone, the line may be one `serux watch` is still writing
+    assert_eq!(crate::load_model(index_path_str).expect("index loads").document_count().unwrap(), 2);
+    assert_eq!(wal_len(&index_path), complete_len + line.len() as u64);
+
+    // The process that logs next cuts it off, so that its operations start on a line of their own
+    assert_eq!(crate::load_model_to_log(index_path_str).expect("index loads").document_count().unwrap(), 2);
+    assert_eq!(wal_len(&index_path), complete_len);
+    WriteAheadLog::open(&index_path).and_then(|mut log| log.append(&add_op(&model, &dir, 3))).expect("operation is logged");
+    let reloaded = crate::load_model(index_path_str).expect("index loads");
+    assert_eq!(reloaded.document_count().unwrap(), 3);
+    assert!(reloaded.tfpd.contains_key(&dir.path().join("3.txt")));
+}
+
+#[test]
+fn the_log_is_only_emptied_once_the_saved_index_replaced_the_old_one() {
+    let dir = TestDir::new("persistence-atomic-save");
+    let (model, index_path) = empty_index(&dir, "index.json");
+    let index_path_str = index_path.to_str().expect("path is UTF-8");
+    WriteAheadLog::open(&index_path).and_then(|mut log| log.append(&add_op(&model, &dir, 0))).expect("operation is logged");
+    // Left over from a save that was interrupted
+    fs::write(crate::temporary_index_path(&index_path), "half an index").expect("temporary file is written");
+
+    let loaded = crate::load_model(index_path_str).expect("index loads");
+    crate::save_model(&loaded, index_path_str).expect("index is saved");
+    assert_eq!(wal_len(&index_path), 0);
+    assert!(!crate::temporary_index_path(&index_path).exists());
+    assert_eq!(crate::load_model(index_path_str).expect("index loads").document_count().unwrap(), 1);
+
+    // An index that can't replace the file at its path leaves the log as it was
+    let (model, blocked_path) = empty_index(&dir, "blocked.json");
+    WriteAheadLog::open(&blocked_path).and_then(|mut log| log.append(&add_op(&model, &dir, 1))).expect("operation is logged");
+    let logged_len = wal_len(&blocked_path);
+    fs::remove_file(&blocked_path).expect("index is removed");
+    fs::create_dir_all(blocked_path.join("in-the-way")).expect("folder is created");
+    assert!(crate::save_model(&model, blocked_path.to_str().expect("path is UTF-8")).is_err());
+    assert_eq!(wal_len(&blocked_path), logged_len);
+    assert!(!crate::temporary_index_path(&blocked_path).exists());
+}
+
+#[test]
+fn an_index_migrated_to_sqlite_finds_the_same_top_results() {
+    let dir = TestDir::new("persistence-migrate-sqlite");
+    for (name, content) in [
+        ("rust.txt", "rust is a systems programming language, a language for systems"),
+        ("go.txt", "go is a programming language with garbage collection"),
+        ("zig.txt", "zig is a language"),
+        ("python.txt", "python is a programming language and a large snake"),
+        ("garden.txt", "tomatoes and basil grow in the garden"),
+        ("recipe.txt", "basil pesto with garden tomatoes"),
+    ] {
+        dir.write(&format!("corpus/{name}"), content);
+    }
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(&dir.path().join("corpus"), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    let index_path = dir.path().join("index.json");
+    crate::save_model(&model, index_path.to_str().expect("path is UTF-8")).expect("index is saved");
+    model.finalize();
+
+    let db_path = dir.path().join("index.sqlite");
+    assert_eq!(crate::migrate_to_sqlite(index_path.to_str().expect("path is UTF-8"), &db_path).expect("index is migrated"), 6);
+    let migrated = SqliteModel::open(&db_path).expect("database opens");
+    assert_eq!(migrated.document_count().unwrap(), 6);
+    for query in ["programming language", "garden basil", "snake"] {
+        let query = query.chars().collect::<Vec<_>>();
+        let expected = model.search_query(&query, 0, 3).expect("search runs").results;
+        let results = migrated.search_query(&query, 0, 3).expect("search runs").results;
+        assert_eq!(results.iter().map(|(path, _)| path).collect::<Vec<_>>(), expected.iter().map(|(path, _)| path).collect::<Vec<_>>());
+        for ((_, score), (_, expected)) in results.iter().zip(&expected) {
+            assert!((score - expected).abs() < 1e-5, "{score} != {expected}");
+        }
+    }
+
+    // Migrating again would mix the documents up with the ones already there
+    assert!(matches!(crate::migrate_to_sqlite(index_path.to_str().expect("path is UTF-8"), &db_path), Err(SearchError::Usage(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn paths_that_are_not_utf8_are_percent_encoded_in_sqlite() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    assert_eq!(crate::sqlite_path(Path::new("docs/café 100%.txt")), "docs/café 100%.txt");
+    assert_eq!(crate::sqlite_path(Path::new(OsStr::from_bytes(b"docs/caf\xe9 100%.txt"))), "docs/caf%E9 100%25.txt");
+}
+
+#[cfg(unix)]
+#[test]
+fn index_paths_that_are_not_utf8_are_refused_instead_of_panicking() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    assert_eq!(crate::index_path_str(Path::new("index.json")).expect("path is UTF-8"), "index.json");
+    assert!(matches!(crate::index_path_str(Path::new(OsStr::from_bytes(b"caf\xe9.json"))), Err(SearchError::Usage(_))));
+}
@@ -0,0 +1,49 @@
+use std::fs;
+
+use crate::query_log::{QueryLog, QueryLogEntry};
+
+use super::TestDir;
+
+#[test]
+fn every_recorded_search_is_one_json_line() {
+    let dir = TestDir::new("query-log-lines");
+    let path = dir.path().join("queries.jsonl");
+    let log = QueryLog::open(&path).expect("log is opened");
+    for i in 0..100 {
+        log.record(&format!("query {}", i % 7), i, i as u64 * 2);
+    }
+    log.flush().expect("log is flushed");
+    // Nothing is written twice
+    log.flush().expect("log is flushed");
+
+    let content = fs::read_to_string(&path).expect("log is read");
+    let entries = content.lines()
+        .map(|line| serde_json::from_str::<QueryLogEntry>(line).unwrap_or_else(|err| panic!("{line} is not an entry: {err}")))
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 100);
+    assert_eq!(entries[42].query, "query 0");
+    assert_eq!((entries[42].result_count, entries[42].duration_ms), (42, 84));
+    assert!(entries.iter().all(|entry| entry.timestamp_unix > 0));
+}
+
+#[test]
+fn top_queries_are_the_most_frequent_with_their_averages() {
+    let dir = TestDir::new("query-log-top");
+    let path = dir.path().join("queries.jsonl");
+    let log = QueryLog::open(&path).expect("log is opened");
+    for (query, result_count, duration_ms) in [("rust", 10, 4), ("go", 1, 1), ("rust", 20, 8), ("zig", 0, 2), ("go", 3, 3), ("rust", 0, 0)] {
+        log.record(query, result_count, duration_ms);
+    }
+    let top = log.top_queries(2);
+    assert_eq!(top.iter().map(|stats| (stats.query.as_str(), stats.count)).collect::<Vec<_>>(), [("rust", 3), ("go", 2)]);
+    assert_eq!((top[0].avg_result_count, top[0].avg_duration_ms), (10.0, 4.0));
+    assert_eq!((top[1].avg_result_count, top[1].avg_duration_ms), (2.0, 2.0));
+    log.flush().expect("log is flushed");
+
+    // A reopened log counts the queries of the file; a line cut short by a crash is skipped
+    let mut content = fs::read(&path).expect("log is read");
+    content.extend_from_slice(br#"{"query": "cut"#);
+    fs::write(&path, content).expect("log is written");
+    let reopened = QueryLog::open(&path).expect("log is opened");
+    assert_eq!(reopened.top_queries(20), log.top_queries(20));
+}
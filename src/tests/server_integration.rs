@@ -0,0 +1,899 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::model::{InMemoryModel, Model, RankingAlgorithm};
+use crate::server::{self, BasicAuth, SharedModel};
+
+// How long to wait for the server to listen, in tries of CONNECT_RETRY_DELAY
+const CONNECT_RETRIES: u32 = 100;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// Documents of the servers that don't ask for others
+const DOCUMENTS: [(&str, &str); 2] = [("a.txt", "a test of the server"), ("b.txt", "another document")];
+
+// A server on a port of its own, searching small documents
+struct TestServer {
+    address: String,
+    shutdown: mpsc::Sender<()>,
+    thread: JoinHandle<()>,
+    dir: PathBuf,
+    // The model the server searches, to change it while it runs
+    model: SharedModel<InMemoryModel>,
+}
+
+impl TestServer {
+    fn start(name: &str) -> Self {
+        Self::start_with(name, &Config::default(), None)
+    }
+
+    // Server with the settings of `config`, requiring the credentials of `auth` if given
+    fn start_with(name: &str, config: &Config, auth: Option<BasicAuth>) -> Self {
+        Self::start_with_documents(name, &DOCUMENTS, config, auth)
+    }
+
+    // Server searching `documents`, given as file name and content
+    fn start_with_documents(name: &str, documents: &[(&str, &str)], config: &Config, auth: Option<BasicAuth>) -> Self {
+        let dir = std::env::temp_dir().join(format!("serux-server-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("temporary directory is created");
+        let mut model = InMemoryModel::default();
+        for &(file_name, content) in documents {
+            let path = dir.join(file_name);
+            fs::write(&path, content).expect("document is written");
+            model.add_document(path, &content.chars().collect::<Vec<_>>(), Some("text/plain")).expect("document is indexed");
+        }
+        model.finalize();
+
+        // The port of a listener that is closed right away is free, unless another process takes it meanwhile
+        let address = TcpListener::bind("127.0.0.1:0").and_then(|listener| listener.local_addr()).expect("a port is free").to_string();
+        let (shutdown, receiver) = mpsc::channel::<()>();
+        let model = SharedModel::new(model);
+        let thread = {
+            let address = address.clone();
+            let config = config.clone();
+            let model = model.clone();
+            thread::spawn(move || {
+                let shutdown = async move {
+                    let _ = tokio::task::spawn_blocking(move || receiver.recv()).await;
+                };
+                server::start_until(&address, model, RankingAlgorithm::TfIdf, &config, None, auth, shutdown)
+                    .expect("server runs");
+            })
+        };
+
+        let connected = (0..CONNECT_RETRIES).any(|_| {
+            let connected = TcpStream::connect(&address).is_ok();
+            if !connected {
+                thread::sleep(CONNECT_RETRY_DELAY);
+            }
+            connected
+        });
+        assert!(connected, "server is not listening at {address}");
+        Self { address, shutdown, thread, dir, model }
+    }
+
+    // Status and body of the response to GET `path`
+    fn get(&self, path: &str) -> (u16, String) {
+        let response = self.get_with_headers(path, &[]);
+        (response.status, response.body)
+    }
+
+    // Response to GET `path` with `headers` added to the request
+    fn get_with_headers(&self, path: &str, headers: &[(&str, &str)]) -> TestResponse {
+        self.request("GET", path, headers)
+    }
+
+    // Response to a `method` request without a body
+    fn request(&self, method: &str, path: &str, headers: &[(&str, &str)]) -> TestResponse {
+        let mut stream = TcpStream::connect(&self.address).expect("server accepts connections");
+        let headers = headers.iter().map(|(name, value)| format!("{name}: {value}\r\n")).collect::<String>();
+        write!(stream, "{method} {path} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n{headers}\r\n", self.address).expect("request is sent");
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).expect("response is read");
+
+        let end_of_head = response.windows(4).position(|window| window == b"\r\n\r\n").expect("response has a head");
+        let head = String::from_utf8_lossy(&response[..end_of_head]);
+        let mut lines = head.lines();
+        let status = lines.next().and_then(|line| line.split(' ').nth(1)).and_then(|status| status.parse().ok()).expect("response has a status");
+        let headers = lines.filter_map(|line| line.split_once(':')).map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string())).collect();
+        let mut response = TestResponse { status, headers, bytes: response[end_of_head + 4..].to_vec(), body: String::new() };
+        if response.header("transfer-encoding") == Some("chunked") {
+            response.bytes = dechunk(&response.bytes);
+        }
+        // Compressed bodies are left to the test to decode
+        response.body = String::from_utf8_lossy(&response.bytes).into_owned();
+        response
+    }
+
+    fn stop(self) {
+        self.shutdown.send(()).expect("server is running");
+        self.thread.join().expect("server stops");
+        let _ = fs::remove_dir_all(&self.dir);
+        assert!(TcpListener::bind(&self.address).is_ok(), "{} is still in use after the server stopped", self.address);
+    }
+}
+
+struct TestResponse {
+    status: u16,
+    // Names in lowercase
+    headers: Vec<(String, String)>,
+    // Without the chunked transfer encoding
+    bytes: Vec<u8>,
+    // The bytes as text
+    body: String,
+}
+
+impl TestResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+// Body of a chunked response: each chunk is its size in hexadecimal, CRLF, the data and CRLF, up to a chunk of size 0
+fn dechunk(mut chunked: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let end_of_size = chunked.windows(2).position(|window| window == b"\r\n").expect("chunk has a size");
+        let size = std::str::from_utf8(&chunked[..end_of_size]).ok()
+            .and_then(|size| usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16).ok())
+            .expect("chunk size is hexadecimal");
+        if size == 0 {
+            return body;
+        }
+        let data = end_of_size + 2;
+        body.extend_from_slice(&chunked[data..data + size]);
+        chunked = &chunked[data + size + 2..];
+    }
+}
+
+fn json(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|err| panic!("{body} is not JSON: {err}"))
+}
+
+#[test]
+fn stats_are_json() {
+    let server = TestServer::start("stats");
+    let (status, body) = server.get("/api/stats");
+    assert_eq!(status, 200);
+    assert_eq!(json(&body)["document_count"], 2);
+    server.stop();
+}
+
+#[test]
+fn search_lists_results() {
+    let server = TestServer::start("search");
+    let (status, body) = server.get("/api/search?q=test");
+    assert_eq!(status, 200);
+    let results = json(&body)["results"].as_array().cloned().expect("results are an array");
+    assert_eq!(results.len(), 1);
+    server.stop();
+}
+
+#[test]
+fn unknown_path_is_not_found() {
+    let server = TestServer::start("not-found");
+    let (status, _) = server.get("/nonexistent");
+    assert_eq!(status, 404);
+    server.stop();
+}
+
+#[test]
+fn simultaneous_requests_are_answered() {
+    let server = TestServer::start("simultaneous");
+    let statuses = thread::scope(|scope| {
+        let requests = [scope.spawn(|| server.get("/api/search?q=test")), scope.spawn(|| server.get("/api/stats"))];
+        requests.map(|request| request.join().expect("request is answered").0)
+    });
+    assert_eq!(statuses, [200, 200]);
+    server.stop();
+}
+
+#[test]
+fn quoted_queries_are_phrases() {
+    let server = TestServer::start("phrase");
+    let paths = |query: &str| {
+        let (status, body) = server.get(&format!("/api/search?q={query}"));
+        assert_eq!(status, 200, "{body}");
+        json(&body)["results"].as_array().expect("results are an array").len()
+    };
+    assert_eq!(paths("%22test+of%22"), 1);
+    assert_eq!(paths("%22of+test%22"), 0);
+    assert_eq!(paths("of+test"), 1);
+    server.stop();
+}
+
+#[test]
+fn fuzzy_searches_allow_at_most_two_edits() {
+    let server = TestServer::start("fuzzy");
+    let (status, body) = server.get("/api/search?q=tset&fuzzy=1");
+    assert_eq!(status, 200);
+    assert_eq!(json(&body)["results"].as_array().expect("results are an array").len(), 1);
+    assert_eq!(server.get("/api/search?q=tset&fuzzy=1&max_edit=2").0, 200);
+    let (status, body) = server.get("/api/search?q=tset&fuzzy=1&max_edit=3");
+    assert_eq!(status, 400);
+    assert!(json(&body)["error"].as_str().is_some_and(|error| error.contains("max_edit")), "{body}");
+    server.stop();
+}
+
+#[test]
+fn offset_and_limit_page_through_the_results() {
+    let server = TestServer::start("pages");
+    let page = |offset: usize| {
+        let (status, body) = server.get(&format!("/api/search?q=test+document&offset={offset}&limit=1"));
+        assert_eq!(status, 200, "{body}");
+        json(&body)
+    };
+    let (first, second, third) = (page(0), page(1), page(2));
+    assert_eq!(first["total"], 2);
+    assert_eq!(first["has_more"], true);
+    assert_eq!(second["has_more"], false);
+    assert_ne!(first["results"][0]["path"], second["results"][0]["path"]);
+    assert_eq!(third["results"].as_array().map(Vec::len), Some(0));
+    assert_eq!(third["total"], 2);
+    assert_eq!(server.get("/api/search?q=test&offset=-1").0, 400);
+    server.stop();
+}
+
+#[test]
+fn api_responses_are_json_that_is_not_cached() {
+    let server = TestServer::start("headers");
+    for path in ["/api/search?q=test", "/api/stats", "/api/search"] {
+        let response = server.get_with_headers(path, &[]);
+        assert_eq!(response.header("content-type"), Some("application/json"), "{path}");
+        assert_eq!(response.header("cache-control"), Some("no-cache"), "{path}");
+        json(&response.body);
+    }
+    server.stop();
+}
+
+#[test]
+fn search_results_have_a_path_score_and_snippet() {
+    let server = TestServer::start("results");
+    let (_, body) = server.get("/api/search?q=test&offset=0&limit=10");
+    let body = json(&body);
+    assert_eq!(body["total"], 1);
+    let result = &body["results"][0];
+    assert_eq!(result["path"], server.dir.join("a.txt").display().to_string());
+    assert!(result["score"].as_f64().is_some_and(|score| score > 0.0), "{result}");
+    assert_eq!(result["snippet"], "a <mark>test</mark> of the server");
+    server.stop();
+}
+
+#[test]
+fn missing_or_malformed_queries_are_bad_requests() {
+    let server = TestServer::start("bad-requests");
+    for path in ["/api/search", "/api/search?q=test&limit=x", "/api/search?q=test&offset=1.5", "/api/search?q=rust+AND"] {
+        let (status, body) = server.get(path);
+        assert_eq!(status, 400, "{path}: {body}");
+        assert!(json(&body)["error"].is_string(), "{path}: {body}");
+    }
+    server.stop();
+}
+
+#[test]
+fn documents_have_metadata() {
+    let server = TestServer::start("documents");
+    let path = server.dir.join("b.txt").display().to_string();
+    let (status, body) = server.get(&format!("/api/documents/{path}"));
+    assert_eq!(status, 200, "{body}");
+    let info = json(&body);
+    assert_eq!(info["path"], path);
+    assert_eq!(info["term_count"], 2);
+    assert_eq!(info["mime_type"], "text/plain");
+    assert_eq!(server.get("/api/documents/not/indexed.txt").0, 404);
+    server.stop();
+}
+
+#[test]
+fn requests_need_the_credentials_the_server_was_started_with() {
+    let auth = BasicAuth::new("reader".to_string(), "s3cret".to_string()).expect("password is hashed");
+    let server = TestServer::start_with("basic-auth", &Config::default(), Some(auth));
+    let basic = |credentials: &str| format!("Basic {}", BASE64.encode(credentials));
+
+    let response = server.get_with_headers("/api/stats", &[]);
+    assert_eq!(response.status, 401);
+    assert_eq!(response.header("www-authenticate"), Some("Basic realm=\"serux\""));
+    for credentials in ["reader:wrong", "writer:s3cret", "reader", ""] {
+        let response = server.get_with_headers("/api/stats", &[("Authorization", &basic(credentials))]);
+        assert_eq!(response.status, 401, "{credentials:?} is accepted");
+    }
+    assert_eq!(server.get_with_headers("/api/stats", &[("Authorization", "Bearer reader:s3cret")]).status, 401);
+
+    let response = server.get_with_headers("/api/stats", &[("Authorization", &basic("reader:s3cret"))]);
+    assert_eq!(response.status, 200);
+    assert_eq!(json(&response.body)["document_count"], 2);
+    server.stop();
+}
+
+#[test]
+fn suggestions_need_a_prefix_of_two_characters() {
+    let server = TestServer::start("suggest");
+    let (status, body) = server.get("/api/suggest?prefix=an");
+    assert_eq!(status, 200);
+    assert_eq!(json(&body), serde_json::json!(["ANOTHER"]));
+    assert_eq!(json(&server.get("/api/suggest?prefix=a").1), serde_json::json!([]));
+    assert_eq!(json(&server.get("/api/suggest?prefix=SER&limit=1").1), serde_json::json!(["SERVER"]));
+    assert_eq!(json(&server.get("/api/suggest?prefix=ser&limit=0").1), serde_json::json!([]));
+    assert_eq!(server.get("/api/suggest?prefix=ser&limit=-1").0, 400);
+    server.stop();
+}
+
+#[test]
+fn scores_are_explained_term_by_term() {
+    let server = TestServer::start("explain");
+    let path = server.dir.join("a.txt");
+    let (status, body) = server.get(&format!("/api/explain?q=test%20server&path={}", path.display()));
+    assert_eq!(status, 200);
+    let explanation = json(&body);
+    let terms = explanation["term_scores"].as_array().expect("term scores are an array");
+    assert_eq!(terms.len(), 2);
+    let sum = terms.iter().map(|term| term["contribution"].as_f64().expect("contributions are numbers")).sum::<f64>();
+    assert!((sum - explanation["total_score"].as_f64().expect("the total is a number")).abs() < 1e-5);
+
+    let (status, _) = server.get(&format!("/api/explain?q=test&path={}", server.dir.join("missing.txt").display()));
+    assert_eq!(status, 404);
+    assert_eq!(server.get("/api/explain?q=test").0, 400);
+    server.stop();
+}
+
+#[test]
+fn case_sensitive_search_needs_a_case_sensitive_index() {
+    let server = TestServer::start("case");
+    assert_eq!(server.get("/api/search?q=Test&case=0").0, 200);
+    assert_eq!(server.get("/api/search?q=Test&case=1").0, 400);
+    assert_eq!(server.get("/api/search?q=Test&case=yes").0, 400);
+    server.stop();
+}
+
+#[test]
+fn regular_expression_searches_need_a_valid_pattern() {
+    let server = TestServer::start("regex");
+    let (status, body) = server.get("/api/search?regex=1&q=%5Ete.t%24");
+    assert_eq!(status, 200);
+    assert_eq!(json(&body)["results"].as_array().map(Vec::len), Some(1));
+    let (status, body) = server.get("/api/search?regex=1&q=%5Ezzz");
+    assert_eq!(status, 200);
+    assert_eq!(json(&body)["results"], serde_json::json!([]));
+
+    let (status, body) = server.get("/api/search?regex=1&q=te(st");
+    assert_eq!(status, 400);
+    assert!(json(&body)["error"].as_str().is_some_and(|error| error.starts_with("invalid query")), "{body}");
+    server.stop();
+}
+
+#[test]
+fn facets_count_the_results_of_every_extension() {
+    let documents = [
+        ("guide.txt", "rust guide"),
+        ("notes.md", "rust notes"),
+        ("tips.md", "rust tips"),
+        ("api.xml", "rust api"),
+        ("README", "rust readme"),
+        ("garden.md", "tomatoes"),
+    ];
+    let server = TestServer::start_with_documents("facets", &documents, &Config::default(), None);
+    let (status, body) = server.get("/api/search?q=rust");
+    assert_eq!(status, 200);
+    let response = json(&body);
+    let facets: std::collections::HashMap<String, usize> = serde_json::from_value(response["facets"].clone()).expect("facets are counts");
+    assert_eq!(facets, [("txt", 1), ("md", 2), ("xml", 1), ("", 1)].map(|(ext, count)| (ext.to_string(), count)).into());
+    assert_eq!(facets.values().sum::<usize>(), response["total"].as_u64().expect("total is a number") as usize);
+
+    // Filtering by extension narrows the results, but the facets still count every extension
+    for ext in ["md", ".MD"] {
+        let (status, body) = server.get(&format!("/api/search?q=rust&ext={ext}"));
+        assert_eq!(status, 200);
+        let filtered = json(&body);
+        assert_eq!(filtered["total"], 2, "{ext}");
+        assert_eq!(filtered["facets"], response["facets"], "{ext}");
+        assert!(filtered["results"].as_array().expect("results are an array").iter()
+            .all(|result| result["path"].as_str().is_some_and(|path| path.ends_with(".md"))));
+    }
+    let (_, body) = server.get("/api/search?q=rust&ext=pdf");
+    assert_eq!(json(&body)["total"], 0);
+    server.stop();
+}
+
+#[test]
+fn searches_are_logged_and_the_most_frequent_listed() {
+    let log_dir = super::TestDir::new("server-query-log");
+    let log_path = log_dir.path().join("queries.jsonl");
+    let config = Config { query_log_path: Some(log_path.clone()), ..Config::default() };
+    let server = TestServer::start_with("query-log", &config, None);
+    for query in ["test", "document", "test", "missing"] {
+        assert_eq!(server.get(&format!("/api/search?q={query}")).0, 200);
+    }
+    let (status, body) = server.get("/api/logs/top-queries?limit=1");
+    assert_eq!(status, 200);
+    let top = json(&body);
+    assert_eq!(top.as_array().map(Vec::len), Some(1));
+    assert_eq!((&top[0]["query"], &top[0]["count"], &top[0]["avg_result_count"]), (&Value::from("test"), &Value::from(2), &Value::from(1.0)));
+    assert_eq!(server.get("/api/logs/top-queries?limit=many").0, 400);
+    server.stop();
+
+    // The searches are written when the server stops
+    let content = fs::read_to_string(&log_path).expect("log is written");
+    assert_eq!(content.lines().count(), 4);
+
+    let server = TestServer::start("no-query-log");
+    assert_eq!(server.get("/api/logs/top-queries").0, 404);
+    server.stop();
+}
+
+// Every byte but letters, digits, '-', '.' and '_' as %XX
+fn percent_encode(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' => (byte as char).to_string(),
+        _ => format!("%{byte:02X}"),
+    }).collect()
+}
+
+#[test]
+fn similar_documents_are_listed_by_similarity() {
+    let documents = [("a.txt", "rust go"), ("b.txt", "rust go"), ("c.txt", "zig odin"), ("d.txt", "rust zig")];
+    let server = TestServer::start_with_documents("similar", &documents, &Config::default(), None);
+    let path = server.dir.join("a.txt");
+    let (status, body) = server.get(&format!("/api/similar?path={}&limit=10", percent_encode(&path.to_string_lossy())));
+    assert_eq!(status, 200);
+    let results = json(&body);
+    let names = results.as_array().expect("results are an array").iter()
+        .map(|result| PathBuf::from(result["path"].as_str().expect("path is a string")).file_name().map(|name| name.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    assert_eq!(names, [Some("b.txt".to_string()), Some("d.txt".to_string())]);
+    assert!((results[0]["score"].as_f64().expect("score is a number") - 1.0).abs() < 1e-5);
+
+    let (status, _) = server.get(&format!("/api/similar?path={}", percent_encode(&server.dir.join("missing.txt").to_string_lossy())));
+    assert_eq!(status, 404);
+    assert_eq!(server.get("/api/similar").0, 400);
+    server.stop();
+}
+
+#[test]
+fn min_score_drops_the_low_scoring_results() {
+    let documents = [
+        ("a.txt", "rust rust rust rust"),
+        ("b.txt", "rust rust rust snake"),
+        ("c.txt", "rust snake snake snake"),
+        ("d.txt", "snake snake snake snake"),
+    ];
+    let server = TestServer::start_with_documents("min-score", &documents, &Config::default(), None);
+    let total = |path: &str| {
+        let (status, body) = server.get(path);
+        assert_eq!(status, 200, "{path}: {body}");
+        json(&body)["total"].as_u64().expect("total is a number")
+    };
+    assert_eq!(total("/api/search?q=rust"), 3);
+    assert_eq!(total("/api/search?q=rust&min_score=-0.5"), 2);
+    assert_eq!(total("/api/search?q=rust&min_score=1000"), 0);
+    for min_score in ["x", "-2", "NaN"] {
+        assert_eq!(server.get(&format!("/api/search?q=rust&min_score={min_score}")).0, 400, "{min_score}");
+    }
+    server.stop();
+}
+
+#[test]
+fn responses_are_gzipped_for_clients_that_accept_it() {
+    let server = TestServer::start("gzip");
+    let path = "/api/search?q=test&offset=0&limit=10";
+    let plain = server.get_with_headers(path, &[]);
+    assert_eq!(plain.header("content-encoding"), None);
+
+    let gzipped = server.get_with_headers(path, &[("Accept-Encoding", "gzip")]);
+    assert_eq!(gzipped.status, 200);
+    assert_eq!(gzipped.header("content-encoding"), Some("gzip"));
+    assert!(gzipped.header("vary").is_some_and(|vary| vary.to_ascii_lowercase().contains("accept-encoding")), "{:?}", gzipped.headers);
+    let mut body = String::new();
+    GzDecoder::new(gzipped.bytes.as_slice()).read_to_string(&mut body).expect("body is gzip");
+    assert_eq!(json(&body), json(&plain.body));
+    server.stop();
+}
+
+#[test]
+fn preflight_requests_are_answered_with_the_cors_headers() {
+    let config = Config { cors_origin: Some("https://search.example.com".to_string()), ..Config::default() };
+    let auth = BasicAuth::new("reader".to_string(), "s3cret".to_string()).expect("password is hashed");
+    let server = TestServer::start_with("cors-preflight", &config, Some(auth));
+    // Browsers send preflight requests without credentials
+    let headers = [("Origin", "https://search.example.com"), ("Access-Control-Request-Method", "GET")];
+    let response = server.request("OPTIONS", "/api/search?q=test", &headers);
+    assert_eq!(response.status, 204);
+    assert_eq!(response.header("access-control-allow-origin"), Some("https://search.example.com"));
+    assert_eq!(response.header("access-control-allow-methods"), Some("GET, POST"));
+    assert_eq!(response.header("access-control-allow-headers"), Some("Content-Type, Authorization"));
+    assert_eq!(response.header("vary"), Some("Origin"));
+    server.stop();
+}
+
+#[test]
+fn api_responses_carry_the_cors_origin_only_when_configured() {
+    let config = Config { cors_origin: Some("*".to_string()), ..Config::default() };
+    let server = TestServer::start_with("cors", &config, None);
+    let response = server.get_with_headers("/api/search?q=test", &[("Origin", "https://elsewhere.example.com")]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("access-control-allow-origin"), Some("*"));
+    // Any origin is allowed, so the response is the same for all of them
+    assert!(!response.headers.iter().any(|(name, value)| name == "vary" && value.contains("Origin")), "{:?}", response.headers);
+    // Only the API is meant for other origins
+    assert_eq!(server.get_with_headers("/", &[]).header("access-control-allow-origin"), None);
+    server.stop();
+
+    let server = TestServer::start("no-cors");
+    let response = server.get_with_headers("/api/search?q=test", &[("Origin", "https://elsewhere.example.com")]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("access-control-allow-origin"), None);
+    assert_ne!(server.request("OPTIONS", "/api/search?q=test", &[]).status, 204);
+    server.stop();
+}
+
+#[test]
+fn health_and_readiness_of_a_finalized_model() {
+    let server = TestServer::start("health");
+    for path in ["/health", "/ready"] {
+        let (status, body) = server.get(path);
+        assert_eq!(status, 200, "{path}: {body}");
+        let health = json(&body);
+        assert_eq!(health["status"], "ok", "{path}");
+        assert_eq!(health["doc_count"], DOCUMENTS.len(), "{path}");
+        assert!(health["uptime_seconds"].is_u64(), "{path}: {body}");
+    }
+    server.stop();
+}
+
+#[test]
+fn models_that_are_not_finalized_are_not_ready() {
+    let server = TestServer::start("not-ready");
+    let mut loading = InMemoryModel::default();
+    loading.add_document(server.dir.join("c.txt"), &"still loading".chars().collect::<Vec<_>>(), None).expect("document is indexed");
+    *server.model.write().expect("model is not poisoned") = loading;
+
+    let (status, body) = server.get("/ready");
+    assert_eq!(status, 503, "{body}");
+    assert_eq!(json(&body)["status"], "loading");
+    // The server is still alive
+    let (status, body) = server.get("/health");
+    assert_eq!(status, 200, "{body}");
+    assert_eq!(json(&body)["doc_count"], 1);
+
+    server.model.write().expect("model is not poisoned").finalize();
+    assert_eq!(server.get("/ready").0, 200);
+    server.stop();
+}
+
+#[test]
+fn metrics_count_the_searches_and_their_errors() {
+    let server = TestServer::start("metrics");
+    for path in ["/api/search?q=test", "/api/search?q=another", "/api/search?q=server", "/api/search?q=document", "/api/search?q=rust+AND"] {
+        server.get(path);
+    }
+    let response = server.get_with_headers("/metrics", &[]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-type"), Some("text/plain; version=0.0.4"));
+    // Samples by name, the comments left out
+    let samples = response.body.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.rsplit_once(' '))
+        .map(|(name, value)| (name.to_string(), value.parse::<f64>().unwrap_or_else(|err| panic!("{name} {value}: {err}"))))
+        .collect::<std::collections::HashMap<_, _>>();
+    assert_eq!(samples["serux_searches_total"], 5.0, "{}", response.body);
+    assert_eq!(samples["serux_search_errors_total"], 1.0, "{}", response.body);
+    assert_eq!(samples["serux_documents_indexed_total"], DOCUMENTS.len() as f64, "{}", response.body);
+    assert_eq!(samples["serux_search_duration_seconds_count"], 5.0, "{}", response.body);
+    assert_eq!(samples["serux_search_duration_seconds_bucket{le=\"+Inf\"}"], 5.0, "{}", response.body);
+    // The buckets are cumulative
+    let buckets = ["0.001", "0.005", "0.01", "0.05", "0.1", "0.5", "1", "+Inf"]
+        .map(|bound| samples[&format!("serux_search_duration_seconds_bucket{{le=\"{bound}\"}}")]);
+    assert!(buckets.windows(2).all(|pair| pair[0] <= pair[1]), "{}", response.body);
+    server.stop();
+}
+
+// Number of documents /health reports
+fn doc_count(server: &TestServer) -> u64 {
+    json(&server.get("/health").1)["doc_count"].as_u64().expect("doc_count is a number")
+}
+
+#[test]
+fn a_replaced_index_file_is_reloaded() {
+    let server = TestServer::start("reload");
+    let index_path = server.dir.join("index.json");
+    let index = |contents: &[(&str, &str)]| {
+        let mut model = InMemoryModel::default();
+        for &(file_name, content) in contents {
+            model.add_document(server.dir.join(file_name), &content.chars().collect::<Vec<_>>(), None).expect("document is indexed");
+        }
+        crate::save_model(&model, &index_path.to_string_lossy()).expect("index is saved");
+    };
+    index(&DOCUMENTS);
+    let load = |index_path: &Path| {
+        let mut model = crate::load_model(&index_path.to_string_lossy())?;
+        model.finalize();
+        Ok(model)
+    };
+    server::reload_on_change(index_path.clone(), server.model.clone(), Duration::from_millis(50), load);
+    assert_eq!(doc_count(&server), 2);
+
+    // An index that can't be loaded leaves the old one in place
+    thread::sleep(Duration::from_millis(20));
+    fs::write(&index_path, "{ not an index").expect("index is overwritten");
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(doc_count(&server), 2);
+
+    index(&[("a.txt", "a test of the server"), ("b.txt", "another document"), ("c.txt", "a third one")]);
+    let reloaded = (0..200).any(|_| {
+        thread::sleep(CONNECT_RETRY_DELAY);
+        doc_count(&server) == 3
+    });
+    assert!(reloaded, "the index was not reloaded within 10 seconds");
+    assert_eq!(server.get("/ready").0, 200);
+    server.stop();
+}
+
+#[cfg(unix)]
+#[test]
+fn the_index_is_reloaded_on_sighup() {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    let server = TestServer::start("sighup");
+    let index_path = server.dir.join("index.json");
+    let load = |index_path: &Path| {
+        let mut model = crate::load_model(&index_path.to_string_lossy())?;
+        model.finalize();
+        Ok(model)
+    };
+    server::reload_on_sighup(index_path.clone(), server.model.clone(), load).expect("SIGHUP handler is installed");
+    assert_eq!(doc_count(&server), 2);
+
+    let mut model = InMemoryModel::default();
+    for (file_name, content) in [("a.txt", "a test of the server"), ("b.txt", "another document"), ("c.txt", "a third one")] {
+        model.add_document(server.dir.join(file_name), &content.chars().collect::<Vec<_>>(), None).expect("document is indexed");
+    }
+    crate::save_model(&model, &index_path.to_string_lossy()).expect("index is saved");
+    kill(Pid::this(), Signal::SIGHUP).expect("SIGHUP is sent");
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while doc_count(&server) != 3 {
+        assert!(Instant::now() < deadline, "the index was not reloaded within 2 seconds of SIGHUP");
+        thread::sleep(Duration::from_millis(10));
+    }
+    server.stop();
+}
+
+#[test]
+fn boosted_terms_weigh_more() {
+    let documents = [("a.txt", "rust"), ("b.txt", "python"), ("c.txt", "other")];
+    let server = TestServer::start_with_documents("boosts", &documents, &Config::default(), None);
+    let first = |query: &str| {
+        let (status, body) = server.get(&format!("/api/search?q={query}"));
+        assert_eq!(status, 200, "{query}: {body}");
+        let path = json(&body)["results"][0]["path"].as_str().expect("path is a string").to_string();
+        PathBuf::from(path).file_name().map(|name| name.to_string_lossy().into_owned())
+    };
+    assert_eq!(first("rust%5E2+python"), Some("a.txt".to_string()));
+    assert_eq!(first("rust+python%5E2"), Some("b.txt".to_string()));
+    let (status, body) = server.get("/api/search?q=rust%5E0+python");
+    assert_eq!(status, 400);
+    assert!(json(&body)["error"].as_str().is_some_and(|error| error.contains("boost")), "{body}");
+    server.stop();
+}
+
+#[test]
+fn excluded_terms_are_left_out_of_the_total() {
+    let documents = [("a.txt", "python snake"), ("b.txt", "python code"), ("c.txt", "other")];
+    let server = TestServer::start_with_documents("must-not", &documents, &Config::default(), None);
+    let (status, body) = server.get("/api/search?q=python+-snake");
+    assert_eq!(status, 200, "{body}");
+    let body = json(&body);
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["results"][0]["path"], server.dir.join("b.txt").display().to_string());
+    server.stop();
+}
+
+#[test]
+fn msm_sets_how_many_terms_documents_must_contain() {
+    let documents = [("a.txt", "rust go zig"), ("b.txt", "rust go"), ("c.txt", "rust"), ("d.txt", "other")];
+    let config = Config { default_min_should_match: 2, ..Config::default() };
+    let server = TestServer::start_with_documents("msm", &documents, &config, None);
+    let total = |path: &str| {
+        let (status, body) = server.get(path);
+        assert_eq!(status, 200, "{path}: {body}");
+        json(&body)["total"].as_u64().expect("total is a number")
+    };
+    // The default of the config is 2
+    assert_eq!(total("/api/search?q=rust+go+zig"), 2);
+    assert_eq!(total("/api/search?q=rust+go+zig&msm=1"), 3);
+    assert_eq!(total("/api/search?q=rust+go+zig&msm=3"), 1);
+    assert_eq!(total("/api/search?q=rust+go+zig&msm=4"), 0);
+    for msm in ["0", "-1", "x"] {
+        assert_eq!(server.get(&format!("/api/search?q=rust&msm={msm}")).0, 400, "{msm}");
+    }
+    server.stop();
+}
+
+#[test]
+fn mmr_reranks_near_duplicates_down() {
+    let documents = [
+        ("dup1.txt", "rust rust rust async tokio"),
+        ("dup2.txt", "rust rust rust async tokio"),
+        ("go.txt", "rust go"),
+        ("nim.txt", "rust nim"),
+        ("odin.txt", "rust odin"),
+        ("zig.txt", "rust zig"),
+        ("c.txt", "rust c"),
+        ("other.txt", "nothing here"),
+    ];
+    let server = TestServer::start_with_documents("mmr", &documents, &Config::default(), None);
+    let names = |path: &str| {
+        let (status, body) = server.get(path);
+        assert_eq!(status, 200, "{path}: {body}");
+        json(&body)["results"].as_array().expect("results are an array").iter()
+            .map(|result| PathBuf::from(result["path"].as_str().expect("path is a string")).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("/api/search?q=rust&limit=5")[..2], ["dup1.txt", "dup2.txt"]);
+    let reranked = names("/api/search?q=rust&limit=5&mmr=1&lambda=0.7");
+    assert_eq!(reranked.len(), 5);
+    assert_eq!(reranked[0], "dup1.txt");
+    assert!(!reranked.contains(&"dup2.txt".to_string()), "{reranked:?}");
+    for query in ["mmr=2", "mmr=1&lambda=1.5", "mmr=1&lambda=x"] {
+        assert_eq!(server.get(&format!("/api/search?q=rust&{query}")).0, 400, "{query}");
+    }
+    server.stop();
+}
+
+#[test]
+fn prf_searches_the_query_expanded_with_terms_of_its_top_results() {
+    let documents = [
+        ("a.txt", "car automobile automobile engine"),
+        ("b.txt", "car automobile automobile wheels"),
+        ("c.txt", "car automobile automobile brakes"),
+        ("d.txt", "automobile engine road"),
+        ("e.txt", "automobile wheels highway"),
+        ("f.txt", "automobile brakes garage"),
+        ("g.txt", "cooking recipe pasta"),
+        ("h.txt", "garden flowers soil"),
+        ("i.txt", "music guitar song"),
+        ("j.txt", "ocean waves sand"),
+    ];
+    let server = TestServer::start_with_documents("prf", &documents, &Config::default(), None);
+    let (_, body) = server.get("/api/search?q=car");
+    let plain = json(&body);
+    assert_eq!(plain["total"], 3);
+    assert!(plain.get("query_expansion").is_none(), "{plain}");
+
+    let (status, body) = server.get("/api/search?q=car&prf=1&prf_top=3&expand=1");
+    assert_eq!(status, 200, "{body}");
+    let expanded = json(&body);
+    assert_eq!(expanded["total"], 6);
+    assert_eq!(expanded["query_expansion"]["original_terms"][0][0], "CAR");
+    assert_eq!(expanded["query_expansion"]["expanded_terms"][0][0], "AUTOMOBILE");
+    assert_eq!(expanded["query_expansion"]["expanded_terms"].as_array().map(Vec::len), Some(1));
+
+    for query in ["prf_top=0", "prf_top=x", "expand=-1"] {
+        assert_eq!(server.get(&format!("/api/search?q=car&prf=1&{query}")).0, 400, "{query}");
+    }
+    server.stop();
+}
+
+#[test]
+fn cursor_pages_add_up_to_all_results() {
+    let documents = [
+        ("a.txt", "rust rust"), ("b.txt", "rust rust"), ("c.txt", "rust go"), ("d.txt", "rust go"),
+        ("e.txt", "rust go zig"), ("f.txt", "nothing here"),
+    ];
+    let server = TestServer::start_with_documents("cursors", &documents, &Config::default(), None);
+    let paths = |body: &Value| body["results"].as_array().expect("results are an array").iter()
+        .map(|result| result["path"].as_str().expect("path is a string").to_string())
+        .collect::<Vec<_>>();
+    let (_, body) = server.get("/api/search?q=rust&limit=10");
+    let all = paths(&json(&body));
+    assert_eq!(all.len(), 5);
+
+    let mut paged = Vec::new();
+    let mut path = "/api/search?q=rust&limit=2".to_string();
+    loop {
+        let (status, body) = server.get(&path);
+        assert_eq!(status, 200, "{path}: {body}");
+        let body = json(&body);
+        paged.extend(paths(&body));
+        match body["next_cursor"].as_str() {
+            Some(cursor) => path = format!("/api/search?q=rust&limit=2&after={cursor}"),
+            None => break,
+        }
+    }
+    assert_eq!(paged, all);
+    assert_eq!(server.get("/api/search?q=rust&after=not-a-cursor").0, 400);
+    server.stop();
+}
+
+#[test]
+fn slow_clients_are_cut_off_after_the_read_timeout() {
+    let config = Config { read_timeout_ms: 500, ..Config::default() };
+    let server = TestServer::start_with("slow-client", &config, None);
+
+    // 80 bytes at 1 byte per 100 ms take 8 s to send, far longer than the timeout
+    let request = format!("GET /api/stats HTTP/1.1\r\nHost: {}\r\nX-Padding: {}\r\n\r\n", server.address, "x".repeat(30));
+    let mut stream = TcpStream::connect(&server.address).expect("server accepts connections");
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let started = std::time::Instant::now();
+    let sender = thread::spawn(move || {
+        for byte in request.bytes() {
+            if writer.write_all(&[byte]).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    });
+
+    // Closed rather than answered, possibly with a reset for the bytes still coming in
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(400), "connection closed after {elapsed:?}");
+    assert!(elapsed < Duration::from_secs(4), "connection still open after {elapsed:?}");
+    assert!(!String::from_utf8_lossy(&response).starts_with("HTTP/1.1 200"));
+    sender.join().unwrap();
+    server.stop();
+}
+
+#[test]
+fn requests_that_take_too_long_are_answered_408() {
+    let config = Config { request_timeout_ms: 200, ..Config::default() };
+    let server = TestServer::start_with("request-timeout", &config, None);
+
+    // The search waits for the model as long as it is being changed
+    let model = server.model.clone();
+    let changing = model.write().unwrap();
+    let (status, body) = server.get("/api/search?q=test");
+    drop(changing);
+    assert_eq!(status, 408);
+    assert_eq!(json(&body), serde_json::json!({"error": "timeout", "message": "request timed out"}));
+
+    assert_eq!(server.get("/api/search?q=test").0, 200);
+    server.stop();
+}
+
+#[test]
+fn connections_beyond_max_connections_are_answered_503() {
+    let config = Config { max_connections: 10, ..Config::default() };
+    let server = TestServer::start_with("max-connections", &config, None);
+
+    // All connected before any request, and kept open so that the served ones hold on to their place
+    let mut streams = (0..200).map(|_| TcpStream::connect(&server.address).expect("server accepts connections")).collect::<Vec<_>>();
+    let mut statuses = Vec::new();
+    for stream in &mut streams {
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        write!(stream, "GET /api/stats HTTP/1.1\r\nHost: {}\r\n\r\n", server.address).expect("request is sent");
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).expect("response is read");
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8_lossy(&head).into_owned();
+        statuses.push(head.split(' ').nth(1).expect("response has a status").to_string());
+        if statuses.last().unwrap() == "503" {
+            assert!(head.to_ascii_lowercase().contains("retry-after: 5\r\n"), "{head}");
+        }
+    }
+    let served = statuses.iter().filter(|&status| status == "200").count();
+    let refused = statuses.iter().filter(|&status| status == "503").count();
+    assert!((1..=10).contains(&served), "{served} connections served");
+    assert_eq!(served + refused, 200);
+
+    // Places are freed as connections close
+    drop(streams);
+    thread::sleep(CONNECT_RETRY_DELAY);
+    assert_eq!(server.get("/api/stats").0, 200);
+    server.stop();
+}
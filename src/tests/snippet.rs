@@ -0,0 +1,72 @@
+use crate::model::{default_stop_words, InMemoryModel};
+use crate::snippet::{highlight_snippet, snippet_of, snippet_tokens, HighlightMode};
+
+fn chars(text: &str) -> Vec<char> {
+    text.chars().collect()
+}
+
+fn tokens(tokens: &[&str]) -> Vec<String> {
+    tokens.iter().map(|token| token.to_string()).collect()
+}
+
+// Highlighted snippet of `content` for `query`, the way the server cuts them
+fn snippet(model: &InMemoryModel, content: &str, query: &str, window: usize) -> Option<String> {
+    let tokens = snippet_tokens(model, &chars(query));
+    let snippet = snippet_of(model, content, &tokens, &[], window)?;
+    Some(highlight_snippet(model, &snippet, &tokens, HighlightMode::Html))
+}
+
+const DOCUMENT: &str = "Rust is a systems programming language. It runs fast and prevents segfaults.";
+
+#[test]
+fn snippet_is_centered_on_the_first_match() {
+    let model = InMemoryModel::default();
+    assert_eq!(snippet(&model, DOCUMENT, "language", 20).as_deref(), Some("…mming <mark>language</mark>. It r…"));
+}
+
+#[test]
+fn snippet_stays_inside_the_document_at_either_end() {
+    let model = InMemoryModel::default();
+    assert_eq!(snippet(&model, DOCUMENT, "rust", 20).as_deref(), Some("<mark>Rust</mark> is a systems pr…"));
+    assert_eq!(snippet(&model, DOCUMENT, "segfaults", 20).as_deref(), Some("… prevents <mark>segfaults</mark>."));
+    assert_eq!(snippet(&model, "short", "short", 20).as_deref(), Some("<mark>short</mark>"));
+    assert_eq!(snippet(&model, DOCUMENT, "python", 20), None);
+}
+
+#[test]
+fn snippet_matches_terms_the_way_the_index_does() {
+    let model = InMemoryModel::default();
+    assert_eq!(snippet(&model, DOCUMENT, "running", 20), None);
+
+    let model = InMemoryModel::builder().stemming(true).stop_words(default_stop_words()).build();
+    assert_eq!(snippet_tokens(&model, &chars("the running \"and\" rust*")), ["RUN", "RUST"]);
+    assert_eq!(snippet(&model, DOCUMENT, "it running", 20).as_deref(), Some("…age. It <mark>runs</mark> fast an…"));
+}
+
+#[test]
+fn snippet_text_is_escaped() {
+    let model = InMemoryModel::default();
+    assert_eq!(snippet(&model, "if a < b && c", "c", 40).as_deref(), Some("if a &lt; b &amp;&amp; <mark>c</mark>"));
+}
+
+#[test]
+fn every_occurrence_is_highlighted_in_each_mode() {
+    let model = InMemoryModel::default();
+    let snippet = "Rust & rust, not RUSTY <rust>";
+    let query = tokens(&["RUST"]);
+    assert_eq!(highlight_snippet(&model, snippet, &query, HighlightMode::Ansi),
+               "\x1b[1;33mRust\x1b[0m & \x1b[1;33mrust\x1b[0m, not RUSTY <\x1b[1;33mrust\x1b[0m>");
+    assert_eq!(highlight_snippet(&model, snippet, &query, HighlightMode::Html),
+               "<mark>Rust</mark> &amp; <mark>rust</mark>, not RUSTY &lt;<mark>rust</mark>&gt;");
+    assert_eq!(highlight_snippet(&model, snippet, &query, HighlightMode::None), snippet);
+}
+
+#[test]
+fn overlapping_query_terms_are_highlighted_once() {
+    let model = InMemoryModel::default();
+    let query = tokens(&["FOO", "FOOBAR"]);
+    assert_eq!(highlight_snippet(&model, "foobar and foo", &query, HighlightMode::Html),
+               "<mark>foobar</mark> and <mark>foo</mark>");
+    assert_eq!(highlight_snippet(&model, "foobar and foo", &query, HighlightMode::Ansi),
+               "\x1b[1;33mfoobar\x1b[0m and \x1b[1;33mfoo\x1b[0m");
+}
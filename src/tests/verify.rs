@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::model::InMemoryModel;
+use crate::verify::{print_verification, verify, IntegrityError};
+
+use super::TestDir;
+
+// Index of two files of the directory, consistent until a test breaks it
+fn index(dir: &TestDir) -> (InMemoryModel, PathBuf, PathBuf) {
+    let a = dir.write("a.txt", "rust and go");
+    let b = dir.write("b.txt", "rust again");
+    let mut model = InMemoryModel::default();
+    crate::add_folder_to_in_memory_model(dir.path(), &mut model, &Config::default(), None, false).expect("folder is indexed");
+    (model, a, b)
+}
+
+#[test]
+fn consistent_index_has_no_errors() {
+    let dir = TestDir::new("verify-consistent");
+    let (model, _, _) = index(&dir);
+    assert_eq!(verify(&model), []);
+}
+
+#[test]
+fn deleted_file_is_missing() {
+    let dir = TestDir::new("verify-missing");
+    let (model, a, _) = index(&dir);
+    fs::remove_file(&a).unwrap();
+    assert_eq!(verify(&model), [IntegrityError::MissingFile { path: a }]);
+}
+
+#[test]
+fn term_without_document_frequency_is_found() {
+    let dir = TestDir::new("verify-df-missing");
+    let (mut model, a, b) = index(&dir);
+    model.df.remove("RUST");
+    assert_eq!(verify(&model), [
+        IntegrityError::TermMissingFromDf { path: a, term: "RUST".to_string() },
+        IntegrityError::TermMissingFromDf { path: b, term: "RUST".to_string() },
+    ]);
+}
+
+#[test]
+fn zero_document_frequency_is_found() {
+    let dir = TestDir::new("verify-df-zero");
+    let (mut model, _, _) = index(&dir);
+    model.df.insert("GONE".to_string(), 0);
+    assert_eq!(verify(&model), [IntegrityError::ZeroDocumentFrequency { term: "GONE".to_string() }]);
+}
+
+#[test]
+fn term_count_that_is_not_the_sum_of_frequencies_is_found() {
+    let dir = TestDir::new("verify-term-count");
+    let (mut model, _, b) = index(&dir);
+    model.tfpd.get_mut(&b).unwrap().term_count = 5;
+    assert_eq!(verify(&model), [IntegrityError::TermCountMismatch { path: b, term_count: 5, frequency_sum: 2 }]);
+}
+
+#[test]
+fn empty_document_with_terms_is_found() {
+    let dir = TestDir::new("verify-empty");
+    let (mut model, a, _) = index(&dir);
+    model.tfpd.get_mut(&a).unwrap().term_count = 0;
+    let errors = verify(&model);
+    // Both of the checks these errors belong to fail
+    assert_eq!(print_verification(&errors), 2);
+    assert_eq!(errors, [
+        IntegrityError::TermCountMismatch { path: a.clone(), term_count: 0, frequency_sum: 3 },
+        IntegrityError::EmptyDocumentWithTerms { path: a, unique_terms: 3 },
+    ]);
+}
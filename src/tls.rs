@@ -0,0 +1,127 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use pkcs8::EncryptedPrivateKeyInfoRef;
+use pkcs8::der::Document;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::server::TlsStream;
+use tracing::error;
+
+use super::model::SearchError;
+
+// Password of an encrypted private key
+pub const TLS_PASSWORD_VAR: &str = "SERUX_TLS_PASSWORD";
+
+// Connections that finished their handshake but were not picked up by the server yet
+const PENDING_CONNECTIONS: usize = 64;
+
+// Accepting fails when the process runs out of file descriptors, retrying right away would spin
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Clients that connect but never finish the handshake are dropped after this
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn invalid_data(path: &Path, msg: String) -> SearchError {
+    SearchError::io_at(path, io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+// Certificate chain and private key, both PEM encoded.
+// A key in an `ENCRYPTED PRIVATE KEY` block is decrypted with the password in SERUX_TLS_PASSWORD.
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>, SearchError> {
+    let cert_file = fs::File::open(cert_path).map_err(|err| SearchError::io_at(cert_path, err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| SearchError::io_at(cert_path, err))?;
+    if certs.is_empty() {
+        return Err(invalid_data(cert_path, "no certificate found".to_string()));
+    }
+
+    let key_pem = fs::read_to_string(key_path).map_err(|err| SearchError::io_at(key_path, err))?;
+    let key = match Document::from_pem(&key_pem) {
+        Ok(("ENCRYPTED PRIVATE KEY", document)) => decrypt_private_key(key_path, &document)?,
+        _ => rustls_pemfile::private_key(&mut key_pem.as_bytes())
+            .map_err(|err| SearchError::io_at(key_path, err))?
+            .ok_or_else(|| invalid_data(key_path, "no private key found".to_string()))?,
+    };
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| invalid_data(key_path, err.to_string()))?;
+    Ok(Arc::new(config))
+}
+
+fn decrypt_private_key(key_path: &Path, document: &Document) -> Result<PrivateKeyDer<'static>, SearchError> {
+    let password = env::var(TLS_PASSWORD_VAR).map_err(|_| {
+        invalid_data(key_path, format!("private key is encrypted, set {TLS_PASSWORD_VAR} to its password"))
+    })?;
+    let encrypted = EncryptedPrivateKeyInfoRef::try_from(document.as_bytes())
+        .map_err(|err| invalid_data(key_path, err.to_string()))?;
+    let decrypted = encrypted.decrypt(password)
+        .map_err(|err| invalid_data(key_path, format!("could not decrypt private key: {err}")))?;
+    Ok(PrivatePkcs8KeyDer::from(decrypted.as_bytes().to_vec()).into())
+}
+
+// Hands TLS connections to axum once their handshake is done.
+// Handshakes run in tasks of their own, so that a slow or broken client doesn't hold up the others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (sender, connections) = mpsc::channel(PENDING_CONNECTIONS);
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        error!(error = %err, "could not accept connection");
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(err)) => error!(%addr, error = %err, "TLS handshake failed"),
+                        Err(_) => error!(%addr, "TLS handshake timed out"),
+                    }
+                });
+            }
+        });
+        Ok(Self { local_addr, connections })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accepting task only stops with the runtime
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use super::model::{DocumentEntry, InMemoryModel};
+
+// Violations listed under a failed check, the rest are only counted
+const MAX_SHOWN_VIOLATIONS: usize = 10;
+
+/// An inconsistency of an index, as found by `verify`
+#[derive(Debug, PartialEq)]
+pub enum IntegrityError {
+    // The document was indexed but its file is gone
+    MissingFile { path: PathBuf },
+    // A term of the document has no document frequency
+    TermMissingFromDf { path: PathBuf, term: String },
+    ZeroDocumentFrequency { term: String },
+    // The number of terms of the document is not the sum of the frequencies of its terms
+    TermCountMismatch { path: PathBuf, term_count: usize, frequency_sum: usize },
+    // The document claims no terms but has some
+    EmptyDocumentWithTerms { path: PathBuf, unique_terms: usize },
+}
+
+// What `verify` checks, in the order the results are printed
+const CHECKS: [&str; 5] = [
+    "every indexed file exists",
+    "every term of a document has a document frequency",
+    "every document frequency is at least 1",
+    "term counts match the term frequencies",
+    "no document without terms has term frequencies",
+];
+
+impl IntegrityError {
+    // Index into CHECKS of the check that found this error
+    fn check(&self) -> usize {
+        match self {
+            IntegrityError::MissingFile { .. } => 0,
+            IntegrityError::TermMissingFromDf { .. } => 1,
+            IntegrityError::ZeroDocumentFrequency { .. } => 2,
+            IntegrityError::TermCountMismatch { .. } => 3,
+            IntegrityError::EmptyDocumentWithTerms { .. } => 4,
+        }
+    }
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::MissingFile { path } => write!(f, "{path} does not exist", path = path.display()),
+            IntegrityError::TermMissingFromDf { path, term } => write!(f, "{term} of {path} has no document frequency", path = path.display()),
+            IntegrityError::ZeroDocumentFrequency { term } => write!(f, "{term} has a document frequency of 0"),
+            IntegrityError::TermCountMismatch { path, term_count, frequency_sum } => {
+                write!(f, "{path} has {term_count} terms, but their frequencies add up to {frequency_sum}", path = path.display())
+            }
+            IntegrityError::EmptyDocumentWithTerms { path, unique_terms } => {
+                write!(f, "{path} has no terms, but frequencies for {unique_terms} terms", path = path.display())
+            }
+        }
+    }
+}
+
+/// Everything that is inconsistent in `model`, or that no longer matches the files on disk.
+/// Documents are checked in path order so that the result is stable.
+pub fn verify(model: &InMemoryModel) -> Vec<IntegrityError> {
+    let mut errors = Vec::new();
+
+    let mut documents = model.tfpd.iter().collect::<Vec<_>>();
+    documents.sort_by_key(|(path, _)| *path);
+    for (path, entry) in documents {
+        let DocumentEntry { term_count, tf, .. } = entry;
+        if !path.exists() {
+            errors.push(IntegrityError::MissingFile { path: path.clone() });
+        }
+
+        let mut terms = entry.terms().collect::<Vec<_>>();
+        terms.sort();
+        for term in terms {
+            if !model.df.contains_key(term) {
+                errors.push(IntegrityError::TermMissingFromDf { path: path.clone(), term: term.clone() });
+            }
+        }
+
+        let frequency_sum = tf.values().map(|(freq, _)| freq).sum::<usize>();
+        if frequency_sum != *term_count {
+            errors.push(IntegrityError::TermCountMismatch { path: path.clone(), term_count: *term_count, frequency_sum });
+        }
+
+        if *term_count == 0 && !tf.is_empty() {
+            errors.push(IntegrityError::EmptyDocumentWithTerms { path: path.clone(), unique_terms: tf.len() });
+        }
+    }
+
+    for (term, &df) in &model.df {
+        if df < 1 {
+            errors.push(IntegrityError::ZeroDocumentFrequency { term: term.clone() });
+        }
+    }
+
+    errors
+}
+
+/// PASS or FAIL for every check, with the violations found by the failed ones.
+/// Returns how many checks failed.
+pub fn print_verification(errors: &[IntegrityError]) -> usize {
+    let mut failed = 0;
+    for (check, description) in CHECKS.iter().enumerate() {
+        let violations = errors.iter().filter(|err| err.check() == check).collect::<Vec<_>>();
+        if violations.is_empty() {
+            println!("PASS {description}");
+            continue;
+        }
+
+        failed += 1;
+        println!("FAIL {description} ({count} violations)", count = violations.len());
+        for err in violations.iter().take(MAX_SHOWN_VIOLATIONS) {
+            println!("    {err}");
+        }
+        if violations.len() > MAX_SHOWN_VIOLATIONS {
+            println!("    ... and {more} more", more = violations.len() - MAX_SHOWN_VIOLATIONS);
+        }
+    }
+    failed
+}
@@ -0,0 +1,150 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::model::{AnalyzedDocument, DocumentFields, InMemoryModel, Model, SearchError, TermFreq, TermPositions};
+
+// Watching a folder saves the whole index once its log has grown this big, so that replaying it stays quick
+pub const CHECKPOINT_SIZE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// A change of an index, logged before it is made to the model
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op")]
+pub enum IndexOp {
+    AddDocument {
+        path: PathBuf,
+        tf: TermFreq,
+        // Number of terms of the document
+        n: usize,
+        #[serde(default)]
+        term_positions: TermPositions,
+        // Boxed, a removal should not take the room of an addition
+        #[serde(default)]
+        fields: Box<DocumentFields>,
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        mime_type: Option<String>,
+        // In seconds since the Unix epoch. The file may have changed again by the time the log is replayed,
+        // and must then still look out of date to an incremental run.
+        #[serde(default)]
+        last_modified: Option<u64>,
+    },
+    RemoveDocument {
+        path: PathBuf,
+    },
+}
+
+impl IndexOp {
+    pub fn add_document(path: PathBuf, document: AnalyzedDocument, title: Option<String>, mime_type: Option<&str>,
+                        last_modified: SystemTime) -> Self {
+        let AnalyzedDocument { tf, term_positions, term_count, fields } = document;
+        let last_modified = last_modified.duration_since(SystemTime::UNIX_EPOCH).ok().map(|since| since.as_secs());
+        IndexOp::AddDocument { path, tf, n: term_count, term_positions, fields: Box::new(fields), title, mime_type: mime_type.map(str::to_string), last_modified }
+    }
+
+    // Whether the model changed, i.e. false for removing a document it doesn't have
+    pub fn apply(self, model: &mut InMemoryModel) -> Result<bool, SearchError> {
+        match self {
+            IndexOp::AddDocument { path, tf, n, term_positions, fields, title, mime_type, last_modified } => {
+                let document = AnalyzedDocument { tf, term_positions, term_count: n, fields: *fields };
+                model.merge_document(path.clone(), document, title, mime_type.as_deref());
+                if let (Some(last_modified), Some(entry)) = (last_modified, model.tfpd.get_mut(&path)) {
+                    entry.last_modified = Some(last_modified);
+                }
+                Ok(true)
+            }
+            IndexOp::RemoveDocument { path } => model.remove_document(&path),
+        }
+    }
+}
+
+// The log of index.json is index.json.wal
+pub fn wal_path(index_path: &Path) -> PathBuf {
+    let mut path = index_path.as_os_str().to_owned();
+    path.push(".wal");
+    PathBuf::from(path)
+}
+
+/// Changes of an index made since it was last saved whole, one IndexOp per line of JSON
+pub struct WriteAheadLog {
+    file: File,
+    path: PathBuf,
+}
+
+impl WriteAheadLog {
+    pub fn open(index_path: &Path) -> Result<Self, SearchError> {
+        let path = wal_path(index_path);
+        let file = OpenOptions::new().create(true).append(true).open(&path).map_err(|err| SearchError::io_at(&path, err))?;
+        Ok(Self { file, path })
+    }
+
+    // Returns once the operation is on disk, so that it survives the process being killed right after
+    pub fn append(&mut self, op: &IndexOp) -> Result<(), SearchError> {
+        let mut line = serde_json::to_vec(op)?;
+        line.push(b'\n');
+        self.file.write_all(&line).and_then(|()| self.file.sync_data()).map_err(|err| SearchError::io_at(&self.path, err))
+    }
+
+    pub fn size_bytes(&self) -> Result<u64, SearchError> {
+        Ok(self.file.metadata().map_err(|err| SearchError::io_at(&self.path, err))?.len())
+    }
+}
+
+// Apply the operations logged for the index to the model loaded from it, returning how many there were.
+// A last line without its newline is an operation that was being written when the process was killed,
+// which never happened as far as the model is concerned. With `repair` it is cut off the log, so that the
+// operations appended next start on a line of their own. Only the process about to append may repair the log:
+// to anyone else, e.g. a search while `serux watch` runs, the line another process is appending looks the same.
+pub fn replay(index_path: &Path, model: &mut InMemoryModel, repair: bool) -> Result<usize, SearchError> {
+    let path = wal_path(index_path);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(SearchError::io_at(&path, err)),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    let mut count = 0;
+    // Length of the complete lines read so far
+    let mut complete_len = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|err| SearchError::io_at(&path, err))? == 0 {
+            break;
+        }
+        if !line.ends_with('\n') {
+            warn!(path = %path.display(), "ignoring the incomplete last operation of the write-ahead log");
+            if repair {
+                let file = OpenOptions::new().write(true).open(&path).map_err(|err| SearchError::io_at(&path, err))?;
+                file.set_len(complete_len).and_then(|()| file.sync_all()).map_err(|err| SearchError::io_at(&path, err))?;
+            }
+            break;
+        }
+        let op: IndexOp = serde_json::from_str(&line).map_err(|err| {
+            SearchError::IndexCorrupted(format!("{path}: operation {number}: {err}", path = path.display(), number = count + 1))
+        })?;
+        op.apply(model)?;
+        count += 1;
+        complete_len += line.len() as u64;
+    }
+    if count > 0 {
+        info!(path = %path.display(), ops = count, "replayed write-ahead log");
+    }
+    Ok(count)
+}
+
+// Forget the logged operations once the index they were made to is saved whole
+pub fn truncate(index_path: &Path) -> Result<(), SearchError> {
+    let path = wal_path(index_path);
+    match OpenOptions::new().write(true).open(&path) {
+        Ok(file) => file.set_len(0).and_then(|()| file.sync_all()).map_err(|err| SearchError::io_at(&path, err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(SearchError::io_at(&path, err)),
+    }
+}
+
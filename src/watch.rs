@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use notify::{EventKind, RecursiveMode, Watcher};
+use tracing::{error, info};
+
+use super::config::Config;
+use super::model::{InMemoryModel, Model, SearchError};
+use super::wal::{self, IndexOp, WriteAheadLog};
+use super::{add_folder_to_in_memory_model, parse_file_or_report, save_model, temporary_index_path};
+
+pub const DEFAULT_DEBOUNCE_MS: u64 = 500;
+
+// A folder that never goes quiet for the debounce interval, e.g. one a build keeps writing to,
+// still has its changes indexed this often
+const MAX_BATCH_AGE: Duration = Duration::from_secs(10);
+
+fn notify_error(err: notify::Error) -> SearchError {
+    match err.kind {
+        notify::ErrorKind::Io(err) => SearchError::IoError(err),
+        _ => SearchError::IoError(io::Error::other(err.to_string())),
+    }
+}
+
+// Keep the index of `dir_path` up to date until the process is killed.
+// Events are collected until none arrives for `debounce`, so that an editor saving a file
+// in several writes or a `git checkout` touching many files are handled together, up to MAX_BATCH_AGE.
+// Every change is logged to the write-ahead log of the index before it is made, and the index is
+// only saved whole once the log grows big, so a small edit costs an append instead of a rewrite.
+pub fn watch(dir_path: &Path, index_path: &str, model: &mut InMemoryModel, config: &Config, debounce: Duration) -> Result<(), SearchError> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(notify_error)?;
+    watcher.watch(dir_path, RecursiveMode::Recursive).map_err(notify_error)?;
+    info!(path = %dir_path.display(), "watching folder for changes");
+    let mut wal = WriteAheadLog::open(Path::new(index_path))?;
+
+    // The index may be saved inside the watched folder, its own changes are not documents. Neither are those
+    // of its log and of the file it is saved to before replacing it, which come and go.
+    let index_files = [PathBuf::from(index_path), wal::wal_path(Path::new(index_path)), temporary_index_path(Path::new(index_path))]
+        .iter()
+        .filter_map(|path| absolute_path(path))
+        .collect::<Vec<_>>();
+    // Events come with absolute paths, while the index keeps them the way the folder was given
+    let root = fs::canonicalize(dir_path).map_err(|err| SearchError::io_at(dir_path, err))?;
+
+    while let Ok(event) = receiver.recv() {
+        let batch_start = Instant::now();
+        let mut changed = BTreeSet::new();
+        let mut next = Some(event);
+        while let Some(event) = next {
+            match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) => {
+                    changed.extend(event.paths.into_iter()
+                        .filter(|path| absolute_path(path).is_none_or(|path| !index_files.contains(&path)))
+                        .map(|path| match path.strip_prefix(&root) {
+                            Ok(relative) => dir_path.join(relative),
+                            Err(_) => path,
+                        }));
+                }
+                Ok(_) => {}
+                // E.g. the kernel dropped events because its queue overflowed; later ones still arrive
+                Err(err) => error!(error = %notify_error(err), "could not watch for changes"),
+            }
+            let batch_left = MAX_BATCH_AGE.saturating_sub(batch_start.elapsed());
+            if batch_left.is_zero() {
+                break;
+            }
+            next = receiver.recv_timeout(debounce.min(batch_left)).ok();
+        }
+
+        if changed.is_empty() {
+            continue;
+        }
+        let mut checkpoint = false;
+        for path in &changed {
+            // One unreadable file should not stop watching the others
+            match update_path(path, model, config, &mut wal) {
+                Ok(logged) => checkpoint |= !logged,
+                Err(err) => error!(path = %path.display(), error = %err, "could not update index"),
+            }
+        }
+        if checkpoint || wal.size_bytes()? >= wal::CHECKPOINT_SIZE_BYTES {
+            model.cache_idf();
+            model.cache_term_filter();
+            save_model(model, index_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+// `path` in its canonical folder, for files that may not exist, e.g. one that was just removed
+fn absolute_path(path: &Path) -> Option<PathBuf> {
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    Some(fs::canonicalize(folder).ok()?.join(path.file_name()?))
+}
+
+// Bring the index in line with the current state of `path`, which may have been created, changed or deleted.
+// Returns false for changes that were made to the model without being logged, which need the index saved.
+fn update_path(path: &Path, model: &mut InMemoryModel, config: &Config, wal: &mut WriteAheadLog) -> Result<bool, SearchError> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            // A deleted folder takes all the documents below it along
+            let removed = model.tfpd.keys().filter(|file_path| file_path.starts_with(path)).cloned().collect::<Vec<PathBuf>>();
+            for file_path in removed {
+                let op = IndexOp::RemoveDocument { path: file_path.clone() };
+                wal.append(&op)?;
+                op.apply(model)?;
+                info!(path = %file_path.display(), doc_count = model.tfpd.len(), "removed document");
+            }
+            return Ok(true);
+        }
+        Err(err) => return Err(SearchError::io_at(path, err)),
+    };
+
+    if config.excludes_path(path, metadata.is_dir()) {
+        return Ok(true);
+    }
+
+    if metadata.is_dir() {
+        // Files moved in together with a folder don't get events of their own
+        // No progress bar, the updates are logged
+        add_folder_to_in_memory_model(path, model, config, None, false)?;
+        info!(path = %path.display(), doc_count = model.tfpd.len(), "updated folder");
+        return Ok(false);
+    }
+
+    if !config.indexes_file(path) {
+        return Ok(true);
+    }
+    let last_modified = metadata.modified().map_err(|err| SearchError::io_at(path, err))?;
+    if !model.requires_reindexing(path, last_modified)? {
+        return Ok(true);
+    }
+
+    match parse_file_or_report(path, config) {
+        Some(file) => {
+            let document = model.analyze(&file.content, file.title.as_deref(), file.metadata.as_deref());
+            let op = IndexOp::add_document(path.to_path_buf(), document, file.title, Some(file.mime_type), last_modified);
+            wal.append(&op)?;
+            op.apply(model)?;
+            info!(path = %path.display(), doc_count = model.tfpd.len(), "updated document");
+        }
+        // A file that no longer parses is no longer searchable either
+        None => {
+            if model.tfpd.contains_key(path) {
+                let op = IndexOp::RemoveDocument { path: path.to_path_buf() };
+                wal.append(&op)?;
+                op.apply(model)?;
+                info!(path = %path.display(), doc_count = model.tfpd.len(), "removed document");
+            }
+        }
+    }
+    Ok(true)
+}
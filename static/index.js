@@ -1,40 +1,147 @@
-async function search(prompt) {
-    let requests = document.getElementById("results");
-    requests.innerHTML = "";
-    const response = await fetch("/api/search", {
-        method: 'POST',
-        headers: {'Content-Type': 'text/plain'},
-        body: prompt,
-    });
-
-    let resultList = document.createElement("ul");
-    resultList.className = "result-list";
-
-    for ([path, rank] of await response.json()) {
-        let item = document.createElement("li");
-
-        let pathSpan = document.createElement("span");
-        pathSpan.appendChild(document.createTextNode(path));
-        item.appendChild(pathSpan);
-
-        // 添加一个空格
-        let space = document.createTextNode(" ");
-        item.appendChild(space);
-
-        let rankSpan = document.createElement("span");
-        rankSpan.className = "rank";
-        rankSpan.appendChild(document.createTextNode(rank));
-        item.appendChild(rankSpan);
-
-        resultList.appendChild(item);
-    }
-    requests.appendChild(resultList);
-}
-
-let query = document.getElementById("query");
-
-query.addEventListener("keypress", (e) => {
-    if (e.key == "Enter") {
-        search(query.value);
-    }
+// Table of what every term of the prompt adds to the score of the document at path
+async function explain(prompt, path, panel) {
+    const response = await fetch(`/api/explain?q=${encodeURIComponent(prompt)}&path=${encodeURIComponent(path)}`);
+    const explanation = await response.json();
+    panel.innerHTML = "";
+    if (!response.ok) {
+        panel.appendChild(document.createTextNode(explanation.error));
+        return;
+    }
+
+    let summary = document.createElement("p");
+    summary.appendChild(document.createTextNode(
+        `${explanation.document_length} terms in the document, ${explanation.document_count} documents in the corpus`));
+    panel.appendChild(summary);
+
+    let table = document.createElement("table");
+    let header = table.insertRow();
+    for (const name of ["term", "weight", "count", "df", "tf", "idf", "contribution"]) {
+        let cell = document.createElement("th");
+        cell.appendChild(document.createTextNode(name));
+        header.appendChild(cell);
+    }
+    for (const {term, weight, raw_count, document_frequency, tf, idf, contribution} of explanation.term_scores) {
+        let row = table.insertRow();
+        for (const value of [term.toLowerCase(), weight, raw_count, document_frequency, tf.toFixed(6), idf.toFixed(6), contribution.toFixed(6)]) {
+            row.insertCell().appendChild(document.createTextNode(value));
+        }
+    }
+    panel.appendChild(table);
+}
+
+// One chip per file extension of the results, clicking one searches again for that extension only
+function facetChips(prompt, facets, ext) {
+    let chips = document.createElement("div");
+    chips.className = "facets";
+    const total = Object.values(facets).reduce((sum, count) => sum + count, 0);
+    const entries = Object.entries(facets).sort(([a, countA], [b, countB]) => countB - countA || a.localeCompare(b));
+    for (const [extension, count] of [[null, total], ...entries]) {
+        let chip = document.createElement("button");
+        chip.className = extension === ext ? "facet active" : "facet";
+        const label = extension === null ? "all" : (extension || "no extension");
+        chip.appendChild(document.createTextNode(`${label} (${count})`));
+        chip.addEventListener("click", () => search(prompt, extension));
+        chips.appendChild(chip);
+    }
+    return chips;
+}
+
+async function search(prompt, ext = null) {
+    let requests = document.getElementById("results");
+    requests.innerHTML = "";
+    const extParam = ext === null ? "" : `&ext=${encodeURIComponent(ext)}`;
+    const response = await fetch(`/api/search?q=${encodeURIComponent(prompt)}${extParam}`);
+
+    let resultList = document.createElement("ul");
+    resultList.className = "result-list";
+
+    const page = await response.json();
+    if (Object.keys(page.facets).length > 1) {
+        requests.appendChild(facetChips(prompt, page.facets, ext));
+    }
+    for (const {path, score: rank, title, snippet} of page.results) {
+        let item = document.createElement("li");
+
+        let pathSpan = document.createElement("span");
+        pathSpan.appendChild(document.createTextNode(title ? `${title} (${path})` : path));
+        item.appendChild(pathSpan);
+
+        // 添加一个空格
+        let space = document.createTextNode(" ");
+        item.appendChild(space);
+
+        let rankSpan = document.createElement("span");
+        rankSpan.className = "rank";
+        rankSpan.appendChild(document.createTextNode(rank));
+        item.appendChild(rankSpan);
+
+        let explainLink = document.createElement("a");
+        explainLink.className = "explain";
+        explainLink.href = "#";
+        explainLink.appendChild(document.createTextNode("Explain"));
+        item.appendChild(explainLink);
+
+        // The server escapes the snippet, leaving only the <mark> around the match
+        if (snippet) {
+            let snippetDiv = document.createElement("div");
+            snippetDiv.className = "snippet";
+            snippetDiv.innerHTML = snippet;
+            item.appendChild(snippetDiv);
+        }
+
+        let explainPanel = document.createElement("div");
+        explainPanel.className = "explanation";
+        explainPanel.hidden = true;
+        item.appendChild(explainPanel);
+        explainLink.addEventListener("click", (e) => {
+            e.preventDefault();
+            explainPanel.hidden = !explainPanel.hidden;
+            if (!explainPanel.hidden) {
+                explain(prompt, path, explainPanel);
+            }
+        });
+
+        resultList.appendChild(item);
+    }
+    requests.appendChild(resultList);
+}
+
+// Complete the last word of the prompt with the indexed terms starting with it
+let suggestController = null;
+async function suggest(prompt) {
+    const words = prompt.split(/\s+/);
+    const prefix = words.pop();
+    if (suggestController) {
+        suggestController.abort();
+    }
+    suggestController = new AbortController();
+
+    let terms = [];
+    try {
+        const response = await fetch(`/api/suggest?prefix=${encodeURIComponent(prefix)}`, {signal: suggestController.signal});
+        terms = await response.json();
+    } catch (e) {
+        // Typing on aborted the request
+        return;
+    }
+
+    let suggestions = document.getElementById("suggestions");
+    suggestions.innerHTML = "";
+    for (const term of terms) {
+        let option = document.createElement("option");
+        option.value = [...words, term.toLowerCase()].join(" ");
+        suggestions.appendChild(option);
+    }
+}
+
+let query = document.getElementById("query");
+
+query.addEventListener("input", () => {
+    suggest(query.value);
+})
+
+query.addEventListener("keypress", (e) => {
+    if (e.key == "Enter") {
+        search(query.value);
+    }
 })
\ No newline at end of file
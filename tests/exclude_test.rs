@@ -0,0 +1,71 @@
+// `serux index` leaves out the files and folders matching --exclude-pattern or the .seruxignore of the folder
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CORPUS: [(&str, &str); 5] = [
+    ("notes.txt", "project notes"),
+    ("node_modules/left-pad/readme.txt", "module docs"),
+    ("app/node_modules/react/readme.txt", "nested module"),
+    ("target/out.txt", "compiled"),
+    ("app/readme.txt", "app docs"),
+];
+
+fn serux(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_serux")).args(args).current_dir(dir).output().expect("serux runs");
+    assert!(output.status.success(), "serux {args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("output is UTF-8")
+}
+
+// Directory with the corpus, removed when dropped
+struct Corpus(PathBuf);
+
+impl Corpus {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("serux-exclude-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, content) in CORPUS {
+            let path = dir.join("corpus").join(name);
+            fs::create_dir_all(path.parent().expect("files are in the corpus")).expect("folder is created");
+            fs::write(path, content).expect("corpus is written");
+        }
+        Self(dir)
+    }
+
+    // Paths of the documents indexed with `args`, relative to the corpus
+    fn indexed(&self, args: &[&str]) -> Vec<String> {
+        serux(&self.0, &[&["index", "corpus"], args].concat());
+        let mut paths = serux(&self.0, &["list", "index.json"]).lines()
+            .map(|line| line.split_once(' ').map_or(line, |(path, _)| path))
+            .map(|path| Path::new(path).strip_prefix("corpus").expect("documents are in the corpus").to_string_lossy().replace('\\', "/"))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+}
+
+impl Drop for Corpus {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn node_modules_are_left_out_with_an_exclude_pattern() {
+    let corpus = Corpus::new("pattern");
+    assert_eq!(corpus.indexed(&[]).len(), CORPUS.len());
+    assert_eq!(corpus.indexed(&["--exclude-pattern", "**/node_modules/**"]), ["app/readme.txt", "notes.txt", "target/out.txt"]);
+    assert_eq!(
+        corpus.indexed(&["--exclude-pattern", "**/node_modules/**", "--exclude-pattern", "**/target/**"]),
+        ["app/readme.txt", "notes.txt"],
+    );
+}
+
+#[test]
+fn the_seruxignore_of_the_folder_applies_with_the_exclude_patterns() {
+    let corpus = Corpus::new("ignore-file");
+    fs::write(corpus.0.join("corpus").join(".seruxignore"), "node_modules/\n").expect("ignore file is written");
+    assert_eq!(corpus.indexed(&[]), ["app/readme.txt", "notes.txt", "target/out.txt"]);
+    assert_eq!(corpus.indexed(&["--exclude-pattern", "**/target/**"]), ["app/readme.txt", "notes.txt"]);
+}
@@ -0,0 +1,110 @@
+// Scores of fixed queries over the corpora of tests/golden, checked against the values worked out by hand
+// in tests/golden/README.md. After an intentional change of the formulas, run with SERUX_UPDATE_GOLDEN=1
+// to write the current scores to the golden files instead.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+const UPDATE_VAR: &str = "SERUX_UPDATE_GOLDEN";
+
+// Scores may differ by this much relative to the golden ones, as f32 rounds differently than by hand
+const RELATIVE_TOLERANCE: f64 = 1e-5;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Golden {
+    query: String,
+    results: Vec<GoldenResult>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct GoldenResult {
+    path: String,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    path: PathBuf,
+    score: f64,
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn serux(dir: &Path, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_serux")).args(args).current_dir(dir).output().expect("serux runs");
+    assert!(output.status.success(), "serux {args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+// Index a copy of the corpus with `index_args` and search the query of the golden file with `search_args`
+fn check_golden(corpus: &str, variant: &str, index_args: &[&str], search_args: &[&str]) {
+    let golden_path = golden_dir().join(format!("{corpus}.{variant}.json"));
+    let golden: Golden = serde_json::from_slice(&fs::read(&golden_path).expect("golden file is readable")).expect("golden file is valid");
+
+    // The index is written to the current directory, so every test runs in a directory of its own
+    let dir = std::env::temp_dir().join(format!("serux-golden-{}-{corpus}-{variant}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("corpus")).expect("temporary directory is created");
+    for file in fs::read_dir(golden_dir().join(corpus)).expect("corpus is readable") {
+        let file = file.expect("corpus is readable").path();
+        fs::copy(&file, dir.join("corpus").join(file.file_name().expect("corpus files have names"))).expect("corpus is copied");
+    }
+
+    serux(&dir, &[index_args, &["index", "corpus"]].concat());
+    let output = serux(&dir, &[search_args, &["search", "--output-format", "json", "index.json", &golden.query]].concat());
+    let results: Vec<SearchResult> = serde_json::from_slice(&output).expect("search prints JSON");
+    let _ = fs::remove_dir_all(&dir);
+
+    let results = results.into_iter().map(|SearchResult { path, score }| GoldenResult {
+        path: path.strip_prefix("corpus").expect("results are in the corpus").to_string_lossy().into_owned(),
+        score,
+    }).collect::<Vec<_>>();
+
+    if std::env::var_os(UPDATE_VAR).is_some_and(|value| value == "1") {
+        let golden = Golden { query: golden.query, results };
+        fs::write(&golden_path, serde_json::to_string_pretty(&golden).expect("golden file is serialized") + "\n").expect("golden file is written");
+        return;
+    }
+
+    let paths = |results: &[GoldenResult]| results.iter().map(|result| result.path.clone()).collect::<Vec<_>>();
+    assert_eq!(paths(&results), paths(&golden.results), "{corpus}.{variant}: results are not those of the golden file");
+    for (result, expected) in results.iter().zip(&golden.results) {
+        let error = (result.score - expected.score).abs() / expected.score.abs();
+        assert!(error <= RELATIVE_TOLERANCE, "{corpus}.{variant}: {} scores {}, expected {}", result.path, result.score, expected.score);
+    }
+}
+
+#[test]
+fn animals_tf_idf() {
+    check_golden("animals", "tfidf", &[], &[]);
+}
+
+#[test]
+fn animals_bm25() {
+    check_golden("animals", "bm25", &[], &["--bm25"]);
+}
+
+#[test]
+fn animals_log_tf() {
+    check_golden("animals", "log_tf", &["--tf", "log"], &[]);
+}
+
+#[test]
+fn languages_tf_idf() {
+    check_golden("languages", "tfidf", &[], &[]);
+}
+
+#[test]
+fn languages_bm25() {
+    check_golden("languages", "bm25", &[], &["--bm25"]);
+}
+
+#[test]
+fn languages_log_tf() {
+    check_golden("languages", "log_tf", &["--tf", "log"], &[]);
+}
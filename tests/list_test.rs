@@ -0,0 +1,91 @@
+// `serux list` prints the documents of an index built from a known directory, sorted and filtered as asked
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+// Name, content: 4 terms in 17 bytes, 1 in 5 and 2 in 11
+const CORPUS: [(&str, &str); 3] = [("a.txt", "rust zig odin nim"), ("b.txt", "alpha"), ("c.md", "gamma delta")];
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Listed {
+    path: PathBuf,
+    term_count: usize,
+    title: Option<String>,
+    file_size_bytes: u64,
+}
+
+fn serux(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_serux")).args(args).current_dir(dir).output().expect("serux runs");
+    assert!(output.status.success(), "serux {args:?} failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("output is UTF-8")
+}
+
+// Directory with the corpus indexed into index.json, removed when dropped
+struct Indexed(PathBuf);
+
+impl Indexed {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("serux-list-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("corpus")).expect("temporary directory is created");
+        for (name, content) in CORPUS {
+            fs::write(dir.join("corpus").join(name), content).expect("corpus is written");
+        }
+        serux(&dir, &["index", "corpus"]);
+        Self(dir)
+    }
+
+    // Paths of the listed documents, one per line, without their size
+    fn list(&self, args: &[&str]) -> Vec<String> {
+        serux(&self.0, &[&["list", "index.json"], args].concat()).lines()
+            .map(|line| line.split_once(' ').map_or(line, |(path, _)| path).to_string())
+            .collect()
+    }
+}
+
+impl Drop for Indexed {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn in_corpus(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| Path::new("corpus").join(name).display().to_string()).collect()
+}
+
+#[test]
+fn documents_are_listed_by_path_size_or_term_count() {
+    let index = Indexed::new("sort");
+    assert_eq!(index.list(&[]), in_corpus(&["a.txt", "b.txt", "c.md"]));
+    assert_eq!(index.list(&["--sort", "alpha"]), in_corpus(&["a.txt", "b.txt", "c.md"]));
+    assert_eq!(index.list(&["--sort", "size"]), in_corpus(&["a.txt", "c.md", "b.txt"]));
+    assert_eq!(index.list(&["--sort", "score"]), in_corpus(&["a.txt", "c.md", "b.txt"]));
+    let lines = serux(&index.0, &["list", "index.json"]);
+    assert_eq!(lines.lines().next(), Some(format!("{} 17 bytes", in_corpus(&["a.txt"])[0]).as_str()));
+}
+
+#[test]
+fn documents_are_filtered_by_extension_and_counted() {
+    let index = Indexed::new("filter");
+    assert_eq!(index.list(&["--filter-ext", "md"]), in_corpus(&["c.md"]));
+    assert_eq!(index.list(&["--filter-ext", ".TXT"]), in_corpus(&["a.txt", "b.txt"]));
+    assert!(index.list(&["--filter-ext", "html"]).is_empty());
+    assert_eq!(serux(&index.0, &["list", "index.json", "--count-only"]).trim(), "3");
+    assert_eq!(serux(&index.0, &["list", "index.json", "--count-only", "--filter-ext", "txt"]).trim(), "2");
+}
+
+#[test]
+fn documents_are_listed_as_json() {
+    let index = Indexed::new("json");
+    let listed: Vec<Listed> = serde_json::from_str(&serux(&index.0, &["list", "index.json", "--json", "--sort", "score"])).expect("list prints JSON");
+    let expected = [("a.txt", 4, 17), ("c.md", 2, 11), ("b.txt", 1, 5)].map(|(name, term_count, file_size_bytes)| Listed {
+        path: Path::new("corpus").join(name),
+        term_count,
+        title: None,
+        file_size_bytes,
+    });
+    assert_eq!(listed, expected);
+}
@@ -0,0 +1,81 @@
+// `serux watch` picks up files created while it runs, checked by searching the index from another process
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct SearchResult {
+    path: PathBuf,
+}
+
+// The watching process, killed when the test ends however it ends
+struct Watcher(Child);
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn search(dir: &Path, query: &str) -> Option<Vec<PathBuf>> {
+    let output = Command::new(env!("CARGO_BIN_EXE_serux"))
+        .args(["search", "--output-format", "json", "index.json", query])
+        .current_dir(dir)
+        .output()
+        .expect("serux runs");
+    // The index is not there until the watcher has indexed the folder once
+    if !output.status.success() {
+        return None;
+    }
+    let results: Vec<SearchResult> = serde_json::from_slice(&output.stdout).expect("search prints JSON");
+    Some(results.into_iter().map(|result| result.path).collect())
+}
+
+// Search until the results are not None, or None once `timeout` is over
+fn poll<T>(timeout: Duration, mut attempt: impl FnMut() -> Option<T>) -> Option<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(value) = attempt() {
+            return Some(value);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn created_file_is_indexed_within_two_seconds() {
+    let dir = std::env::temp_dir().join(format!("serux-watch-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("corpus")).expect("temporary directory is created");
+    // Terms that every document contains score 0, so the corpus needs a second one
+    fs::write(dir.join("corpus").join("old.txt"), "wombats dig burrows").expect("file is written");
+    fs::write(dir.join("corpus").join("other.txt"), "otters float on their backs").expect("file is written");
+
+    let watcher = Watcher(Command::new(env!("CARGO_BIN_EXE_serux"))
+        .args(["--no-progress", "watch", "corpus", "index.json", "--debounce-ms", "100"])
+        .current_dir(&dir)
+        .env("SERUX_LOG", "warn")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("serux watch starts"));
+
+    let old = poll(Duration::from_secs(30), || search(&dir, "wombats").filter(|results| !results.is_empty()));
+    assert_eq!(old, Some(vec![PathBuf::from("corpus/old.txt")]), "the folder is indexed when watching starts");
+
+    fs::write(dir.join("corpus").join("new.txt"), "platypuses lay eggs").expect("file is written");
+    let created = Instant::now();
+    let new = poll(Duration::from_secs(2), || search(&dir, "platypuses").filter(|results| !results.is_empty()));
+    assert_eq!(new, Some(vec![PathBuf::from("corpus/new.txt")]), "not indexed after {:?}", created.elapsed());
+
+    drop(watcher);
+    let _ = fs::remove_dir_all(&dir);
+}